@@ -0,0 +1,178 @@
+// The hash map behind x dictionaries. It is compiled by `x compile` and merged
+// into the object file of every program that uses dictionaries, the functions
+// are weak so that objects compiled separately can be linked together.
+//
+// Keys and values are copied in and out as bytes, the compiler passes their
+// sizes along with functions hashing and comparing keys of the key type.
+
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+
+#define X_RUNTIME __attribute__((weak))
+
+typedef uint64_t (*x_hash_function)(const void *key);
+typedef int32_t (*x_equal_function)(const void *a, const void *b);
+
+enum { SLOT_EMPTY, SLOT_FULL, SLOT_REMOVED };
+
+typedef struct XDictionary {
+    uint64_t key_size;
+    uint64_t value_size;
+    x_hash_function hash;
+    x_equal_function equal;
+    int64_t count;
+    // full and removed slots, the table grows before probes get long
+    int64_t used;
+    int64_t capacity;
+    uint8_t *states;
+    uint8_t *keys;
+    uint8_t *values;
+} XDictionary;
+
+// Spreads the bits of a hash so that keys hashing to nearby values, as small
+// integers do, do not end up in neighbouring slots.
+static uint64_t mix(uint64_t hash) {
+    hash ^= hash >> 33;
+    hash *= 0xff51afd7ed558ccdULL;
+    hash ^= hash >> 33;
+    hash *= 0xc4ceb9fe1a85ec53ULL;
+    hash ^= hash >> 33;
+    return hash;
+}
+
+static void allocate(XDictionary *dictionary, int64_t capacity) {
+    dictionary->capacity = capacity;
+    dictionary->states = calloc(capacity, 1);
+    dictionary->keys = malloc(capacity * dictionary->key_size);
+    dictionary->values = malloc(capacity * dictionary->value_size);
+}
+
+// The slot holding `key`, or the slot it would be inserted in when it is not
+// in the table.
+static int64_t find(const XDictionary *dictionary, const void *key, bool *found) {
+    uint64_t mask = dictionary->capacity - 1;
+    uint64_t index = mix(dictionary->hash(key)) & mask;
+    int64_t removed = -1;
+
+    for (;;) {
+        uint8_t state = dictionary->states[index];
+
+        if (state == SLOT_EMPTY) {
+            *found = false;
+            return removed >= 0 ? removed : (int64_t)index;
+        }
+
+        if (state == SLOT_REMOVED) {
+            if (removed < 0) {
+                removed = index;
+            }
+        } else if (dictionary->equal(dictionary->keys + index * dictionary->key_size, key)) {
+            *found = true;
+            return index;
+        }
+
+        index = (index + 1) & mask;
+    }
+}
+
+static void grow(XDictionary *dictionary) {
+    XDictionary old = *dictionary;
+
+    allocate(dictionary, old.capacity * 2);
+    dictionary->used = dictionary->count;
+
+    for (int64_t i = 0; i < old.capacity; i++) {
+        if (old.states[i] != SLOT_FULL) {
+            continue;
+        }
+
+        bool found;
+        int64_t index = find(dictionary, old.keys + i * old.key_size, &found);
+
+        dictionary->states[index] = SLOT_FULL;
+        memcpy(dictionary->keys + index * old.key_size, old.keys + i * old.key_size,
+               old.key_size);
+        memcpy(dictionary->values + index * old.value_size, old.values + i * old.value_size,
+               old.value_size);
+    }
+
+    free(old.states);
+    free(old.keys);
+    free(old.values);
+}
+
+X_RUNTIME XDictionary *x_dictionary_new(uint64_t key_size, uint64_t value_size,
+                                        x_hash_function hash, x_equal_function equal) {
+    XDictionary *dictionary = malloc(sizeof(XDictionary));
+
+    dictionary->key_size = key_size;
+    dictionary->value_size = value_size;
+    dictionary->hash = hash;
+    dictionary->equal = equal;
+    dictionary->count = 0;
+    dictionary->used = 0;
+    allocate(dictionary, 8);
+
+    return dictionary;
+}
+
+// A pointer to the value of `key`, null when the dictionary does not hold it.
+X_RUNTIME void *x_dictionary_get(XDictionary *dictionary, const void *key) {
+    bool found;
+    int64_t index = find(dictionary, key, &found);
+
+    return found ? dictionary->values + index * dictionary->value_size : NULL;
+}
+
+X_RUNTIME void x_dictionary_insert(XDictionary *dictionary, const void *key, const void *value) {
+    if ((dictionary->used + 1) * 4 > dictionary->capacity * 3) {
+        grow(dictionary);
+    }
+
+    bool found;
+    int64_t index = find(dictionary, key, &found);
+
+    if (!found) {
+        if (dictionary->states[index] == SLOT_EMPTY) {
+            dictionary->used++;
+        }
+
+        dictionary->states[index] = SLOT_FULL;
+        dictionary->count++;
+        memcpy(dictionary->keys + index * dictionary->key_size, key, dictionary->key_size);
+    }
+
+    memcpy(dictionary->values + index * dictionary->value_size, value, dictionary->value_size);
+}
+
+X_RUNTIME void x_dictionary_remove(XDictionary *dictionary, const void *key) {
+    bool found;
+    int64_t index = find(dictionary, key, &found);
+
+    if (found) {
+        dictionary->states[index] = SLOT_REMOVED;
+        dictionary->count--;
+    }
+}
+
+X_RUNTIME int64_t x_dictionary_count(const XDictionary *dictionary) {
+    return dictionary->count;
+}
+
+// Strings are compared by their contents, the key is a pointer to the string.
+X_RUNTIME uint64_t x_hash_string(const void *key) {
+    const unsigned char *string = *(const unsigned char *const *)key;
+    uint64_t hash = 14695981039346656037ULL;
+
+    for (; *string; string++) {
+        hash = (hash ^ *string) * 1099511628211ULL;
+    }
+
+    return hash;
+}
+
+X_RUNTIME int32_t x_equal_string(const void *a, const void *b) {
+    return strcmp(*(const char *const *)a, *(const char *const *)b) == 0;
+}
@@ -0,0 +1,429 @@
+use crate::context::*;
+
+/// The C calling conventions extern functions can be called with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    /// The System V AMD64 ABI used on x86-64 Linux and macOS.
+    SystemV,
+    /// The procedure call standard of 64-bit ARM (AAPCS64).
+    AArch64,
+    /// Any other target, values are passed as LLVM types and left to the backend.
+    Unknown,
+}
+
+impl Target {
+    pub fn from_triple(triple: &str) -> Self {
+        if triple.starts_with("x86_64") && !triple.contains("windows") {
+            Target::SystemV
+        } else if triple.starts_with("aarch64") || triple.starts_with("arm64") {
+            Target::AArch64
+        } else {
+            Target::Unknown
+        }
+    }
+}
+
+/// The smallest pieces a value is made of as far as the calling convention is
+/// concerned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scalar {
+    /// An integer of the given size in bytes.
+    Integer(u64),
+    Float,
+    Double,
+    Pointer,
+}
+
+impl Scalar {
+    pub fn size(&self) -> u64 {
+        match self {
+            Scalar::Integer(size) => *size,
+            Scalar::Float => 4,
+            Scalar::Double | Scalar::Pointer => 8,
+        }
+    }
+
+    fn is_float(&self) -> bool {
+        matches!(self, Scalar::Float | Scalar::Double)
+    }
+}
+
+/// The C memory layout of a type along with the offset of every scalar in it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    pub size: u64,
+    pub align: u64,
+    pub scalars: Vec<(u64, Scalar)>,
+    /// Aggregates are structures, they are classified by their contents.
+    pub aggregate: bool,
+}
+
+impl Layout {
+    fn scalar(scalar: Scalar) -> Self {
+        Self {
+            size: scalar.size(),
+            align: scalar.size(),
+            scalars: vec![(0, scalar)],
+            aggregate: false,
+        }
+    }
+
+    fn structure(members: Vec<Layout>) -> Self {
+        let mut size: u64 = 0;
+        let mut align = 1;
+        let mut scalars = Vec::new();
+
+        for member in members {
+            size = size.next_multiple_of(member.align);
+            align = align.max(member.align);
+
+            for (offset, scalar) in member.scalars {
+                scalars.push((size + offset, scalar));
+            }
+
+            size += member.size;
+        }
+
+        Self {
+            size: size.next_multiple_of(align),
+            align,
+            scalars,
+            aggregate: true,
+        }
+    }
+}
+
+/// The C layout of a type. The type checker only accepts extern and exported
+/// functions whose types have one, the other types are an error.
+pub fn layout(ty: &Type, context: &Context) -> Result<Layout, String> {
+    let no_layout = || format!("{} has no C layout", ty);
+
+    Ok(match ty {
+        Type::Void => Layout {
+            size: 0,
+            align: 1,
+            scalars: Vec::new(),
+            aggregate: false,
+        },
+        // dictionaries are a pointer to their hash map
+        Type::Pointer(_) | Type::Dictionary(..) => Layout::scalar(Scalar::Pointer),
+        // closures are a pointer to their function and one to their environment
+        Type::Function(_) => Layout::structure(vec![
+            Layout::scalar(Scalar::Pointer),
+            Layout::scalar(Scalar::Pointer),
+        ]),
+        // nil is the null pointer, other optionals are a flag followed by the value
+        Type::Optional(wrapped) if wrapped.is_pointer() => Layout::scalar(Scalar::Pointer),
+        Type::Optional(wrapped) => Layout::structure(vec![
+            Layout::scalar(Scalar::Integer(1)),
+            layout(wrapped, context)?,
+        ]),
+        Type::Result(value, error) => Layout::structure(vec![
+            Layout::scalar(Scalar::Integer(1)),
+            layout(value, context)?,
+            layout(error, context)?,
+        ]),
+        // the count followed by a pointer to the elements
+        Type::Array(_) => Layout::structure(vec![
+            Layout::scalar(Scalar::Integer(8)),
+            Layout::scalar(Scalar::Pointer),
+        ]),
+        // laid out like a struct of the elements
+        Type::Tuple(elements) => Layout::structure(layouts(elements, context)?),
+        Type::Generic(name, _) => match context.lookup_type(name).map(|d| &d.kind) {
+            Some(TypeDefinitionKind::Enumeration(_)) => Layout::scalar(Scalar::Integer(4)),
+            Some(TypeDefinitionKind::Structure(_)) => {
+                let fields = context.fields(ty).ok_or_else(no_layout)?;
+                Layout::structure(layouts(fields.iter().map(|field| &field.ty), context)?)
+            }
+            _ => return Err(no_layout()),
+        },
+        Type::Identifier(name) => match name.as_str() {
+            "f64" => Layout::scalar(Scalar::Double),
+            "f32" => Layout::scalar(Scalar::Float),
+            "bool" | "i8" | "u8" => Layout::scalar(Scalar::Integer(1)),
+            "i16" | "u16" => Layout::scalar(Scalar::Integer(2)),
+            "i32" | "u32" => Layout::scalar(Scalar::Integer(4)),
+            "i64" | "u64" => Layout::scalar(Scalar::Integer(8)),
+            _ => match context.lookup_type(name).map(|d| &d.kind) {
+                Some(TypeDefinitionKind::Structure(fields)) => {
+                    Layout::structure(layouts(fields.iter().map(|field| &field.ty), context)?)
+                }
+                // enums are passed as the index of their case
+                Some(TypeDefinitionKind::Enumeration(_)) => Layout::scalar(Scalar::Integer(4)),
+                Some(TypeDefinitionKind::Protocol) => Layout::structure(vec![
+                    Layout::scalar(Scalar::Pointer),
+                    Layout::scalar(Scalar::Pointer),
+                ]),
+                _ => return Err(no_layout()),
+            },
+        },
+    })
+}
+
+fn layouts<'a>(
+    types: impl IntoIterator<Item = &'a Type>,
+    context: &Context,
+) -> Result<Vec<Layout>, String> {
+    types.into_iter().map(|ty| layout(ty, context)).collect()
+}
+
+/// The type a piece of a value is reinterpreted as when it is passed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AbiType {
+    /// An integer of the given size in bits.
+    Integer(u64),
+    Float,
+    Double,
+    Array(Box<AbiType>, u64),
+}
+
+/// How a parameter or the return value of a C function is passed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PassMode {
+    /// As the LLVM type of the value, used for scalars.
+    Direct,
+    /// Through memory, reinterpreted as these types which are each passed as a
+    /// separate argument or returned together in a literal struct.
+    Coerced(Vec<AbiType>),
+    /// As a pointer to a copy of the value, `byval` when the copy is made on
+    /// the stack of the callee. Returned values are written to a pointer the
+    /// caller passes as a hidden first argument.
+    Indirect { byval: bool },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionAbi {
+    pub parameters: Vec<PassMode>,
+    pub return_mode: PassMode,
+}
+
+pub fn classify_function(
+    target: Target,
+    function: &TypeFunction,
+    context: &Context,
+) -> Result<FunctionAbi, String> {
+    let parameters = layouts(
+        function.parameters.iter().map(|parameter| &parameter.ty),
+        context,
+    )?;
+    let return_layout = layout(&function.return_type, context)?;
+
+    Ok(match target {
+        Target::SystemV => classify_system_v(&parameters, &return_layout),
+        Target::AArch64 => FunctionAbi {
+            parameters: parameters.iter().map(classify_aarch64).collect(),
+            return_mode: classify_aarch64(&return_layout),
+        },
+        Target::Unknown => FunctionAbi {
+            parameters: vec![PassMode::Direct; parameters.len()],
+            return_mode: PassMode::Direct,
+        },
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Class {
+    Integer,
+    Sse,
+}
+
+/// Classifies each eightbyte of an aggregate of at most 16 bytes, an eightbyte
+/// holding any integer is passed in a general purpose register.
+fn system_v_classes(layout: &Layout) -> Vec<Class> {
+    let mut classes = vec![Class::Sse; layout.size.div_ceil(8) as usize];
+
+    for (offset, scalar) in &layout.scalars {
+        if !scalar.is_float() {
+            classes[(offset / 8) as usize] = Class::Integer;
+        }
+    }
+
+    classes
+}
+
+fn system_v_pieces(layout: &Layout, classes: &[Class]) -> Vec<AbiType> {
+    classes
+        .iter()
+        .enumerate()
+        .map(|(i, class)| {
+            let size = (layout.size - i as u64 * 8).min(8);
+
+            match class {
+                Class::Integer => AbiType::Integer(size * 8),
+                Class::Sse if size <= 4 => AbiType::Float,
+                Class::Sse => AbiType::Double,
+            }
+        })
+        .collect()
+}
+
+fn classify_system_v(parameters: &[Layout], return_layout: &Layout) -> FunctionAbi {
+    let mut integer_registers = 6;
+    let mut sse_registers = 8;
+
+    let return_mode = if !return_layout.aggregate {
+        PassMode::Direct
+    } else if return_layout.size > 16 {
+        // the pointer to the result takes the first integer register
+        integer_registers -= 1;
+        PassMode::Indirect { byval: false }
+    } else {
+        PassMode::Coerced(system_v_pieces(
+            return_layout,
+            &system_v_classes(return_layout),
+        ))
+    };
+
+    let parameters = parameters
+        .iter()
+        .map(|layout| {
+            if !layout.aggregate {
+                match layout.scalars.first() {
+                    Some((_, scalar)) if scalar.is_float() => sse_registers -= 1,
+                    Some(_) => integer_registers -= 1,
+                    None => {}
+                }
+
+                return PassMode::Direct;
+            }
+
+            if layout.size > 16 {
+                return PassMode::Indirect { byval: true };
+            }
+
+            let classes = system_v_classes(layout);
+            let integers = classes.iter().filter(|c| **c == Class::Integer).count() as i32;
+            let sses = classes.len() as i32 - integers;
+
+            // an aggregate is never split between registers and the stack
+            if integers > integer_registers || sses > sse_registers {
+                return PassMode::Indirect { byval: true };
+            }
+
+            integer_registers -= integers;
+            sse_registers -= sses;
+
+            PassMode::Coerced(system_v_pieces(layout, &classes))
+        })
+        .collect();
+
+    FunctionAbi {
+        parameters,
+        return_mode,
+    }
+}
+
+/// A homogeneous floating-point aggregate has one to four members of the same
+/// floating-point type and is passed in consecutive floating-point registers.
+fn homogeneous_float(layout: &Layout) -> Option<(Scalar, u64)> {
+    let (_, first) = layout.scalars.first()?;
+
+    let is_homogeneous = first.is_float()
+        && layout.scalars.len() <= 4
+        && layout.scalars.iter().all(|(_, scalar)| scalar == first)
+        && layout.size == first.size() * layout.scalars.len() as u64;
+
+    is_homogeneous.then_some((*first, layout.scalars.len() as u64))
+}
+
+/// Parameters and return values are classified the same way, a large result is
+/// written to memory the caller passes in x8.
+fn classify_aarch64(layout: &Layout) -> PassMode {
+    if !layout.aggregate {
+        return PassMode::Direct;
+    }
+
+    if let Some((scalar, count)) = homogeneous_float(layout) {
+        let element = match scalar {
+            Scalar::Float => AbiType::Float,
+            _ => AbiType::Double,
+        };
+
+        return PassMode::Coerced(vec![AbiType::Array(Box::new(element), count)]);
+    }
+
+    if layout.size > 16 {
+        // the caller makes the copy and passes its address in a register
+        return PassMode::Indirect { byval: false };
+    }
+
+    if layout.size > 8 {
+        return PassMode::Coerced(vec![AbiType::Array(Box::new(AbiType::Integer(64)), 2)]);
+    }
+
+    PassMode::Coerced(vec![AbiType::Integer(64)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn structure(fields: &[&str]) -> Layout {
+        let context = Context::new();
+
+        Layout::structure(
+            fields
+                .iter()
+                .map(|name| layout(&Type::Identifier(name.to_string()), &context).unwrap())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn system_v_mixed_eightbytes() {
+        let abi = classify_system_v(
+            &[structure(&["i32", "f32", "f64"])],
+            &structure(&["f64", "f64"]),
+        );
+
+        assert_eq!(
+            abi.parameters,
+            vec![PassMode::Coerced(vec![
+                AbiType::Integer(64),
+                AbiType::Double
+            ])]
+        );
+        assert_eq!(
+            abi.return_mode,
+            PassMode::Coerced(vec![AbiType::Double, AbiType::Double])
+        );
+    }
+
+    #[test]
+    fn system_v_large_aggregates_go_through_memory() {
+        let large = structure(&["f64", "f64", "f64"]);
+        let abi = classify_system_v(std::slice::from_ref(&large), &large);
+
+        assert_eq!(abi.parameters, vec![PassMode::Indirect { byval: true }]);
+        assert_eq!(abi.return_mode, PassMode::Indirect { byval: false });
+    }
+
+    #[test]
+    fn system_v_aggregates_are_not_split_across_registers() {
+        let pair = structure(&["i64", "i64"]);
+        let abi = classify_system_v(&vec![pair; 4], &Layout::scalar(Scalar::Double));
+
+        assert_eq!(
+            abi.parameters[2],
+            PassMode::Coerced(vec![AbiType::Integer(64), AbiType::Integer(64)])
+        );
+        assert_eq!(abi.parameters[3], PassMode::Indirect { byval: true });
+    }
+
+    #[test]
+    fn aarch64_homogeneous_float_aggregates() {
+        assert_eq!(
+            classify_aarch64(&structure(&["f32", "f32", "f32"])),
+            PassMode::Coerced(vec![AbiType::Array(Box::new(AbiType::Float), 3)])
+        );
+        assert_eq!(
+            classify_aarch64(&structure(&["f64", "f32"])),
+            PassMode::Coerced(vec![AbiType::Array(Box::new(AbiType::Integer(64)), 2)])
+        );
+        assert_eq!(
+            classify_aarch64(&structure(&["i64", "i64", "i64"])),
+            PassMode::Indirect { byval: false }
+        );
+    }
+}
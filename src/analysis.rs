@@ -6,73 +6,477 @@ pub struct AnalysisVisitor {}
 impl AnalysisVisitor {
     pub fn visit_source_file(&self, node: &SourceFile, context: &mut Context) {
         self.visit_code_block(&node.code_block, context);
+
+        // libraries and extensions can appear before the protocol or type they refer to
+        for declaration in node.code_block.declarations() {
+            if let Declaration::ExternLibraryDeclaration(library) = declaration {
+                self.visit_extern_library_declaration(library, context);
+            }
+        }
+
+        for declaration in node.code_block.declarations() {
+            if let Declaration::ExtensionDeclaration(extension) = declaration {
+                self.visit_extension_declaration(extension, context);
+            }
+        }
+
+        self.inherit_default_implementations(context);
     }
 
     pub fn visit_code_block(&self, node: &CodeBlock, context: &mut Context) {
         for item in &node.items {
-            self.visit_code_block_item(&item, context);
+            self.visit_code_block_item(item, context);
         }
     }
 
     pub fn visit_code_block_item(&self, node: &CodeBlockItem, context: &mut Context) {
-        match node {
-            CodeBlockItem::Declaration(declaration) => {
-                self.visit_declaration(&declaration, context)
-            }
-            _ => return,
+        if let CodeBlockItem::Declaration(declaration) = node {
+            self.visit_declaration(declaration, context)
         }
     }
 
     pub fn visit_declaration(&self, node: &Declaration, context: &mut Context) {
         match node {
             Declaration::VariableDeclaration(variable) => {
-                self.visit_variable_declaration(&variable, context)
+                self.visit_variable_declaration(variable, context)
             }
             Declaration::FunctionDeclaration(function) => {
-                self.visit_function_declaration(&function, context)
+                self.visit_function_declaration(function, context)
             }
-            Declaration::ExternDeclaration(ext) => self.visit_extern_declaration(&ext, context),
+            Declaration::ExternDeclaration(ext) => self.visit_extern_declaration(ext, context),
             Declaration::EnumDeclaration(enumeration) => {
-                self.visit_enum_declaration(&enumeration, context)
+                self.visit_enum_declaration(enumeration, context)
+            }
+            Declaration::TypeDeclaration(_type) => self.visit_type_declaration(_type, context),
+            Declaration::ProtocolDeclaration(protocol) => {
+                self.visit_protocol_declaration(protocol, context)
+            }
+            // applied once every type is registered, see visit_source_file
+            Declaration::ExtensionDeclaration(_) | Declaration::ExternLibraryDeclaration(_) => {}
+            // replaced by the declarations of the header, see c_import::expand_imports
+            Declaration::CImportDeclaration(_) => {}
+            Declaration::ModuleImportDeclaration(import) => {
+                for declaration in &import.declarations {
+                    self.visit_declaration(declaration, context);
+                }
             }
-            Declaration::TypeDeclaration(_type) => self.visit_type_declaration(&_type, context),
         }
     }
 
-    pub fn visit_variable_declaration(&self, node: &VariableDeclaration, context: &mut Context) {}
+    pub fn visit_variable_declaration(&self, _node: &VariableDeclaration, _context: &mut Context) {}
 
     pub fn visit_function_declaration(&self, node: &FunctionDeclaration, context: &mut Context) {
-        let mut parameters = Vec::new();
+        let mut function = self.visit_function_signature(&node.signature);
+        function.generic_parameters = self.visit_generic_parameters(&node.generic_parameters);
 
-        for param in &node.signature.parameters.parameters {
-            parameters.push(TypeFunctionParameter::new(
-                param.label.clone().map(|i| i.name),
-                param.name.name.clone(),
-                Type::from(param.parameter_type.clone()),
-            ));
-        }
+        let symbol = Symbol {
+            name: node.identifier.name.clone(),
+            symbol_type: Type::Function(function),
+            declared: true,
+        };
 
-        let return_type = node
-            .signature
-            .return_clause
-            .as_ref()
-            .map(|r| Type::from(r.return_type.clone()))
-            .unwrap_or(Type::Void);
+        context.register_symbol(symbol);
+
+        for attribute in &node.attributes {
+            match attribute.name.name.as_str() {
+                "export" => context.register_export(Export {
+                    function: node.identifier.name.clone(),
+                    symbol: attribute
+                        .value
+                        .clone()
+                        .unwrap_or_else(|| node.identifier.name.clone()),
+                }),
+                name => context.error(format!(
+                    "unknown attribute {:?} on function {:?}",
+                    name, node.identifier.name
+                )),
+            }
+        }
+    }
 
+    pub fn visit_extern_declaration(&self, node: &ExternDeclaration, context: &mut Context) {
         let symbol = Symbol {
             name: node.identifier.name.clone(),
-            symbol_type: Type::Function(TypeFunction {
-                parameters,
-                return_type: Box::new(return_type),
-            }),
+            symbol_type: Type::Function(self.visit_function_signature(&node.signature)),
+            declared: true,
         };
 
         context.register_symbol(symbol);
     }
 
-    pub fn visit_extern_declaration(&self, node: &ExternDeclaration, context: &mut Context) {}
+    pub fn visit_function_signature(&self, node: &FunctionSignature) -> TypeFunction {
+        TypeFunction::from(node)
+    }
+
+    pub fn visit_generic_parameters(&self, node: &[GenericParameter]) -> Vec<TypeParameter> {
+        node.iter().cloned().map(TypeParameter::from).collect()
+    }
+
+    /// Conformances are implemented by the methods of a type, those of a generic
+    /// type would need a witness table for each of its instances.
+    fn check_generic_conformances(
+        &self,
+        name: &Identifier,
+        generic_parameters: &[GenericParameter],
+        conformances: &[Identifier],
+        context: &mut Context,
+    ) {
+        if !generic_parameters.is_empty() && !conformances.is_empty() {
+            context.error(format!(
+                "generic type {:?} cannot conform to protocols, bound its type parameters instead",
+                name.name
+            ));
+        }
+    }
+
+    pub fn visit_enum_declaration(&self, node: &EnumDeclaration, context: &mut Context) {
+        let mut cases = Vec::new();
+
+        for member in &node.container.member_block.members {
+            if let MemberBlockItem::EnumCaseDeclaration(case) = member {
+                cases.push(case.name.name.clone());
+            }
+        }
+
+        self.check_generic_conformances(
+            &node.name,
+            &node.generic_parameters,
+            &node.conformances,
+            context,
+        );
+
+        context.register_type(TypeDefinition {
+            name: node.name.name.clone(),
+            kind: TypeDefinitionKind::Enumeration(cases),
+            generic_parameters: self.visit_generic_parameters(&node.generic_parameters),
+            conformances: node.conformances.iter().map(|c| c.name.clone()).collect(),
+            methods: self.visit_methods(&node.container.member_block),
+        });
+    }
+
+    pub fn visit_type_declaration(&self, node: &TypeDeclaration, context: &mut Context) {
+        let mut fields = Vec::new();
+
+        for member in &node.container.member_block.members {
+            let MemberBlockItem::VariableDeclaration(variable) = member else {
+                continue;
+            };
+
+            let Some(type_annotation) = &variable.type_annotation else {
+                context.error(format!(
+                    "field {:?} of {:?} needs a type annotation",
+                    variable.identifier.name, node.name.name
+                ));
+                continue;
+            };
+
+            fields.push(Field {
+                name: variable.identifier.name.clone(),
+                ty: Type::from(type_annotation.clone()),
+            });
+        }
+
+        self.check_generic_conformances(
+            &node.name,
+            &node.generic_parameters,
+            &node.conformances,
+            context,
+        );
+
+        context.register_type(TypeDefinition {
+            name: node.name.name.clone(),
+            kind: TypeDefinitionKind::Structure(fields),
+            generic_parameters: self.visit_generic_parameters(&node.generic_parameters),
+            conformances: node.conformances.iter().map(|c| c.name.clone()).collect(),
+            methods: self.visit_methods(&node.container.member_block),
+        });
+    }
+
+    pub fn visit_protocol_declaration(&self, node: &ProtocolDeclaration, context: &mut Context) {
+        let mut methods = Vec::new();
+
+        for requirement in &node.requirements {
+            let mut symbol = None;
 
-    pub fn visit_enum_declaration(&self, node: &EnumDeclaration, context: &mut Context) {}
+            for attribute in &requirement.attributes {
+                match attribute.name.name.as_str() {
+                    "name" => symbol = self.attribute_value(attribute, context),
+                    _ => context.error(format!(
+                        "unknown attribute {:?} on requirement {:?}",
+                        attribute.name.name, requirement.identifier.name
+                    )),
+                }
+            }
 
-    pub fn visit_type_declaration(&self, node: &TypeDeclaration, context: &mut Context) {}
+            if requirement.selfless && requirement.body.is_some() {
+                context.error(format!(
+                    "selfless requirement {:?} cannot have a default implementation",
+                    requirement.identifier.name
+                ));
+            }
+
+            methods.push(Method {
+                name: requirement.identifier.name.clone(),
+                selfless: requirement.selfless,
+                function: self.visit_function_signature(&requirement.signature),
+                symbol,
+                implementation: match requirement.body {
+                    Some(_) if !requirement.selfless => MethodImplementation::Declared,
+                    _ => MethodImplementation::Required,
+                },
+            });
+        }
+
+        context.register_type(TypeDefinition {
+            name: node.name.name.clone(),
+            kind: TypeDefinitionKind::Protocol,
+            generic_parameters: Vec::new(),
+            conformances: Vec::new(),
+            methods,
+        });
+    }
+
+    pub fn visit_extension_declaration(&self, node: &ExtensionDeclaration, context: &mut Context) {
+        let type_name = &node.name.name;
+        let mut errors = Vec::new();
+
+        for member in &node.container.member_block.members {
+            match member {
+                MemberBlockItem::VariableDeclaration(variable) => errors.push(format!(
+                    "extensions cannot add the stored field {:?} to {:?}",
+                    variable.identifier.name, type_name
+                )),
+                MemberBlockItem::EnumCaseDeclaration(case) => errors.push(format!(
+                    "extensions cannot add the case {:?} to {:?}",
+                    case.name.name, type_name
+                )),
+                MemberBlockItem::FunctionDeclaration(_) => {}
+            }
+        }
+
+        let Some(definition) = context.lookup_type(type_name) else {
+            context.error(format!("cannot extend the unknown type {:?}", type_name));
+            return;
+        };
+
+        if definition.kind == TypeDefinitionKind::Protocol {
+            context.error(format!(
+                "protocol {:?} cannot be extended, declare a default implementation in it instead",
+                type_name
+            ));
+            return;
+        }
+
+        if definition.kind == TypeDefinitionKind::Library {
+            context.error(format!("extern library {:?} cannot be extended", type_name));
+            return;
+        }
+
+        if !definition.generic_parameters.is_empty() {
+            context.error(format!(
+                "generic type {:?} cannot be extended, declare its methods in it instead",
+                type_name
+            ));
+            return;
+        }
+
+        let mut methods = Vec::new();
+
+        for method in self.visit_methods(&node.container.member_block) {
+            if definition.method(&method.name).is_some() {
+                errors.push(format!(
+                    "{:?} already has a method {:?}, an extension cannot redeclare it",
+                    type_name, method.name
+                ));
+                continue;
+            }
+
+            methods.push(method);
+        }
+
+        let mut conformances = Vec::new();
+
+        for conformance in &node.conformances {
+            if definition.conformances.contains(&conformance.name) {
+                errors.push(format!(
+                    "{:?} already conforms to {:?}",
+                    type_name, conformance.name
+                ));
+                continue;
+            }
+
+            conformances.push(conformance.name.clone());
+        }
+
+        for error in errors {
+            context.error(error);
+        }
+
+        let definition = context.lookup_type_mut(type_name).unwrap();
+        definition.methods.extend(methods);
+        definition.conformances.extend(conformances);
+    }
+
+    /// Registers a foreign library as a type whose methods are the selfless
+    /// requirements of its protocol, each bound to a symbol of the library.
+    pub fn visit_extern_library_declaration(
+        &self,
+        node: &ExternLibraryDeclaration,
+        context: &mut Context,
+    ) {
+        let library_name = &node.name.name;
+        let protocol_name = &node.protocol.name;
+        let mut prefix = String::new();
+        let mut errors = Vec::new();
+
+        for attribute in &node.attributes {
+            match attribute.name.name.as_str() {
+                "prefix" => prefix = self.attribute_value(attribute, context).unwrap_or_default(),
+                _ => errors.push(format!(
+                    "unknown attribute {:?} on extern library {:?}",
+                    attribute.name.name, library_name
+                )),
+            }
+        }
+
+        if node.abi != "C" {
+            errors.push(format!(
+                "extern library {:?} uses the unsupported ABI {:?}, only \"C\" is supported",
+                library_name, node.abi
+            ));
+        }
+
+        let mut methods = Vec::new();
+
+        match context.lookup_type(protocol_name) {
+            Some(protocol) if protocol.kind == TypeDefinitionKind::Protocol => {
+                for requirement in &protocol.methods {
+                    if !requirement.selfless {
+                        errors.push(format!(
+                            "extern library {:?} cannot implement {:?} of {:?}, library functions must be selfless",
+                            library_name, requirement.name, protocol_name
+                        ));
+                        continue;
+                    }
+
+                    let symbol = requirement
+                        .symbol
+                        .clone()
+                        .unwrap_or_else(|| format!("{}{}", prefix, requirement.name));
+
+                    methods.push(Method {
+                        implementation: MethodImplementation::External(symbol),
+                        ..requirement.clone()
+                    });
+                }
+            }
+            Some(_) => errors.push(format!(
+                "extern library {:?} cannot conform to {:?} because it is not a protocol",
+                library_name, protocol_name
+            )),
+            None => errors.push(format!("unknown protocol {:?}", protocol_name)),
+        }
+
+        for error in errors {
+            context.error(error);
+        }
+
+        context.register_type(TypeDefinition {
+            name: library_name.clone(),
+            kind: TypeDefinitionKind::Library,
+            generic_parameters: Vec::new(),
+            conformances: vec![protocol_name.clone()],
+            methods,
+        });
+    }
+
+    /// Gives every type the default implementations of the protocol requirements
+    /// it does not implement itself, a method declared by the type or one of its
+    /// extensions always takes precedence over a default.
+    pub fn inherit_default_implementations(&self, context: &mut Context) {
+        let mut inherited = Vec::new();
+        let mut errors = Vec::new();
+
+        for definition in context.types() {
+            let mut methods: Vec<Method> = Vec::new();
+
+            for protocol_name in &definition.conformances {
+                let Some(protocol) = context.lookup_type(protocol_name) else {
+                    continue;
+                };
+
+                for requirement in &protocol.methods {
+                    if requirement.implementation != MethodImplementation::Declared
+                        || definition.method(&requirement.name).is_some()
+                    {
+                        continue;
+                    }
+
+                    if let Some(method) = methods.iter().find(|m| m.name == requirement.name) {
+                        if let MethodImplementation::Default(other) = &method.implementation {
+                            errors.push(format!(
+                                "{:?} inherits conflicting default implementations of {:?} from {:?} and {:?}, implement it to resolve the ambiguity",
+                                definition.name, requirement.name, other, protocol_name
+                            ));
+                        }
+
+                        continue;
+                    }
+
+                    methods.push(Method {
+                        name: requirement.name.clone(),
+                        selfless: false,
+                        function: requirement.function.clone(),
+                        symbol: None,
+                        implementation: MethodImplementation::Default(protocol_name.clone()),
+                    });
+                }
+            }
+
+            if !methods.is_empty() {
+                inherited.push((definition.name.clone(), methods));
+            }
+        }
+
+        for error in errors {
+            context.error(error);
+        }
+
+        for (type_name, methods) in inherited {
+            context
+                .lookup_type_mut(&type_name)
+                .unwrap()
+                .methods
+                .extend(methods);
+        }
+    }
+
+    fn attribute_value(&self, attribute: &Attribute, context: &mut Context) -> Option<String> {
+        if attribute.value.is_none() {
+            context.error(format!(
+                "attribute {:?} needs a value, as in @{}(\"value\")",
+                attribute.name.name, attribute.name.name
+            ));
+        }
+
+        attribute.value.clone()
+    }
+
+    fn visit_methods(&self, node: &MemberBlock) -> Vec<Method> {
+        let mut methods = Vec::new();
+
+        for member in &node.members {
+            if let MemberBlockItem::FunctionDeclaration(function) = member {
+                methods.push(Method {
+                    name: function.identifier.name.clone(),
+                    selfless: false,
+                    function: self.visit_function_signature(&function.signature),
+                    symbol: None,
+                    implementation: MethodImplementation::Declared,
+                });
+            }
+        }
+
+        methods
+    }
 }
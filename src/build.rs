@@ -0,0 +1,258 @@
+use crate::driver::{self, CompileOptions, Emit, LinkOptions};
+use crate::manifest::{Manifest, PackageType, MANIFEST_FILE};
+use crate::module;
+use crate::runtime;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+/// A project taking part in a build, the one built or one of its
+/// dependencies.
+struct Package {
+    directory: PathBuf,
+    manifest: Manifest,
+    /// The source roots of every package it depends on, directly or not, where
+    /// the modules it imports are looked up.
+    search_paths: Vec<PathBuf>,
+}
+
+/// Builds the project whose manifest is in a directory into its `target`
+/// directory: every module of it and of its dependencies is compiled to
+/// `target/obj/<package>/<module>.o`, then linked into `target/<name>`, or
+/// archived into `target/lib<name>.a` with the headers of its modules in
+/// `target/include` for a library. A release build leaves out the bounds
+/// checks of array indices.
+pub fn build(directory: &Path, release: bool) -> Result<(), String> {
+    let directory = directory
+        .canonicalize()
+        .map_err(|error| format!("cannot find project {}: {}", directory.display(), error))?;
+
+    let mut packages = Vec::new();
+    resolve(
+        &directory,
+        &mut Vec::new(),
+        &mut HashMap::new(),
+        &mut packages,
+    )?;
+
+    let target = directory.join("target");
+    let root = packages.last().unwrap();
+    let library = root.manifest.package_type == PackageType::Library;
+
+    let mut modules: HashMap<String, String> = HashMap::new();
+    let mut objects = Vec::new();
+
+    for package in &packages {
+        let object_directory = target.join("obj").join(&package.manifest.name);
+        create_directory(&object_directory)?;
+
+        let headers = match library && package.directory == root.directory {
+            true => Some(target.join("include")),
+            false => None,
+        };
+
+        if let Some(headers) = &headers {
+            create_directory(headers)?;
+        }
+
+        for source in package_modules(package)? {
+            let name = module::module_name(&source)?;
+
+            // the symbols of a module are named after it, two modules of the same name would clash
+            if let Some(other) = modules.insert(name.clone(), package.manifest.name.clone()) {
+                return Err(format!(
+                    "module {:?} is defined by both package {:?} and package {:?}",
+                    name, other, package.manifest.name
+                ));
+            }
+
+            let options = CompileOptions {
+                header: headers
+                    .as_ref()
+                    .map(|headers| headers.join(format!("{}.h", name))),
+                emit: Emit::Obj,
+                bounds_checks: !release,
+                search_paths: package.search_paths.clone(),
+            };
+
+            let object = object_directory.join(format!("{}.o", name));
+            driver::compile_module(&source, &object, &options)?;
+            objects.push(object);
+        }
+
+        for source in &package.manifest.c_sources {
+            let object = c_object(&object_directory, source);
+
+            if let Some(parent) = object.parent() {
+                create_directory(parent)?;
+            }
+
+            runtime::run(
+                Command::new(runtime::c_compiler())
+                    .arg("-c")
+                    .arg(package.directory.join(source))
+                    .arg("-o")
+                    .arg(&object),
+            )?;
+            objects.push(object);
+        }
+
+        for object in &package.manifest.c_objects {
+            objects.push(package.directory.join(object));
+        }
+    }
+
+    match root.manifest.package_type {
+        PackageType::Executable => {
+            let link = LinkOptions {
+                library_paths: Vec::new(),
+                libraries: packages
+                    .iter()
+                    .flat_map(|package| package.manifest.libraries.clone())
+                    .collect(),
+            };
+
+            driver::link_executable(&objects, &target.join(&root.manifest.name), &link)
+        }
+        PackageType::Library => {
+            let archive = target.join(format!("lib{}.a", root.manifest.name));
+            let _ = fs::remove_file(&archive);
+
+            runtime::run(Command::new("ar").arg("rcs").arg(archive).args(&objects))
+        }
+    }
+}
+
+/// Reads the manifest of a package and of everything it depends on, adding
+/// each package after its dependencies, once.
+fn resolve(
+    directory: &Path,
+    stack: &mut Vec<PathBuf>,
+    resolved: &mut HashMap<PathBuf, usize>,
+    packages: &mut Vec<Package>,
+) -> Result<usize, String> {
+    if let Some(index) = resolved.get(directory) {
+        return Ok(*index);
+    }
+
+    if stack.iter().any(|package| package == directory) {
+        return Err(format!(
+            "package {} depends on itself, directly or through its dependencies",
+            directory.display()
+        ));
+    }
+
+    let manifest = Manifest::read(directory)?;
+    let mut search_paths = Vec::new();
+
+    stack.push(directory.to_path_buf());
+
+    for dependency in &manifest.dependencies {
+        let path = directory.join(&dependency.path);
+        let path = path.canonicalize().map_err(|error| {
+            format!(
+                "cannot find dependency {:?} of {} at {}: {}",
+                dependency.name,
+                directory.join(MANIFEST_FILE).display(),
+                path.display(),
+                error
+            )
+        })?;
+
+        let index = resolve(&path, stack, resolved, packages)?;
+        let package = &packages[index];
+
+        if package.manifest.name != dependency.name {
+            return Err(format!(
+                "dependency {:?} of {} is the package {:?}",
+                dependency.name,
+                directory.join(MANIFEST_FILE).display(),
+                package.manifest.name
+            ));
+        }
+
+        for path in package
+            .manifest
+            .sources
+            .iter()
+            .map(|source| package.directory.join(source))
+            .chain(package.search_paths.iter().cloned())
+        {
+            if !search_paths.contains(&path) {
+                search_paths.push(path);
+            }
+        }
+    }
+
+    stack.pop();
+
+    packages.push(Package {
+        directory: directory.to_path_buf(),
+        manifest,
+        search_paths,
+    });
+    resolved.insert(directory.to_path_buf(), packages.len() - 1);
+
+    Ok(packages.len() - 1)
+}
+
+/// The modules of a package, the `.x` files and the directories of `.x` files
+/// directly in its source roots, in order of their names.
+fn package_modules(package: &Package) -> Result<Vec<PathBuf>, String> {
+    let mut modules = Vec::new();
+
+    for source in &package.manifest.sources {
+        let source = package.directory.join(source);
+        let entries = fs::read_dir(&source)
+            .map_err(|error| format!("cannot read sources {}: {}", source.display(), error))?;
+
+        let mut paths = Vec::new();
+
+        for entry in entries {
+            let path = entry
+                .map_err(|error| format!("cannot read sources {}: {}", source.display(), error))?
+                .path();
+            let is_module = match path.is_dir() {
+                true => !module::read_module(&path)?.is_empty(),
+                false => path.extension().is_some_and(|extension| extension == "x"),
+            };
+
+            if is_module {
+                paths.push(path);
+            }
+        }
+
+        paths.sort();
+        modules.append(&mut paths);
+    }
+
+    Ok(modules)
+}
+
+/// The object file of a C source mirrors its path in the package, so that
+/// sources of the same name in different directories do not clash:
+/// `native/util.c` is compiled to `native/util.c.o`.
+fn c_object(object_directory: &Path, source: &Path) -> PathBuf {
+    let mut object = object_directory.to_path_buf();
+
+    for component in source.components() {
+        match component {
+            Component::Normal(name) => object.push(name),
+            // sources outside of the package directory stay in the object directory
+            Component::ParentDir => object.push("_"),
+            _ => {}
+        }
+    }
+
+    let mut name = object.into_os_string();
+    name.push(".o");
+
+    PathBuf::from(name)
+}
+
+fn create_directory(directory: &Path) -> Result<(), String> {
+    fs::create_dir_all(directory)
+        .map_err(|error| format!("cannot create {}: {}", directory.display(), error))
+}
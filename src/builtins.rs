@@ -0,0 +1,626 @@
+use crate::interpreter::Value;
+
+use std::ffi::{CStr, CString};
+use std::io::Write;
+use std::iter::Peekable;
+use std::os::unix::ffi::OsStringExt;
+use std::rc::Rc;
+
+type Builtin = for<'a> fn(&[Value<'a>]) -> Result<Value<'a>, String>;
+
+/// The C functions the interpreter provides in place of the C library, a
+/// program run by it can only call the extern functions listed here.
+const BUILTINS: &[(&str, Builtin)] = &[
+    ("printf", printf),
+    ("puts", puts),
+    ("putchar", putchar),
+    ("atoi", atoi),
+    ("strlen", strlen),
+    ("getenv", getenv),
+    ("abs", abs),
+    ("labs", labs),
+    ("sqrt", sqrt),
+    ("floor", floor),
+    ("ceil", ceil),
+    ("fabs", fabs),
+    ("pow", pow),
+    ("exit", exit),
+];
+
+/// Calls the builtin implementing a C function, none when there is no such
+/// builtin.
+pub fn call<'a>(name: &str, arguments: &[Value<'a>]) -> Option<Result<Value<'a>, String>> {
+    let (_, builtin) = BUILTINS.iter().find(|(builtin, _)| *builtin == name)?;
+
+    Some(builtin(arguments).map_err(|error| format!("{}: {}", name, error)))
+}
+
+fn printf<'a>(arguments: &[Value<'a>]) -> Result<Value<'a>, String> {
+    let output = format(string(arguments, 0)?.to_bytes(), &arguments[1..])?;
+    write(&output)?;
+
+    Ok(Value::Integer(output.len() as i64))
+}
+
+fn puts<'a>(arguments: &[Value<'a>]) -> Result<Value<'a>, String> {
+    let mut output = string(arguments, 0)?.to_bytes().to_vec();
+    output.push(b'\n');
+    write(&output)?;
+
+    Ok(Value::Integer(output.len() as i64))
+}
+
+fn putchar<'a>(arguments: &[Value<'a>]) -> Result<Value<'a>, String> {
+    let character = integer(arguments, 0)?;
+    write(&[character as u8])?;
+
+    Ok(Value::Integer(character as u8 as i64))
+}
+
+/// Reads the decimal integer at the start of a string, after any whitespace,
+/// ignoring what follows it.
+fn atoi<'a>(arguments: &[Value<'a>]) -> Result<Value<'a>, String> {
+    let bytes = string(arguments, 0)?.to_bytes();
+    let mut bytes = bytes
+        .iter()
+        .skip_while(|byte| byte.is_ascii_whitespace())
+        .peekable();
+
+    let negative = bytes.peek() == Some(&&b'-');
+
+    if let Some(b'-' | b'+') = bytes.peek() {
+        bytes.next();
+    }
+
+    let mut value: i64 = 0;
+
+    for byte in bytes.take_while(|byte| byte.is_ascii_digit()) {
+        value = value.wrapping_mul(10).wrapping_add((byte - b'0') as i64);
+    }
+
+    if negative {
+        value = value.wrapping_neg();
+    }
+
+    Ok(Value::Integer(value as i32 as i64))
+}
+
+fn strlen<'a>(arguments: &[Value<'a>]) -> Result<Value<'a>, String> {
+    Ok(Value::Integer(string(arguments, 0)?.to_bytes().len() as i64))
+}
+
+/// Returns the value of an environment variable, nil when it is not set. The
+/// extern function has to be declared as returning `*u8?`.
+fn getenv<'a>(arguments: &[Value<'a>]) -> Result<Value<'a>, String> {
+    let name = string(arguments, 0)?.to_string_lossy().to_string();
+
+    let value = std::env::var_os(name).map(|value| {
+        let value = CString::new(value.into_vec()).unwrap();
+        Box::new(Value::String(Rc::new(value)))
+    });
+
+    Ok(Value::Optional(value))
+}
+
+fn abs<'a>(arguments: &[Value<'a>]) -> Result<Value<'a>, String> {
+    Ok(Value::Integer(
+        (integer(arguments, 0)? as i32).wrapping_abs() as i64,
+    ))
+}
+
+fn labs<'a>(arguments: &[Value<'a>]) -> Result<Value<'a>, String> {
+    Ok(Value::Integer(integer(arguments, 0)?.wrapping_abs()))
+}
+
+fn sqrt<'a>(arguments: &[Value<'a>]) -> Result<Value<'a>, String> {
+    Ok(Value::Float(float(arguments, 0)?.sqrt()))
+}
+
+fn floor<'a>(arguments: &[Value<'a>]) -> Result<Value<'a>, String> {
+    Ok(Value::Float(float(arguments, 0)?.floor()))
+}
+
+fn ceil<'a>(arguments: &[Value<'a>]) -> Result<Value<'a>, String> {
+    Ok(Value::Float(float(arguments, 0)?.ceil()))
+}
+
+fn fabs<'a>(arguments: &[Value<'a>]) -> Result<Value<'a>, String> {
+    Ok(Value::Float(float(arguments, 0)?.abs()))
+}
+
+fn pow<'a>(arguments: &[Value<'a>]) -> Result<Value<'a>, String> {
+    Ok(Value::Float(
+        float(arguments, 0)?.powf(float(arguments, 1)?),
+    ))
+}
+
+/// Stops the program, what it printed is written out first.
+fn exit<'a>(arguments: &[Value<'a>]) -> Result<Value<'a>, String> {
+    let status = integer(arguments, 0)?;
+    let _ = std::io::stdout().flush();
+
+    std::process::exit(status as i32)
+}
+
+fn write(output: &[u8]) -> Result<(), String> {
+    std::io::stdout()
+        .write_all(output)
+        .map_err(|error| format!("cannot write to the standard output: {}", error))
+}
+
+fn argument<'v, 'a>(arguments: &'v [Value<'a>], index: usize) -> Result<&'v Value<'a>, String> {
+    arguments
+        .get(index)
+        .ok_or_else(|| format!("missing argument {}", index + 1))
+}
+
+fn string<'v>(arguments: &'v [Value], index: usize) -> Result<&'v CStr, String> {
+    match argument(arguments, index)? {
+        Value::String(string) => Ok(string),
+        Value::Optional(Some(value)) => match value.as_ref() {
+            Value::String(string) => Ok(string),
+            _ => Err(format!("argument {} is not a string", index + 1)),
+        },
+        _ => Err(format!("argument {} is not a string", index + 1)),
+    }
+}
+
+fn integer(arguments: &[Value], index: usize) -> Result<i64, String> {
+    match argument(arguments, index)? {
+        Value::Integer(value) => Ok(*value),
+        Value::Bool(value) => Ok(*value as i64),
+        Value::Case(case) => Ok(*case as i64),
+        _ => Err(format!("argument {} is not an integer", index + 1)),
+    }
+}
+
+fn float(arguments: &[Value], index: usize) -> Result<f64, String> {
+    match argument(arguments, index)? {
+        Value::Float(value) => Ok(*value),
+        _ => Err(format!(
+            "argument {} is not a floating point number",
+            index + 1
+        )),
+    }
+}
+
+/// A conversion specification of a format, as in `%-8.3f`.
+#[derive(Default)]
+struct Specification {
+    left: bool,
+    plus: bool,
+    space: bool,
+    zero: bool,
+    alternate: bool,
+    width: usize,
+    precision: Option<usize>,
+    /// The size of the integer argument in bits, as given by `hh`, `h` or `l`.
+    bits: u32,
+}
+
+/// Formats the arguments of `printf` the way the C library does, for the
+/// conversions of integers, floating point numbers, characters, strings and
+/// pointers.
+pub fn format(format: &[u8], arguments: &[Value]) -> Result<Vec<u8>, String> {
+    let mut output = Vec::new();
+    let mut bytes = format.iter().copied().peekable();
+    let mut arguments = arguments.iter().map(std::slice::from_ref);
+
+    let mut next = || {
+        arguments
+            .next()
+            .ok_or_else(|| "the format has more conversions than arguments".to_string())
+    };
+
+    while let Some(byte) = bytes.next() {
+        if byte != b'%' {
+            output.push(byte);
+            continue;
+        }
+
+        let mut specification = Specification {
+            bits: 32,
+            ..Specification::default()
+        };
+
+        while let Some(flag) = bytes.peek() {
+            match flag {
+                b'-' => specification.left = true,
+                b'+' => specification.plus = true,
+                b' ' => specification.space = true,
+                b'0' => specification.zero = true,
+                b'#' => specification.alternate = true,
+                _ => break,
+            }
+
+            bytes.next();
+        }
+
+        if bytes.next_if_eq(&b'*').is_some() {
+            let width = integer(next()?, 0).map_err(|_| expected(b'*', "an integer"))? as i32;
+            specification.left |= width < 0;
+            specification.width = width.unsigned_abs() as usize;
+        } else {
+            specification.width = digits(&mut bytes);
+        }
+
+        if bytes.next_if_eq(&b'.').is_some() {
+            specification.precision = match bytes.next_if_eq(&b'*') {
+                Some(_) => {
+                    let precision =
+                        integer(next()?, 0).map_err(|_| expected(b'*', "an integer"))?;
+                    usize::try_from(precision as i32).ok()
+                }
+                None => Some(digits(&mut bytes)),
+            };
+        }
+
+        while let Some(length) = bytes.next_if(|byte| b"hlLqjzt".contains(byte)) {
+            specification.bits = match length {
+                b'h' if specification.bits == 16 => 8,
+                b'h' => 16,
+                _ => 64,
+            };
+        }
+
+        let Some(conversion) = bytes.next() else {
+            return Err("the format ends in the middle of a conversion".to_string());
+        };
+
+        match conversion {
+            b'%' => output.push(b'%'),
+            b'd' | b'i' => {
+                let value = integer(next()?, 0).map_err(|_| expected(conversion, "an integer"))?;
+                let value = truncate(value, specification.bits, true);
+                let sign = sign(value < 0, &specification);
+                let digits = integer_digits(value.unsigned_abs(), 10, &specification);
+
+                // zeros are not added to integers given a precision
+                let zeros = specification.precision.is_none();
+                pad(&mut output, sign, &digits, &specification, zeros);
+            }
+            b'u' | b'x' | b'X' | b'o' => {
+                let value = integer(next()?, 0).map_err(|_| expected(conversion, "an integer"))?;
+                let value = truncate(value, specification.bits, false) as u64;
+                let radix = match conversion {
+                    b'u' => 10,
+                    b'o' => 8,
+                    _ => 16,
+                };
+
+                let mut digits = integer_digits(value, radix, &specification);
+                let mut prefix = "";
+
+                if conversion == b'X' {
+                    digits = digits.to_uppercase();
+                }
+
+                if specification.alternate && value != 0 {
+                    match conversion {
+                        b'x' => prefix = "0x",
+                        b'X' => prefix = "0X",
+                        b'o' if !digits.starts_with('0') => digits.insert(0, '0'),
+                        _ => {}
+                    }
+                }
+
+                let zeros = specification.precision.is_none();
+                pad(&mut output, prefix, &digits, &specification, zeros);
+            }
+            b'c' => {
+                let character =
+                    integer(next()?, 0).map_err(|_| expected(conversion, "an integer"))? as u8;
+                pad_bytes(&mut output, b"", &[character], &specification, false);
+            }
+            b's' => {
+                let argument = next()?;
+                let bytes = match argument {
+                    [Value::Optional(None)] => &b"(null)"[..],
+                    _ => string(argument, 0)
+                        .map_err(|_| expected(conversion, "a string"))?
+                        .to_bytes(),
+                };
+                let length = specification
+                    .precision
+                    .unwrap_or(bytes.len())
+                    .min(bytes.len());
+
+                pad_bytes(&mut output, b"", &bytes[..length], &specification, false);
+            }
+            b'p' => {
+                let address = match next()? {
+                    [Value::String(string)] => format!("{:#x}", string.as_ptr() as usize),
+                    [Value::Optional(Some(value))] => match value.as_ref() {
+                        Value::String(string) => format!("{:#x}", string.as_ptr() as usize),
+                        _ => return Err(expected(conversion, "a pointer")),
+                    },
+                    [Value::Optional(None)] => "(nil)".to_string(),
+                    _ => return Err(expected(conversion, "a pointer")),
+                };
+
+                pad(&mut output, "", &address, &specification, false);
+            }
+            b'f' | b'F' | b'e' | b'E' | b'g' | b'G' => {
+                let value = float(next()?, 0)
+                    .map_err(|_| expected(conversion, "a floating point number"))?;
+                let sign = sign(value.is_sign_negative(), &specification);
+                let upper = conversion.is_ascii_uppercase();
+
+                if !value.is_finite() {
+                    let text = match (value.is_nan(), upper) {
+                        (true, false) => "nan",
+                        (true, true) => "NAN",
+                        (false, false) => "inf",
+                        (false, true) => "INF",
+                    };
+
+                    pad(&mut output, sign, text, &specification, false);
+                    continue;
+                }
+
+                let text = float_digits(value.abs(), conversion, &specification);
+                pad(&mut output, sign, &text, &specification, true);
+            }
+            _ => {
+                return Err(format!(
+                    "the conversion %{} is not supported",
+                    conversion as char
+                ))
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+fn expected(conversion: u8, what: &str) -> String {
+    format!("%{} expects {}", conversion as char, what)
+}
+
+fn digits(bytes: &mut Peekable<impl Iterator<Item = u8>>) -> usize {
+    let mut value = 0;
+
+    while let Some(digit) = bytes.next_if(u8::is_ascii_digit) {
+        value = value * 10 + (digit - b'0') as usize;
+    }
+
+    value
+}
+
+/// Converts an integer argument to the size given by the conversion, C
+/// passes smaller integers promoted to `int`.
+fn truncate(value: i64, bits: u32, signed: bool) -> i64 {
+    match (bits, signed) {
+        (8, true) => value as i8 as i64,
+        (16, true) => value as i16 as i64,
+        (32, true) => value as i32 as i64,
+        (8, false) => value as u8 as i64,
+        (16, false) => value as u16 as i64,
+        (32, false) => value as u32 as i64,
+        _ => value,
+    }
+}
+
+fn sign(negative: bool, specification: &Specification) -> &'static str {
+    match (negative, specification.plus, specification.space) {
+        (true, _, _) => "-",
+        (false, true, _) => "+",
+        (false, false, true) => " ",
+        _ => "",
+    }
+}
+
+/// The digits of an integer, with at least as many digits as the precision.
+fn integer_digits(value: u64, radix: u32, specification: &Specification) -> String {
+    let digits = match radix {
+        8 => format!("{:o}", value),
+        16 => format!("{:x}", value),
+        _ => value.to_string(),
+    };
+
+    match specification.precision {
+        Some(0) if value == 0 => String::new(),
+        Some(precision) => format!("{:0>width$}", digits, width = precision),
+        None => digits,
+    }
+}
+
+/// The digits of a positive finite number in the notation of a conversion,
+/// `%g` uses the shorter one without trailing zeros.
+fn float_digits(value: f64, conversion: u8, specification: &Specification) -> String {
+    let precision = specification.precision.unwrap_or(6);
+
+    let mut text = match conversion.to_ascii_lowercase() {
+        b'f' => {
+            let mut text = format!("{:.*}", precision, value);
+
+            if specification.alternate && precision == 0 {
+                text.push('.');
+            }
+
+            text
+        }
+        b'e' => scientific(value, precision),
+        _ => {
+            let precision = precision.max(1);
+            let exponent = match value {
+                0.0 => 0,
+                _ => exponent(&format!("{:.*e}", precision - 1, value)),
+            };
+
+            let text = if exponent < precision as i32 && exponent >= -4 {
+                format!("{:.*}", (precision as i32 - 1 - exponent) as usize, value)
+            } else {
+                scientific(value, precision - 1)
+            };
+
+            match specification.alternate {
+                true => text,
+                false => strip_zeros(&text),
+            }
+        }
+    };
+
+    if conversion.is_ascii_uppercase() {
+        text = text.to_uppercase();
+    }
+
+    text
+}
+
+/// Writes a number as `d.ddde+dd`, the exponent has at least two digits.
+fn scientific(value: f64, precision: usize) -> String {
+    let text = format!("{:.*e}", precision, value);
+    let (mantissa, _) = text.split_once('e').unwrap();
+    let exponent = exponent(&text);
+    let sign = if exponent < 0 { '-' } else { '+' };
+
+    format!("{}e{}{:02}", mantissa, sign, exponent.abs())
+}
+
+fn exponent(scientific: &str) -> i32 {
+    let (_, exponent) = scientific.split_once('e').unwrap();
+    exponent.parse().unwrap()
+}
+
+/// Removes the trailing zeros of the fraction of a number, and its point
+/// when nothing follows it.
+fn strip_zeros(text: &str) -> String {
+    let (number, exponent) = match text.find('e') {
+        Some(index) => text.split_at(index),
+        None => (text, ""),
+    };
+
+    if !number.contains('.') {
+        return text.to_string();
+    }
+
+    let number = number.trim_end_matches('0').trim_end_matches('.');
+
+    format!("{}{}", number, exponent)
+}
+
+fn pad(
+    output: &mut Vec<u8>,
+    prefix: &str,
+    text: &str,
+    specification: &Specification,
+    numeric: bool,
+) {
+    pad_bytes(
+        output,
+        prefix.as_bytes(),
+        text.as_bytes(),
+        specification,
+        numeric,
+    );
+}
+
+/// Writes a converted argument padded to the width, with spaces or, for
+/// numbers given the `0` flag, with zeros after the sign.
+fn pad_bytes(
+    output: &mut Vec<u8>,
+    prefix: &[u8],
+    text: &[u8],
+    specification: &Specification,
+    numeric: bool,
+) {
+    let padding = specification
+        .width
+        .saturating_sub(prefix.len() + text.len());
+    let zeros = numeric && specification.zero && !specification.left;
+
+    if specification.left {
+        output.extend_from_slice(prefix);
+        output.extend_from_slice(text);
+        output.extend(std::iter::repeat_n(b' ', padding));
+    } else if zeros {
+        output.extend_from_slice(prefix);
+        output.extend(std::iter::repeat_n(b'0', padding));
+        output.extend_from_slice(text);
+    } else {
+        output.extend(std::iter::repeat_n(b' ', padding));
+        output.extend_from_slice(prefix);
+        output.extend_from_slice(text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(text: &str) -> Value<'static> {
+        Value::String(Rc::new(CString::new(text).unwrap()))
+    }
+
+    fn formatted(format_string: &str, arguments: &[Value]) -> String {
+        String::from_utf8(format(format_string.as_bytes(), arguments).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn integers_and_strings_are_formatted() {
+        assert_eq!(
+            formatted(
+                "%d|%5d|%-5d|%05d|%+d|%.3d|%x|%#X|%o|%u|%lld|%hhd",
+                &[
+                    Value::Integer(42),
+                    Value::Integer(-42),
+                    Value::Integer(7),
+                    Value::Integer(-7),
+                    Value::Integer(3),
+                    Value::Integer(5),
+                    Value::Integer(255),
+                    Value::Integer(255),
+                    Value::Integer(8),
+                    Value::Integer(-1),
+                    Value::Integer(1 << 40),
+                    Value::Integer(200),
+                ]
+            ),
+            "42|  -42|7    |-0007|+3|005|ff|0XFF|10|4294967295|1099511627776|-56"
+        );
+
+        assert_eq!(
+            formatted(
+                "%s|%4s|%-4s|%.2s|%c|%d|%%",
+                &[
+                    string("ab"),
+                    string("ab"),
+                    string("ab"),
+                    string("abc"),
+                    Value::Integer(b'x' as i64),
+                    Value::Bool(true),
+                ]
+            ),
+            "ab|  ab|ab  |ab|x|1|%"
+        );
+    }
+
+    #[test]
+    fn floats_are_formatted() {
+        let values = [0.5, 2.0, 1e-5, 123456789.0, 0.0001234, -1.25, 100.0]
+            .map(Value::Float)
+            .to_vec();
+
+        assert_eq!(
+            formatted("%g %g %g %g %g %g %g", &values),
+            "0.5 2 1e-05 1.23457e+08 0.0001234 -1.25 100"
+        );
+        assert_eq!(
+            formatted("%f %.2f %8.3f %-8.1f| %e %.0f %E", &values),
+            "0.500000 2.00    0.000 123456789.0| 1.234000e-04 -1 1.000000E+02"
+        );
+        assert_eq!(
+            formatted(
+                "%g %G %08.2f %+.1e",
+                &[
+                    Value::Float(f64::INFINITY),
+                    Value::Float(f64::NAN),
+                    Value::Float(-2.75),
+                    Value::Float(12345.0),
+                ]
+            ),
+            "inf NAN -0002.75 +1.2e+04"
+        );
+    }
+}
@@ -0,0 +1,1359 @@
+use crate::context::Context;
+use crate::syntax::*;
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Replaces every `import c "header.h"` of a file with the extern functions,
+/// types and constants the header declares. Paths are relative to the
+/// directory of the importing file.
+pub fn expand_imports(file: &mut SourceFile, directory: &Path, context: &mut Context) {
+    let items = std::mem::take(&mut file.code_block.items);
+
+    for item in items {
+        let CodeBlockItem::Declaration(Declaration::CImportDeclaration(import)) = item else {
+            file.code_block.items.push(item);
+            continue;
+        };
+
+        let mut header = CHeader::default();
+        header.read(&directory.join(&import.path), context);
+
+        for declaration in header.declarations(context) {
+            file.code_block
+                .items
+                .push(CodeBlockItem::Declaration(declaration));
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum CToken {
+    Identifier(String),
+    Number(String),
+    String(String),
+    Punctuation(String),
+    /// A header included with `#include "path"`, read where it appears.
+    Include(PathBuf),
+}
+
+/// A C type as written in the header, typedef names are resolved when the x
+/// declarations are generated.
+#[derive(Debug, Clone, PartialEq)]
+enum CType {
+    Void,
+    /// `char` without a sign, used for strings.
+    Char,
+    Primitive(&'static str),
+    Struct(String),
+    Enum(String),
+    Typedef(String),
+    Pointer(Box<CType>),
+    Array(Box<CType>, u64),
+    Function,
+    Unsupported(String),
+}
+
+#[derive(Debug)]
+struct CStruct {
+    tag: String,
+    fields: Vec<(String, CType)>,
+}
+
+#[derive(Debug)]
+struct CEnum {
+    tag: String,
+    cases: Vec<(String, i128)>,
+}
+
+#[derive(Debug)]
+struct CFunction {
+    name: String,
+    parameters: Vec<(Option<String>, CType)>,
+    variadic: bool,
+    return_type: CType,
+}
+
+/// The named or unnamed parameters of a function and whether it is variadic.
+type Parameters = (Vec<(Option<String>, CType)>, bool);
+
+/// What a declarator adds to the type in front of it, `*name[4]` or
+/// `name(int, char *)`.
+struct Declarator {
+    name: Option<String>,
+    ty: CType,
+    parameters: Option<Parameters>,
+}
+
+/// The declarations read from a header and the headers it includes.
+#[derive(Default)]
+struct CHeader {
+    included: HashSet<PathBuf>,
+    macros: HashSet<String>,
+    constants: Vec<(String, i128, bool)>,
+    constant_values: HashMap<String, i128>,
+    structs: Vec<CStruct>,
+    enums: Vec<CEnum>,
+    typedefs: HashMap<String, CType>,
+    functions: Vec<CFunction>,
+    tokens: Vec<CToken>,
+    position: usize,
+    anonymous_count: usize,
+}
+
+impl CHeader {
+    fn read(&mut self, path: &Path, context: &mut Context) {
+        let path = path.canonicalize().unwrap_or(path.to_path_buf());
+
+        if !self.included.insert(path.clone()) {
+            return;
+        }
+
+        let Ok(source) = fs::read_to_string(&path) else {
+            context.error(format!("cannot read C header {:?}", path));
+            return;
+        };
+
+        let directory = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        self.parse(&source, &directory, context);
+    }
+
+    fn parse(&mut self, source: &str, directory: &Path, context: &mut Context) {
+        let tokens = self.preprocess(source, directory, context);
+
+        let outer_tokens = std::mem::replace(&mut self.tokens, tokens);
+        let outer_position = std::mem::replace(&mut self.position, 0);
+
+        while self.position < self.tokens.len() {
+            self.parse_top_level(context);
+        }
+
+        self.tokens = outer_tokens;
+        self.position = outer_position;
+    }
+
+    /// Removes comments, applies the conditional directives and collects the
+    /// integer constants defined with `#define`. Conditions other than
+    /// `defined(NAME)` and integers are assumed to hold.
+    fn preprocess(&mut self, source: &str, directory: &Path, context: &mut Context) -> Vec<CToken> {
+        let source = strip_comments(source).replace("\\\n", " ");
+        let mut tokens = Vec::new();
+        // for every open conditional, whether it is active and whether a branch was taken
+        let mut conditionals: Vec<(bool, bool)> = Vec::new();
+
+        for line in source.lines() {
+            let active = conditionals.iter().all(|(active, _)| *active);
+            let line = line.trim();
+
+            let Some(directive) = line.strip_prefix('#') else {
+                if active {
+                    tokens.extend(tokenize(line));
+                }
+                continue;
+            };
+
+            let directive = directive.trim_start();
+            let (name, rest) = directive
+                .split_once(|c: char| c.is_whitespace())
+                .unwrap_or((directive, ""));
+            let rest = rest.trim();
+
+            match name {
+                "ifdef" | "ifndef" | "if" => {
+                    let holds = match name {
+                        "ifdef" => self.macros.contains(rest),
+                        "ifndef" => !self.macros.contains(rest),
+                        _ => self.condition_holds(rest),
+                    };
+                    conditionals.push((holds, holds));
+                }
+                "elif" => {
+                    if let Some((active, taken)) = conditionals.last_mut() {
+                        *active = !*taken && self.condition_holds(rest);
+                        *taken |= *active;
+                    }
+                }
+                "else" => {
+                    if let Some((active, taken)) = conditionals.last_mut() {
+                        *active = !*taken;
+                        *taken = true;
+                    }
+                }
+                "endif" => {
+                    conditionals.pop();
+                }
+                "define" if active => self.define(rest, context),
+                "undef" if active => {
+                    self.macros.remove(rest);
+                }
+                // system headers are left to the C compiler
+                "include" if active => {
+                    if let Some(path) = rest.strip_prefix('"').and_then(|r| r.strip_suffix('"')) {
+                        tokens.push(CToken::Include(directory.join(path)));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        tokens
+    }
+
+    fn condition_holds(&self, condition: &str) -> bool {
+        let condition = condition.replace(' ', "");
+
+        if let Some(name) = condition
+            .strip_prefix("!defined(")
+            .and_then(|c| c.strip_suffix(')'))
+        {
+            return !self.macros.contains(name);
+        }
+
+        if let Some(name) = condition
+            .strip_prefix("defined(")
+            .and_then(|c| c.strip_suffix(')'))
+        {
+            return self.macros.contains(name);
+        }
+
+        match parse_integer(&condition) {
+            Some((value, _)) => value != 0,
+            None => true,
+        }
+    }
+
+    /// Object-like macros whose value is an integer constant expression become
+    /// constants, other macros are only remembered for the conditionals.
+    fn define(&mut self, definition: &str, context: &mut Context) {
+        let name_end = definition
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(definition.len());
+        let (name, value) = definition.split_at(name_end);
+
+        self.macros.insert(name.to_string());
+
+        // function-like macros
+        if value.starts_with('(') {
+            return;
+        }
+
+        let tokens = tokenize(value);
+
+        // macros without a value only mark something as defined
+        if tokens.is_empty() {
+            return;
+        }
+
+        match evaluate_constant(&tokens, &self.constant_values) {
+            Some((value, unsigned)) => self.add_constant(name, value, unsigned),
+            None => context.warning(format!(
+                "skipping #define {}, its value is not an integer constant expression",
+                name
+            )),
+        }
+    }
+
+    fn add_constant(&mut self, name: &str, value: i128, unsigned: bool) {
+        if self
+            .constant_values
+            .insert(name.to_string(), value)
+            .is_none()
+        {
+            self.constants.push((name.to_string(), value, unsigned));
+        }
+    }
+
+    fn peek(&self) -> Option<&CToken> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<CToken> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn is_punctuation(&self, punctuation: &str) -> bool {
+        matches!(self.peek(), Some(CToken::Punctuation(p)) if p == punctuation)
+    }
+
+    fn expect(&mut self, punctuation: &str) -> Option<()> {
+        if !self.is_punctuation(punctuation) {
+            return None;
+        }
+
+        self.position += 1;
+        Some(())
+    }
+
+    fn identifier(&mut self) -> Option<String> {
+        match self.peek() {
+            Some(CToken::Identifier(name)) => {
+                let name = name.clone();
+                self.position += 1;
+                Some(name)
+            }
+            _ => None,
+        }
+    }
+
+    /// Skips a parenthesized or braced group, the current token opens it.
+    fn skip_group(&mut self) {
+        let mut depth = 0;
+
+        while let Some(token) = self.next() {
+            match token {
+                CToken::Punctuation(p) if p == "(" || p == "{" || p == "[" => depth += 1,
+                CToken::Punctuation(p) if p == ")" || p == "}" || p == "]" => depth -= 1,
+                _ => {}
+            }
+
+            if depth == 0 {
+                return;
+            }
+        }
+    }
+
+    /// Skips the rest of a declaration the importer does not understand.
+    fn skip_declaration(&mut self) {
+        while let Some(token) = self.peek() {
+            match token {
+                CToken::Punctuation(p) if p == ";" => {
+                    self.position += 1;
+                    return;
+                }
+                CToken::Punctuation(p) if p == "(" || p == "[" => self.skip_group(),
+                CToken::Punctuation(p) if p == "{" => {
+                    self.skip_group();
+                    // the body of a function ends the declaration
+                    if !self.is_punctuation(";") && !self.is_punctuation(",") {
+                        return;
+                    }
+                }
+                CToken::Include(_) => return,
+                _ => self.position += 1,
+            }
+        }
+    }
+
+    fn parse_top_level(&mut self, context: &mut Context) {
+        match self.peek().cloned() {
+            Some(CToken::Include(path)) => {
+                self.position += 1;
+                self.read(&path, context);
+            }
+            Some(CToken::Punctuation(p)) if p == ";" || p == "}" => self.position += 1,
+            // `extern "C" {` of headers shared with C++
+            Some(CToken::Identifier(keyword))
+                if keyword == "extern"
+                    && matches!(self.tokens.get(self.position + 1), Some(CToken::String(_))) =>
+            {
+                self.position += 2;
+                self.expect("{");
+            }
+            _ => {
+                let start = self.position;
+
+                if self.parse_declaration().is_none() {
+                    self.position = start;
+                    self.skip_declaration();
+
+                    let text = self.tokens[start..self.position.min(self.tokens.len())]
+                        .iter()
+                        .take(12)
+                        .map(token_text)
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    context.warning(format!("skipping unsupported C declaration: {}", text));
+                }
+            }
+        }
+    }
+
+    fn parse_declaration(&mut self) -> Option<()> {
+        let is_typedef = self.peek() == Some(&CToken::Identifier("typedef".to_string()));
+
+        if is_typedef {
+            self.position += 1;
+        }
+
+        let (base, is_static) = self.parse_specifiers()?;
+
+        if self.expect(";").is_some() {
+            return Some(());
+        }
+
+        loop {
+            let declarator = self.parse_declarator(base.clone())?;
+
+            // the initializer of a variable
+            if self.expect("=").is_some() {
+                while !self.is_punctuation(",") && !self.is_punctuation(";") {
+                    match self.peek()? {
+                        CToken::Punctuation(p) if p == "(" || p == "{" => self.skip_group(),
+                        _ => self.position += 1,
+                    }
+                }
+            }
+
+            if is_typedef {
+                let name = declarator.name?;
+                let ty = if declarator.parameters.is_some() {
+                    CType::Function
+                } else {
+                    declarator.ty
+                };
+
+                self.typedefs.entry(name).or_insert(ty);
+            } else if let Some((parameters, variadic)) = declarator.parameters {
+                // inline functions are not part of the library
+                if self.is_punctuation("{") {
+                    self.skip_group();
+                    return Some(());
+                }
+
+                let name = declarator.name?;
+
+                if !is_static && !self.functions.iter().any(|f| f.name == name) {
+                    self.functions.push(CFunction {
+                        name,
+                        parameters,
+                        variadic,
+                        return_type: declarator.ty,
+                    });
+                }
+            }
+
+            if self.expect(",").is_none() {
+                break;
+            }
+        }
+
+        self.expect(";")
+    }
+
+    /// Parses the type in front of the declarators, along with whether the
+    /// declaration is `static`.
+    fn parse_specifiers(&mut self) -> Option<(CType, bool)> {
+        let mut words: Vec<String> = Vec::new();
+        let mut ty = None;
+        let mut is_static = false;
+
+        while let Some(CToken::Identifier(word)) = self.peek().cloned() {
+            match word.as_str() {
+                "const" | "volatile" | "restrict" | "__restrict" | "extern" | "register"
+                | "inline" | "__inline" | "__inline__" | "_Noreturn" | "__extension__" => {
+                    self.position += 1
+                }
+                "static" => {
+                    is_static = true;
+                    self.position += 1;
+                }
+                "__attribute__" | "__declspec" | "_Alignas" => {
+                    self.position += 1;
+                    self.skip_group();
+                }
+                "signed" | "unsigned" | "short" | "long" | "int" | "char" | "float" | "double"
+                | "void" | "_Bool" | "bool" => {
+                    words.push(word);
+                    self.position += 1;
+                }
+                "struct" | "union" => {
+                    self.position += 1;
+                    ty = Some(self.parse_struct(word == "union")?);
+                }
+                "enum" => {
+                    self.position += 1;
+                    ty = Some(self.parse_enum()?);
+                }
+                // a typedef name, unless the type is already known and this is the declarator
+                _ if ty.is_none() && words.is_empty() => {
+                    self.position += 1;
+                    ty = Some(typedef_type(&word));
+                }
+                _ => break,
+            }
+        }
+
+        if let Some(ty) = ty {
+            return Some((ty, is_static));
+        }
+
+        if words.is_empty() {
+            return None;
+        }
+
+        Some((primitive_type(&words), is_static))
+    }
+
+    fn parse_struct(&mut self, is_union: bool) -> Option<CType> {
+        let tag = self.identifier();
+
+        if !self.is_punctuation("{") {
+            return match is_union {
+                true => Some(CType::Unsupported(format!(
+                    "union {} is not supported",
+                    tag?
+                ))),
+                false => Some(CType::Struct(tag?)),
+            };
+        }
+
+        let tag = tag.unwrap_or_else(|| {
+            self.anonymous_count += 1;
+            format!("anonymous{}", self.anonymous_count)
+        });
+
+        if is_union {
+            self.skip_group();
+            return Some(CType::Unsupported(format!(
+                "union {} is not supported",
+                tag
+            )));
+        }
+
+        self.expect("{")?;
+        let mut fields = Vec::new();
+        let mut supported = true;
+
+        while self.expect("}").is_none() {
+            let (base, _) = self.parse_specifiers()?;
+
+            loop {
+                let declarator = self.parse_declarator(base.clone())?;
+
+                // bit-fields have no x counterpart
+                if self.expect(":").is_some() {
+                    self.next();
+                    supported = false;
+                }
+
+                fields.push((declarator.name?, declarator.ty));
+
+                if self.expect(",").is_none() {
+                    break;
+                }
+            }
+
+            self.expect(";")?;
+        }
+
+        if !supported {
+            return Some(CType::Unsupported(format!(
+                "struct {} with bit-fields",
+                tag
+            )));
+        }
+
+        self.structs.retain(|s| s.tag != tag);
+        self.structs.push(CStruct {
+            tag: tag.clone(),
+            fields,
+        });
+
+        Some(CType::Struct(tag))
+    }
+
+    fn parse_enum(&mut self) -> Option<CType> {
+        let tag = self.identifier();
+
+        if self.expect("{").is_none() {
+            return Some(CType::Enum(tag?));
+        }
+
+        let mut cases = Vec::new();
+        let mut next_value = 0;
+
+        while self.expect("}").is_none() {
+            let name = self.identifier()?;
+
+            if self.expect("=").is_some() {
+                let start = self.position;
+
+                while !self.is_punctuation(",") && !self.is_punctuation("}") {
+                    self.next()?;
+                }
+
+                next_value = self.evaluate(start, self.position)?;
+            }
+
+            cases.push((name.clone(), next_value));
+            self.constant_values.insert(name, next_value);
+            next_value += 1;
+
+            self.expect(",");
+        }
+
+        let tag = tag.unwrap_or_else(|| {
+            self.anonymous_count += 1;
+            format!("anonymous{}", self.anonymous_count)
+        });
+
+        self.enums.push(CEnum {
+            tag: tag.clone(),
+            cases,
+        });
+
+        Some(CType::Enum(tag))
+    }
+
+    /// Evaluates the integer constant expression between two token positions.
+    fn evaluate(&self, start: usize, end: usize) -> Option<i128> {
+        evaluate_constant(&self.tokens[start..end], &self.constant_values).map(|(value, _)| value)
+    }
+
+    fn parse_declarator(&mut self, base: CType) -> Option<Declarator> {
+        let mut ty = base;
+
+        while self.expect("*").is_some() {
+            ty = CType::Pointer(Box::new(ty));
+
+            while let Some(CToken::Identifier(qualifier)) = self.peek() {
+                if !["const", "volatile", "restrict", "__restrict"].contains(&qualifier.as_str()) {
+                    break;
+                }
+                self.position += 1;
+            }
+        }
+
+        // function pointers, `(*name)(parameters)`
+        if self.is_punctuation("(")
+            && self.tokens.get(self.position + 1) == Some(&CToken::Punctuation("*".to_string()))
+        {
+            self.position += 1;
+
+            while self.expect("*").is_some() {}
+
+            let name = self.identifier();
+            self.expect(")")?;
+
+            if self.is_punctuation("(") {
+                self.skip_group();
+            }
+
+            return Some(Declarator {
+                name,
+                ty: CType::Pointer(Box::new(CType::Function)),
+                parameters: None,
+            });
+        }
+
+        let name = self.identifier();
+        let mut parameters = None;
+
+        if self.is_punctuation("(") {
+            parameters = Some(self.parse_parameters()?);
+        }
+
+        while self.expect("[").is_some() {
+            let start = self.position;
+
+            while !self.is_punctuation("]") {
+                self.next()?;
+            }
+
+            ty = match self.evaluate(start, self.position) {
+                Some(size) => CType::Array(Box::new(ty), size as u64),
+                None => CType::Pointer(Box::new(ty)),
+            };
+
+            self.expect("]")?;
+        }
+
+        // attributes and assembler names after the declarator
+        while let Some(CToken::Identifier(word)) = self.peek() {
+            if !["__attribute__", "__asm", "__asm__", "asm"].contains(&word.as_str()) {
+                break;
+            }
+            self.position += 1;
+            self.skip_group();
+        }
+
+        Some(Declarator {
+            name,
+            ty,
+            parameters,
+        })
+    }
+
+    fn parse_parameters(&mut self) -> Option<Parameters> {
+        self.expect("(")?;
+
+        let mut parameters = Vec::new();
+        let mut variadic = false;
+
+        while self.expect(")").is_none() {
+            if self.expect("...").is_some() {
+                variadic = true;
+                continue;
+            }
+
+            let (base, _) = self.parse_specifiers()?;
+            let declarator = self.parse_declarator(base)?;
+
+            // arrays and functions are passed as pointers
+            let ty = match declarator.ty {
+                CType::Array(element, _) => CType::Pointer(element),
+                _ if declarator.parameters.is_some() => CType::Pointer(Box::new(CType::Function)),
+                ty => ty,
+            };
+
+            // `(void)` takes no parameters
+            if ty != CType::Void {
+                parameters.push((declarator.name, ty));
+            }
+
+            self.expect(",");
+        }
+
+        Some((parameters, variadic))
+    }
+
+    /// The x declarations of everything read so far. Declarations that use
+    /// types without an x counterpart are skipped with a warning.
+    fn declarations(&self, context: &mut Context) -> Vec<Declaration> {
+        let mut declarations = Vec::new();
+        let mut skipped = Vec::new();
+
+        for definition in &self.structs {
+            let mut members = Vec::new();
+
+            for (name, ty) in &definition.fields {
+                match ty {
+                    // fixed size arrays are laid out like that many fields
+                    CType::Array(element, count) => {
+                        for i in 0..*count {
+                            members.push((format!("{}_{}", name, i), self.x_type(element)));
+                        }
+                    }
+                    ty => members.push((name.clone(), self.x_type(ty))),
+                }
+            }
+
+            let members = members
+                .into_iter()
+                .map(|(name, ty)| {
+                    Ok(MemberBlockItem::VariableDeclaration(VariableDeclaration {
+                        visibility: Visibility::Public,
+                        location: SourceLocation::default(),
+                        identifier: Identifier { name },
+                        type_annotation: Some(ty?),
+                        expression: None,
+                    }))
+                })
+                .collect::<Result<Vec<_>, String>>();
+
+            match members {
+                Ok(members) => declarations.push(Declaration::TypeDeclaration(TypeDeclaration {
+                    visibility: Visibility::Public,
+                    location: SourceLocation::default(),
+                    name: Identifier {
+                        name: self.struct_name(&definition.tag),
+                    },
+                    generic_parameters: Vec::new(),
+                    conformances: Vec::new(),
+                    container: MemberBlockContainer {
+                        member_block: MemberBlock { members },
+                    },
+                })),
+                Err(reason) => skipped.push(format!("struct {}: {}", definition.tag, reason)),
+            }
+        }
+
+        let mut constants = self.constants.clone();
+
+        for definition in &self.enums {
+            match self.enum_name(definition) {
+                Some(name) => {
+                    let members = definition
+                        .cases
+                        .iter()
+                        .map(|(case, _)| {
+                            MemberBlockItem::EnumCaseDeclaration(EnumCaseDeclaration {
+                                name: Identifier { name: case.clone() },
+                            })
+                        })
+                        .collect();
+
+                    declarations.push(Declaration::EnumDeclaration(EnumDeclaration {
+                        visibility: Visibility::Public,
+                        location: SourceLocation::default(),
+                        name: Identifier { name },
+                        generic_parameters: Vec::new(),
+                        conformances: Vec::new(),
+                        container: MemberBlockContainer {
+                            member_block: MemberBlock { members },
+                        },
+                    }));
+                }
+                // cases with explicit values are constants of type i32
+                None => constants.extend(
+                    definition
+                        .cases
+                        .iter()
+                        .map(|(case, value)| (case.clone(), *value, false)),
+                ),
+            }
+        }
+
+        for (name, value, unsigned) in constants {
+            let ty = match (unsigned, value) {
+                (false, value) if i32::try_from(value).is_ok() => "i32",
+                (true, value) if u32::try_from(value).is_ok() => "u32",
+                (false, value) if i64::try_from(value).is_ok() => "i64",
+                _ => "u64",
+            };
+
+            declarations.push(Declaration::VariableDeclaration(VariableDeclaration {
+                visibility: Visibility::Public,
+                location: SourceLocation::default(),
+                identifier: Identifier { name },
+                type_annotation: Some(identifier_type(ty)),
+                expression: Some(Expression::FloatNumberLiteral(value as f64)),
+            }));
+        }
+
+        for function in &self.functions {
+            match self.extern_declaration(function) {
+                Ok(declaration) => declarations.push(Declaration::ExternDeclaration(declaration)),
+                Err(reason) => skipped.push(format!("function {}: {}", function.name, reason)),
+            }
+        }
+
+        for reason in skipped {
+            context.warning(format!("skipping C declaration of {}", reason));
+        }
+
+        declarations
+    }
+
+    fn extern_declaration(&self, function: &CFunction) -> Result<ExternDeclaration, String> {
+        let mut parameters = Vec::new();
+
+        for (i, (name, ty)) in function.parameters.iter().enumerate() {
+            parameters.push(FunctionParameter {
+                label: None,
+                name: Identifier {
+                    name: name.clone().unwrap_or(format!("arg{}", i)),
+                },
+                parameter_type: self.x_type(ty)?,
+            });
+        }
+
+        let return_clause = match self.resolve(&function.return_type) {
+            CType::Void => None,
+            ty => Some(ReturnClause {
+                return_type: self.x_type(&ty)?,
+            }),
+        };
+
+        Ok(ExternDeclaration {
+            identifier: Identifier {
+                name: function.name.clone(),
+            },
+            signature: FunctionSignature {
+                parameters: FunctionParameters {
+                    parameters,
+                    variadic: function.variadic,
+                },
+                return_clause,
+            },
+        })
+    }
+
+    /// Follows typedef names to the type they stand for.
+    fn resolve(&self, ty: &CType) -> CType {
+        let mut ty = ty.clone();
+        let mut depth = 0;
+
+        while let CType::Typedef(name) = &ty {
+            depth += 1;
+
+            match self.typedefs.get(name) {
+                Some(aliased) if depth < 32 => ty = aliased.clone(),
+                _ => return CType::Unsupported(format!("unknown type {}", name)),
+            }
+        }
+
+        ty
+    }
+
+    fn x_type(&self, ty: &CType) -> Result<TypeSyntax, String> {
+        match self.resolve(ty) {
+            CType::Primitive(name) => Ok(identifier_type(name)),
+            CType::Char => Ok(identifier_type("i8")),
+            CType::Struct(tag) if self.structs.iter().any(|s| s.tag == tag) => {
+                Ok(identifier_type(&self.struct_name(&tag)))
+            }
+            CType::Struct(tag) => Err(format!("struct {} is incomplete", tag)),
+            CType::Enum(tag) => Ok(identifier_type(
+                &self
+                    .enums
+                    .iter()
+                    .find(|e| e.tag == tag)
+                    .and_then(|e| self.enum_name(e))
+                    .unwrap_or("i32".to_string()),
+            )),
+            CType::Pointer(pointee) => {
+                let pointee = match self.resolve(&pointee) {
+                    // strings are `*u8` in x
+                    CType::Char => Ok(identifier_type("u8")),
+                    pointee => self.x_type(&pointee),
+                };
+
+                // void, functions and incomplete structs are pointed to as bytes
+                Ok(TypeSyntax::PointerType(Box::new(
+                    pointee.unwrap_or(identifier_type("u8")),
+                )))
+            }
+            CType::Void => Err("void is not a value".to_string()),
+            CType::Array(..) => Err("arrays are not supported".to_string()),
+            CType::Function => Err("functions are not values".to_string()),
+            CType::Unsupported(reason) => Err(reason),
+            CType::Typedef(_) => unreachable!(),
+        }
+    }
+
+    /// A struct is named after a typedef that names it, or its tag.
+    fn struct_name(&self, tag: &str) -> String {
+        self.typedef_name(&CType::Struct(tag.to_string()))
+            .unwrap_or(tag.to_string())
+    }
+
+    /// Enums whose cases count up from zero become x enums, the others are
+    /// only constants.
+    fn enum_name(&self, definition: &CEnum) -> Option<String> {
+        let sequential = definition
+            .cases
+            .iter()
+            .enumerate()
+            .all(|(i, (_, value))| *value == i as i128);
+
+        if !sequential {
+            return None;
+        }
+
+        match self.typedef_name(&CType::Enum(definition.tag.clone())) {
+            Some(name) => Some(name),
+            None if definition.tag.starts_with("anonymous") => None,
+            None => Some(definition.tag.clone()),
+        }
+    }
+
+    /// The first name in alphabetical order that is a typedef of a type.
+    fn typedef_name(&self, ty: &CType) -> Option<String> {
+        self.typedefs
+            .iter()
+            .filter(|(_, aliased)| *aliased == ty)
+            .map(|(name, _)| name.clone())
+            .min()
+    }
+}
+
+fn identifier_type(name: &str) -> TypeSyntax {
+    TypeSyntax::IdentifierType(Identifier {
+        name: name.to_string(),
+    })
+}
+
+/// The type of a typedef name, the fixed width integers of `<stdint.h>` and
+/// the sizes of `<stddef.h>` are known without their header.
+fn typedef_type(name: &str) -> CType {
+    let primitive = match name {
+        "int8_t" => "i8",
+        "int16_t" => "i16",
+        "int32_t" => "i32",
+        "int64_t" | "ssize_t" | "ptrdiff_t" | "intptr_t" | "off_t" => "i64",
+        "uint8_t" => "u8",
+        "uint16_t" => "u16",
+        "uint32_t" => "u32",
+        "uint64_t" | "size_t" | "uintptr_t" => "u64",
+        _ => return CType::Typedef(name.to_string()),
+    };
+
+    CType::Primitive(primitive)
+}
+
+/// The type named by a list of C type keywords, `long` is 64 bits as on the
+/// LP64 targets x supports.
+fn primitive_type(words: &[String]) -> CType {
+    let has = |word: &str| words.iter().any(|w| w == word);
+    let unsigned = has("unsigned");
+    let longs = words.iter().filter(|w| *w == "long").count();
+
+    let (signed, unsigned_name) = if has("void") {
+        return CType::Void;
+    } else if has("_Bool") || has("bool") {
+        return CType::Primitive("bool");
+    } else if has("float") {
+        return CType::Primitive("f32");
+    } else if has("double") && longs > 0 {
+        return CType::Unsupported("long double".to_string());
+    } else if has("double") {
+        return CType::Primitive("f64");
+    } else if has("char") {
+        if !unsigned && !has("signed") {
+            return CType::Char;
+        }
+        ("i8", "u8")
+    } else if has("short") {
+        ("i16", "u16")
+    } else if longs > 0 {
+        ("i64", "u64")
+    } else {
+        ("i32", "u32")
+    };
+
+    CType::Primitive(if unsigned { unsigned_name } else { signed })
+}
+
+/// Parses a C integer literal with its suffixes, returning whether it is
+/// unsigned.
+fn parse_integer(literal: &str) -> Option<(i128, bool)> {
+    let literal = literal.to_ascii_lowercase();
+    let digits = literal.trim_end_matches(['u', 'l']);
+    let unsigned = literal[digits.len()..].contains('u');
+
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i128::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i128::from_str_radix(binary, 2).ok()?
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i128::from_str_radix(&digits[1..], 8).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+
+    Some((value, unsigned))
+}
+
+/// Evaluates an integer constant expression of literals and known constants
+/// combined with parentheses and the arithmetic and bitwise operators. The
+/// result is unsigned when one of the literals is.
+fn evaluate_constant(tokens: &[CToken], constants: &HashMap<String, i128>) -> Option<(i128, bool)> {
+    let mut evaluator = ConstantEvaluator {
+        tokens,
+        position: 0,
+        constants,
+    };
+    let value = evaluator.binary(0)?;
+
+    (evaluator.position == tokens.len()).then_some(value)
+}
+
+struct ConstantEvaluator<'a> {
+    tokens: &'a [CToken],
+    position: usize,
+    constants: &'a HashMap<String, i128>,
+}
+
+impl ConstantEvaluator<'_> {
+    fn punctuation(&self, offset: usize) -> Option<&str> {
+        match self.tokens.get(self.position + offset) {
+            Some(CToken::Punctuation(punctuation)) => Some(punctuation),
+            _ => None,
+        }
+    }
+
+    /// The binary operator at the current position, its precedence and the
+    /// number of tokens it spans.
+    fn operator(&self) -> Option<(&'static str, u8, usize)> {
+        let operator = match (self.punctuation(0)?, self.punctuation(1)) {
+            ("<", Some("<")) => ("<<", 4, 2),
+            (">", Some(">")) => (">>", 4, 2),
+            // logical operators and comparisons are not supported
+            ("|", Some("|")) | ("&", Some("&")) => return None,
+            ("|", _) => ("|", 1, 1),
+            ("^", _) => ("^", 2, 1),
+            ("&", _) => ("&", 3, 1),
+            ("+", _) => ("+", 5, 1),
+            ("-", _) => ("-", 5, 1),
+            ("*", _) => ("*", 6, 1),
+            ("/", _) => ("/", 6, 1),
+            ("%", _) => ("%", 6, 1),
+            _ => return None,
+        };
+
+        Some(operator)
+    }
+
+    fn binary(&mut self, minimum_precedence: u8) -> Option<(i128, bool)> {
+        let (mut value, mut unsigned) = self.unary()?;
+
+        while let Some((operator, precedence, length)) = self.operator() {
+            if precedence < minimum_precedence {
+                break;
+            }
+
+            self.position += length;
+            let (right, right_unsigned) = self.binary(precedence + 1)?;
+
+            value = match operator {
+                "|" => value | right,
+                "^" => value ^ right,
+                "&" => value & right,
+                "<<" => value.checked_shl(u32::try_from(right).ok()?)?,
+                ">>" => value.checked_shr(u32::try_from(right).ok()?)?,
+                "+" => value.checked_add(right)?,
+                "-" => value.checked_sub(right)?,
+                "*" => value.checked_mul(right)?,
+                "/" => value.checked_div(right)?,
+                _ => value.checked_rem(right)?,
+            };
+            unsigned |= right_unsigned;
+        }
+
+        Some((value, unsigned))
+    }
+
+    fn unary(&mut self) -> Option<(i128, bool)> {
+        let token = self.tokens.get(self.position)?;
+        self.position += 1;
+
+        match token {
+            CToken::Number(number) => parse_integer(number),
+            CToken::Identifier(name) => self.constants.get(name).map(|value| (*value, false)),
+            CToken::Punctuation(punctuation) => match punctuation.as_str() {
+                "+" => self.unary(),
+                "-" => {
+                    let (value, unsigned) = self.unary()?;
+                    Some((value.checked_neg()?, unsigned))
+                }
+                // the complement of an unsigned value stays within its width
+                "~" => self.unary().map(|(value, unsigned)| match unsigned {
+                    true if value <= u32::MAX.into() => (!value & i128::from(u32::MAX), true),
+                    true => (!value & i128::from(u64::MAX), true),
+                    false => (!value, false),
+                }),
+                "(" => {
+                    let value = self.binary(0)?;
+
+                    if self.punctuation(0) != Some(")") {
+                        return None;
+                    }
+
+                    self.position += 1;
+                    Some(value)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Replaces comments with spaces, keeping the line breaks of block comments
+/// so that directives stay on their own line.
+fn strip_comments(source: &str) -> String {
+    let mut output = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.peek().is_some_and(|c| *c != '\n') {
+                    chars.next();
+                }
+                output.push(' ');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+
+                for c in chars.by_ref() {
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    if c == '\n' {
+                        output.push('\n');
+                    }
+                    last = c;
+                }
+                output.push(' ');
+            }
+            '"' | '\'' => {
+                output.push(c);
+
+                while let Some(s) = chars.next() {
+                    output.push(s);
+
+                    if s == '\\' {
+                        output.extend(chars.next());
+                    } else if s == c {
+                        break;
+                    }
+                }
+            }
+            _ => output.push(c),
+        }
+    }
+
+    output
+}
+
+fn tokenize(line: &str) -> Vec<CToken> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_alphabetic() || c == '_' {
+            let mut word = String::new();
+
+            while let Some(&c) = chars.peek().filter(|c| c.is_alphanumeric() || **c == '_') {
+                word.push(c);
+                chars.next();
+            }
+
+            tokens.push(CToken::Identifier(word));
+        } else if c.is_ascii_digit() {
+            let mut number = String::new();
+
+            while let Some(&c) = chars.peek().filter(|c| c.is_alphanumeric() || **c == '.') {
+                number.push(c);
+                chars.next();
+            }
+
+            tokens.push(CToken::Number(number));
+        } else if c == '"' {
+            chars.next();
+            let mut string = String::new();
+
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => string.extend(chars.next()),
+                    _ => string.push(c),
+                }
+            }
+
+            tokens.push(CToken::String(string));
+        } else if chars.clone().take(3).eq("...".chars()) {
+            chars.nth(2);
+            tokens.push(CToken::Punctuation("...".to_string()));
+        } else {
+            chars.next();
+            tokens.push(CToken::Punctuation(c.to_string()));
+        }
+    }
+
+    tokens
+}
+
+fn token_text(token: &CToken) -> String {
+    match token {
+        CToken::Identifier(text) | CToken::Number(text) | CToken::Punctuation(text) => text.clone(),
+        CToken::String(text) => format!("{:?}", text),
+        CToken::Include(path) => format!("#include {:?}", path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> CHeader {
+        let mut header = CHeader::default();
+        header.parse(source, Path::new("."), &mut Context::new());
+        header
+    }
+
+    #[test]
+    fn prototypes_and_typedefs() {
+        let header = parse(
+            "typedef unsigned long size_type;\n\
+             typedef struct point { double x, y; } point_t;\n\
+             static inline int twice(int a) { return a * 2; }\n\
+             size_type length(const char *string, point_t *points[], ...);\n\
+             void reset(void) __attribute__((nothrow));\n",
+        );
+
+        let names = header
+            .functions
+            .iter()
+            .map(|f| f.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["length", "reset"]);
+
+        let length = &header.functions[0];
+        assert!(length.variadic);
+        assert_eq!(header.resolve(&length.return_type), CType::Primitive("u64"));
+        assert_eq!(
+            length.parameters[0].1,
+            CType::Pointer(Box::new(CType::Char))
+        );
+        assert_eq!(
+            length.parameters[1].1,
+            CType::Pointer(Box::new(CType::Pointer(Box::new(CType::Typedef(
+                "point_t".to_string()
+            )))))
+        );
+        assert!(header.functions[1].parameters.is_empty());
+        assert_eq!(header.struct_name("point"), "point_t");
+    }
+
+    #[test]
+    fn constants_and_conditionals() {
+        let header = parse(
+            "#define BASE 0x10\n\
+             #define ALIAS BASE\n\
+             #define NEGATIVE (-3)\n\
+             #define MASK 255u\n\
+             #define SHIFT (1 << 4)\n\
+             #define FLAGS (SHIFT | 0x3) * 2 - ~0\n\
+             #define MAX(a, b) a\n\
+             #ifdef MISSING\n\
+             #define HIDDEN 1\n\
+             #else\n\
+             #define SHOWN 2\n\
+             #endif\n\
+             enum flags { FLAG_A = 1, FLAG_B = BASE };\n\
+             enum kind { KIND_A, KIND_B };\n",
+        );
+
+        assert_eq!(
+            header.constants,
+            vec![
+                ("BASE".to_string(), 16, false),
+                ("ALIAS".to_string(), 16, false),
+                ("NEGATIVE".to_string(), -3, false),
+                ("MASK".to_string(), 255, true),
+                ("SHIFT".to_string(), 16, false),
+                ("FLAGS".to_string(), 39, false),
+                ("SHOWN".to_string(), 2, false),
+            ]
+        );
+
+        assert_eq!(header.enum_name(&header.enums[0]), None);
+        assert_eq!(header.enum_name(&header.enums[1]), Some("kind".to_string()));
+    }
+
+    #[test]
+    fn defines_without_an_integer_value_are_reported() {
+        let mut header = CHeader::default();
+        let mut context = Context::new();
+        header.parse(
+            "#define GUARD_H\n\
+             #define VERSION \"1.2\"\n\
+             #define UNKNOWN (MISSING + 1)\n\
+             #define TOP (~0u)\n",
+            Path::new("."),
+            &mut context,
+        );
+
+        assert_eq!(
+            header.constants,
+            vec![("TOP".to_string(), u32::MAX.into(), true)]
+        );
+        assert_eq!(
+            context.diagnostics(),
+            [
+                "warning: skipping #define VERSION, its value is not an integer constant expression",
+                "warning: skipping #define UNKNOWN, its value is not an integer constant expression",
+            ]
+        );
+        assert!(header.macros.contains("GUARD_H"));
+    }
+}
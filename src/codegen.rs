@@ -1,4 +1,5 @@
 use llvm::analysis::*;
+use llvm::bit_writer::*;
 use llvm::core::*;
 use llvm::prelude::*;
 use llvm::target::*;
@@ -6,17 +7,43 @@ use llvm::target_machine::*;
 use llvm::*;
 
 use std::collections::HashMap;
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
 use std::os::unix::ffi::OsStrExt;
 use std::path::PathBuf;
 use std::ptr;
 
+use crate::abi::*;
+use crate::context::*;
 use crate::syntax::*;
 
 pub struct FunctionRef {
-    pub name: String,
     pub function_type: LLVMTypeRef,
     pub function_ref: LLVMValueRef,
+    /// How the arguments of a C function are lowered, x functions take their
+    /// arguments as LLVM values.
+    pub abi: Option<FunctionAbi>,
+}
+
+/// How the program is compiled, as given on the command line.
+pub struct CodeGenOptions {
+    /// The name of the module being compiled, the symbols of its functions are
+    /// prefixed with it.
+    pub module_name: String,
+    /// The name of the source file, runtime errors report locations in it.
+    pub source_name: String,
+    /// Whether array indices are checked against the count of the array,
+    /// an index out of bounds stops the program with a message.
+    pub bounds_checks: bool,
+}
+
+/// A generic function or method emitted for one set of type arguments.
+struct Instance {
+    /// The symbol of the instance, as in `identity<i32>` or `Pair<i32, f64>.swap`.
+    name: String,
+    /// The type declaring the method, none for functions.
+    owner: Option<String>,
+    function: String,
+    substitution: HashMap<String, Type>,
 }
 
 pub struct LLVMCodeGenVisitor {
@@ -24,14 +51,42 @@ pub struct LLVMCodeGenVisitor {
     module: *mut LLVMModule,
     builder: *mut LLVMBuilder,
     named_values: HashMap<String, LLVMValueRef>,
+    /// The constant values of the top-level variables.
+    globals: HashMap<String, LLVMValueRef>,
+    /// The variables of an interactive session, each held by a global along
+    /// with the type of its value.
+    bindings: HashMap<String, (LLVMTypeRef, LLVMValueRef)>,
     function_table: HashMap<String, FunctionRef>,
+    struct_types: HashMap<String, LLVMTypeRef>,
+    witness_tables: HashMap<String, LLVMValueRef>,
+    current_type: Option<String>,
+    return_type: Type,
+    /// The calling convention of the function being emitted when it is
+    /// exported to C.
+    function_abi: Option<FunctionAbi>,
+    /// Whether the payloads of the existentials built by the function being
+    /// emitted live in its frame, see `build_existential`.
+    frame_existentials: bool,
+    target: Target,
+    /// The type arguments of the generic function being emitted.
+    substitution: HashMap<String, Type>,
+    /// Instances declared but not emitted yet, the function table keeps each
+    /// instance once.
+    instances: Vec<Instance>,
+    options: CodeGenOptions,
+    /// Whether the program calls the hash map runtime, which then has to be
+    /// linked with it.
+    uses_runtime: bool,
+    /// The number of closures emitted, their functions are numbered in order.
+    closure_count: usize,
 }
 
 impl LLVMCodeGenVisitor {
-    pub fn new() -> Self {
+    pub fn new(options: CodeGenOptions) -> Self {
         unsafe {
             let context = LLVMContextCreate();
-            let module = LLVMModuleCreateWithNameInContext("main\0".as_ptr() as *const _, context);
+            let module_name = CString::new(options.module_name.clone()).unwrap();
+            let module = LLVMModuleCreateWithNameInContext(module_name.as_ptr(), context);
             let builder = LLVMCreateBuilderInContext(context);
             let named_values = HashMap::<String, LLVMValueRef>::new();
             let function_table = HashMap::<String, FunctionRef>::new();
@@ -42,383 +97,3965 @@ impl LLVMCodeGenVisitor {
             LLVM_InitializeAllAsmParsers();
             LLVM_InitializeAllAsmPrinters();
 
+            let triple = LLVMGetDefaultTargetTriple();
+            let target = Target::from_triple(&CStr::from_ptr(triple).to_string_lossy());
+            LLVMDisposeMessage(triple);
+
             Self {
                 context,
                 module,
                 builder,
                 named_values,
+                globals: HashMap::new(),
+                bindings: HashMap::new(),
                 function_table,
+                struct_types: HashMap::new(),
+                witness_tables: HashMap::new(),
+                current_type: None,
+                return_type: Type::Void,
+                function_abi: None,
+                frame_existentials: false,
+                target,
+                substitution: HashMap::new(),
+                instances: Vec::new(),
+                options,
+                uses_runtime: false,
+                closure_count: 0,
             }
         }
     }
 
-    pub fn emit_ir(&self) {
+    /// Writes the module as an object file or as assembly for the host.
+    pub fn emit_asm(
+        &self,
+        output_file: PathBuf,
+        file_type: LLVMCodeGenFileType,
+    ) -> Result<(), String> {
         unsafe {
-            LLVMDumpModule(self.module);
+            let target_machine = self.target_machine();
+            let filename = CString::new(output_file.as_os_str().as_bytes()).unwrap();
+            let mut error: *mut i8 = ptr::null_mut();
+
+            let failed = LLVMTargetMachineEmitToFile(
+                target_machine,
+                self.module,
+                filename.as_ptr() as *mut _,
+                file_type,
+                &mut error,
+            );
+            LLVMDisposeTargetMachine(target_machine);
+
+            match failed {
+                0 => Ok(()),
+                _ => Err(llvm_message(error)),
+            }
         }
     }
 
-    pub fn emit_asm(&self, output_file: PathBuf) {
+    /// Writes the module as textual LLVM IR.
+    pub fn emit_llvm_ir(&self, output_file: PathBuf) -> Result<(), String> {
         unsafe {
-            let triple = LLVMGetDefaultTargetTriple();
+            LLVMDisposeTargetMachine(self.target_machine());
+
+            let filename = CString::new(output_file.as_os_str().as_bytes()).unwrap();
             let mut error: *mut i8 = ptr::null_mut();
-            let mut target: LLVMTargetRef = ptr::null_mut();
-            LLVMGetTargetFromTriple(triple, &mut target, &mut error);
 
-            let target_machine = LLVMCreateTargetMachine(
-                target,
-                triple,
-                "generic\0".as_ptr() as *const _,
-                "\0".as_ptr() as *const _,
-                LLVMCodeGenOptLevel::LLVMCodeGenLevelDefault,
-                LLVMRelocMode::LLVMRelocDefault,
-                LLVMCodeModel::LLVMCodeModelDefault,
-            );
+            match LLVMPrintModuleToFile(self.module, filename.as_ptr(), &mut error) {
+                0 => Ok(()),
+                _ => Err(llvm_message(error)),
+            }
+        }
+    }
+
+    /// The LLVM IR of a function of the module, as text.
+    pub fn function_ir(&self, name: &str) -> Option<String> {
+        let name = CString::new(name).unwrap();
+
+        unsafe {
+            let function = LLVMGetNamedFunction(self.module, name.as_ptr());
+
+            if function.is_null() {
+                return None;
+            }
+
+            let text = LLVMPrintValueToString(function);
+            let ir = CStr::from_ptr(text).to_string_lossy().to_string();
+            LLVMDisposeMessage(text);
+
+            Some(ir)
+        }
+    }
 
-            let data_layout = LLVMCreateTargetDataLayout(target_machine);
-            LLVMSetModuleDataLayout(self.module, data_layout);
-            LLVMSetTarget(self.module, triple);
+    /// Writes the module as LLVM bitcode.
+    pub fn emit_bitcode(&self, output_file: PathBuf) -> Result<(), String> {
+        unsafe {
+            LLVMDisposeTargetMachine(self.target_machine());
 
             let filename = CString::new(output_file.as_os_str().as_bytes()).unwrap();
 
-            LLVMTargetMachineEmitToFile(
-                target_machine,
-                self.module,
-                filename.as_ptr() as *mut _,
-                LLVMCodeGenFileType::LLVMObjectFile,
-                &mut error,
-            );
-        };
+            match LLVMWriteBitcodeToFile(self.module, filename.as_ptr()) {
+                0 => Ok(()),
+                _ => Err(format!("cannot write {}", output_file.display())),
+            }
+        }
+    }
+
+    /// Creates a target machine for the host and sets the module's target and
+    /// data layout to it. Objects are position independent, they link into
+    /// executables whether or not the C compiler makes them PIE.
+    unsafe fn target_machine(&self) -> LLVMTargetMachineRef {
+        let triple = LLVMGetDefaultTargetTriple();
+        let mut error: *mut i8 = ptr::null_mut();
+        let mut target: LLVMTargetRef = ptr::null_mut();
+        LLVMGetTargetFromTriple(triple, &mut target, &mut error);
+
+        let target_machine = LLVMCreateTargetMachine(
+            target,
+            triple,
+            c"generic".as_ptr(),
+            c"".as_ptr(),
+            LLVMCodeGenOptLevel::LLVMCodeGenLevelDefault,
+            LLVMRelocMode::LLVMRelocPIC,
+            LLVMCodeModel::LLVMCodeModelDefault,
+        );
+
+        let data_layout = LLVMCreateTargetDataLayout(target_machine);
+        LLVMSetModuleDataLayout(self.module, data_layout);
+        LLVMSetTarget(self.module, triple);
+        LLVMDisposeMessage(triple);
+
+        target_machine
+    }
+
+    /// Whether the object file needs the runtime in `runtime/` to link.
+    pub fn uses_runtime(&self) -> bool {
+        self.uses_runtime
+    }
+
+    /// Hands the module over to an execution engine, which disposes of it.
+    /// The visitor has to be finished after the engine is disposed of, the
+    /// module belongs to its LLVM context.
+    pub fn take_module(&mut self) -> LLVMModuleRef {
+        std::mem::replace(&mut self.module, ptr::null_mut())
     }
 
     pub fn finish(&self) {
         unsafe {
             LLVMDisposeBuilder(self.builder);
-            LLVMDisposeModule(self.module);
+            if !self.module.is_null() {
+                LLVMDisposeModule(self.module);
+            }
             LLVMContextDispose(self.context);
         }
     }
 
-    pub fn visit_source_file(&mut self, node: &SourceFile) {
-        self.visit_code_block(&node.code_block);
-    }
+    pub fn visit_source_file(&mut self, node: &SourceFile, context: &Context) {
+        self.declare_types(&node.code_block, context);
+        self.declare_functions(&node.code_block, context);
+        self.declare_inherited_methods(context);
+        self.declare_witness_tables(context);
+        self.visit_inherited_methods(context);
+        self.declare_globals(&node.code_block, context);
+
+        for item in &node.code_block.items {
+            // globals are already declared
+            if let CodeBlockItem::Declaration(Declaration::VariableDeclaration(_)) = item {
+                continue;
+            }
 
-    pub fn visit_code_block(&mut self, node: &CodeBlock) {
-        for item in &node.items {
-            self.visit_code_block_item(item);
+            self.visit_code_block_item(item, context);
         }
+
+        self.visit_instances(&[&node.code_block], context);
     }
 
-    pub fn visit_code_block_container(&mut self, node: &CodeBlockContainer, bb: LLVMBasicBlockRef) {
-        unsafe {
-            LLVMPositionBuilderAtEnd(self.builder, bb);
+    /// Emits an input of an interactive session. The declarations of the
+    /// inputs before it are only declared, the module of their own input
+    /// defines them. The code of the input runs in the function `evaluate`,
+    /// the variables declared at the top of its body are stored into globals
+    /// for the inputs that follow, as is the value of the expression `value`
+    /// into the global it names.
+    pub fn visit_input(
+        &mut self,
+        node: &SourceFile,
+        previous: &[SourceFile],
+        evaluate: &str,
+        value: Option<(&Expression, &str)>,
+        context: &Context,
+    ) {
+        let blocks = previous
+            .iter()
+            .chain([node])
+            .map(|file| &file.code_block)
+            .collect::<Vec<_>>();
+
+        for block in &blocks {
+            self.declare_types(block, context);
+        }
+
+        for block in &blocks {
+            self.declare_functions(block, context);
+        }
+
+        self.declare_inherited_methods(context);
+        self.declare_witness_tables(context);
+        self.visit_inherited_methods(context);
+
+        for block in &blocks {
+            self.declare_globals(block, context);
+        }
+
+        let Some(function) = find_function(&node.code_block, None, evaluate) else {
+            panic!("Function {:?} not found", evaluate);
+        };
 
-            for item in &node.code_block.items {
-                self.visit_code_block_item(item);
+        let mut bound = function
+            .body
+            .code_block
+            .items
+            .iter()
+            .flat_map(declared_variables)
+            .collect::<Vec<_>>();
+        bound.extend(value.map(|(_, name)| name));
+        self.declare_bindings(&bound, context);
+
+        for item in &node.code_block.items {
+            match item {
+                CodeBlockItem::Declaration(Declaration::FunctionDeclaration(function))
+                    if function.identifier.name == evaluate =>
+                {
+                    self.visit_evaluate(function, value, context)
+                }
+                _ => self.visit_code_block_item(item, context),
             }
         }
+
+        self.visit_instances(&blocks, context);
     }
 
-    pub fn visit_code_block_item(&mut self, node: &CodeBlockItem) {
-        match node {
-            CodeBlockItem::Declaration(decl) => {
-                self.visit_declaration(decl);
+    /// Declares a global for every variable of the session, the module
+    /// defines those `bound` by its input, zeroed until the input runs.
+    fn declare_bindings(&mut self, bound: &[&str], context: &Context) {
+        for symbol in context.global_variables() {
+            // the constants of imported modules
+            if self.globals.contains_key(&symbol.name) {
+                continue;
             }
-            CodeBlockItem::Statement(stmt) => {
-                self.visit_statement(stmt);
-            }
-            CodeBlockItem::Expression(expr) => {
-                self.visit_expression(expr);
+
+            let ty = self.llvm_type(&symbol.symbol_type, context);
+            let llvm_name = CString::new(self.symbol(&symbol.name, context)).unwrap();
+
+            unsafe {
+                let global = LLVMAddGlobal(self.module, ty, llvm_name.as_ptr());
+
+                if bound.contains(&symbol.name.as_str()) {
+                    LLVMSetInitializer(global, LLVMConstNull(ty));
+                }
+
+                self.bindings.insert(symbol.name.clone(), (ty, global));
             }
         }
     }
 
-    pub fn visit_declaration(&mut self, node: &Declaration) {
-        match node {
-            Declaration::VariableDeclaration(variable) => self.visit_variable_declaration(variable),
-            Declaration::FunctionDeclaration(function) => self.visit_function_declaration(function),
-            Declaration::ExternDeclaration(e) => self.visit_extern_declaration(e),
-            _ => unimplemented!(),
-        }
-    }
+    /// Emits the function running the code of an input, see `visit_input`.
+    fn visit_evaluate(
+        &mut self,
+        node: &FunctionDeclaration,
+        value: Option<(&Expression, &str)>,
+        context: &Context,
+    ) {
+        let function = self.function_table[&node.identifier.name].function_ref;
+        let return_type = node
+            .signature
+            .return_clause
+            .as_ref()
+            .map(|r| Type::from(r.return_type.clone()))
+            .unwrap_or(Type::Void);
 
-    pub fn visit_variable_declaration(&mut self, node: &VariableDeclaration) {
-        let value = self.visit_expression(&node.expression);
+        unsafe {
+            let entry = LLVMAppendBasicBlockInContext(self.context, function, c"".as_ptr());
+            LLVMPositionBuilderAtEnd(self.builder, entry);
 
-        self.named_values
-            .insert(node.identifier.name.clone(), value);
-    }
+            self.named_values.clear();
+            self.return_type = return_type.clone();
 
-    pub fn visit_function_declaration(&mut self, node: &FunctionDeclaration) {
-        let function_type = self.visit_function_signature(&node.signature);
+            let mut tail_value = ptr::null_mut();
 
-        unsafe {
-            let function = LLVMAddFunction(
-                self.module,
-                node.identifier.name.as_ptr() as *const _,
-                function_type,
-            );
+            for item in &node.body.code_block.items {
+                // anything following a return is unreachable and is not emitted
+                if self.is_terminated() {
+                    break;
+                }
 
-            self.function_table.insert(
-                node.identifier.name.clone(),
-                FunctionRef {
-                    name: node.identifier.name.clone(),
-                    function_type,
-                    function_ref: function,
-                },
-            );
+                tail_value = match item {
+                    CodeBlockItem::Expression(expression) => {
+                        self.visit_expression(expression, context)
+                    }
+                    _ => {
+                        self.visit_code_block_item(item, context);
+                        ptr::null_mut()
+                    }
+                };
 
-            for i in 0..node.signature.parameters.parameters.len() {
-                let param = LLVMGetParam(function, i as u32);
-                let name = node.signature.parameters.parameters[i].name.name.as_ptr() as *const _;
-                LLVMSetValueName2(
-                    param,
-                    name,
-                    node.signature.parameters.parameters[i].name.name.len() as usize,
-                );
+                let mut stored = declared_variables(item)
+                    .into_iter()
+                    .map(|name| (name, self.named_values[name]))
+                    .collect::<Vec<_>>();
+
+                if let (Some((expression, name)), CodeBlockItem::Expression(item)) = (value, item) {
+                    if ptr::eq(expression, item) && self.has_value(tail_value) {
+                        stored.push((name, tail_value));
+                    }
+                }
+
+                for (name, value) in stored {
+                    LLVMBuildStore(self.builder, value, self.bindings[name].1);
+                }
             }
 
-            self.visit_function_body(&node.body, &node.signature, function);
+            self.build_tail_return(&node.body, tail_value, &return_type, context);
+            self.return_type = Type::Void;
+
             LLVMVerifyFunction(function, LLVMVerifierFailureAction::LLVMPrintMessageAction);
         }
     }
 
-    pub fn visit_extern_declaration(&mut self, node: &ExternDeclaration) {
-        let function_type = self.visit_function_signature(&node.signature);
+    /// Emits the bodies of the generic functions and methods used by the
+    /// program, an instance may use further instances. The generic
+    /// declarations are looked up in the blocks given.
+    fn visit_instances(&mut self, blocks: &[&CodeBlock], context: &Context) {
+        while let Some(instance) = self.instances.pop() {
+            let Some(declaration) = blocks.iter().find_map(|block| {
+                find_function(block, instance.owner.as_deref(), &instance.function)
+            }) else {
+                panic!("Generic function {:?} not found", instance.function);
+            };
+
+            let function = self.function_table[&instance.name].function_ref;
+            self.substitution = instance.substitution;
+
+            unsafe {
+                self.visit_function_body(
+                    &declaration.body,
+                    &declaration.signature,
+                    function,
+                    instance.owner.as_deref(),
+                    None,
+                    context,
+                );
+                LLVMVerifyFunction(function, LLVMVerifierFailureAction::LLVMPrintMessageAction);
+            }
+
+            self.substitution.clear();
+        }
+    }
+
+    /// Declares the instance of a generic function for a set of type
+    /// arguments the first time it is used.
+    fn instantiate(&mut self, instance: Instance, function: &TypeFunction, context: &Context) {
+        if self.function_table.contains_key(&instance.name) {
+            return;
+        }
+
+        let function_type = self.function_type(function, instance.owner.is_some(), context);
+        let llvm_name = CString::new(instance.name.clone()).unwrap();
 
         unsafe {
-            let function = LLVMAddFunction(
-                self.module,
-                node.identifier.name.as_ptr() as *const _,
-                function_type,
-            );
+            let function_ref = LLVMAddFunction(self.module, llvm_name.as_ptr(), function_type);
+            LLVMSetLinkage(function_ref, LLVMLinkage::LLVMPrivateLinkage);
 
             self.function_table.insert(
-                node.identifier.name.clone(),
+                instance.name.clone(),
                 FunctionRef {
-                    name: node.identifier.name.clone(),
                     function_type,
-                    function_ref: function,
+                    function_ref,
+                    abi: None,
                 },
             );
-
-            for i in 0..node.signature.parameters.parameters.len() {
-                let param = LLVMGetParam(function, i as u32);
-                let name = node.signature.parameters.parameters[i].name.name.as_ptr() as *const _;
-                LLVMSetValueName2(
-                    param,
-                    name,
-                    node.signature.parameters.parameters[i].name.name.len() as usize,
-                );
-            }
         }
-    }
 
-    pub fn visit_function_signature(&mut self, node: &FunctionSignature) -> LLVMTypeRef {
-        unsafe {
-            let mut types = self.visit_function_parameters(&node.parameters);
-            let return_type = self.visit_return_clause(&node.return_clause);
+        self.instances.push(instance);
+    }
 
-            // TODO: what is isVarArg, setting to no for now
-            LLVMFunctionType(return_type, types.as_mut_ptr(), types.len() as u32, 0)
-        }
+    /// Replaces the type parameters of the generic function being emitted by
+    /// its type arguments.
+    fn resolve(&self, ty: &Type) -> Type {
+        ty.substitute(&self.substitution)
     }
 
-    pub fn visit_function_parameters(&mut self, node: &FunctionParameters) -> Vec<LLVMTypeRef> {
-        (0..node.parameters.len())
-            .map(|_| unsafe { LLVMDoubleTypeInContext(self.context) })
-            .collect()
+    fn expression_type(&self, node: &Expression, context: &Context) -> Type {
+        self.resolve(&context.expression_type(node))
     }
 
-    pub fn visit_return_clause(&mut self, node: &Option<ReturnClause>) -> LLVMTypeRef {
-        unsafe {
-            match node {
-                Some(_) => LLVMDoubleTypeInContext(self.context),
-                None => LLVMVoidTypeInContext(self.context),
+    /// Top-level variables are initialized with literals, their uses are
+    /// replaced by the constant.
+    fn declare_globals(&mut self, node: &CodeBlock, context: &Context) {
+        for declaration in node.declarations() {
+            if let Declaration::VariableDeclaration(variable) = declaration {
+                let expression = variable
+                    .expression
+                    .as_ref()
+                    .expect("globals are initialized after type checking");
+                let value = self.visit_expression(expression, context);
+
+                self.globals.insert(variable.identifier.name.clone(), value);
             }
         }
     }
 
-    pub fn visit_function_body(
-        &mut self,
-        node: &CodeBlockContainer,
-        signature: &FunctionSignature,
-        function: LLVMValueRef,
-    ) {
-        unsafe {
-            let entry =
-                LLVMAppendBasicBlockInContext(self.context, function, "\0".as_ptr() as *const _);
-            LLVMPositionBuilderAtEnd(self.builder, entry);
+    /// Creates the named struct of every type up front so that declarations can
+    /// refer to types declared later in the file.
+    fn declare_types(&mut self, node: &CodeBlock, context: &Context) {
+        for declaration in node.declarations() {
+            if let Declaration::TypeDeclaration(declaration) = declaration {
+                // instances of generic types are literal structs
+                if !declaration.generic_parameters.is_empty() {
+                    continue;
+                }
 
-            self.named_values.clear();
-            for i in 0..signature.parameters.parameters.len() {
-                let param = &signature.parameters.parameters[i];
-                let value = LLVMGetParam(function, i as u32);
-                self.named_values.insert(param.name.name.clone(), value);
+                let name = CString::new(declaration.name.name.clone()).unwrap();
+
+                unsafe {
+                    let struct_type = LLVMStructCreateNamed(self.context, name.as_ptr());
+                    self.struct_types
+                        .insert(declaration.name.name.clone(), struct_type);
+                }
             }
+        }
 
-            let mut terminated = false;
+        for declaration in node.declarations() {
+            if let Declaration::TypeDeclaration(declaration) = declaration {
+                let name = &declaration.name.name;
 
-            for i in 0..node.code_block.items.len() {
-                if i != node.code_block.items.len() - 1 {
-                    self.visit_code_block_item(&node.code_block.items[i]);
-                } else {
-                    if let CodeBlockItem::Expression(expr) = &node.code_block.items[i] {
-                        let value = self.visit_expression(expr);
+                if !declaration.generic_parameters.is_empty() {
+                    continue;
+                }
 
-                        if signature.return_clause.is_some() {
-                            LLVMBuildRet(self.builder, value);
-                            terminated = true;
-                        }
-                    } else {
-                        self.visit_code_block_item(&node.code_block.items[i]);
-                    }
+                let Some(TypeDefinitionKind::Structure(fields)) =
+                    context.lookup_type(name).map(|d| &d.kind)
+                else {
+                    continue;
+                };
+
+                let mut field_types = fields
+                    .iter()
+                    .map(|field| self.llvm_type(&field.ty, context))
+                    .collect::<Vec<_>>();
+
+                unsafe {
+                    LLVMStructSetBody(
+                        self.struct_types[name],
+                        field_types.as_mut_ptr(),
+                        field_types.len() as u32,
+                        0,
+                    );
                 }
             }
+        }
+    }
 
-            if !terminated {
-                LLVMBuildRetVoid(self.builder);
+    /// Declares every function and method before any body is emitted so that
+    /// calls do not depend on declaration order.
+    fn declare_functions(&mut self, node: &CodeBlock, context: &Context) {
+        for declaration in node.declarations() {
+            match declaration {
+                // generic functions are declared for each instance as it is used
+                Declaration::FunctionDeclaration(function)
+                    if !function.generic_parameters.is_empty() => {}
+                Declaration::TypeDeclaration(declaration)
+                    if !declaration.generic_parameters.is_empty() => {}
+                Declaration::EnumDeclaration(declaration)
+                    if !declaration.generic_parameters.is_empty() => {}
+                Declaration::FunctionDeclaration(function) => {
+                    let name = &function.identifier.name;
+
+                    match context.export_symbol(name) {
+                        Some(symbol) => self.declare_export(name, symbol, context),
+                        None => {
+                            self.declare_function(name, &function.signature, None, context);
+                        }
+                    }
+                }
+                Declaration::ExternDeclaration(ext) => {
+                    let Some(Symbol {
+                        symbol_type: Type::Function(function),
+                        ..
+                    }) = context.lookup(ext.identifier.name.clone())
+                    else {
+                        panic!("Extern {:?} not registered", ext.identifier.name);
+                    };
+
+                    self.declare_extern(
+                        &ext.identifier.name,
+                        &ext.identifier.name,
+                        &function,
+                        context,
+                    );
+                }
+                Declaration::ExternLibraryDeclaration(library) => {
+                    self.declare_library(&library.name.name, context);
+                }
+                Declaration::TypeDeclaration(declaration) => self.declare_methods(
+                    &declaration.name.name,
+                    &declaration.container.member_block,
+                    context,
+                ),
+                Declaration::EnumDeclaration(declaration) => self.declare_methods(
+                    &declaration.name.name,
+                    &declaration.container.member_block,
+                    context,
+                ),
+                Declaration::ExtensionDeclaration(declaration) => self.declare_methods(
+                    &declaration.name.name,
+                    &declaration.container.member_block,
+                    context,
+                ),
+                Declaration::ProtocolDeclaration(declaration) => {
+                    for requirement in &declaration.requirements {
+                        if requirement.body.is_some() {
+                            let name = format!(
+                                "{}.{}",
+                                declaration.name.name, requirement.identifier.name
+                            );
+                            self.declare_function(
+                                &name,
+                                &requirement.signature,
+                                Some(&declaration.name.name),
+                                context,
+                            );
+                        }
+                    }
+                }
+                _ => {}
             }
         }
     }
 
-    pub fn visit_statement(&mut self, node: &Statement) {}
-
-    pub fn visit_expression(&mut self, node: &Expression) -> LLVMValueRef {
-        match node {
-            Expression::BooleanLiteral(value) => self.visit_boolean_literal(*value),
-            Expression::FloatNumberLiteral(value) => self.visit_float_number_literal(*value),
-            Expression::Identifier(identifier) => self.visit_identifier(identifier),
-            Expression::Tuple(tuple) => self.visit_tuple(tuple),
-            Expression::BinaryOperator(op) => self.visit_binary_operator_expression(op),
-            Expression::FunctionCall(function_call) => self.visit_function_call(function_call),
-            Expression::If(if_expression) => self.visit_if_expression(if_expression),
-            _ => unimplemented!(),
+    fn declare_methods(&mut self, type_name: &str, node: &MemberBlock, context: &Context) {
+        for member in &node.members {
+            if let MemberBlockItem::FunctionDeclaration(function) = member {
+                let name = format!("{}.{}", type_name, function.identifier.name);
+                self.declare_function(&name, &function.signature, Some(type_name), context);
+            }
         }
     }
 
-    pub fn visit_if_expression(&mut self, node: &IfExpression) -> LLVMValueRef {
+    /// Adds a function to the module, methods take a pointer to their receiver
+    /// as an implicit first parameter.
+    fn declare_function(
+        &mut self,
+        name: &str,
+        signature: &FunctionSignature,
+        self_type: Option<&str>,
+        context: &Context,
+    ) -> LLVMValueRef {
+        let function_type = self.visit_function_signature(signature, self_type.is_some(), context);
+        let llvm_name = CString::new(self.symbol(name, context)).unwrap();
+
         unsafe {
-            let condition = self.visit_expression(&node.condition);
-            let bb = LLVMGetBasicBlockParent(LLVMGetInsertBlock(self.builder));
+            let function = LLVMAddFunction(self.module, llvm_name.as_ptr(), function_type);
 
-            let mut then_bb =
-                LLVMAppendBasicBlockInContext(self.context, bb, "\0".as_ptr() as *const _);
-            let mut else_bb =
-                LLVMAppendBasicBlockInContext(self.context, bb, "\0".as_ptr() as *const _);
-            let merge_bb =
-                LLVMAppendBasicBlockInContext(self.context, bb, "\0".as_ptr() as *const _);
+            if self.is_internal(name, self_type, context) {
+                LLVMSetLinkage(function, LLVMLinkage::LLVMInternalLinkage);
+            }
 
-            LLVMBuildCondBr(self.builder, condition, then_bb, else_bb);
+            self.function_table.insert(
+                name.to_string(),
+                FunctionRef {
+                    function_type,
+                    function_ref: function,
+                    abi: None,
+                },
+            );
 
-            LLVMPositionBuilderAtEnd(self.builder, then_bb);
-            let mut then_value = self.visit_expression(&node.then_expression);
-            LLVMBuildBr(self.builder, merge_bb);
+            let mut parameter_names = Vec::new();
 
-            LLVMPositionBuilderAtEnd(self.builder, else_bb);
-            let mut else_value = self.visit_expression(&node.else_expression);
-            LLVMBuildBr(self.builder, merge_bb);
+            if self_type.is_some() {
+                parameter_names.push("self");
+            }
 
-            LLVMPositionBuilderAtEnd(self.builder, merge_bb);
-            let phi = LLVMBuildPhi(
-                self.builder,
-                LLVMDoubleTypeInContext(self.context), // TODO: double for now but should be type checked
-                "\0".as_ptr() as *const _,
-            );
+            for parameter in &signature.parameters.parameters {
+                parameter_names.push(parameter.name.name.as_str());
+            }
 
-            LLVMAddIncoming(phi, &mut then_value, &mut then_bb, 1);
-            LLVMAddIncoming(phi, &mut else_value, &mut else_bb, 1);
+            for (i, parameter_name) in parameter_names.iter().enumerate() {
+                let param = LLVMGetParam(function, i as u32);
+                LLVMSetValueName2(
+                    param,
+                    parameter_name.as_ptr() as *const _,
+                    parameter_name.len(),
+                );
+            }
 
-            phi
+            function
         }
     }
 
-    pub fn visit_function_call(&mut self, node: &FunctionCallExpression) -> LLVMValueRef {
-        unsafe {
-            let function_ref = self
-                .function_table
-                .get(&node.function.name)
-                .expect(format!("Function {:?} not registered", node.function.name).as_str());
+    /// The symbol of a function in the object file, the functions of the module
+    /// are prefixed with its name so that they do not clash with those of other
+    /// modules, as in `geometry.area`. Imported functions already are.
+    fn symbol(&self, name: &str, context: &Context) -> String {
+        if name == "main" || context.is_imported(name) {
+            name.to_string()
+        } else {
+            format!("{}.{}", self.options.module_name, name)
+        }
+    }
 
-            let function_type = function_ref.function_type;
-            let function = function_ref.function_ref;
+    /// Whether a function of the module is only called from its object file,
+    /// private functions are unless generic code, which is emitted into the
+    /// object files using it, calls them. Methods implementing a protocol
+    /// requirement are called from the witness tables of other object files.
+    fn is_internal(&self, name: &str, self_type: Option<&str>, context: &Context) -> bool {
+        if context.is_imported(name)
+            || context.is_exposed(name)
+            || context
+                .private_declaration(&self.symbol(name, context))
+                .is_none()
+        {
+            return false;
+        }
 
-            let param_count = LLVMCountParams(function_ref.function_ref);
+        let Some(type_name) = self_type else {
+            return name != "main";
+        };
 
-            let mut args: Vec<LLVMValueRef> = node
-                .arguments
-                .expressions
-                .items
-                .iter()
-                .map(|arg| self.visit_expression(arg))
-                .collect::<Vec<_>>();
+        let method = &name[type_name.len() + 1..];
 
-            let llvm_name = CString::new("").unwrap();
+        context.lookup_type(type_name).is_some_and(|definition| {
+            !definition.conformances.iter().any(|protocol| {
+                context
+                    .lookup_type(protocol)
+                    .is_some_and(|protocol| protocol.method(method).is_some())
+            })
+        })
+    }
 
-            LLVMDumpValue(function);
-            LLVMDumpType(function_type);
-            dbg!(param_count);
+    /// Declares a function exported with `@export`, it is defined under its C
+    /// symbol with the calling convention of an extern function of the same
+    /// signature so that C code can call it.
+    fn declare_export(&mut self, name: &str, symbol: &str, context: &Context) {
+        let Some(Symbol {
+            symbol_type: Type::Function(function),
+            ..
+        }) = context.lookup(name.to_string())
+        else {
+            panic!("Function {:?} not registered", name);
+        };
 
-            LLVMBuildCall2(
-                self.builder,
-                function_type,
-                function,
-                args.as_mut_ptr(),
-                param_count,
-                llvm_name.as_ptr() as *mut _,
-            )
-        }
-    }
+        let abi = self.classify_function(&function, context);
+        let function_type = self.extern_function_type(&function, &abi, context);
+        let llvm_name = CString::new(symbol).unwrap();
 
-    pub fn visit_boolean_literal(&self, value: bool) -> LLVMValueRef {
         unsafe {
-            LLVMConstInt(
-                LLVMInt1TypeInContext(self.context),
-                if value { 1 } else { 0 },
-                0,
-            )
+            let function_ref = LLVMAddFunction(self.module, llvm_name.as_ptr(), function_type);
+
+            for (index, attribute) in self.extern_attributes(&function, &abi, context) {
+                LLVMAddAttributeAtIndex(function_ref, index, attribute);
+            }
+
+            self.function_table.insert(
+                name.to_string(),
+                FunctionRef {
+                    function_type,
+                    function_ref,
+                    abi: Some(abi),
+                },
+            );
         }
     }
 
-    pub fn visit_float_number_literal(&self, value: f64) -> LLVMValueRef {
-        println!("creating ssa var: {}", value);
-        unsafe { LLVMConstReal(LLVMDoubleTypeInContext(self.context), value) }
-    }
+    /// Declares the foreign functions of a library, calls to `Library.function`
+    /// go to the symbol the function is bound to.
+    fn declare_library(&mut self, library_name: &str, context: &Context) {
+        let Some(definition) = context.lookup_type(library_name) else {
+            return;
+        };
 
-    pub fn visit_identifier(&mut self, identifier: &Identifier) -> LLVMValueRef {
-        if let Some(value) = self.named_values.get_mut(&identifier.name) {
-            *value
-        } else {
-            panic!("Unknown identifier: {}", identifier.name);
+        for method in &definition.methods {
+            if let MethodImplementation::External(symbol) = &method.implementation {
+                let name = format!("{}.{}", library_name, method.name);
+                self.declare_extern(&name, symbol, &method.function, context);
+            }
         }
     }
 
-    pub fn visit_tuple(&mut self, tuple: &TupleExpression) -> LLVMValueRef {
-        self.visit_expression(&tuple.expressions.items[0])
+    /// How the parameters and the return value of an extern or exported
+    /// function are passed, the type checker rejected the functions using
+    /// types without a C layout.
+    fn classify_function(&self, function: &TypeFunction, context: &Context) -> FunctionAbi {
+        classify_function(self.target, function, context)
+            .unwrap_or_else(|error| panic!("{} but the type checker accepted it", error))
     }
 
-    pub fn visit_binary_operator_expression(
+    /// Declares a C function, its parameters and return value are lowered to
+    /// the calling convention of the target.
+    fn declare_extern(
         &mut self,
-        node: &BinaryOperatorExpression,
-    ) -> LLVMValueRef {
-        let lhs = self.visit_expression(&node.left);
-        let rhs = self.visit_expression(&node.right);
+        name: &str,
+        symbol: &str,
+        function: &TypeFunction,
+        context: &Context,
+    ) {
+        let abi = self.classify_function(function, context);
+        let function_type = self.extern_function_type(function, &abi, context);
+        let llvm_name = CString::new(symbol).unwrap();
 
         unsafe {
-            match node.operator {
-                BinaryOperator::Add => {
-                    LLVMBuildFAdd(self.builder, lhs, rhs, "\0".as_ptr() as *const _)
-                }
-                BinaryOperator::Subtract => {
-                    LLVMBuildFSub(self.builder, lhs, rhs, "\0".as_ptr() as *const _)
-                }
-                BinaryOperator::Multiply => {
-                    LLVMBuildFMul(self.builder, lhs, rhs, "\0".as_ptr() as *const _)
-                }
-                BinaryOperator::Divide => {
-                    LLVMBuildFDiv(self.builder, lhs, rhs, "\0".as_ptr() as *const _)
+            // libraries may share symbols
+            let mut function_ref = LLVMGetNamedFunction(self.module, llvm_name.as_ptr());
+
+            if function_ref.is_null() {
+                function_ref = LLVMAddFunction(self.module, llvm_name.as_ptr(), function_type);
+
+                for (index, attribute) in self.extern_attributes(function, &abi, context) {
+                    LLVMAddAttributeAtIndex(function_ref, index, attribute);
                 }
             }
+
+            self.function_table.insert(
+                name.to_string(),
+                FunctionRef {
+                    function_type,
+                    function_ref,
+                    abi: Some(abi),
+                },
+            );
+        }
+    }
+
+    /// The LLVM type of a C function, see `PassMode` for how values are lowered.
+    fn extern_function_type(
+        &self,
+        function: &TypeFunction,
+        abi: &FunctionAbi,
+        context: &Context,
+    ) -> LLVMTypeRef {
+        let mut types = Vec::new();
+
+        let return_type = match &abi.return_mode {
+            PassMode::Direct => self.llvm_type(&function.return_type, context),
+            PassMode::Coerced(pieces) => self.coerced_type(pieces),
+            PassMode::Indirect { .. } => {
+                types.push(self.pointer_type());
+                unsafe { LLVMVoidTypeInContext(self.context) }
+            }
+        };
+
+        for (parameter, mode) in function.parameters.iter().zip(&abi.parameters) {
+            match mode {
+                PassMode::Direct => types.push(self.llvm_type(&parameter.ty, context)),
+                PassMode::Coerced(pieces) => {
+                    types.extend(pieces.iter().map(|piece| self.abi_type(piece)))
+                }
+                PassMode::Indirect { .. } => types.push(self.pointer_type()),
+            }
+        }
+
+        unsafe {
+            LLVMFunctionType(
+                return_type,
+                types.as_mut_ptr(),
+                types.len() as u32,
+                function.variadic as i32,
+            )
+        }
+    }
+
+    /// The attributes a C function and its calls need, indexed the way LLVM
+    /// does with 0 for the return value and i + 1 for the parameter i.
+    fn extern_attributes(
+        &self,
+        function: &TypeFunction,
+        abi: &FunctionAbi,
+        context: &Context,
+    ) -> Vec<(u32, LLVMAttributeRef)> {
+        let mut attributes = Vec::new();
+        let mut index = 1;
+
+        match &abi.return_mode {
+            PassMode::Direct => {
+                if let Some(extension) = self.extension_attribute(&function.return_type) {
+                    attributes.push((0, extension));
+                }
+            }
+            PassMode::Coerced(_) => {}
+            PassMode::Indirect { .. } => {
+                let return_type = self.llvm_type(&function.return_type, context);
+                attributes.push((index, self.type_attribute("sret", return_type)));
+                index += 1;
+            }
+        }
+
+        for (parameter, mode) in function.parameters.iter().zip(&abi.parameters) {
+            match mode {
+                PassMode::Direct => {
+                    if let Some(extension) = self.extension_attribute(&parameter.ty) {
+                        attributes.push((index, extension));
+                    }
+
+                    index += 1;
+                }
+                PassMode::Coerced(pieces) => index += pieces.len() as u32,
+                PassMode::Indirect { byval } => {
+                    if *byval {
+                        let parameter_type = self.llvm_type(&parameter.ty, context);
+                        // the abi was classified, the type has a layout
+                        let align = layout(&parameter.ty, context).map_or(8, |l| l.align.max(8));
+
+                        attributes.push((index, self.type_attribute("byval", parameter_type)));
+                        attributes.push((index, self.enum_attribute("align", align)));
+                    }
+
+                    index += 1;
+                }
+            }
+        }
+
+        attributes
+    }
+
+    /// C expects integers smaller than 32 bits to be extended by the caller.
+    fn extension_attribute(&self, ty: &Type) -> Option<LLVMAttributeRef> {
+        let Type::Identifier(name) = ty else {
+            return None;
+        };
+
+        match name.as_str() {
+            "bool" | "u8" | "u16" => Some(self.enum_attribute("zeroext", 0)),
+            "i8" | "i16" => Some(self.enum_attribute("signext", 0)),
+            _ => None,
+        }
+    }
+
+    fn enum_attribute(&self, name: &str, value: u64) -> LLVMAttributeRef {
+        unsafe {
+            let kind = LLVMGetEnumAttributeKindForName(name.as_ptr() as *const _, name.len());
+            LLVMCreateEnumAttribute(self.context, kind, value)
+        }
+    }
+
+    fn type_attribute(&self, name: &str, ty: LLVMTypeRef) -> LLVMAttributeRef {
+        unsafe {
+            let kind = LLVMGetEnumAttributeKindForName(name.as_ptr() as *const _, name.len());
+            LLVMCreateTypeAttribute(self.context, kind, ty)
+        }
+    }
+
+    fn abi_type(&self, ty: &AbiType) -> LLVMTypeRef {
+        unsafe {
+            match ty {
+                AbiType::Integer(bits) => LLVMIntTypeInContext(self.context, *bits as u32),
+                AbiType::Float => LLVMFloatTypeInContext(self.context),
+                AbiType::Double => LLVMDoubleTypeInContext(self.context),
+                AbiType::Array(element, count) => LLVMArrayType2(self.abi_type(element), *count),
+            }
+        }
+    }
+
+    /// A single piece is returned as is and several pieces in a literal struct.
+    fn coerced_type(&self, pieces: &[AbiType]) -> LLVMTypeRef {
+        if let [piece] = pieces {
+            return self.abi_type(piece);
+        }
+
+        let mut types = pieces
+            .iter()
+            .map(|piece| self.abi_type(piece))
+            .collect::<Vec<_>>();
+
+        unsafe { LLVMStructTypeInContext(self.context, types.as_mut_ptr(), types.len() as u32, 0) }
+    }
+
+    /// Declares the methods types inherit from protocol default implementations,
+    /// `T.m` forwards to `P.m` with the receiver wrapped into an existential.
+    fn declare_inherited_methods(&mut self, context: &Context) {
+        for definition in context.types() {
+            for method in &definition.methods {
+                if let MethodImplementation::Default(_) = method.implementation {
+                    let name = format!("{}.{}", definition.name, method.name);
+                    let function_type = self.function_type(&method.function, true, context);
+                    let llvm_name = CString::new(self.symbol(&name, context)).unwrap();
+
+                    unsafe {
+                        let function =
+                            LLVMAddFunction(self.module, llvm_name.as_ptr(), function_type);
+                        // emitted by every object file using the type
+                        LLVMSetLinkage(function, LLVMLinkage::LLVMLinkOnceODRLinkage);
+
+                        self.function_table.insert(
+                            name.clone(),
+                            FunctionRef {
+                                function_type,
+                                function_ref: function,
+                                abi: None,
+                            },
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn visit_inherited_methods(&mut self, context: &Context) {
+        for definition in context.types() {
+            for method in &definition.methods {
+                let MethodImplementation::Default(protocol_name) = &method.implementation else {
+                    continue;
+                };
+
+                let thunk = &self.function_table[&format!("{}.{}", definition.name, method.name)];
+                let thunk = thunk.function_ref;
+                let default = &self.function_table[&format!("{}.{}", protocol_name, method.name)];
+                let default_type = default.function_type;
+                let default = default.function_ref;
+
+                unsafe {
+                    let entry = LLVMAppendBasicBlockInContext(self.context, thunk, c"".as_ptr());
+                    LLVMPositionBuilderAtEnd(self.builder, entry);
+
+                    // the receiver outlives the call, the existential refers to it
+                    let witness_table =
+                        self.witness_tables[&format!("{}.{}", definition.name, protocol_name)];
+                    let mut existential = LLVMGetUndef(self.existential_type());
+                    existential = LLVMBuildInsertValue(
+                        self.builder,
+                        existential,
+                        LLVMGetParam(thunk, 0),
+                        0,
+                        c"".as_ptr(),
+                    );
+                    existential = LLVMBuildInsertValue(
+                        self.builder,
+                        existential,
+                        witness_table,
+                        1,
+                        c"".as_ptr(),
+                    );
+                    let self_pointer = self.build_entry_alloca(self.existential_type());
+                    LLVMBuildStore(self.builder, existential, self_pointer);
+
+                    let mut args = vec![self_pointer];
+                    for i in 0..method.function.parameters.len() {
+                        args.push(LLVMGetParam(thunk, (i + 1) as u32));
+                    }
+
+                    let value = LLVMBuildCall2(
+                        self.builder,
+                        default_type,
+                        default,
+                        args.as_mut_ptr(),
+                        args.len() as u32,
+                        c"".as_ptr(),
+                    );
+
+                    if *method.function.return_type == Type::Void {
+                        LLVMBuildRetVoid(self.builder);
+                    } else {
+                        LLVMBuildRet(self.builder, value);
+                    }
+
+                    LLVMVerifyFunction(thunk, LLVMVerifierFailureAction::LLVMPrintMessageAction);
+                }
+            }
+        }
+    }
+
+    /// Emits a witness table for every protocol conformance, a constant array
+    /// holding the implementation of each requirement in declaration order.
+    fn declare_witness_tables(&mut self, context: &Context) {
+        for definition in context.types() {
+            let type_name = &definition.name;
+
+            // the functions of a library are only called statically
+            if definition.kind == TypeDefinitionKind::Library {
+                continue;
+            }
+
+            for protocol_name in &definition.conformances {
+                let protocol = context.lookup_type(protocol_name).unwrap();
+
+                let mut witnesses = protocol
+                    .methods
+                    .iter()
+                    .map(|requirement| {
+                        self.function_table[&format!("{}.{}", type_name, requirement.name)]
+                            .function_ref
+                    })
+                    .collect::<Vec<_>>();
+
+                let name = format!("{}.{}", type_name, protocol_name);
+                let llvm_name = CString::new(format!("{}.witness_table", name)).unwrap();
+
+                unsafe {
+                    let table = LLVMConstArray2(
+                        self.pointer_type(),
+                        witnesses.as_mut_ptr(),
+                        witnesses.len() as u64,
+                    );
+
+                    let global = LLVMAddGlobal(self.module, LLVMTypeOf(table), llvm_name.as_ptr());
+                    LLVMSetInitializer(global, table);
+                    LLVMSetGlobalConstant(global, 1);
+                    LLVMSetLinkage(global, LLVMLinkage::LLVMPrivateLinkage);
+
+                    self.witness_tables.insert(name, global);
+                }
+            }
+        }
+    }
+
+    pub fn visit_code_block_item(&mut self, node: &CodeBlockItem, context: &Context) {
+        match node {
+            CodeBlockItem::Declaration(decl) => {
+                self.visit_declaration(decl, context);
+            }
+            CodeBlockItem::Statement(stmt) => {
+                self.visit_statement(stmt, context);
+            }
+            CodeBlockItem::Expression(expr) => {
+                self.visit_expression(expr, context);
+            }
+        }
+    }
+
+    pub fn visit_declaration(&mut self, node: &Declaration, context: &Context) {
+        match node {
+            Declaration::VariableDeclaration(variable) => {
+                self.visit_variable_declaration(variable, context)
+            }
+            // generic functions are emitted for each instance once the program is
+            Declaration::FunctionDeclaration(function)
+                if !function.generic_parameters.is_empty() => {}
+            Declaration::TypeDeclaration(declaration)
+                if !declaration.generic_parameters.is_empty() => {}
+            Declaration::EnumDeclaration(declaration)
+                if !declaration.generic_parameters.is_empty() => {}
+            Declaration::FunctionDeclaration(function) => {
+                self.visit_function_declaration(function, context)
+            }
+            // externs only need the declaration emitted up front
+            Declaration::ExternDeclaration(_) | Declaration::ExternLibraryDeclaration(_) => {}
+            Declaration::CImportDeclaration(_) => {}
+            // only declared, the object file of the module defines them
+            Declaration::ModuleImportDeclaration(_) => {}
+            Declaration::TypeDeclaration(declaration) => self.visit_member_block(
+                &declaration.name.name,
+                &declaration.container.member_block,
+                context,
+            ),
+            Declaration::EnumDeclaration(declaration) => self.visit_member_block(
+                &declaration.name.name,
+                &declaration.container.member_block,
+                context,
+            ),
+            Declaration::ExtensionDeclaration(declaration) => self.visit_member_block(
+                &declaration.name.name,
+                &declaration.container.member_block,
+                context,
+            ),
+            Declaration::ProtocolDeclaration(declaration) => {
+                self.visit_protocol_declaration(declaration, context)
+            }
+        }
+    }
+
+    /// Emits the default implementations of a protocol, the rest of the
+    /// protocol only exists at compile time.
+    pub fn visit_protocol_declaration(&mut self, node: &ProtocolDeclaration, context: &Context) {
+        for requirement in &node.requirements {
+            let Some(body) = &requirement.body else {
+                continue;
+            };
+
+            let name = format!("{}.{}", node.name.name, requirement.identifier.name);
+            let function = self.function_table[&name].function_ref;
+
+            unsafe {
+                self.visit_function_body(
+                    body,
+                    &requirement.signature,
+                    function,
+                    Some(&node.name.name),
+                    None,
+                    context,
+                );
+                LLVMVerifyFunction(function, LLVMVerifierFailureAction::LLVMPrintMessageAction);
+            }
+        }
+    }
+
+    pub fn visit_variable_declaration(&mut self, node: &VariableDeclaration, context: &Context) {
+        let expression = node
+            .expression
+            .as_ref()
+            .expect("variables are initialized after type checking");
+        let mut value = self.visit_expression(expression, context);
+
+        if let Some(type_annotation) = &node.type_annotation {
+            let value_type = self.expression_type(expression, context);
+            value = self.coerce(
+                value,
+                &value_type,
+                &Type::from(type_annotation.clone()),
+                context,
+            );
+        }
+
+        self.named_values
+            .insert(node.identifier.name.clone(), value);
+    }
+
+    pub fn visit_function_declaration(&mut self, node: &FunctionDeclaration, context: &Context) {
+        let function = &self.function_table[&node.identifier.name];
+        let abi = function.abi.clone();
+        let function = function.function_ref;
+
+        unsafe {
+            self.visit_function_body(&node.body, &node.signature, function, None, abi, context);
+            LLVMVerifyFunction(function, LLVMVerifierFailureAction::LLVMPrintMessageAction);
+        }
+    }
+
+    pub fn visit_member_block(&mut self, type_name: &str, node: &MemberBlock, context: &Context) {
+        for member in &node.members {
+            let MemberBlockItem::FunctionDeclaration(method) = member else {
+                continue;
+            };
+
+            let name = format!("{}.{}", type_name, method.identifier.name);
+            let function = self.function_table[&name].function_ref;
+
+            unsafe {
+                self.visit_function_body(
+                    &method.body,
+                    &method.signature,
+                    function,
+                    Some(type_name),
+                    None,
+                    context,
+                );
+                LLVMVerifyFunction(function, LLVMVerifierFailureAction::LLVMPrintMessageAction);
+            }
+        }
+    }
+
+    /// The type of a function from its signature, see `visit_function_signature`.
+    fn function_type(
+        &self,
+        function: &TypeFunction,
+        has_self: bool,
+        context: &Context,
+    ) -> LLVMTypeRef {
+        let mut types = Vec::new();
+
+        if has_self {
+            types.push(self.pointer_type());
+        }
+
+        for parameter in &function.parameters {
+            types.push(self.llvm_type(&parameter.ty, context));
+        }
+
+        unsafe {
+            LLVMFunctionType(
+                self.llvm_type(&function.return_type, context),
+                types.as_mut_ptr(),
+                types.len() as u32,
+                0,
+            )
+        }
+    }
+
+    pub fn visit_function_signature(
+        &mut self,
+        node: &FunctionSignature,
+        has_self: bool,
+        context: &Context,
+    ) -> LLVMTypeRef {
+        unsafe {
+            let mut types = Vec::new();
+
+            if has_self {
+                types.push(self.pointer_type());
+            }
+
+            types.extend(self.visit_function_parameters(&node.parameters, context));
+            let return_type = self.visit_return_clause(&node.return_clause, context);
+
+            LLVMFunctionType(
+                return_type,
+                types.as_mut_ptr(),
+                types.len() as u32,
+                node.parameters.variadic as i32,
+            )
+        }
+    }
+
+    pub fn visit_function_parameters(
+        &mut self,
+        node: &FunctionParameters,
+        context: &Context,
+    ) -> Vec<LLVMTypeRef> {
+        node.parameters
+            .iter()
+            .map(|parameter| self.llvm_type(&Type::from(parameter.parameter_type.clone()), context))
+            .collect()
+    }
+
+    pub fn visit_return_clause(
+        &mut self,
+        node: &Option<ReturnClause>,
+        context: &Context,
+    ) -> LLVMTypeRef {
+        match node {
+            Some(clause) => self.llvm_type(&Type::from(clause.return_type.clone()), context),
+            None => unsafe { LLVMVoidTypeInContext(self.context) },
+        }
+    }
+
+    pub fn visit_function_body(
+        &mut self,
+        node: &CodeBlockContainer,
+        signature: &FunctionSignature,
+        function: LLVMValueRef,
+        self_type: Option<&str>,
+        abi: Option<FunctionAbi>,
+        context: &Context,
+    ) {
+        unsafe {
+            let entry = LLVMAppendBasicBlockInContext(self.context, function, c"".as_ptr());
+            LLVMPositionBuilderAtEnd(self.builder, entry);
+
+            self.named_values.clear();
+
+            let mut offset = 0;
+
+            // fields are visible unqualified in method bodies
+            if let Some(type_name) = self_type {
+                let self_type =
+                    self.resolve(&context.lookup_type(type_name).unwrap().declared_type());
+                let self_value = LLVMBuildLoad2(
+                    self.builder,
+                    self.llvm_type(&self_type, context),
+                    LLVMGetParam(function, 0),
+                    c"self".as_ptr(),
+                );
+
+                self.named_values.insert("self".to_string(), self_value);
+
+                if let Some(fields) = context.fields(&self_type) {
+                    for (i, field) in fields.iter().enumerate() {
+                        let name = CString::new(field.name.clone()).unwrap();
+                        let value = LLVMBuildExtractValue(
+                            self.builder,
+                            self_value,
+                            i as u32,
+                            name.as_ptr(),
+                        );
+                        self.named_values.insert(field.name.clone(), value);
+                    }
+                }
+
+                offset = 1;
+            }
+
+            match &abi {
+                Some(abi) => self.visit_exported_parameters(signature, function, abi, context),
+                None => {
+                    for i in 0..signature.parameters.parameters.len() {
+                        let param = &signature.parameters.parameters[i];
+                        let value = LLVMGetParam(function, (i + offset) as u32);
+                        self.named_values.insert(param.name.name.clone(), value);
+                    }
+                }
+            }
+
+            let return_type = signature
+                .return_clause
+                .as_ref()
+                .map(|r| self.resolve(&Type::from(r.return_type.clone())))
+                .unwrap_or(Type::Void);
+
+            // values only escape a function through its parameters and its result
+            let mut escaping = signature
+                .parameters
+                .parameters
+                .iter()
+                .map(|param| Type::from(param.parameter_type.clone()))
+                .chain(self_type.map(|name| context.lookup_type(name).unwrap().declared_type()))
+                .chain([return_type.clone()]);
+            let frame_existentials =
+                !escaping.any(|ty| self.holds_existentials(&ty, &mut Vec::new(), context));
+
+            let outer_type = std::mem::replace(&mut self.current_type, self_type.map(String::from));
+            let outer_return_type = std::mem::replace(&mut self.return_type, return_type.clone());
+            let outer_abi = std::mem::replace(&mut self.function_abi, abi);
+            let outer_frame_existentials =
+                std::mem::replace(&mut self.frame_existentials, frame_existentials);
+
+            let tail_value = self.visit_code_block_value(&node.code_block, context);
+            self.build_tail_return(node, tail_value, &return_type, context);
+
+            self.current_type = outer_type;
+            self.return_type = outer_return_type;
+            self.function_abi = outer_abi;
+            self.frame_existentials = outer_frame_existentials;
+        }
+    }
+
+    /// Returns the value of the tail expression of a function body, unless
+    /// the body already returned.
+    fn build_tail_return(
+        &mut self,
+        node: &CodeBlockContainer,
+        tail_value: LLVMValueRef,
+        return_type: &Type,
+        context: &Context,
+    ) {
+        if self.is_terminated() {
+            return;
+        }
+
+        unsafe {
+            match return_type {
+                Type::Void => {
+                    LLVMBuildRetVoid(self.builder);
+                }
+                _ if self.has_value(tail_value) => {
+                    let Some(CodeBlockItem::Expression(tail)) = node.code_block.items.last() else {
+                        unreachable!();
+                    };
+
+                    let tail_type = self.expression_type(tail, context);
+                    let value = self.coerce(tail_value, &tail_type, return_type, context);
+                    self.build_return(value);
+                }
+                _ => {
+                    // the type checker guarantees every path returned before this point
+                    LLVMBuildUnreachable(self.builder);
+                }
+            }
+        }
+    }
+
+    /// Rebuilds the parameters of an exported function from the C arguments,
+    /// the reverse of the lowering done by `build_call`.
+    fn visit_exported_parameters(
+        &mut self,
+        signature: &FunctionSignature,
+        function: LLVMValueRef,
+        abi: &FunctionAbi,
+        context: &Context,
+    ) {
+        let mut index = match abi.return_mode {
+            PassMode::Indirect { .. } => 1,
+            _ => 0,
+        };
+
+        for (param, mode) in signature.parameters.parameters.iter().zip(&abi.parameters) {
+            let ty = self.llvm_type(&Type::from(param.parameter_type.clone()), context);
+            let name = CString::new(param.name.name.clone()).unwrap();
+
+            unsafe {
+                let value = match mode {
+                    PassMode::Direct => {
+                        index += 1;
+                        LLVMGetParam(function, index - 1)
+                    }
+                    PassMode::Coerced(pieces) => {
+                        let memory = self.build_entry_alloca(LLVMArrayType2(
+                            LLVMInt64TypeInContext(self.context),
+                            4,
+                        ));
+
+                        for k in 0..pieces.len() {
+                            let address = self.build_byte_offset(memory, k as u64 * 8);
+                            LLVMBuildStore(self.builder, LLVMGetParam(function, index), address);
+                            index += 1;
+                        }
+
+                        LLVMBuildLoad2(self.builder, ty, memory, name.as_ptr())
+                    }
+                    PassMode::Indirect { .. } => {
+                        index += 1;
+                        LLVMBuildLoad2(
+                            self.builder,
+                            ty,
+                            LLVMGetParam(function, index - 1),
+                            name.as_ptr(),
+                        )
+                    }
+                };
+
+                self.named_values.insert(param.name.name.clone(), value);
+            }
+        }
+    }
+
+    /// Returns from the current function, exported functions return their
+    /// value following the C calling convention.
+    fn build_return(&mut self, value: LLVMValueRef) {
+        unsafe {
+            match self
+                .function_abi
+                .as_ref()
+                .map(|abi| abi.return_mode.clone())
+            {
+                None | Some(PassMode::Direct) => {
+                    LLVMBuildRet(self.builder, value);
+                }
+                Some(PassMode::Coerced(pieces)) => {
+                    let memory = self.build_spill(value);
+                    let value = LLVMBuildLoad2(
+                        self.builder,
+                        self.coerced_type(&pieces),
+                        memory,
+                        c"".as_ptr(),
+                    );
+                    LLVMBuildRet(self.builder, value);
+                }
+                Some(PassMode::Indirect { .. }) => {
+                    let function = LLVMGetBasicBlockParent(LLVMGetInsertBlock(self.builder));
+                    LLVMBuildStore(self.builder, value, LLVMGetParam(function, 0));
+                    LLVMBuildRetVoid(self.builder);
+                }
+            }
+        }
+    }
+
+    pub fn visit_statement(&mut self, node: &Statement, context: &Context) {
+        match node {
+            Statement::Return(statement) => self.visit_return_statement(statement, context),
+            Statement::Assignment(statement) => self.visit_assignment_statement(statement, context),
+            Statement::Destructuring(statement) => {
+                self.visit_destructuring_statement(statement, context)
+            }
+        }
+    }
+
+    pub fn visit_destructuring_statement(
+        &mut self,
+        node: &DestructuringStatement,
+        context: &Context,
+    ) {
+        let mut value = self.visit_expression(&node.value, context);
+
+        if let Some(type_annotation) = &node.type_annotation {
+            let value_type = self.expression_type(&node.value, context);
+            value = self.coerce(
+                value,
+                &value_type,
+                &Type::from(type_annotation.clone()),
+                context,
+            );
+        }
+
+        for (i, name) in node.names.iter().enumerate() {
+            let Some(name) = name else {
+                continue;
+            };
+
+            let element =
+                unsafe { LLVMBuildExtractValue(self.builder, value, i as u32, c"".as_ptr()) };
+
+            self.named_values.insert(name.name.clone(), element);
+        }
+    }
+
+    pub fn visit_assignment_statement(&mut self, node: &AssignmentStatement, context: &Context) {
+        let Expression::Index(target) = &node.target else {
+            panic!("Only array elements can be assigned to");
+        };
+
+        if let Type::Dictionary(key_type, value_type) = self.expression_type(&target.base, context)
+        {
+            return self.build_dictionary_assignment(
+                target,
+                &node.value,
+                &key_type,
+                &value_type,
+                context,
+            );
+        }
+
+        let (pointer, element_type) = self.build_element_pointer(target, context);
+
+        let value = self.visit_expression(&node.value, context);
+        let value_type = self.expression_type(&node.value, context);
+        let value = self.coerce(value, &value_type, &element_type, context);
+
+        unsafe {
+            LLVMBuildStore(self.builder, value, pointer);
+        }
+    }
+
+    pub fn visit_return_statement(&mut self, node: &ReturnStatement, context: &Context) {
+        unsafe {
+            match &node.expression {
+                Some(expression) => {
+                    let value = self.visit_expression(expression, context);
+                    let value_type = self.expression_type(expression, context);
+                    let return_type = self.return_type.clone();
+                    let value = self.coerce(value, &value_type, &return_type, context);
+                    self.build_return(value);
+                }
+                None => {
+                    LLVMBuildRetVoid(self.builder);
+                }
+            }
+        }
+    }
+
+    pub fn visit_expression(&mut self, node: &Expression, context: &Context) -> LLVMValueRef {
+        match node {
+            Expression::BooleanLiteral(value) => self.visit_boolean_literal(*value),
+            Expression::FloatNumberLiteral(value) => {
+                let ty = self.expression_type(node, context);
+                self.visit_float_number_literal(*value, &ty, context)
+            }
+            Expression::StringLiteral(value) => self.visit_string_literal(value),
+            Expression::Identifier(identifier) => self.visit_identifier(identifier, context),
+            Expression::Tuple(tuple) => {
+                let ty = self.expression_type(node, context);
+                self.visit_tuple(tuple, &ty, context)
+            }
+            Expression::BinaryOperator(op) => self.visit_binary_operator_expression(op, context),
+            Expression::FunctionCall(function_call) => {
+                self.visit_function_call(function_call, context)
+            }
+            Expression::MethodCall(method_call) => self.visit_method_call(method_call, context),
+            Expression::MemberAccess(member_access) => {
+                self.visit_member_access(member_access, context)
+            }
+            Expression::If(if_expression) => {
+                let ty = self.expression_type(node, context);
+                self.visit_if_expression(if_expression, &ty, context)
+            }
+            Expression::Match(match_expression) => {
+                let ty = self.expression_type(node, context);
+                self.visit_match_expression(match_expression, &ty, context)
+            }
+            Expression::NilLiteral => self.build_nil(&self.expression_type(node, context), context),
+            Expression::Result(result) => {
+                let ty = self.expression_type(node, context);
+                self.visit_result_expression(result, &ty, context)
+            }
+            Expression::Try(try_expression) => self.visit_try_expression(try_expression, context),
+            Expression::Array(array) => {
+                let ty = self.expression_type(node, context);
+                self.visit_array_expression(array, &ty, context)
+            }
+            Expression::Dictionary(dictionary) => {
+                let ty = self.expression_type(node, context);
+                self.visit_dictionary_expression(dictionary, &ty, context)
+            }
+            Expression::Index(index) => self.visit_index_expression(index, context),
+            Expression::Closure(closure) => {
+                let ty = self.expression_type(node, context);
+                self.visit_closure_expression(closure, &ty, context)
+            }
+        }
+    }
+
+    /// Builds the (function, environment) pair of a closure. The variables it
+    /// captures are copied to an environment on the heap, which the function
+    /// of the closure takes as its first parameter.
+    pub fn visit_closure_expression(
+        &mut self,
+        node: &ClosureExpression,
+        ty: &Type,
+        context: &Context,
+    ) -> LLVMValueRef {
+        let Type::Function(function) = ty else {
+            unreachable!();
+        };
+
+        // variables the closure uses from enclosing closures are not visible
+        // when it is emitted as the body of a declared function
+        let captures = context
+            .captures(node)
+            .iter()
+            .filter_map(|name| Some((name.clone(), *self.named_values.get(name)?)))
+            .collect::<Vec<_>>();
+
+        unsafe {
+            let mut field_types = captures
+                .iter()
+                .map(|(_, value)| LLVMTypeOf(*value))
+                .collect::<Vec<_>>();
+            let environment_type = LLVMStructTypeInContext(
+                self.context,
+                field_types.as_mut_ptr(),
+                field_types.len() as u32,
+                0,
+            );
+
+            let environment = if captures.is_empty() {
+                LLVMConstNull(self.pointer_type())
+            } else {
+                let mut values = LLVMGetUndef(environment_type);
+
+                for (i, (_, value)) in captures.iter().enumerate() {
+                    values =
+                        LLVMBuildInsertValue(self.builder, values, *value, i as u32, c"".as_ptr());
+                }
+
+                let environment = self.build_malloc(environment_type);
+                LLVMBuildStore(self.builder, values, environment);
+                environment
+            };
+
+            let name = CString::new(format!("closure.{}", self.closure_count)).unwrap();
+            self.closure_count += 1;
+
+            let closure = LLVMAddFunction(
+                self.module,
+                name.as_ptr(),
+                self.function_type(function, true, context),
+            );
+            LLVMSetLinkage(closure, LLVMLinkage::LLVMPrivateLinkage);
+
+            let block = LLVMGetInsertBlock(self.builder);
+            let return_type = self.resolve(&function.return_type);
+            let outer_values = std::mem::take(&mut self.named_values);
+            let outer_return_type = std::mem::replace(&mut self.return_type, return_type.clone());
+            let outer_abi = self.function_abi.take();
+            // the values a closure captures may be reached once it returned
+            let outer_frame_existentials = std::mem::take(&mut self.frame_existentials);
+
+            let entry = LLVMAppendBasicBlockInContext(self.context, closure, c"".as_ptr());
+            LLVMPositionBuilderAtEnd(self.builder, entry);
+
+            if !captures.is_empty() {
+                let values = LLVMBuildLoad2(
+                    self.builder,
+                    environment_type,
+                    LLVMGetParam(closure, 0),
+                    c"".as_ptr(),
+                );
+
+                for (i, (name, _)) in captures.iter().enumerate() {
+                    let c_name = CString::new(name.clone()).unwrap();
+                    let value =
+                        LLVMBuildExtractValue(self.builder, values, i as u32, c_name.as_ptr());
+                    self.named_values.insert(name.clone(), value);
+                }
+            }
+
+            for (i, parameter) in node.signature.parameters.parameters.iter().enumerate() {
+                self.named_values.insert(
+                    parameter.name.name.clone(),
+                    LLVMGetParam(closure, i as u32 + 1),
+                );
+            }
+
+            let tail_value = self.visit_code_block_value(&node.body.code_block, context);
+            self.build_tail_return(&node.body, tail_value, &return_type, context);
+
+            self.named_values = outer_values;
+            self.return_type = outer_return_type;
+            self.function_abi = outer_abi;
+            self.frame_existentials = outer_frame_existentials;
+            LLVMPositionBuilderAtEnd(self.builder, block);
+
+            self.build_closure(closure, environment)
+        }
+    }
+
+    fn build_closure(&mut self, function: LLVMValueRef, environment: LLVMValueRef) -> LLVMValueRef {
+        unsafe {
+            let mut closure = LLVMGetUndef(self.closure_type());
+            closure = LLVMBuildInsertValue(self.builder, closure, function, 0, c"".as_ptr());
+            LLVMBuildInsertValue(self.builder, closure, environment, 1, c"".as_ptr())
+        }
+    }
+
+    /// A declared function used as a value, it is called through a thunk that
+    /// takes and ignores an environment like the function of a closure does.
+    fn build_function_value(
+        &mut self,
+        name: &str,
+        function: &TypeFunction,
+        context: &Context,
+    ) -> LLVMValueRef {
+        let thunk_name = CString::new(format!("{}.thunk", name)).unwrap();
+
+        unsafe {
+            let mut thunk = LLVMGetNamedFunction(self.module, thunk_name.as_ptr());
+
+            if thunk.is_null() {
+                thunk = LLVMAddFunction(
+                    self.module,
+                    thunk_name.as_ptr(),
+                    self.function_type(function, true, context),
+                );
+                LLVMSetLinkage(thunk, LLVMLinkage::LLVMPrivateLinkage);
+
+                let block = LLVMGetInsertBlock(self.builder);
+                let entry = LLVMAppendBasicBlockInContext(self.context, thunk, c"".as_ptr());
+                LLVMPositionBuilderAtEnd(self.builder, entry);
+
+                let args = (0..function.parameters.len())
+                    .map(|i| LLVMGetParam(thunk, i as u32 + 1))
+                    .collect();
+                let value = self.build_call(name, function, args, context);
+
+                match *function.return_type {
+                    Type::Void => LLVMBuildRetVoid(self.builder),
+                    _ => LLVMBuildRet(self.builder, value),
+                };
+
+                LLVMPositionBuilderAtEnd(self.builder, block);
+            }
+
+            self.build_closure(thunk, LLVMConstNull(self.pointer_type()))
+        }
+    }
+
+    /// Calls the function of a closure with its environment.
+    fn build_closure_call(
+        &mut self,
+        closure: LLVMValueRef,
+        function: &TypeFunction,
+        arguments: &ArgumentList,
+        context: &Context,
+    ) -> LLVMValueRef {
+        unsafe {
+            let callee = LLVMBuildExtractValue(self.builder, closure, 0, c"".as_ptr());
+            let environment = LLVMBuildExtractValue(self.builder, closure, 1, c"".as_ptr());
+
+            let mut args = vec![environment];
+            args.extend(self.build_arguments(function, arguments, context));
+
+            LLVMBuildCall2(
+                self.builder,
+                self.function_type(function, true, context),
+                callee,
+                args.as_mut_ptr(),
+                args.len() as u32,
+                c"".as_ptr(),
+            )
+        }
+    }
+
+    /// Copies the elements of an array literal to the heap, arrays refer to
+    /// their elements so copies of an array share them.
+    pub fn visit_array_expression(
+        &mut self,
+        node: &ArrayExpression,
+        ty: &Type,
+        context: &Context,
+    ) -> LLVMValueRef {
+        let Type::Array(element_type) = ty else {
+            panic!("Array literal of type {}", ty);
+        };
+
+        unsafe {
+            let element_llvm_type = self.llvm_type(element_type, context);
+            let count = node.elements.len() as u64;
+            let data = self.build_malloc(LLVMArrayType2(element_llvm_type, count));
+
+            for (i, element) in node.elements.iter().enumerate() {
+                let value = self.visit_expression(element, context);
+                let value_type = self.expression_type(element, context);
+                let value = self.coerce(value, &value_type, element_type, context);
+
+                let mut indices = [LLVMConstInt(
+                    LLVMInt64TypeInContext(self.context),
+                    i as u64,
+                    0,
+                )];
+                let pointer = LLVMBuildGEP2(
+                    self.builder,
+                    element_llvm_type,
+                    data,
+                    indices.as_mut_ptr(),
+                    1,
+                    c"".as_ptr(),
+                );
+                LLVMBuildStore(self.builder, value, pointer);
+            }
+
+            let mut array = LLVMGetUndef(self.llvm_type(ty, context));
+            array = LLVMBuildInsertValue(
+                self.builder,
+                array,
+                LLVMConstInt(LLVMInt64TypeInContext(self.context), count, 0),
+                0,
+                c"".as_ptr(),
+            );
+            LLVMBuildInsertValue(self.builder, array, data, 1, c"".as_ptr())
+        }
+    }
+
+    pub fn visit_index_expression(
+        &mut self,
+        node: &IndexExpression,
+        context: &Context,
+    ) -> LLVMValueRef {
+        if let Type::Dictionary(key_type, value_type) = self.expression_type(&node.base, context) {
+            return self.build_dictionary_get(node, &key_type, &value_type, context);
+        }
+
+        let (pointer, element_type) = self.build_element_pointer(node, context);
+
+        unsafe {
+            LLVMBuildLoad2(
+                self.builder,
+                self.llvm_type(&element_type, context),
+                pointer,
+                c"".as_ptr(),
+            )
+        }
+    }
+
+    /// The address of an element of an array along with its type, the index is
+    /// checked against the count first unless bounds checks are disabled.
+    fn build_element_pointer(
+        &mut self,
+        node: &IndexExpression,
+        context: &Context,
+    ) -> (LLVMValueRef, Type) {
+        let Type::Array(element_type) = self.expression_type(&node.base, context) else {
+            panic!("Indexing a value that is not an array");
+        };
+
+        let array = self.visit_expression(&node.base, context);
+        let index = self.visit_expression(&node.index, context);
+        let index_type = self.expression_type(&node.index, context);
+
+        unsafe {
+            let int64 = LLVMInt64TypeInContext(self.context);
+
+            let index = match index_type {
+                Type::Identifier(name) if name == "i64" || name == "u64" => index,
+                ty if ty.is_unsigned_integer() => {
+                    LLVMBuildZExt(self.builder, index, int64, c"".as_ptr())
+                }
+                _ => LLVMBuildSExt(self.builder, index, int64, c"".as_ptr()),
+            };
+
+            if self.options.bounds_checks {
+                self.build_bounds_check(array, index, node.location);
+            }
+
+            let data = LLVMBuildExtractValue(self.builder, array, 1, c"".as_ptr());
+            let element_llvm_type = self.llvm_type(&element_type, context);
+
+            let mut indices = [index];
+            let pointer = LLVMBuildGEP2(
+                self.builder,
+                element_llvm_type,
+                data,
+                indices.as_mut_ptr(),
+                1,
+                c"".as_ptr(),
+            );
+
+            (pointer, *element_type)
+        }
+    }
+
+    /// Stops the program with a message naming the location of the indexing
+    /// when the index is not below the count, negative indices included.
+    fn build_bounds_check(
+        &mut self,
+        array: LLVMValueRef,
+        index: LLVMValueRef,
+        location: SourceLocation,
+    ) {
+        unsafe {
+            let int32 = LLVMInt32TypeInContext(self.context);
+            let count = LLVMBuildExtractValue(self.builder, array, 0, c"count".as_ptr());
+            let out_of_bounds = LLVMBuildICmp(
+                self.builder,
+                LLVMIntPredicate::LLVMIntUGE,
+                index,
+                count,
+                c"".as_ptr(),
+            );
+
+            let function = LLVMGetBasicBlockParent(LLVMGetInsertBlock(self.builder));
+            let fail_bb = LLVMAppendBasicBlockInContext(self.context, function, c"".as_ptr());
+            let ok_bb = LLVMAppendBasicBlockInContext(self.context, function, c"".as_ptr());
+
+            LLVMBuildCondBr(self.builder, out_of_bounds, fail_bb, ok_bb);
+            LLVMPositionBuilderAtEnd(self.builder, fail_bb);
+
+            let message = format!(
+                "{}:{}:{}: index %lld is out of bounds for an array of %lld elements\n",
+                self.options.source_name.replace('%', "%%"),
+                location.line,
+                location.column
+            );
+            let message = self.visit_string_literal(&message);
+
+            // what the program printed so far is not lost when it stops
+            let mut parameter_types = [self.pointer_type()];
+            let fflush_type = LLVMFunctionType(int32, parameter_types.as_mut_ptr(), 1, 0);
+            let fflush = self.runtime_function("fflush", fflush_type);
+            let mut args = [LLVMConstNull(self.pointer_type())];
+            LLVMBuildCall2(
+                self.builder,
+                fflush_type,
+                fflush,
+                args.as_mut_ptr(),
+                1,
+                c"".as_ptr(),
+            );
+
+            let mut parameter_types = [int32, self.pointer_type()];
+            let dprintf_type = LLVMFunctionType(int32, parameter_types.as_mut_ptr(), 2, 1);
+            let dprintf = self.runtime_function("dprintf", dprintf_type);
+
+            // the message goes to the standard error
+            let mut args = [LLVMConstInt(int32, 2, 0), message, index, count];
+            LLVMBuildCall2(
+                self.builder,
+                dprintf_type,
+                dprintf,
+                args.as_mut_ptr(),
+                args.len() as u32,
+                c"".as_ptr(),
+            );
+
+            let abort_type =
+                LLVMFunctionType(LLVMVoidTypeInContext(self.context), ptr::null_mut(), 0, 0);
+            let abort = self.runtime_function("abort", abort_type);
+            LLVMBuildCall2(
+                self.builder,
+                abort_type,
+                abort,
+                ptr::null_mut(),
+                0,
+                c"".as_ptr(),
+            );
+            LLVMBuildUnreachable(self.builder);
+
+            LLVMPositionBuilderAtEnd(self.builder, ok_bb);
+        }
+    }
+
+    /// Creates a dictionary and inserts the entries of the literal in order, a
+    /// dictionary is a pointer to a hash map of the runtime.
+    pub fn visit_dictionary_expression(
+        &mut self,
+        node: &DictionaryExpression,
+        ty: &Type,
+        context: &Context,
+    ) -> LLVMValueRef {
+        let Type::Dictionary(key_type, value_type) = self.resolve(ty) else {
+            panic!("Dictionary literal of type {}", ty);
+        };
+
+        let (hash, equal) = self.key_functions(&key_type, context);
+
+        unsafe {
+            let int64 = LLVMInt64TypeInContext(self.context);
+            let mut parameter_types = [int64, int64, self.pointer_type(), self.pointer_type()];
+            let new_type =
+                LLVMFunctionType(self.pointer_type(), parameter_types.as_mut_ptr(), 4, 0);
+            let new = self.dictionary_function("x_dictionary_new", new_type);
+
+            let mut args = [
+                LLVMSizeOf(self.llvm_type(&key_type, context)),
+                LLVMSizeOf(self.llvm_type(&value_type, context)),
+                hash,
+                equal,
+            ];
+            let dictionary = LLVMBuildCall2(
+                self.builder,
+                new_type,
+                new,
+                args.as_mut_ptr(),
+                args.len() as u32,
+                c"".as_ptr(),
+            );
+
+            for (key, value) in &node.entries {
+                let key_pointer = self.build_key(key, &key_type, context);
+
+                let value_pointer = {
+                    let value_llvm = self.visit_expression(value, context);
+                    let ty = self.expression_type(value, context);
+                    let value_llvm = self.coerce(value_llvm, &ty, &value_type, context);
+                    self.build_temporary(value_llvm)
+                };
+
+                self.build_dictionary_insert(dictionary, key_pointer, value_pointer);
+            }
+
+            dictionary
+        }
+    }
+
+    /// Looks a key up, the value is copied out of the dictionary when it holds
+    /// the key and nil otherwise.
+    fn build_dictionary_get(
+        &mut self,
+        node: &IndexExpression,
+        key_type: &Type,
+        value_type: &Type,
+        context: &Context,
+    ) -> LLVMValueRef {
+        let dictionary = self.visit_expression(&node.base, context);
+        let key = self.build_key(&node.index, key_type, context);
+
+        unsafe {
+            let mut parameter_types = [self.pointer_type(), self.pointer_type()];
+            let get_type =
+                LLVMFunctionType(self.pointer_type(), parameter_types.as_mut_ptr(), 2, 0);
+            let get = self.dictionary_function("x_dictionary_get", get_type);
+
+            let mut args = [dictionary, key];
+            let slot = LLVMBuildCall2(
+                self.builder,
+                get_type,
+                get,
+                args.as_mut_ptr(),
+                2,
+                c"".as_ptr(),
+            );
+
+            // the slot is a pointer to the value that is null when the key is missing
+            let slot_type = Type::Pointer(Box::new(value_type.clone()));
+
+            self.build_optional_chain(slot, &slot_type, value_type, context, |this, slot| {
+                LLVMBuildLoad2(
+                    this.builder,
+                    this.llvm_type(value_type, context),
+                    slot,
+                    c"".as_ptr(),
+                )
+            })
+        }
+    }
+
+    /// Inserts the value for the key or, when the value is nil, removes the key.
+    fn build_dictionary_assignment(
+        &mut self,
+        target: &IndexExpression,
+        value: &Expression,
+        key_type: &Type,
+        value_type: &Type,
+        context: &Context,
+    ) {
+        let dictionary = self.visit_expression(&target.base, context);
+        let key = self.build_key(&target.index, key_type, context);
+
+        let optional_type = value_type.clone().or_nil();
+        let Type::Optional(wrapped) = &optional_type else {
+            unreachable!();
+        };
+
+        let value_llvm = self.visit_expression(value, context);
+        let ty = self.expression_type(value, context);
+        let optional = self.coerce(value_llvm, &ty, &optional_type, context);
+
+        unsafe {
+            let has_value = self.build_has_value(optional, wrapped);
+
+            let function = LLVMGetBasicBlockParent(LLVMGetInsertBlock(self.builder));
+            let insert_bb = LLVMAppendBasicBlockInContext(self.context, function, c"".as_ptr());
+            let remove_bb = LLVMAppendBasicBlockInContext(self.context, function, c"".as_ptr());
+            let merge_bb = LLVMAppendBasicBlockInContext(self.context, function, c"".as_ptr());
+
+            LLVMBuildCondBr(self.builder, has_value, insert_bb, remove_bb);
+
+            LLVMPositionBuilderAtEnd(self.builder, insert_bb);
+            // a dictionary of optionals stores the optional itself
+            let stored = if value_type == &optional_type {
+                optional
+            } else {
+                self.build_unwrap(optional, wrapped)
+            };
+            let value_pointer = self.build_temporary(stored);
+            self.build_dictionary_insert(dictionary, key, value_pointer);
+            LLVMBuildBr(self.builder, merge_bb);
+
+            LLVMPositionBuilderAtEnd(self.builder, remove_bb);
+            let mut parameter_types = [self.pointer_type(), self.pointer_type()];
+            let remove_type = LLVMFunctionType(
+                LLVMVoidTypeInContext(self.context),
+                parameter_types.as_mut_ptr(),
+                2,
+                0,
+            );
+            let remove = self.dictionary_function("x_dictionary_remove", remove_type);
+            let mut args = [dictionary, key];
+            LLVMBuildCall2(
+                self.builder,
+                remove_type,
+                remove,
+                args.as_mut_ptr(),
+                2,
+                c"".as_ptr(),
+            );
+            LLVMBuildBr(self.builder, merge_bb);
+
+            LLVMPositionBuilderAtEnd(self.builder, merge_bb);
+        }
+    }
+
+    fn build_dictionary_insert(
+        &mut self,
+        dictionary: LLVMValueRef,
+        key: LLVMValueRef,
+        value: LLVMValueRef,
+    ) {
+        unsafe {
+            let mut parameter_types = [self.pointer_type(); 3];
+            let insert_type = LLVMFunctionType(
+                LLVMVoidTypeInContext(self.context),
+                parameter_types.as_mut_ptr(),
+                3,
+                0,
+            );
+            let insert = self.dictionary_function("x_dictionary_insert", insert_type);
+
+            let mut args = [dictionary, key, value];
+            LLVMBuildCall2(
+                self.builder,
+                insert_type,
+                insert,
+                args.as_mut_ptr(),
+                3,
+                c"".as_ptr(),
+            );
+        }
+    }
+
+    fn build_dictionary_count(&mut self, dictionary: LLVMValueRef) -> LLVMValueRef {
+        unsafe {
+            let mut parameter_types = [self.pointer_type()];
+            let count_type = LLVMFunctionType(
+                LLVMInt64TypeInContext(self.context),
+                parameter_types.as_mut_ptr(),
+                1,
+                0,
+            );
+            let count = self.dictionary_function("x_dictionary_count", count_type);
+
+            let mut args = [dictionary];
+            LLVMBuildCall2(
+                self.builder,
+                count_type,
+                count,
+                args.as_mut_ptr(),
+                1,
+                c"count".as_ptr(),
+            )
+        }
+    }
+
+    /// Evaluates a key and returns a pointer to a copy of it, the runtime
+    /// copies keys and values as bytes.
+    fn build_key(&mut self, node: &Expression, key_type: &Type, context: &Context) -> LLVMValueRef {
+        let key = self.visit_expression(node, context);
+        let ty = self.expression_type(node, context);
+        let key = self.coerce(key, &ty, key_type, context);
+
+        self.build_temporary(key)
+    }
+
+    fn build_temporary(&mut self, value: LLVMValueRef) -> LLVMValueRef {
+        unsafe {
+            let memory = self.build_entry_alloca(LLVMTypeOf(value));
+            LLVMBuildStore(self.builder, value, memory);
+
+            memory
+        }
+    }
+
+    /// A function of the hash map runtime, calling one links the runtime with
+    /// the program.
+    fn dictionary_function(&mut self, name: &str, function_type: LLVMTypeRef) -> LLVMValueRef {
+        self.uses_runtime = true;
+        self.runtime_function(name, function_type)
+    }
+
+    /// The functions hashing a key and comparing two keys, both take pointers
+    /// to the keys. Strings use the functions of the runtime, integers, bools
+    /// and enums are hashed by their value and other types call their
+    /// `Hashable` methods.
+    fn key_functions(
+        &mut self,
+        key_type: &Type,
+        context: &Context,
+    ) -> (LLVMValueRef, LLVMValueRef) {
+        unsafe {
+            let mut parameter_types = [self.pointer_type(), self.pointer_type()];
+            let hash_type = LLVMFunctionType(
+                LLVMInt64TypeInContext(self.context),
+                parameter_types.as_mut_ptr(),
+                1,
+                0,
+            );
+            let equal_type = LLVMFunctionType(
+                LLVMInt32TypeInContext(self.context),
+                parameter_types.as_mut_ptr(),
+                2,
+                0,
+            );
+
+            // strings are compared by their characters, other pointers by address
+            if *key_type == Type::Pointer(Box::new(Type::Identifier("u8".to_string()))) {
+                return (
+                    self.dictionary_function("x_hash_string", hash_type),
+                    self.dictionary_function("x_equal_string", equal_type),
+                );
+            }
+
+            let hash_name = CString::new(format!("x.hash.{}", key_type)).unwrap();
+            let equal_name = CString::new(format!("x.equal.{}", key_type)).unwrap();
+
+            let hash = LLVMGetNamedFunction(self.module, hash_name.as_ptr());
+
+            if !hash.is_null() {
+                return (hash, LLVMGetNamedFunction(self.module, equal_name.as_ptr()));
+            }
+
+            let hash = LLVMAddFunction(self.module, hash_name.as_ptr(), hash_type);
+            let equal = LLVMAddFunction(self.module, equal_name.as_ptr(), equal_type);
+            LLVMSetLinkage(hash, LLVMLinkage::LLVMPrivateLinkage);
+            LLVMSetLinkage(equal, LLVMLinkage::LLVMPrivateLinkage);
+
+            let insert_block = LLVMGetInsertBlock(self.builder);
+            let key_llvm_type = self.llvm_type(key_type, context);
+            let int64 = LLVMInt64TypeInContext(self.context);
+            let int32 = LLVMInt32TypeInContext(self.context);
+
+            let entry = LLVMAppendBasicBlockInContext(self.context, hash, c"".as_ptr());
+            LLVMPositionBuilderAtEnd(self.builder, entry);
+
+            // other keys are integers, bools and enums compared by value
+            let structure = match key_type {
+                Type::Identifier(name) if context.fields(key_type).is_some() => Some(name),
+                _ => None,
+            };
+
+            let key = LLVMGetParam(hash, 0);
+            let hash_value = match structure {
+                Some(name) => {
+                    let method = &self.function_table[&format!("{}.hash", name)];
+                    let mut args = [key];
+                    LLVMBuildCall2(
+                        self.builder,
+                        method.function_type,
+                        method.function_ref,
+                        args.as_mut_ptr(),
+                        1,
+                        c"".as_ptr(),
+                    )
+                }
+                None => {
+                    let value = LLVMBuildLoad2(self.builder, key_llvm_type, key, c"".as_ptr());
+
+                    if key_type.is_pointer() {
+                        LLVMBuildPtrToInt(self.builder, value, int64, c"".as_ptr())
+                    } else {
+                        LLVMBuildZExt(self.builder, value, int64, c"".as_ptr())
+                    }
+                }
+            };
+            LLVMBuildRet(self.builder, hash_value);
+
+            let entry = LLVMAppendBasicBlockInContext(self.context, equal, c"".as_ptr());
+            LLVMPositionBuilderAtEnd(self.builder, entry);
+
+            let a = LLVMGetParam(equal, 0);
+            let b = LLVMGetParam(equal, 1);
+            let b_value = LLVMBuildLoad2(self.builder, key_llvm_type, b, c"".as_ptr());
+            let is_equal = match structure {
+                Some(name) => {
+                    let method = &self.function_table[&format!("{}.equals", name)];
+                    let mut args = [a, b_value];
+                    LLVMBuildCall2(
+                        self.builder,
+                        method.function_type,
+                        method.function_ref,
+                        args.as_mut_ptr(),
+                        2,
+                        c"".as_ptr(),
+                    )
+                }
+                None => {
+                    let a_value = LLVMBuildLoad2(self.builder, key_llvm_type, a, c"".as_ptr());
+                    LLVMBuildICmp(
+                        self.builder,
+                        LLVMIntPredicate::LLVMIntEQ,
+                        a_value,
+                        b_value,
+                        c"".as_ptr(),
+                    )
+                }
+            };
+            let is_equal = LLVMBuildZExt(self.builder, is_equal, int32, c"".as_ptr());
+            LLVMBuildRet(self.builder, is_equal);
+
+            LLVMPositionBuilderAtEnd(self.builder, insert_block);
+
+            (hash, equal)
+        }
+    }
+
+    /// The value of each branch is converted to the type `ty` of the if, which
+    /// can be a protocol the values of the branches conform to.
+    pub fn visit_if_expression(
+        &mut self,
+        node: &IfExpression,
+        ty: &Type,
+        context: &Context,
+    ) -> LLVMValueRef {
+        unsafe {
+            let mut condition = self.visit_expression(&node.condition, context);
+
+            // `if let` runs the then branch when the optional holds a value
+            let wrapped = match self.expression_type(&node.condition, context) {
+                Type::Optional(wrapped) if node.binding.is_some() => Some(*wrapped),
+                _ => None,
+            };
+            let optional = condition;
+
+            if let Some(wrapped) = &wrapped {
+                condition = self.build_has_value(optional, wrapped);
+            }
+
+            let function = LLVMGetBasicBlockParent(LLVMGetInsertBlock(self.builder));
+
+            let then_bb = LLVMAppendBasicBlockInContext(self.context, function, c"".as_ptr());
+            let else_bb = LLVMAppendBasicBlockInContext(self.context, function, c"".as_ptr());
+            let merge_bb = LLVMAppendBasicBlockInContext(self.context, function, c"".as_ptr());
+
+            LLVMBuildCondBr(self.builder, condition, then_bb, else_bb);
+
+            LLVMPositionBuilderAtEnd(self.builder, then_bb);
+            let outer_values = self.named_values.clone();
+
+            if let (Some(binding), Some(wrapped)) = (&node.binding, &wrapped) {
+                let value = self.build_unwrap(optional, wrapped);
+                self.named_values.insert(binding.name.clone(), value);
+            }
+
+            let then_value = self.visit_branch_value(&node.then_branch.code_block, ty, context);
+            let then_end = self.branch_to(merge_bb);
+            self.named_values = outer_values;
+
+            LLVMPositionBuilderAtEnd(self.builder, else_bb);
+            let else_value = match &node.else_clause {
+                Some(ElseClause::Else(container)) => {
+                    self.visit_branch_value(&container.code_block, ty, context)
+                }
+                Some(ElseClause::ElseIf(if_expression)) => {
+                    self.visit_if_expression(if_expression, ty, context)
+                }
+                None => ptr::null_mut(),
+            };
+            let else_end = self.branch_to(merge_bb);
+
+            LLVMPositionBuilderAtEnd(self.builder, merge_bb);
+
+            // a branch that diverged never reaches the merge block, the value of the
+            // other branch already dominates it and no phi is needed
+            match (then_end, else_end) {
+                (Some(mut then_end), Some(mut else_end)) => {
+                    if !self.has_value(then_value) || !self.has_value(else_value) {
+                        return ptr::null_mut();
+                    }
+
+                    let phi_type = self.llvm_type(ty, context);
+                    let phi = LLVMBuildPhi(self.builder, phi_type, c"".as_ptr());
+
+                    let mut then_value = then_value;
+                    let mut else_value = else_value;
+                    LLVMAddIncoming(phi, &mut then_value, &mut then_end, 1);
+                    LLVMAddIncoming(phi, &mut else_value, &mut else_end, 1);
+
+                    phi
+                }
+                (Some(_), None) => then_value,
+                (None, Some(_)) => else_value,
+                (None, None) => ptr::null_mut(),
+            }
+        }
+    }
+
+    pub fn visit_result_expression(
+        &mut self,
+        node: &ResultExpression,
+        ty: &Type,
+        context: &Context,
+    ) -> LLVMValueRef {
+        let Type::Result(value, error) = ty else {
+            panic!("Result built as a value of type {}", ty);
+        };
+
+        let payload_type = match node.variant {
+            ResultVariant::Ok => value,
+            ResultVariant::Err => error,
+        };
+
+        let payload = self.visit_expression(&node.value, context);
+        let payload = self.coerce(
+            payload,
+            &self.expression_type(&node.value, context),
+            payload_type,
+            context,
+        );
+
+        self.build_result(ty, node.variant, payload, context)
+    }
+
+    /// Continues with the value of a result or an optional, or returns its
+    /// error or nil from the current function.
+    pub fn visit_try_expression(
+        &mut self,
+        node: &TryExpression,
+        context: &Context,
+    ) -> LLVMValueRef {
+        let operand = self.visit_expression(&node.expression, context);
+        let operand_type = self.expression_type(&node.expression, context);
+        let return_type = self.return_type.clone();
+
+        unsafe {
+            let has_value = match &operand_type {
+                Type::Optional(wrapped) => self.build_has_value(operand, wrapped),
+                _ => LLVMBuildExtractValue(self.builder, operand, 0, c"".as_ptr()),
+            };
+
+            let function = LLVMGetBasicBlockParent(LLVMGetInsertBlock(self.builder));
+            let value_bb = LLVMAppendBasicBlockInContext(self.context, function, c"".as_ptr());
+            let return_bb = LLVMAppendBasicBlockInContext(self.context, function, c"".as_ptr());
+
+            LLVMBuildCondBr(self.builder, has_value, value_bb, return_bb);
+
+            LLVMPositionBuilderAtEnd(self.builder, return_bb);
+
+            let returned = match (&operand_type, &return_type) {
+                (Type::Result(_, error), Type::Result(_, returned_error)) => {
+                    let payload = self.build_result_payload(operand, ResultVariant::Err);
+                    let payload = self.coerce(payload, error, returned_error, context);
+
+                    self.build_result(&return_type, ResultVariant::Err, payload, context)
+                }
+                _ => self.build_nil(&return_type, context),
+            };
+            self.build_return(returned);
+
+            LLVMPositionBuilderAtEnd(self.builder, value_bb);
+
+            match &operand_type {
+                Type::Optional(wrapped) => self.build_unwrap(operand, wrapped),
+                _ => self.build_result_payload(operand, ResultVariant::Ok),
+            }
+        }
+    }
+
+    /// Tests the arms in order, the first whose pattern matches runs. The type
+    /// checker made sure that one always does.
+    pub fn visit_match_expression(
+        &mut self,
+        node: &MatchExpression,
+        ty: &Type,
+        context: &Context,
+    ) -> LLVMValueRef {
+        unsafe {
+            let subject = self.visit_expression(&node.subject, context);
+            let subject_type = self.expression_type(&node.subject, context);
+            let function = LLVMGetBasicBlockParent(LLVMGetInsertBlock(self.builder));
+
+            let merge_bb = LLVMAppendBasicBlockInContext(self.context, function, c"".as_ptr());
+
+            let mut values = Vec::new();
+            let mut blocks = Vec::new();
+
+            for arm in &node.arms {
+                let arm_bb = LLVMAppendBasicBlockInContext(self.context, function, c"".as_ptr());
+                let next_bb = LLVMAppendBasicBlockInContext(self.context, function, c"".as_ptr());
+
+                match self.build_pattern_test(&arm.pattern, subject, &subject_type, context) {
+                    Some(condition) => LLVMBuildCondBr(self.builder, condition, arm_bb, next_bb),
+                    None => LLVMBuildBr(self.builder, arm_bb),
+                };
+
+                LLVMPositionBuilderAtEnd(self.builder, arm_bb);
+                let outer_values = self.named_values.clone();
+
+                match &arm.pattern {
+                    Pattern::Binding(name) => {
+                        let value = match &subject_type {
+                            Type::Optional(wrapped) => self.build_unwrap(subject, wrapped),
+                            _ => subject,
+                        };
+
+                        self.named_values.insert(name.name.clone(), value);
+                    }
+                    Pattern::Result(variant, Some(name)) => {
+                        let value = self.build_result_payload(subject, *variant);
+                        self.named_values.insert(name.name.clone(), value);
+                    }
+                    _ => {}
+                }
+
+                let value = self.visit_branch_value(&arm.body.code_block, ty, context);
+
+                if let Some(end) = self.branch_to(merge_bb) {
+                    values.push(value);
+                    blocks.push(end);
+                }
+
+                self.named_values = outer_values;
+                LLVMPositionBuilderAtEnd(self.builder, next_bb);
+            }
+
+            // no arm matched, which the exhaustiveness check rules out
+            LLVMBuildUnreachable(self.builder);
+
+            LLVMPositionBuilderAtEnd(self.builder, merge_bb);
+
+            if values.is_empty() || !values.iter().all(|value| self.has_value(*value)) {
+                return ptr::null_mut();
+            }
+
+            let phi = LLVMBuildPhi(self.builder, self.llvm_type(ty, context), c"".as_ptr());
+            LLVMAddIncoming(
+                phi,
+                values.as_mut_ptr(),
+                blocks.as_mut_ptr(),
+                values.len() as u32,
+            );
+
+            phi
+        }
+    }
+
+    /// The condition under which a pattern matches, none when it always does.
+    fn build_pattern_test(
+        &mut self,
+        pattern: &Pattern,
+        subject: LLVMValueRef,
+        subject_type: &Type,
+        context: &Context,
+    ) -> Option<LLVMValueRef> {
+        unsafe {
+            match (pattern, subject_type) {
+                (Pattern::Binding(_), Type::Optional(wrapped)) => {
+                    Some(self.build_has_value(subject, wrapped))
+                }
+                (Pattern::Wildcard | Pattern::Binding(_), _) => None,
+                (Pattern::Nil, Type::Optional(wrapped)) => {
+                    let has_value = self.build_has_value(subject, wrapped);
+                    Some(LLVMBuildNot(self.builder, has_value, c"".as_ptr()))
+                }
+                (Pattern::Result(variant, _), Type::Result(..)) => {
+                    let is_ok = LLVMBuildExtractValue(self.builder, subject, 0, c"".as_ptr());
+
+                    match variant {
+                        ResultVariant::Ok => Some(is_ok),
+                        ResultVariant::Err => Some(LLVMBuildNot(self.builder, is_ok, c"".as_ptr())),
+                    }
+                }
+                (Pattern::EnumCase(case), Type::Identifier(name) | Type::Generic(name, _)) => {
+                    let Some(TypeDefinitionKind::Enumeration(cases)) =
+                        context.lookup_type(name).map(|d| &d.kind)
+                    else {
+                        panic!("Type {:?} has no case {:?}", name, case.name);
+                    };
+
+                    let index = cases.iter().position(|c| *c == case.name).unwrap();
+                    let case_value = LLVMConstInt(self.enum_type(), index as u64, 0);
+
+                    Some(self.build_comparison(
+                        &BinaryOperator::Equal,
+                        subject_type,
+                        subject,
+                        case_value,
+                    ))
+                }
+                (Pattern::Literal(literal), _) => {
+                    let value = self.visit_expression(literal, context);
+
+                    Some(self.build_comparison(
+                        &BinaryOperator::Equal,
+                        subject_type,
+                        subject,
+                        value,
+                    ))
+                }
+                _ => panic!("Pattern {:?} cannot match a {}", pattern, subject_type),
+            }
+        }
+    }
+
+    /// Emits the items of a nested block and returns the value of its tail
+    /// expression, or null when the block does not produce a value. Variables
+    /// declared inside the block are not visible once it ends.
+    pub fn visit_code_block_value(&mut self, node: &CodeBlock, context: &Context) -> LLVMValueRef {
+        let outer_values = self.named_values.clone();
+        let mut tail_value = ptr::null_mut();
+
+        for (i, item) in node.items.iter().enumerate() {
+            // anything following a return is unreachable and is not emitted
+            if self.is_terminated() {
+                break;
+            }
+
+            match item {
+                CodeBlockItem::Expression(expr) if i == node.items.len() - 1 => {
+                    tail_value = self.visit_expression(expr, context);
+                }
+                _ => self.visit_code_block_item(item, context),
+            }
+        }
+
+        self.named_values = outer_values;
+
+        tail_value
+    }
+
+    /// Branches to `target` unless the current block already ended with a
+    /// terminator, returns the block that falls through to `target` if any.
+    fn branch_to(&mut self, target: LLVMBasicBlockRef) -> Option<LLVMBasicBlockRef> {
+        if self.is_terminated() {
+            return None;
+        }
+
+        unsafe {
+            let current_block = LLVMGetInsertBlock(self.builder);
+            LLVMBuildBr(self.builder, target);
+
+            Some(current_block)
+        }
+    }
+
+    /// The value of a branch of an if or a match, converted to the type `ty`
+    /// of the whole expression.
+    fn visit_branch_value(
+        &mut self,
+        node: &CodeBlock,
+        ty: &Type,
+        context: &Context,
+    ) -> LLVMValueRef {
+        let value = self.visit_code_block_value(node, context);
+
+        match node.items.last() {
+            Some(CodeBlockItem::Expression(tail))
+                if self.has_value(value) && !self.is_terminated() =>
+            {
+                let from = self.expression_type(tail, context);
+                self.coerce(value, &from, ty, context)
+            }
+            _ => value,
+        }
+    }
+
+    fn is_terminated(&self) -> bool {
+        unsafe { !LLVMGetBasicBlockTerminator(LLVMGetInsertBlock(self.builder)).is_null() }
+    }
+
+    fn has_value(&self, value: LLVMValueRef) -> bool {
+        unsafe {
+            !value.is_null() && LLVMGetTypeKind(LLVMTypeOf(value)) != LLVMTypeKind::LLVMVoidTypeKind
+        }
+    }
+
+    pub fn visit_function_call(
+        &mut self,
+        node: &FunctionCallExpression,
+        context: &Context,
+    ) -> LLVMValueRef {
+        if let Some(function) = context.indirect_call(node) {
+            let function = function.clone();
+            let closure = self.visit_identifier(&node.function, context);
+            return self.build_closure_call(closure, &function, &node.arguments, context);
+        }
+
+        // methods of the current type can be called without a receiver
+        if let Some(type_name) = self.current_type.clone() {
+            let self_type = self.resolve(&context.lookup_type(&type_name).unwrap().declared_type());
+            let method = context.lookup_method(&self_type, &node.function.name);
+
+            if let Some(method) = method {
+                let receiver = self.named_values["self"];
+
+                // in a default implementation self is a value of the protocol
+                if context.is_protocol(&type_name) {
+                    return self.build_dynamic_call(
+                        &type_name,
+                        receiver,
+                        &method,
+                        &node.arguments,
+                        context,
+                    );
+                }
+
+                return self.build_method_call(
+                    &self_type,
+                    receiver,
+                    &method,
+                    &node.arguments,
+                    context,
+                );
+            }
+        }
+
+        let signature = match context.lookup(node.function.name.clone()) {
+            Some(Symbol {
+                symbol_type: Type::Function(signature),
+                ..
+            }) => signature,
+            _ => return self.visit_initializer(node, context),
+        };
+
+        if !signature.generic_parameters.is_empty() {
+            return self.visit_generic_call(node, &signature, context);
+        }
+
+        let args = self.build_arguments(&signature, &node.arguments, context);
+        self.build_call(&node.function.name, &signature, args, context)
+    }
+
+    /// Calls the instance of a generic function for the type arguments the
+    /// type checker inferred.
+    fn visit_generic_call(
+        &mut self,
+        node: &FunctionCallExpression,
+        signature: &TypeFunction,
+        context: &Context,
+    ) -> LLVMValueRef {
+        let arguments = context
+            .generic_arguments(node)
+            .iter()
+            .map(|argument| self.resolve(argument))
+            .collect::<Vec<_>>();
+
+        let substitution = signature.substitution(&arguments);
+        let function = signature.substitute(&substitution);
+        let name = format!(
+            "{}<{}>",
+            node.function.name,
+            arguments
+                .iter()
+                .map(Type::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        self.instantiate(
+            Instance {
+                name: name.clone(),
+                owner: None,
+                function: node.function.name.clone(),
+                substitution,
+            },
+            &function,
+            context,
+        );
+
+        let args = self.build_arguments(&function, &node.arguments, context);
+        self.build_call(&name, &function, args, context)
+    }
+
+    /// Calls a function of the function table, the arguments of C functions
+    /// are lowered to the calling convention of the target first.
+    fn build_call(
+        &mut self,
+        name: &str,
+        function: &TypeFunction,
+        mut args: Vec<LLVMValueRef>,
+        context: &Context,
+    ) -> LLVMValueRef {
+        let function_ref = &self.function_table[name];
+        let function_type = function_ref.function_type;
+        let callee = function_ref.function_ref;
+
+        let Some(abi) = function_ref.abi.clone() else {
+            unsafe {
+                return LLVMBuildCall2(
+                    self.builder,
+                    function_type,
+                    callee,
+                    args.as_mut_ptr(),
+                    args.len() as u32,
+                    c"".as_ptr(),
+                );
+            }
+        };
+
+        unsafe {
+            let return_type = self.llvm_type(&function.return_type, context);
+            let mut lowered = Vec::new();
+            let mut result = ptr::null_mut();
+
+            if let PassMode::Indirect { .. } = abi.return_mode {
+                result = self.build_entry_alloca(return_type);
+                lowered.push(result);
+            }
+
+            // variadic arguments have no pass mode, they are promoted scalars
+            for (i, value) in args.into_iter().enumerate() {
+                match abi.parameters.get(i) {
+                    None | Some(PassMode::Direct) => lowered.push(value),
+                    Some(PassMode::Coerced(pieces)) => {
+                        let memory = self.build_spill(value);
+
+                        for (k, piece) in pieces.iter().enumerate() {
+                            let address = self.build_byte_offset(memory, k as u64 * 8);
+                            lowered.push(LLVMBuildLoad2(
+                                self.builder,
+                                self.abi_type(piece),
+                                address,
+                                c"".as_ptr(),
+                            ));
+                        }
+                    }
+                    Some(PassMode::Indirect { .. }) => {
+                        let copy = self.build_entry_alloca(LLVMTypeOf(value));
+                        LLVMBuildStore(self.builder, value, copy);
+                        lowered.push(copy);
+                    }
+                }
+            }
+
+            let call = LLVMBuildCall2(
+                self.builder,
+                function_type,
+                callee,
+                lowered.as_mut_ptr(),
+                lowered.len() as u32,
+                c"".as_ptr(),
+            );
+
+            for (index, attribute) in self.extern_attributes(function, &abi, context) {
+                LLVMAddCallSiteAttribute(call, index, attribute);
+            }
+
+            match abi.return_mode {
+                PassMode::Direct => call,
+                PassMode::Coerced(_) => {
+                    let memory = self.build_spill(call);
+                    LLVMBuildLoad2(self.builder, return_type, memory, c"".as_ptr())
+                }
+                PassMode::Indirect { .. } => {
+                    LLVMBuildLoad2(self.builder, return_type, result, c"".as_ptr())
+                }
+            }
+        }
+    }
+
+    /// Stores a value to stack memory large enough for every coerced view of
+    /// it, the largest being a homogeneous aggregate of four doubles.
+    fn build_spill(&mut self, value: LLVMValueRef) -> LLVMValueRef {
+        unsafe {
+            let memory =
+                self.build_entry_alloca(LLVMArrayType2(LLVMInt64TypeInContext(self.context), 4));
+            LLVMBuildStore(self.builder, value, memory);
+
+            memory
+        }
+    }
+
+    fn build_byte_offset(&mut self, pointer: LLVMValueRef, offset: u64) -> LLVMValueRef {
+        unsafe {
+            let mut indices = [LLVMConstInt(
+                LLVMInt64TypeInContext(self.context),
+                offset,
+                0,
+            )];
+
+            LLVMBuildGEP2(
+                self.builder,
+                LLVMInt8TypeInContext(self.context),
+                pointer,
+                indices.as_mut_ptr(),
+                1,
+                c"".as_ptr(),
+            )
+        }
+    }
+
+    /// Builds a value of a type from its fields given in declaration order.
+    pub fn visit_initializer(
+        &mut self,
+        node: &FunctionCallExpression,
+        context: &Context,
+    ) -> LLVMValueRef {
+        let type_name = &node.function.name;
+
+        let ty = match context.generic_arguments(node) {
+            [] => Type::Identifier(type_name.clone()),
+            arguments => Type::Generic(
+                type_name.clone(),
+                arguments.iter().map(|a| self.resolve(a)).collect(),
+            ),
+        };
+
+        let Some(fields) = context.fields(&ty) else {
+            panic!("Function {:?} not registered", type_name);
+        };
+
+        unsafe {
+            let mut value = LLVMGetUndef(self.llvm_type(&ty, context));
+
+            for (i, (field, argument)) in fields.iter().zip(&node.arguments.arguments).enumerate() {
+                let field_value = self.visit_expression(&argument.expression, context);
+                let argument_type = self.expression_type(&argument.expression, context);
+                let field_value = self.coerce(field_value, &argument_type, &field.ty, context);
+
+                value =
+                    LLVMBuildInsertValue(self.builder, value, field_value, i as u32, c"".as_ptr());
+            }
+
+            value
+        }
+    }
+
+    pub fn visit_method_call(
+        &mut self,
+        node: &MethodCallExpression,
+        context: &Context,
+    ) -> LLVMValueRef {
+        // selfless functions are called on the type, as in `LibMath.add(1, 2)`
+        if let Some(type_name) = self.static_type_name(&node.receiver, context) {
+            let method = context
+                .lookup_type(&type_name)
+                .and_then(|definition| definition.method(&node.method.name))
+                .unwrap();
+
+            let name = format!("{}.{}", type_name, method.name);
+            let args = self.build_arguments(&method.function, &node.arguments, context);
+
+            return self.build_call(&name, &method.function, args, context);
+        }
+
+        let receiver = self.visit_expression(&node.receiver, context);
+
+        // `?.` calls the method on the value of the optional if there is one
+        if let (true, Type::Optional(wrapped)) =
+            (node.optional, self.expression_type(&node.receiver, context))
+        {
+            let Some(method) = context.lookup_method(&wrapped, &node.method.name) else {
+                let function = self.function_field(&wrapped, &node.method.name, context);
+                let return_type = function.return_type.as_ref().clone();
+
+                return self.build_optional_chain(
+                    receiver,
+                    &wrapped,
+                    &return_type,
+                    context,
+                    |this, receiver| {
+                        let closure =
+                            this.build_field(receiver, &wrapped, &node.method.name, context);
+                        this.build_closure_call(closure, &function, &node.arguments, context)
+                    },
+                );
+            };
+            let return_type = method.function.return_type.as_ref().clone();
+
+            return self.build_optional_chain(
+                receiver,
+                &wrapped,
+                &return_type,
+                context,
+                |this, receiver| this.build_method(&wrapped, receiver, &method, node, context),
+            );
+        }
+
+        let receiver_type = self.expression_type(&node.receiver, context);
+
+        // a field holding a closure is called like a method
+        let Some(method) = context.lookup_method(&receiver_type, &node.method.name) else {
+            let function = self.function_field(&receiver_type, &node.method.name, context);
+            let closure = self.build_field(receiver, &receiver_type, &node.method.name, context);
+            return self.build_closure_call(closure, &function, &node.arguments, context);
+        };
+
+        self.build_method(&receiver_type, receiver, &method, node, context)
+    }
+
+    /// Calls a method on a value, protocol methods are dispatched dynamically.
+    fn build_method(
+        &mut self,
+        receiver_type: &Type,
+        receiver: LLVMValueRef,
+        method: &Method,
+        node: &MethodCallExpression,
+        context: &Context,
+    ) -> LLVMValueRef {
+        if let Type::Identifier(type_name) = receiver_type {
+            if context.is_protocol(type_name) {
+                return self.build_dynamic_call(
+                    type_name,
+                    receiver,
+                    method,
+                    &node.arguments,
+                    context,
+                );
+            }
+        }
+
+        self.build_method_call(receiver_type, receiver, method, &node.arguments, context)
+    }
+
+    /// Calls the implementation of a method of a concrete type directly, the
+    /// methods of a generic type are instantiated for its type arguments.
+    fn build_method_call(
+        &mut self,
+        receiver_type: &Type,
+        receiver: LLVMValueRef,
+        method: &Method,
+        arguments: &ArgumentList,
+        context: &Context,
+    ) -> LLVMValueRef {
+        let name = format!("{}.{}", receiver_type, method.name);
+
+        if let Type::Generic(type_name, type_arguments) = receiver_type {
+            let definition = context.lookup_type(type_name).unwrap();
+
+            self.instantiate(
+                Instance {
+                    name: name.clone(),
+                    owner: Some(type_name.clone()),
+                    function: method.name.clone(),
+                    substitution: definition.substitution(type_arguments),
+                },
+                &method.function,
+                context,
+            );
+        }
+
+        let function_ref = &self.function_table[&name];
+        let function_type = function_ref.function_type;
+        let function = function_ref.function_ref;
+
+        unsafe {
+            let self_pointer = self.build_entry_alloca(LLVMTypeOf(receiver));
+            LLVMBuildStore(self.builder, receiver, self_pointer);
+
+            let mut args = vec![self_pointer];
+            args.extend(self.build_arguments(&method.function, arguments, context));
+
+            LLVMBuildCall2(
+                self.builder,
+                function_type,
+                function,
+                args.as_mut_ptr(),
+                args.len() as u32,
+                c"".as_ptr(),
+            )
+        }
+    }
+
+    /// Calls a protocol method on an existential through the witness table of
+    /// the concrete type it holds.
+    fn build_dynamic_call(
+        &mut self,
+        protocol_name: &str,
+        existential: LLVMValueRef,
+        method: &Method,
+        arguments: &ArgumentList,
+        context: &Context,
+    ) -> LLVMValueRef {
+        let protocol = context.lookup_type(protocol_name).unwrap();
+        let index = protocol
+            .methods
+            .iter()
+            .position(|requirement| requirement.name == method.name)
+            .unwrap();
+
+        unsafe {
+            let data = LLVMBuildExtractValue(self.builder, existential, 0, c"".as_ptr());
+            let witness_table = LLVMBuildExtractValue(self.builder, existential, 1, c"".as_ptr());
+
+            let mut indices = [LLVMConstInt(
+                LLVMInt32TypeInContext(self.context),
+                index as u64,
+                0,
+            )];
+            let slot = LLVMBuildGEP2(
+                self.builder,
+                self.pointer_type(),
+                witness_table,
+                indices.as_mut_ptr(),
+                1,
+                c"".as_ptr(),
+            );
+            let function = LLVMBuildLoad2(self.builder, self.pointer_type(), slot, c"".as_ptr());
+
+            let mut parameter_types = vec![self.pointer_type()];
+            for parameter in &method.function.parameters {
+                parameter_types.push(self.llvm_type(&parameter.ty, context));
+            }
+
+            let function_type = LLVMFunctionType(
+                self.llvm_type(&method.function.return_type, context),
+                parameter_types.as_mut_ptr(),
+                parameter_types.len() as u32,
+                0,
+            );
+
+            let mut args = vec![data];
+            args.extend(self.build_arguments(&method.function, arguments, context));
+
+            LLVMBuildCall2(
+                self.builder,
+                function_type,
+                function,
+                args.as_mut_ptr(),
+                args.len() as u32,
+                c"".as_ptr(),
+            )
+        }
+    }
+
+    fn build_arguments(
+        &mut self,
+        function: &TypeFunction,
+        node: &ArgumentList,
+        context: &Context,
+    ) -> Vec<LLVMValueRef> {
+        let mut args = function
+            .parameters
+            .iter()
+            .zip(&node.arguments)
+            .map(|(parameter, argument)| {
+                let value = self.visit_expression(&argument.expression, context);
+                let argument_type = self.expression_type(&argument.expression, context);
+                self.coerce(value, &argument_type, &parameter.ty, context)
+            })
+            .collect::<Vec<_>>();
+
+        for argument in node.arguments.iter().skip(function.parameters.len()) {
+            let value = self.visit_expression(&argument.expression, context);
+            let argument_type = self.expression_type(&argument.expression, context);
+            args.push(self.promote_variadic(value, &argument_type));
+        }
+
+        args
+    }
+
+    /// Applies the C default argument promotions to an extra argument of a
+    /// variadic function.
+    fn promote_variadic(&mut self, value: LLVMValueRef, ty: &Type) -> LLVMValueRef {
+        let Type::Identifier(name) = ty else {
+            return value;
+        };
+
+        unsafe {
+            let int32 = LLVMInt32TypeInContext(self.context);
+
+            match name.as_str() {
+                "f32" => LLVMBuildFPExt(
+                    self.builder,
+                    value,
+                    LLVMDoubleTypeInContext(self.context),
+                    c"".as_ptr(),
+                ),
+                "bool" | "u8" | "u16" => LLVMBuildZExt(self.builder, value, int32, c"".as_ptr()),
+                "i8" | "i16" => LLVMBuildSExt(self.builder, value, int32, c"".as_ptr()),
+                _ => value,
+            }
+        }
+    }
+
+    pub fn visit_member_access(
+        &mut self,
+        node: &MemberAccessExpression,
+        context: &Context,
+    ) -> LLVMValueRef {
+        unsafe {
+            if let Some(type_name) = self.static_type_name(&node.base, context) {
+                let Some(TypeDefinitionKind::Enumeration(cases)) =
+                    context.lookup_type(&type_name).map(|d| &d.kind)
+                else {
+                    panic!("Type {:?} has no case {:?}", type_name, node.member.name);
+                };
+
+                let index = cases
+                    .iter()
+                    .position(|case| *case == node.member.name)
+                    .unwrap();
+
+                return LLVMConstInt(self.enum_type(), index as u64, 0);
+            }
+
+            let base = self.visit_expression(&node.base, context);
+
+            // `?.` reads the field of the value of the optional if there is one
+            if let (true, Type::Optional(wrapped)) =
+                (node.optional, self.expression_type(&node.base, context))
+            {
+                let field = self.field(&wrapped, &node.member.name, context);
+
+                return self.build_optional_chain(
+                    base,
+                    &wrapped,
+                    &field.ty,
+                    context,
+                    |this, base| this.build_field(base, &wrapped, &node.member.name, context),
+                );
+            }
+
+            let base_type = self.expression_type(&node.base, context);
+
+            self.build_field(base, &base_type, &node.member.name, context)
+        }
+    }
+
+    fn function_field(&self, ty: &Type, name: &str, context: &Context) -> TypeFunction {
+        match self.field(ty, name, context).ty {
+            Type::Function(function) => function,
+            ty => panic!("Field {:?} of type {} is called as a method", name, ty),
+        }
+    }
+
+    fn field(&self, ty: &Type, name: &str, context: &Context) -> Field {
+        let Some(fields) = context.fields(ty) else {
+            panic!("Type {} has no field {:?}", ty, name);
+        };
+
+        fields.into_iter().find(|field| field.name == name).unwrap()
+    }
+
+    fn build_field(
+        &mut self,
+        base: LLVMValueRef,
+        ty: &Type,
+        name: &str,
+        context: &Context,
+    ) -> LLVMValueRef {
+        // the count of a dictionary is kept by the runtime
+        if let Type::Dictionary(..) = self.resolve(ty) {
+            return self.build_dictionary_count(base);
+        }
+
+        let Some(fields) = context.fields(ty) else {
+            panic!("Type {} has no field {:?}", ty, name);
+        };
+
+        let index = fields.iter().position(|field| field.name == name).unwrap();
+        let name = CString::new(name).unwrap();
+
+        unsafe { LLVMBuildExtractValue(self.builder, base, index as u32, name.as_ptr()) }
+    }
+
+    /// The type named by the base of a member access when it is not a value,
+    /// as in `Color.red`.
+    fn static_type_name(&self, node: &Expression, context: &Context) -> Option<String> {
+        let Expression::Identifier(identifier) = node else {
+            return None;
+        };
+
+        if self.named_values.contains_key(&identifier.name) {
+            return None;
+        }
+
+        context
+            .lookup_type(&identifier.name)
+            .map(|definition| definition.name.clone())
+    }
+
+    /// Converts a value to the type it is used as, concrete values used as a
+    /// protocol are wrapped into an existential and values used as an optional
+    /// into the optional.
+    fn coerce(
+        &mut self,
+        value: LLVMValueRef,
+        from: &Type,
+        to: &Type,
+        context: &Context,
+    ) -> LLVMValueRef {
+        match (&self.resolve(from), &self.resolve(to)) {
+            (Type::Identifier(concrete), Type::Identifier(protocol))
+                if concrete != protocol && context.is_protocol(protocol) =>
+            {
+                self.build_existential(value, concrete, protocol)
+            }
+            (from, Type::Optional(wrapped)) if !matches!(from, Type::Optional(_)) => {
+                let value = self.coerce(value, from, wrapped, context);
+                self.build_some(value, wrapped, context)
+            }
+            _ => value,
+        }
+    }
+
+    /// Builds the (data pointer, witness table pointer) pair of an existential.
+    /// The value is copied to the frame of the current function when no value
+    /// of the function can outlive it, and to the heap otherwise.
+    fn build_existential(
+        &mut self,
+        value: LLVMValueRef,
+        concrete: &str,
+        protocol: &str,
+    ) -> LLVMValueRef {
+        let witness_table = self.witness_tables[&format!("{}.{}", concrete, protocol)];
+
+        unsafe {
+            // there are no loops, each existential is built once per call
+            let data = if self.frame_existentials {
+                self.build_entry_alloca(LLVMTypeOf(value))
+            } else {
+                self.build_malloc(LLVMTypeOf(value))
+            };
+            LLVMBuildStore(self.builder, value, data);
+
+            let mut existential = LLVMGetUndef(self.existential_type());
+            existential = LLVMBuildInsertValue(self.builder, existential, data, 0, c"".as_ptr());
+            existential =
+                LLVMBuildInsertValue(self.builder, existential, witness_table, 1, c"".as_ptr());
+
+            existential
+        }
+    }
+
+    /// Whether a value of the type may refer to an existential, directly or
+    /// through the elements, fields or captures it holds.
+    fn holds_existentials(&self, ty: &Type, visited: &mut Vec<Type>, context: &Context) -> bool {
+        let ty = self.resolve(ty);
+
+        // recursive structures are checked once
+        if visited.contains(&ty) {
+            return false;
+        }
+        visited.push(ty.clone());
+
+        match &ty {
+            Type::Void => false,
+            Type::Function(_) => true,
+            Type::Identifier(name) if context.is_protocol(name) => true,
+            Type::Pointer(ty) | Type::Optional(ty) | Type::Array(ty) => {
+                self.holds_existentials(ty, visited, context)
+            }
+            Type::Result(first, second) | Type::Dictionary(first, second) => {
+                self.holds_existentials(first, visited, context)
+                    || self.holds_existentials(second, visited, context)
+            }
+            Type::Tuple(elements) => elements
+                .iter()
+                .any(|element| self.holds_existentials(element, visited, context)),
+            Type::Identifier(_) | Type::Generic(..) => context
+                .fields(&ty)
+                .unwrap_or_default()
+                .iter()
+                .any(|field| self.holds_existentials(&field.ty, visited, context)),
+        }
+    }
+
+    fn build_malloc(&mut self, ty: LLVMTypeRef) -> LLVMValueRef {
+        unsafe {
+            let mut parameter_types = [LLVMInt64TypeInContext(self.context)];
+            let malloc_type =
+                LLVMFunctionType(self.pointer_type(), parameter_types.as_mut_ptr(), 1, 0);
+            let malloc = self.runtime_function("malloc", malloc_type);
+
+            let mut args = [LLVMSizeOf(ty)];
+
+            LLVMBuildCall2(
+                self.builder,
+                malloc_type,
+                malloc,
+                args.as_mut_ptr(),
+                1,
+                c"".as_ptr(),
+            )
+        }
+    }
+
+    /// A function of the C library the generated code calls, declared the first
+    /// time it is needed.
+    fn runtime_function(&mut self, name: &str, function_type: LLVMTypeRef) -> LLVMValueRef {
+        let llvm_name = CString::new(name).unwrap();
+
+        unsafe {
+            let function = LLVMGetNamedFunction(self.module, llvm_name.as_ptr());
+
+            if !function.is_null() {
+                return function;
+            }
+
+            LLVMAddFunction(self.module, llvm_name.as_ptr(), function_type)
+        }
+    }
+
+    /// Allocates stack memory in the entry block of the current function so that
+    /// allocations are not repeated when the surrounding code runs many times.
+    fn build_entry_alloca(&mut self, ty: LLVMTypeRef) -> LLVMValueRef {
+        unsafe {
+            let function = LLVMGetBasicBlockParent(LLVMGetInsertBlock(self.builder));
+            let entry = LLVMGetEntryBasicBlock(function);
+            let builder = LLVMCreateBuilderInContext(self.context);
+            let first = LLVMGetFirstInstruction(entry);
+
+            if first.is_null() {
+                LLVMPositionBuilderAtEnd(builder, entry);
+            } else {
+                LLVMPositionBuilderBefore(builder, first);
+            }
+
+            let alloca = LLVMBuildAlloca(builder, ty, c"".as_ptr());
+            LLVMDisposeBuilder(builder);
+
+            alloca
+        }
+    }
+
+    pub fn llvm_type(&self, ty: &Type, context: &Context) -> LLVMTypeRef {
+        unsafe {
+            match &self.resolve(ty) {
+                Type::Void => LLVMVoidTypeInContext(self.context),
+                Type::Identifier(name) => match name.as_str() {
+                    "f64" => LLVMDoubleTypeInContext(self.context),
+                    "f32" => LLVMFloatTypeInContext(self.context),
+                    "bool" => LLVMInt1TypeInContext(self.context),
+                    "i8" | "u8" => LLVMInt8TypeInContext(self.context),
+                    "i16" | "u16" => LLVMInt16TypeInContext(self.context),
+                    "i32" | "u32" => LLVMInt32TypeInContext(self.context),
+                    "i64" | "u64" => LLVMInt64TypeInContext(self.context),
+                    _ => match context.lookup_type(name).map(|d| &d.kind) {
+                        Some(TypeDefinitionKind::Structure(_)) => self.struct_types[name],
+                        Some(TypeDefinitionKind::Enumeration(_)) => self.enum_type(),
+                        Some(TypeDefinitionKind::Protocol) => self.existential_type(),
+                        _ => panic!("Unknown type {:?}", name),
+                    },
+                },
+                Type::Function(_) => self.closure_type(),
+                Type::Pointer(_) => self.pointer_type(),
+                Type::Optional(wrapped) if wrapped.is_pointer() => self.pointer_type(),
+                Type::Optional(wrapped) => {
+                    let mut fields = [
+                        LLVMInt1TypeInContext(self.context),
+                        self.llvm_type(wrapped, context),
+                    ];
+                    LLVMStructTypeInContext(self.context, fields.as_mut_ptr(), 2, 0)
+                }
+                Type::Result(value, error) => {
+                    let mut fields = [
+                        LLVMInt1TypeInContext(self.context),
+                        self.llvm_type(value, context),
+                        self.llvm_type(error, context),
+                    ];
+                    LLVMStructTypeInContext(self.context, fields.as_mut_ptr(), 3, 0)
+                }
+                Type::Dictionary(..) => self.pointer_type(),
+                Type::Tuple(elements) => {
+                    let mut fields = elements
+                        .iter()
+                        .map(|element| self.llvm_type(element, context))
+                        .collect::<Vec<_>>();
+                    LLVMStructTypeInContext(
+                        self.context,
+                        fields.as_mut_ptr(),
+                        fields.len() as u32,
+                        0,
+                    )
+                }
+                // the count and a pointer to the elements
+                Type::Array(_) => {
+                    let mut fields = [LLVMInt64TypeInContext(self.context), self.pointer_type()];
+                    LLVMStructTypeInContext(self.context, fields.as_mut_ptr(), 2, 0)
+                }
+                // instances of generic types are literal structs of their fields
+                ty @ Type::Generic(name, _) => match context.fields(ty) {
+                    Some(fields) => {
+                        let mut fields = fields
+                            .iter()
+                            .map(|field| self.llvm_type(&field.ty, context))
+                            .collect::<Vec<_>>();
+                        LLVMStructTypeInContext(
+                            self.context,
+                            fields.as_mut_ptr(),
+                            fields.len() as u32,
+                            0,
+                        )
+                    }
+                    None if context.lookup_type(name).is_some() => self.enum_type(),
+                    None => panic!("Unknown type {:?}", name),
+                },
+            }
+        }
+    }
+
+    /// A closure is a pointer to its function and one to its environment.
+    fn closure_type(&self) -> LLVMTypeRef {
+        unsafe {
+            let mut fields = [self.pointer_type(), self.pointer_type()];
+            LLVMStructTypeInContext(self.context, fields.as_mut_ptr(), 2, 0)
+        }
+    }
+
+    fn pointer_type(&self) -> LLVMTypeRef {
+        unsafe { LLVMPointerTypeInContext(self.context, 0) }
+    }
+
+    /// Enums without payloads are represented by the index of their case.
+    fn enum_type(&self) -> LLVMTypeRef {
+        unsafe { LLVMInt32TypeInContext(self.context) }
+    }
+
+    /// A protocol value is a pointer to the data of its concrete type and a
+    /// pointer to the witness table of the conformance.
+    fn existential_type(&self) -> LLVMTypeRef {
+        unsafe {
+            let mut fields = [self.pointer_type(), self.pointer_type()];
+            LLVMStructTypeInContext(self.context, fields.as_mut_ptr(), 2, 0)
+        }
+    }
+
+    /// Optionals of pointers are the pointer, nil being null, others are a
+    /// `{ i1, T }` whose flag tells whether the value is present. Either way nil
+    /// is the zero value of the type.
+    fn build_nil(&self, ty: &Type, context: &Context) -> LLVMValueRef {
+        unsafe { LLVMConstNull(self.llvm_type(ty, context)) }
+    }
+
+    fn build_some(
+        &mut self,
+        value: LLVMValueRef,
+        wrapped: &Type,
+        context: &Context,
+    ) -> LLVMValueRef {
+        if wrapped.is_pointer() {
+            return value;
+        }
+
+        let optional_type = Type::Optional(Box::new(wrapped.clone()));
+
+        unsafe {
+            let mut optional = LLVMGetUndef(self.llvm_type(&optional_type, context));
+            optional = LLVMBuildInsertValue(
+                self.builder,
+                optional,
+                self.visit_boolean_literal(true),
+                0,
+                c"".as_ptr(),
+            );
+
+            LLVMBuildInsertValue(self.builder, optional, value, 1, c"".as_ptr())
+        }
+    }
+
+    fn build_has_value(&mut self, optional: LLVMValueRef, wrapped: &Type) -> LLVMValueRef {
+        unsafe {
+            if wrapped.is_pointer() {
+                return LLVMBuildIsNotNull(self.builder, optional, c"".as_ptr());
+            }
+
+            LLVMBuildExtractValue(self.builder, optional, 0, c"".as_ptr())
+        }
+    }
+
+    fn build_unwrap(&mut self, optional: LLVMValueRef, wrapped: &Type) -> LLVMValueRef {
+        if wrapped.is_pointer() {
+            return optional;
+        }
+
+        unsafe { LLVMBuildExtractValue(self.builder, optional, 1, c"".as_ptr()) }
+    }
+
+    /// Applies `build` to the value of an optional when it is not nil, the
+    /// result of type `value_type` is wrapped into an optional unless it
+    /// already is one. Void results are only built for their side effects.
+    fn build_optional_chain(
+        &mut self,
+        optional: LLVMValueRef,
+        wrapped: &Type,
+        value_type: &Type,
+        context: &Context,
+        build: impl FnOnce(&mut Self, LLVMValueRef) -> LLVMValueRef,
+    ) -> LLVMValueRef {
+        let result_type = value_type.clone().or_nil();
+
+        unsafe {
+            let has_value = self.build_has_value(optional, wrapped);
+            let function = LLVMGetBasicBlockParent(LLVMGetInsertBlock(self.builder));
+
+            let some_bb = LLVMAppendBasicBlockInContext(self.context, function, c"".as_ptr());
+            let nil_bb = LLVMAppendBasicBlockInContext(self.context, function, c"".as_ptr());
+            let merge_bb = LLVMAppendBasicBlockInContext(self.context, function, c"".as_ptr());
+
+            LLVMBuildCondBr(self.builder, has_value, some_bb, nil_bb);
+
+            LLVMPositionBuilderAtEnd(self.builder, some_bb);
+            let value = self.build_unwrap(optional, wrapped);
+            let mut value = build(self, value);
+
+            if result_type == Type::Void {
+                LLVMBuildBr(self.builder, merge_bb);
+                LLVMPositionBuilderAtEnd(self.builder, nil_bb);
+                LLVMBuildBr(self.builder, merge_bb);
+                LLVMPositionBuilderAtEnd(self.builder, merge_bb);
+
+                return ptr::null_mut();
+            }
+
+            value = self.coerce(value, value_type, &result_type, context);
+            let mut some_end = LLVMGetInsertBlock(self.builder);
+            LLVMBuildBr(self.builder, merge_bb);
+
+            LLVMPositionBuilderAtEnd(self.builder, nil_bb);
+            let mut nil = self.build_nil(&result_type, context);
+            let mut nil_end = nil_bb;
+            LLVMBuildBr(self.builder, merge_bb);
+
+            LLVMPositionBuilderAtEnd(self.builder, merge_bb);
+            let phi = LLVMBuildPhi(self.builder, LLVMTypeOf(value), c"".as_ptr());
+            LLVMAddIncoming(phi, &mut value, &mut some_end, 1);
+            LLVMAddIncoming(phi, &mut nil, &mut nil_end, 1);
+
+            phi
+        }
+    }
+
+    /// A result is a `{ i1, T, E }` whose flag is set when it holds a value,
+    /// only the member of its variant is defined.
+    fn build_result(
+        &mut self,
+        ty: &Type,
+        variant: ResultVariant,
+        payload: LLVMValueRef,
+        context: &Context,
+    ) -> LLVMValueRef {
+        let (flag, index) = match variant {
+            ResultVariant::Ok => (true, 1),
+            ResultVariant::Err => (false, 2),
+        };
+
+        unsafe {
+            let mut result = LLVMGetUndef(self.llvm_type(ty, context));
+            result = LLVMBuildInsertValue(
+                self.builder,
+                result,
+                self.visit_boolean_literal(flag),
+                0,
+                c"".as_ptr(),
+            );
+
+            LLVMBuildInsertValue(self.builder, result, payload, index, c"".as_ptr())
+        }
+    }
+
+    fn build_result_payload(
+        &mut self,
+        result: LLVMValueRef,
+        variant: ResultVariant,
+    ) -> LLVMValueRef {
+        let index = match variant {
+            ResultVariant::Ok => 1,
+            ResultVariant::Err => 2,
+        };
+
+        unsafe { LLVMBuildExtractValue(self.builder, result, index, c"".as_ptr()) }
+    }
+
+    pub fn visit_boolean_literal(&self, value: bool) -> LLVMValueRef {
+        unsafe {
+            LLVMConstInt(
+                LLVMInt1TypeInContext(self.context),
+                if value { 1 } else { 0 },
+                0,
+            )
+        }
+    }
+
+    /// Number literals are f64 unless the type checker gave them the type of
+    /// the value they are used as.
+    pub fn visit_float_number_literal(
+        &self,
+        value: f64,
+        ty: &Type,
+        context: &Context,
+    ) -> LLVMValueRef {
+        let llvm_type = self.llvm_type(ty, context);
+
+        unsafe {
+            if ty.is_integer() {
+                let bits = if ty.is_signed_integer() {
+                    value as i64 as u64
+                } else {
+                    value as u64
+                };
+
+                return LLVMConstInt(llvm_type, bits, ty.is_signed_integer() as i32);
+            }
+
+            LLVMConstReal(llvm_type, value)
+        }
+    }
+
+    /// Strings are NUL-terminated constants, passed around as a pointer to
+    /// their first byte.
+    pub fn visit_string_literal(&mut self, value: &str) -> LLVMValueRef {
+        let string = CString::new(value).unwrap();
+
+        unsafe { LLVMBuildGlobalStringPtr(self.builder, string.as_ptr(), c"string".as_ptr()) }
+    }
+
+    pub fn visit_identifier(&mut self, identifier: &Identifier, context: &Context) -> LLVMValueRef {
+        if let Some(value) = self.named_values.get_mut(&identifier.name) {
+            *value
+        } else if let Some(value) = self.globals.get(&identifier.name) {
+            *value
+        } else if let Some((ty, global)) = self.bindings.get(&identifier.name) {
+            let name = CString::new(identifier.name.clone()).unwrap();
+            unsafe { LLVMBuildLoad2(self.builder, *ty, *global, name.as_ptr()) }
+        } else if let Some(Symbol {
+            symbol_type: Type::Function(function),
+            ..
+        }) = context.lookup(identifier.name.clone())
+        {
+            self.build_function_value(&identifier.name, &function, context)
+        } else {
+            panic!("Unknown identifier: {}", identifier.name);
+        }
+    }
+
+    /// Builds an anonymous struct of the elements, converted to the element
+    /// types of the tuple it is used as.
+    pub fn visit_tuple(
+        &mut self,
+        tuple: &TupleExpression,
+        ty: &Type,
+        context: &Context,
+    ) -> LLVMValueRef {
+        let Type::Tuple(element_types) = self.resolve(ty) else {
+            panic!("Tuple of type {}", ty);
+        };
+
+        unsafe {
+            let mut value = LLVMGetUndef(self.llvm_type(ty, context));
+
+            for (i, (element, element_type)) in tuple
+                .expressions
+                .items
+                .iter()
+                .zip(&element_types)
+                .enumerate()
+            {
+                let element_value = self.visit_expression(element, context);
+                let value_type = self.expression_type(element, context);
+                let element_value = self.coerce(element_value, &value_type, element_type, context);
+
+                value = LLVMBuildInsertValue(
+                    self.builder,
+                    value,
+                    element_value,
+                    i as u32,
+                    c"".as_ptr(),
+                );
+            }
+
+            value
+        }
+    }
+
+    pub fn visit_binary_operator_expression(
+        &mut self,
+        node: &BinaryOperatorExpression,
+        context: &Context,
+    ) -> LLVMValueRef {
+        if let BinaryOperator::Coalesce = node.operator {
+            return self.build_coalesce(node, context);
+        }
+
+        let lhs = self.visit_expression(&node.left, context);
+        let rhs = self.visit_expression(&node.right, context);
+        let operand_type = self.expression_type(&node.left, context);
+
+        unsafe {
+            // optionals are only compared to nil
+            if let Type::Optional(wrapped) = &operand_type {
+                let optional = match *node.left {
+                    Expression::NilLiteral => rhs,
+                    _ => lhs,
+                };
+                let has_value = self.build_has_value(optional, wrapped);
+
+                return match node.operator {
+                    BinaryOperator::Equal => LLVMBuildNot(self.builder, has_value, c"".as_ptr()),
+                    _ => has_value,
+                };
+            }
+
+            if node.operator.is_comparison() {
+                return self.build_comparison(&node.operator, &operand_type, lhs, rhs);
+            }
+
+            if operand_type.is_integer() {
+                return match node.operator {
+                    BinaryOperator::Add => LLVMBuildAdd(self.builder, lhs, rhs, c"".as_ptr()),
+                    BinaryOperator::Subtract => LLVMBuildSub(self.builder, lhs, rhs, c"".as_ptr()),
+                    BinaryOperator::Multiply => LLVMBuildMul(self.builder, lhs, rhs, c"".as_ptr()),
+                    BinaryOperator::Divide if operand_type.is_signed_integer() => {
+                        LLVMBuildSDiv(self.builder, lhs, rhs, c"".as_ptr())
+                    }
+                    BinaryOperator::Divide => LLVMBuildUDiv(self.builder, lhs, rhs, c"".as_ptr()),
+                    _ => unreachable!(),
+                };
+            }
+
+            match node.operator {
+                BinaryOperator::Add => LLVMBuildFAdd(self.builder, lhs, rhs, c"".as_ptr()),
+                BinaryOperator::Subtract => LLVMBuildFSub(self.builder, lhs, rhs, c"".as_ptr()),
+                BinaryOperator::Multiply => LLVMBuildFMul(self.builder, lhs, rhs, c"".as_ptr()),
+                BinaryOperator::Divide => LLVMBuildFDiv(self.builder, lhs, rhs, c"".as_ptr()),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    /// The default of `??` is only evaluated when the optional is nil.
+    fn build_coalesce(
+        &mut self,
+        node: &BinaryOperatorExpression,
+        context: &Context,
+    ) -> LLVMValueRef {
+        let optional = self.visit_expression(&node.left, context);
+        let optional_type = self.expression_type(&node.left, context);
+        let default_type = self.expression_type(&node.right, context);
+
+        let Type::Optional(wrapped) = &optional_type else {
+            panic!("?? applied to a value of type {}", optional_type);
+        };
+
+        // the result is optional when the default is
+        let result_type = if context.is_assignable(&default_type, wrapped) {
+            wrapped.as_ref().clone()
+        } else {
+            optional_type.clone()
+        };
+
+        unsafe {
+            let has_value = self.build_has_value(optional, wrapped);
+            let function = LLVMGetBasicBlockParent(LLVMGetInsertBlock(self.builder));
+
+            let some_bb = LLVMAppendBasicBlockInContext(self.context, function, c"".as_ptr());
+            let default_bb = LLVMAppendBasicBlockInContext(self.context, function, c"".as_ptr());
+            let merge_bb = LLVMAppendBasicBlockInContext(self.context, function, c"".as_ptr());
+
+            LLVMBuildCondBr(self.builder, has_value, some_bb, default_bb);
+
+            LLVMPositionBuilderAtEnd(self.builder, some_bb);
+            let mut value = if result_type == optional_type {
+                optional
+            } else {
+                self.build_unwrap(optional, wrapped)
+            };
+            LLVMBuildBr(self.builder, merge_bb);
+
+            LLVMPositionBuilderAtEnd(self.builder, default_bb);
+            let default = self.visit_expression(&node.right, context);
+            let mut default = self.coerce(default, &default_type, &result_type, context);
+            let mut default_end = LLVMGetInsertBlock(self.builder);
+            LLVMBuildBr(self.builder, merge_bb);
+
+            LLVMPositionBuilderAtEnd(self.builder, merge_bb);
+            let mut some_end = some_bb;
+            let phi = LLVMBuildPhi(self.builder, LLVMTypeOf(value), c"".as_ptr());
+            LLVMAddIncoming(phi, &mut value, &mut some_end, 1);
+            LLVMAddIncoming(phi, &mut default, &mut default_end, 1);
+
+            phi
+        }
+    }
+
+    fn build_comparison(
+        &mut self,
+        operator: &BinaryOperator,
+        operand_type: &Type,
+        lhs: LLVMValueRef,
+        rhs: LLVMValueRef,
+    ) -> LLVMValueRef {
+        unsafe {
+            // bools, enum cases and pointers compare like unsigned integers
+            if !operand_type.is_float() {
+                let signed = operand_type.is_signed_integer();
+
+                let predicate = match operator {
+                    BinaryOperator::Equal => LLVMIntPredicate::LLVMIntEQ,
+                    BinaryOperator::NotEqual => LLVMIntPredicate::LLVMIntNE,
+                    BinaryOperator::LessThan if signed => LLVMIntPredicate::LLVMIntSLT,
+                    BinaryOperator::LessThan => LLVMIntPredicate::LLVMIntULT,
+                    BinaryOperator::LessThanOrEqual if signed => LLVMIntPredicate::LLVMIntSLE,
+                    BinaryOperator::LessThanOrEqual => LLVMIntPredicate::LLVMIntULE,
+                    BinaryOperator::GreaterThan if signed => LLVMIntPredicate::LLVMIntSGT,
+                    BinaryOperator::GreaterThan => LLVMIntPredicate::LLVMIntUGT,
+                    BinaryOperator::GreaterThanOrEqual if signed => LLVMIntPredicate::LLVMIntSGE,
+                    BinaryOperator::GreaterThanOrEqual => LLVMIntPredicate::LLVMIntUGE,
+                    _ => unreachable!(),
+                };
+
+                return LLVMBuildICmp(self.builder, predicate, lhs, rhs, c"".as_ptr());
+            }
+
+            let predicate = match operator {
+                BinaryOperator::Equal => LLVMRealPredicate::LLVMRealOEQ,
+                BinaryOperator::NotEqual => LLVMRealPredicate::LLVMRealONE,
+                BinaryOperator::LessThan => LLVMRealPredicate::LLVMRealOLT,
+                BinaryOperator::LessThanOrEqual => LLVMRealPredicate::LLVMRealOLE,
+                BinaryOperator::GreaterThan => LLVMRealPredicate::LLVMRealOGT,
+                BinaryOperator::GreaterThanOrEqual => LLVMRealPredicate::LLVMRealOGE,
+                _ => unreachable!(),
+            };
+
+            LLVMBuildFCmp(self.builder, predicate, lhs, rhs, c"".as_ptr())
+        }
+    }
+}
+
+/// Takes the text of an error message returned by LLVM.
+unsafe fn llvm_message(message: *mut i8) -> String {
+    let text = CStr::from_ptr(message).to_string_lossy().to_string();
+    LLVMDisposeMessage(message);
+    text
+}
+
+/// The declaration of a function, or of a method when `owner` names the type or
+/// enum declaring it.
+fn find_function<'a>(
+    node: &'a CodeBlock,
+    owner: Option<&str>,
+    name: &str,
+) -> Option<&'a FunctionDeclaration> {
+    node.declarations().into_iter().find_map(|declaration| {
+        let (type_name, member_block) = match declaration {
+            Declaration::FunctionDeclaration(function) => {
+                return (owner.is_none() && function.identifier.name == name).then_some(function);
+            }
+            Declaration::TypeDeclaration(declaration) => {
+                (&declaration.name.name, &declaration.container.member_block)
+            }
+            Declaration::EnumDeclaration(declaration) => {
+                (&declaration.name.name, &declaration.container.member_block)
+            }
+            _ => return None,
+        };
+
+        if owner != Some(type_name.as_str()) {
+            return None;
+        }
+
+        member_block.members.iter().find_map(|member| match member {
+            MemberBlockItem::FunctionDeclaration(function) if function.identifier.name == name => {
+                Some(function)
+            }
+            _ => None,
+        })
+    })
+}
+
+/// The variables a declaration or statement declares.
+fn declared_variables(node: &CodeBlockItem) -> Vec<&str> {
+    match node {
+        CodeBlockItem::Declaration(Declaration::VariableDeclaration(variable)) => {
+            vec![variable.identifier.name.as_str()]
         }
+        CodeBlockItem::Statement(Statement::Destructuring(statement)) => statement
+            .names
+            .iter()
+            .flatten()
+            .map(|name| name.name.as_str())
+            .collect(),
+        _ => Vec::new(),
     }
 }
@@ -1,12 +1,39 @@
-use crate::syntax::TypeSyntax;
+use crate::syntax::{
+    ClosureExpression, Expression, FunctionCallExpression, FunctionSignature, GenericParameter,
+    TypeSyntax,
+};
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Symbol {
     pub name: String,
     pub symbol_type: Type,
+    /// Functions and methods declared by name are called directly, other
+    /// symbols of function type hold closures.
+    pub declared: bool,
+}
+
+/// A function made callable from C under a fixed symbol with `@export`.
+#[derive(Debug, Clone)]
+pub struct Export {
+    pub function: String,
+    pub symbol: String,
+}
+
+/// A declaration only visible in the module declaring it.
+#[derive(Debug, Clone)]
+pub struct PrivateDeclaration {
+    /// What is declared, as in `function` or `field`.
+    pub kind: &'static str,
+    pub module: String,
+    /// Where it is declared, as `path:line:column`.
+    pub location: String,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Scope {
     symbols: Vec<Symbol>,
 }
@@ -16,12 +43,231 @@ pub enum Type {
     Void,
     Identifier(String),
     Function(TypeFunction),
+    Pointer(Box<Type>),
+    /// A value of the wrapped type or nil.
+    Optional(Box<Type>),
+    /// A value of the first type or an error of the second.
+    Result(Box<Type>, Box<Type>),
+    /// An instance of a generic type for the given type arguments.
+    Generic(String, Vec<Type>),
+    /// A sequence of values of the element type.
+    Array(Box<Type>),
+    /// A hash map from keys of the first type to values of the second.
+    Dictionary(Box<Type>, Box<Type>),
+    /// A fixed number of values of the given types.
+    Tuple(Vec<Type>),
+}
+
+impl Type {
+    /// The type of a value that may be missing, as the result of optional
+    /// chaining, optionals are not wrapped a second time.
+    pub fn or_nil(self) -> Type {
+        match self {
+            Type::Optional(_) | Type::Void => self,
+            _ => Type::Optional(Box::new(self)),
+        }
+    }
+
+    /// Optionals of pointers are lowered to the pointer itself with nil as the
+    /// null pointer.
+    pub fn is_pointer(&self) -> bool {
+        matches!(self, Type::Pointer(_))
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.is_signed_integer() || self.is_unsigned_integer()
+    }
+
+    pub fn is_signed_integer(&self) -> bool {
+        matches!(self, Type::Identifier(name) if ["i8", "i16", "i32", "i64"].contains(&name.as_str()))
+    }
+
+    pub fn is_unsigned_integer(&self) -> bool {
+        matches!(self, Type::Identifier(name) if ["u8", "u16", "u32", "u64"].contains(&name.as_str()))
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Type::Identifier(name) if name == "f32" || name == "f64")
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || self.is_float()
+    }
+
+    /// The name of the declared type a value of this type is an instance of.
+    pub fn nominal(&self) -> Option<&str> {
+        match self {
+            Type::Identifier(name) | Type::Generic(name, _) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Replaces the type parameters named in `substitution` by their type.
+    pub fn substitute(&self, substitution: &HashMap<String, Type>) -> Type {
+        match self {
+            Type::Void => Type::Void,
+            Type::Identifier(name) => substitution
+                .get(name)
+                .cloned()
+                .unwrap_or_else(|| self.clone()),
+            Type::Function(function) => Type::Function(function.substitute(substitution)),
+            Type::Pointer(pointee) => Type::Pointer(Box::new(pointee.substitute(substitution))),
+            Type::Optional(wrapped) => Type::Optional(Box::new(wrapped.substitute(substitution))),
+            Type::Result(value, error) => Type::Result(
+                Box::new(value.substitute(substitution)),
+                Box::new(error.substitute(substitution)),
+            ),
+            Type::Generic(name, arguments) => Type::Generic(
+                name.clone(),
+                arguments
+                    .iter()
+                    .map(|argument| argument.substitute(substitution))
+                    .collect(),
+            ),
+            Type::Array(element) => Type::Array(Box::new(element.substitute(substitution))),
+            Type::Dictionary(key, value) => Type::Dictionary(
+                Box::new(key.substitute(substitution)),
+                Box::new(value.substitute(substitution)),
+            ),
+            Type::Tuple(elements) => Type::Tuple(
+                elements
+                    .iter()
+                    .map(|element| element.substitute(substitution))
+                    .collect(),
+            ),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct TypeFunction {
     pub parameters: Vec<TypeFunctionParameter>,
     pub return_type: Box<Type>,
+    /// Variadic functions take any number of extra arguments after their
+    /// parameters, as C functions declared with `...` do.
+    pub variadic: bool,
+    /// The type parameters of a generic function, their types are inferred
+    /// from the arguments of each call.
+    pub generic_parameters: Vec<TypeParameter>,
+}
+
+impl TypeFunction {
+    pub fn substitute(&self, substitution: &HashMap<String, Type>) -> TypeFunction {
+        TypeFunction {
+            parameters: self
+                .parameters
+                .iter()
+                .map(|parameter| TypeFunctionParameter {
+                    ty: parameter.ty.substitute(substitution),
+                    ..parameter.clone()
+                })
+                .collect(),
+            return_type: Box::new(self.return_type.substitute(substitution)),
+            variadic: self.variadic,
+            generic_parameters: self.generic_parameters.clone(),
+        }
+    }
+
+    /// Maps the type parameters of the function to the type arguments of a call.
+    pub fn substitution(&self, arguments: &[Type]) -> HashMap<String, Type> {
+        substitution(&self.generic_parameters, arguments)
+    }
+}
+
+impl From<&FunctionSignature> for TypeFunction {
+    fn from(value: &FunctionSignature) -> Self {
+        let parameters = value
+            .parameters
+            .parameters
+            .iter()
+            .map(|param| {
+                TypeFunctionParameter::new(
+                    param.label.clone().map(|i| i.name),
+                    param.name.name.clone(),
+                    Type::from(param.parameter_type.clone()),
+                )
+            })
+            .collect();
+
+        let return_type = value
+            .return_clause
+            .as_ref()
+            .map(|r| Type::from(r.return_type.clone()))
+            .unwrap_or(Type::Void);
+
+        TypeFunction {
+            parameters,
+            return_type: Box::new(return_type),
+            variadic: value.parameters.variadic,
+            generic_parameters: Vec::new(),
+        }
+    }
+}
+
+/// A type parameter of a generic function or type, the protocols it is bound
+/// by are the conformances its type arguments must have.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TypeParameter {
+    pub name: String,
+    pub bounds: Vec<String>,
+}
+
+impl From<GenericParameter> for TypeParameter {
+    fn from(value: GenericParameter) -> Self {
+        Self {
+            name: value.name.name,
+            bounds: value.bounds.into_iter().map(|bound| bound.name).collect(),
+        }
+    }
+}
+
+fn substitution(parameters: &[TypeParameter], arguments: &[Type]) -> HashMap<String, Type> {
+    parameters
+        .iter()
+        .map(|parameter| parameter.name.clone())
+        .zip(arguments.iter().cloned())
+        .collect()
+}
+
+/// The protocol the keys of dictionaries conform to, `Self` in its requirements
+/// is the conforming type.
+pub const HASHABLE: &str = "Hashable";
+
+fn hashable_protocol() -> TypeDefinition {
+    let requirement = |name: &str, parameters, return_type: &str| Method {
+        name: name.to_string(),
+        selfless: false,
+        function: TypeFunction {
+            parameters,
+            return_type: Box::new(Type::Identifier(return_type.to_string())),
+            variadic: false,
+            generic_parameters: Vec::new(),
+        },
+        symbol: None,
+        implementation: MethodImplementation::Required,
+    };
+
+    let other = TypeFunctionParameter::new(
+        None,
+        "other".to_string(),
+        Type::Identifier("Self".to_string()),
+    );
+
+    TypeDefinition {
+        name: HASHABLE.to_string(),
+        kind: TypeDefinitionKind::Protocol,
+        generic_parameters: Vec::new(),
+        conformances: Vec::new(),
+        methods: vec![
+            requirement("hash", Vec::new(), "u64"),
+            requirement("equals", vec![other], "bool"),
+        ],
+    }
+}
+
+/// Replaces `Self` in the requirements of a protocol by the conforming type.
+pub fn self_substitution(type_name: &str) -> HashMap<String, Type> {
+    HashMap::from([("Self".to_string(), Type::Identifier(type_name.to_string()))])
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -41,32 +287,354 @@ impl TypeFunctionParameter {
     }
 }
 
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Void => write!(f, "void"),
+            Type::Identifier(name) => write!(f, "{}", name),
+            Type::Function(function) => {
+                let parameters = function
+                    .parameters
+                    .iter()
+                    .map(|p| p.ty.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                match *function.return_type {
+                    Type::Void => write!(f, "({}) -> ()", parameters),
+                    ref return_type => write!(f, "({}) -> {}", parameters, return_type),
+                }
+            }
+            Type::Pointer(pointee) => write!(f, "*{}", pointee),
+            // nil has this type until it is used as a particular optional
+            Type::Optional(wrapped) if **wrapped == Type::Void => write!(f, "nil"),
+            Type::Optional(wrapped) => write!(f, "{}?", wrapped),
+            Type::Result(value, error) => write!(f, "result<{}, {}>", value, error),
+            Type::Generic(name, arguments) => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| argument.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                write!(f, "{}<{}>", name, arguments)
+            }
+            // the type of `[]` until it is used as a particular array
+            Type::Array(element) if **element == Type::Void => write!(f, "[]"),
+            Type::Array(element) => write!(f, "[{}]", element),
+            // the type of `[:]` until it is used as a particular dictionary
+            Type::Dictionary(key, _) if **key == Type::Void => write!(f, "[:]"),
+            Type::Dictionary(key, value) => write!(f, "[{}: {}]", key, value),
+            Type::Tuple(elements) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| element.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                write!(f, "({})", elements)
+            }
+        }
+    }
+}
+
 impl From<TypeSyntax> for Type {
     fn from(value: TypeSyntax) -> Self {
         match value {
             TypeSyntax::IdentifierType(identifier) => Type::Identifier(identifier.name),
+            TypeSyntax::PointerType(pointee) => Type::Pointer(Box::new(Type::from(*pointee))),
+            TypeSyntax::OptionalType(wrapped) => Type::Optional(Box::new(Type::from(*wrapped))),
+            TypeSyntax::ResultType(value, error) => {
+                Type::Result(Box::new(Type::from(*value)), Box::new(Type::from(*error)))
+            }
+            TypeSyntax::GenericType(identifier, arguments) => Type::Generic(
+                identifier.name,
+                arguments.into_iter().map(Type::from).collect(),
+            ),
+            TypeSyntax::ArrayType(element) => Type::Array(Box::new(Type::from(*element))),
+            TypeSyntax::DictionaryType(key, value) => {
+                Type::Dictionary(Box::new(Type::from(*key)), Box::new(Type::from(*value)))
+            }
+            // `()` is the return type of functions that return nothing
+            TypeSyntax::TupleType(elements) if elements.is_empty() => Type::Void,
+            TypeSyntax::TupleType(elements) => {
+                Type::Tuple(elements.into_iter().map(Type::from).collect())
+            }
+            TypeSyntax::FunctionType(parameters, return_type) => Type::Function(TypeFunction {
+                parameters: parameters
+                    .into_iter()
+                    .map(|parameter| TypeFunctionParameter {
+                        label: String::new(),
+                        name: String::new(),
+                        ty: Type::from(parameter),
+                    })
+                    .collect(),
+                return_type: Box::new(Type::from(*return_type)),
+                variadic: false,
+                generic_parameters: Vec::new(),
+            }),
         }
     }
 }
 
+#[derive(Clone)]
 pub struct TypeDefinition {
-    name: String,
-    // methods: HashMap<String, >,
+    pub name: String,
+    pub kind: TypeDefinitionKind,
+    pub generic_parameters: Vec<TypeParameter>,
+    pub conformances: Vec<String>,
+    pub methods: Vec<Method>,
+}
+
+impl TypeDefinition {
+    pub fn primitive(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            kind: TypeDefinitionKind::Primitive,
+            generic_parameters: Vec::new(),
+            conformances: Vec::new(),
+            methods: Vec::new(),
+        }
+    }
+
+    pub fn method(&self, name: &str) -> Option<&Method> {
+        self.methods.iter().find(|method| method.name == name)
+    }
+
+    /// The type of `self` in the methods of the type, a generic type is given
+    /// its own parameters as type arguments.
+    pub fn declared_type(&self) -> Type {
+        if self.generic_parameters.is_empty() {
+            return Type::Identifier(self.name.clone());
+        }
+
+        let parameters = self
+            .generic_parameters
+            .iter()
+            .map(|parameter| Type::Identifier(parameter.name.clone()))
+            .collect();
+
+        Type::Generic(self.name.clone(), parameters)
+    }
+
+    /// Maps the type parameters of the type to the type arguments of an instance.
+    pub fn substitution(&self, arguments: &[Type]) -> HashMap<String, Type> {
+        substitution(&self.generic_parameters, arguments)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum TypeDefinitionKind {
+    Primitive,
+    Structure(Vec<Field>),
+    Enumeration(Vec<String>),
+    /// The methods of a protocol are the requirements of its conforming types.
+    Protocol,
+    /// A foreign library, its methods are selfless functions of another language.
+    Library,
+    /// A type parameter in the scope of a generic declaration, its conformances
+    /// are its bounds and its methods the requirements of those protocols.
+    Parameter,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Field {
+    pub name: String,
+    pub ty: Type,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Method {
+    pub name: String,
+    /// Selfless methods are called on the type instead of on a value.
+    pub selfless: bool,
+    pub function: TypeFunction,
+    /// The symbol of a foreign function when it differs from the name, set
+    /// with the `@name` attribute of a requirement.
+    pub symbol: Option<String>,
+    pub implementation: MethodImplementation,
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub enum MethodImplementation {
+    /// A protocol requirement without a default implementation.
+    Required,
+    /// A method with a body, declared by a type, an extension or a protocol.
+    Declared,
+    /// A method a type inherits from the default implementation of a protocol.
+    Default(String),
+    /// A function of a foreign library, called through its symbol name.
+    External(String),
+}
+
+impl Method {
+    /// Whether this method satisfies `requirement`, parameter names may differ but
+    /// labels and types have to match.
+    pub fn satisfies(&self, requirement: &Method) -> bool {
+        let parameters = &self.function.parameters;
+        let required = &requirement.function.parameters;
+
+        self.name == requirement.name
+            && self.selfless == requirement.selfless
+            && self.function.return_type == requirement.function.return_type
+            && parameters.len() == required.len()
+            && parameters
+                .iter()
+                .zip(required)
+                .all(|(p, r)| p.label == r.label && p.ty == r.ty)
+    }
+}
+
+impl Method {
+    pub fn substitute(&self, substitution: &HashMap<String, Type>) -> Method {
+        Method {
+            function: self.function.substitute(substitution),
+            ..self.clone()
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parameters = self
+            .function
+            .parameters
+            .iter()
+            .map(|p| format!("{}: {}", p.label, p.ty))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let selfless = if self.selfless { "selfless " } else { "" };
+        write!(f, "fun {}{}({})", selfless, self.name, parameters)?;
+
+        if *self.function.return_type != Type::Void {
+            write!(f, " -> {}", self.function.return_type)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The function whose body is currently being visited, used to check `return`
+/// statements and tail expressions against its return clause.
+#[derive(Clone)]
+pub struct FunctionScope {
+    pub name: String,
+    pub return_type: Type,
+}
+
+/// A closure whose body is being checked, the variables it uses from the scopes
+/// below `depth` are its captures.
+#[derive(Clone)]
+struct ClosureScope {
+    depth: usize,
+    captures: Vec<String>,
+}
+
+#[derive(Clone)]
 pub struct Context {
     scopes: Vec<Scope>,
     types: Vec<TypeDefinition>,
+    /// The type parameters of the generic declarations being checked.
+    type_parameters: Vec<Vec<TypeDefinition>>,
+    functions: Vec<FunctionScope>,
+    expression_types: HashMap<*const Expression, Type>,
+    generic_arguments: HashMap<*const FunctionCallExpression, Vec<Type>>,
+    closures: Vec<ClosureScope>,
+    captures: HashMap<*const ClosureExpression, Vec<String>>,
+    /// Calls of closures held by variables, along with the type of the closure.
+    indirect_calls: HashMap<*const FunctionCallExpression, TypeFunction>,
+    exports: Vec<Export>,
+    /// The modules imported by the program, directly or through other modules,
+    /// along with the file or directory they are read from.
+    modules: Vec<(String, PathBuf)>,
+    /// The module being checked last, starting with the module being compiled.
+    module_stack: Vec<String>,
+    /// The private declarations of every module by qualified name, as in
+    /// `geometry.Vector2.length`.
+    private_declarations: HashMap<String, PrivateDeclaration>,
+    /// Functions and methods used from generic code, which is emitted into the
+    /// object files of other modules.
+    exposed: HashSet<String>,
+    error_count: usize,
+    /// The errors and warnings reported so far, as they are printed.
+    diagnostics: Vec<String>,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Context {
     pub fn new() -> Self {
-        let mut scopes = Vec::new();
-        scopes.push(Scope::default()); // Global scope
+        let scopes = vec![Scope::default()]; // Global scope
+
+        let mut types: Vec<TypeDefinition> = [
+            "f64", "f32", "bool", "i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64",
+        ]
+        .into_iter()
+        .map(TypeDefinition::primitive)
+        .collect();
+
+        types.push(hashable_protocol());
 
-        let types = vec![]; // TODO: fill this with primitive types ?
+        Self {
+            scopes,
+            types,
+            type_parameters: Vec::new(),
+            functions: Vec::new(),
+            expression_types: HashMap::new(),
+            generic_arguments: HashMap::new(),
+            closures: Vec::new(),
+            captures: HashMap::new(),
+            indirect_calls: HashMap::new(),
+            exports: Vec::new(),
+            modules: Vec::new(),
+            module_stack: Vec::new(),
+            private_declarations: HashMap::new(),
+            exposed: HashSet::new(),
+            error_count: 0,
+            diagnostics: Vec::new(),
+        }
+    }
 
-        Self { scopes, types }
+    pub fn error(&mut self, message: String) {
+        let diagnostic = format!("error: {}", message);
+        println!("{}", diagnostic);
+        self.diagnostics.push(diagnostic);
+        self.error_count += 1;
+    }
+
+    pub fn warning(&mut self, message: String) {
+        let diagnostic = format!("warning: {}", message);
+        println!("{}", diagnostic);
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub fn diagnostics(&self) -> &[String] {
+        &self.diagnostics
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.error_count > 0
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.error_count
+    }
+
+    pub fn enter_function(&mut self, name: String, return_type: Type) {
+        self.functions.push(FunctionScope { name, return_type });
+    }
+
+    pub fn exit_function(&mut self) {
+        self.functions.pop();
+    }
+
+    pub fn current_function(&self) -> Option<&FunctionScope> {
+        self.functions.last()
     }
 
     pub fn enter_scope(&mut self) {
@@ -85,14 +653,403 @@ impl Context {
         let scope = self.scopes.last_mut().unwrap();
 
         if scope.symbols.contains(&symbol) {
-            println!("symbol already exists in scope: {:?}", symbol);
             return;
         }
 
         scope.symbols.push(symbol);
     }
 
-    pub fn lookup(&mut self, identifier: String) -> Option<Symbol> {
+    pub fn register_type(&mut self, definition: TypeDefinition) {
+        if self.lookup_type(&definition.name).is_some() {
+            self.error(format!("type {:?} is already declared", definition.name));
+            return;
+        }
+
+        self.types.push(definition);
+    }
+
+    /// Type parameters shadow the types of the same name while their
+    /// declaration is checked.
+    pub fn lookup_type(&self, name: &str) -> Option<&TypeDefinition> {
+        self.type_parameters
+            .iter()
+            .rev()
+            .flatten()
+            .chain(&self.types)
+            .find(|definition| definition.name == name)
+    }
+
+    /// Declares the type parameters of a generic declaration until
+    /// `exit_type_parameters`, a parameter has the methods of the protocols it
+    /// is bound by.
+    pub fn enter_type_parameters(&mut self, parameters: &[TypeParameter]) {
+        let definitions = parameters
+            .iter()
+            .map(|parameter| TypeDefinition {
+                name: parameter.name.clone(),
+                kind: TypeDefinitionKind::Parameter,
+                generic_parameters: Vec::new(),
+                conformances: parameter.bounds.clone(),
+                methods: parameter
+                    .bounds
+                    .iter()
+                    .filter_map(|bound| self.lookup_type(bound))
+                    .flat_map(|protocol| protocol.methods.clone())
+                    .map(|method| method.substitute(&self_substitution(&parameter.name)))
+                    .collect(),
+            })
+            .collect();
+
+        self.type_parameters.push(definitions);
+    }
+
+    pub fn exit_type_parameters(&mut self) {
+        self.type_parameters.pop();
+    }
+
+    /// The method of a type called on a value of type `ty`, the signature of a
+    /// method of a generic type is given for the type arguments of `ty`.
+    pub fn lookup_method(&self, ty: &Type, name: &str) -> Option<Method> {
+        let definition = self.lookup_type(ty.nominal()?)?;
+        let method = definition.method(name)?;
+
+        match ty {
+            Type::Generic(_, arguments) => {
+                Some(method.substitute(&definition.substitution(arguments)))
+            }
+            _ => Some(method.clone()),
+        }
+    }
+
+    /// The fields of a structure type, with the types given by the type
+    /// arguments for an instance of a generic structure, or the elements of a
+    /// tuple.
+    pub fn fields(&self, ty: &Type) -> Option<Vec<Field>> {
+        // the count is the only member of an array or a dictionary read like a field
+        if let Type::Array(_) | Type::Dictionary(..) = ty {
+            return Some(vec![Field {
+                name: "count".to_string(),
+                ty: Type::Identifier("i64".to_string()),
+            }]);
+        }
+
+        // the elements of a tuple are read like fields named by their index
+        if let Type::Tuple(elements) = ty {
+            return Some(
+                elements
+                    .iter()
+                    .enumerate()
+                    .map(|(i, element)| Field {
+                        name: i.to_string(),
+                        ty: element.clone(),
+                    })
+                    .collect(),
+            );
+        }
+
+        let definition = self.lookup_type(ty.nominal()?)?;
+
+        let TypeDefinitionKind::Structure(fields) = &definition.kind else {
+            return None;
+        };
+
+        let substitution = match ty {
+            Type::Generic(_, arguments) => definition.substitution(arguments),
+            _ => HashMap::new(),
+        };
+
+        Some(
+            fields
+                .iter()
+                .map(|field| Field {
+                    name: field.name.clone(),
+                    ty: field.ty.substitute(&substitution),
+                })
+                .collect(),
+        )
+    }
+
+    pub fn lookup_type_mut(&mut self, name: &str) -> Option<&mut TypeDefinition> {
+        self.types
+            .iter_mut()
+            .find(|definition| definition.name == name)
+    }
+
+    pub fn types(&self) -> &[TypeDefinition] {
+        &self.types
+    }
+
+    pub fn is_protocol(&self, name: &str) -> bool {
+        self.lookup_type(name)
+            .is_some_and(|definition| definition.kind == TypeDefinitionKind::Protocol)
+    }
+
+    pub fn conforms_to(&self, type_name: &str, protocol_name: &str) -> bool {
+        self.lookup_type(type_name)
+            .is_some_and(|definition| definition.conformances.iter().any(|c| c == protocol_name))
+    }
+
+    /// Whether values of a type can be the keys of a dictionary. Integers,
+    /// booleans, enums, strings and pointers are hashed by the compiler, other
+    /// types have to conform to `Hashable`. Floats are not hashable as `nan` is
+    /// not equal to itself.
+    pub fn is_hashable(&self, ty: &Type) -> bool {
+        match ty {
+            Type::Identifier(name) => {
+                ty.is_integer()
+                    || name == "bool"
+                    || self.conforms_to(name, HASHABLE)
+                    || self.lookup_type(name).is_some_and(|definition| {
+                        matches!(definition.kind, TypeDefinitionKind::Enumeration(_))
+                    })
+            }
+            Type::Pointer(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether a value of type `from` can be used where a `to` is expected, a
+    /// concrete value can be used as any protocol it conforms to and any value
+    /// as an optional of its type. Functions of the same types can be used as
+    /// one another whatever their parameters are named.
+    pub fn is_assignable(&self, from: &Type, to: &Type) -> bool {
+        if from == to {
+            return true;
+        }
+
+        match (from, to) {
+            (Type::Function(from), Type::Function(to)) => {
+                from.generic_parameters.is_empty()
+                    && from.variadic == to.variadic
+                    && from.return_type == to.return_type
+                    && from.parameters.len() == to.parameters.len()
+                    && from
+                        .parameters
+                        .iter()
+                        .zip(&to.parameters)
+                        .all(|(from, to)| from.ty == to.ty)
+            }
+            (Type::Identifier(from), Type::Identifier(to)) => {
+                self.is_protocol(to) && self.conforms_to(from, to)
+            }
+            (Type::Optional(from), Type::Optional(_)) => **from == Type::Void,
+            (from, Type::Optional(wrapped)) => {
+                *from != Type::Void && self.is_assignable(from, wrapped)
+            }
+            _ => false,
+        }
+    }
+
+    /// Remembers the type of an expression once it has been checked so that
+    /// later passes do not need to infer it again.
+    pub fn record_expression_type(&mut self, expression: &Expression, ty: Type) {
+        self.expression_types.insert(expression as *const _, ty);
+    }
+
+    pub fn expression_type(&self, expression: &Expression) -> Type {
+        self.expression_types
+            .get(&(expression as *const _))
+            .cloned()
+            .unwrap_or(Type::Void)
+    }
+
+    /// Remembers the type arguments inferred for a call to a generic function
+    /// or initializer.
+    pub fn record_generic_arguments(
+        &mut self,
+        call: &FunctionCallExpression,
+        arguments: Vec<Type>,
+    ) {
+        self.generic_arguments.insert(call as *const _, arguments);
+    }
+
+    pub fn generic_arguments(&self, call: &FunctionCallExpression) -> &[Type] {
+        self.generic_arguments
+            .get(&(call as *const _))
+            .map_or(&[], |arguments| arguments.as_slice())
+    }
+
+    /// Starts collecting the variables of the enclosing scopes a closure uses.
+    pub fn enter_closure(&mut self) {
+        self.closures.push(ClosureScope {
+            depth: self.scopes.len(),
+            captures: Vec::new(),
+        });
+    }
+
+    pub fn exit_closure(&mut self, closure: &ClosureExpression) {
+        let scope = self.closures.pop().unwrap();
+        self.captures.insert(closure as *const _, scope.captures);
+    }
+
+    /// The names of the variables a closure captures, in the order of their
+    /// first use.
+    pub fn captures(&self, closure: &ClosureExpression) -> &[String] {
+        self.captures
+            .get(&(closure as *const _))
+            .map_or(&[], |captures| captures.as_slice())
+    }
+
+    /// Records the use of a symbol, it is captured by the closures being
+    /// checked that it was declared outside of. Globals and functions are not
+    /// captured, the methods of the enclosing type are called on a captured
+    /// `self`.
+    pub fn capture(&mut self, name: &str) {
+        let Some((depth, symbol)) =
+            self.scopes
+                .iter()
+                .enumerate()
+                .rev()
+                .find_map(|(depth, scope)| {
+                    let symbol = scope.symbols.iter().find(|symbol| symbol.name == name)?;
+                    Some((depth, symbol))
+                })
+        else {
+            return;
+        };
+
+        if depth == 0 {
+            return;
+        }
+
+        let name = match symbol.declared {
+            true => "self",
+            false => name,
+        };
+
+        for closure in self.closures.iter_mut().filter(|c| c.depth > depth) {
+            if !closure.captures.iter().any(|capture| capture == name) {
+                closure.captures.push(name.to_string());
+            }
+        }
+    }
+
+    pub fn record_indirect_call(&mut self, call: &FunctionCallExpression, function: TypeFunction) {
+        self.indirect_calls.insert(call as *const _, function);
+    }
+
+    /// The type of the closure called, when the call is not to a declared function.
+    pub fn indirect_call(&self, call: &FunctionCallExpression) -> Option<&TypeFunction> {
+        self.indirect_calls.get(&(call as *const _))
+    }
+
+    pub fn register_export(&mut self, export: Export) {
+        if let Some(existing) = self.exports.iter().find(|e| e.symbol == export.symbol) {
+            let message = format!(
+                "functions {:?} and {:?} are both exported as {:?}",
+                existing.function, export.function, export.symbol
+            );
+            self.error(message);
+            return;
+        }
+
+        self.exports.push(export);
+    }
+
+    /// The C symbol a function is exported as, if it is exported.
+    pub fn export_symbol(&self, function: &str) -> Option<&str> {
+        self.exports
+            .iter()
+            .find(|export| export.function == function)
+            .map(|export| export.symbol.as_str())
+    }
+
+    pub fn exports(&self) -> &[Export] {
+        &self.exports
+    }
+
+    pub fn register_module(&mut self, name: &str, path: &Path) {
+        self.modules.push((name.to_string(), path.to_path_buf()));
+    }
+
+    pub fn is_module(&self, name: &str) -> bool {
+        self.modules.iter().any(|(module, _)| module == name)
+    }
+
+    /// The files or directories of the modules imported by the program.
+    pub fn module_paths(&self) -> Vec<PathBuf> {
+        self.modules.iter().map(|(_, path)| path.clone()).collect()
+    }
+
+    /// Whether a name is qualified by an imported module, as in `geometry.Vector2`,
+    /// the declaration it refers to belongs to another object file.
+    pub fn is_imported(&self, name: &str) -> bool {
+        name.split_once('.')
+            .is_some_and(|(module, _)| self.is_module(module))
+    }
+
+    pub fn enter_module(&mut self, name: &str) {
+        self.module_stack.push(name.to_string());
+    }
+
+    pub fn exit_module(&mut self) {
+        self.module_stack.pop();
+    }
+
+    pub fn current_module(&self) -> Option<&str> {
+        self.module_stack.last().map(String::as_str)
+    }
+
+    pub fn register_private(&mut self, name: String, declaration: PrivateDeclaration) {
+        self.private_declarations.insert(name, declaration);
+    }
+
+    pub fn private_declaration(&self, name: &str) -> Option<&PrivateDeclaration> {
+        self.private_declarations.get(name)
+    }
+
+    /// Reports the use of a declaration private to another module than the
+    /// one using it, `from` is the module using it.
+    pub fn check_visibility(&mut self, name: &str, from: Option<&str>) {
+        let Some(declaration) = self.private_declarations.get(name) else {
+            return;
+        };
+
+        if from == Some(declaration.module.as_str()) {
+            return;
+        }
+
+        let message = format!(
+            "{} {:?} is private to module {:?}, declared at {}, mark it `pub` to use it from other modules",
+            declaration.kind, name, declaration.module, declaration.location
+        );
+        self.error(message);
+    }
+
+    /// Records a function or method used from generic code, it cannot have
+    /// internal linkage as the code can be emitted in other object files.
+    pub fn expose(&mut self, name: &str) {
+        if self
+            .type_parameters
+            .iter()
+            .any(|parameters| !parameters.is_empty())
+        {
+            self.exposed.insert(name.to_string());
+        }
+    }
+
+    pub fn is_exposed(&self, name: &str) -> bool {
+        self.exposed.contains(name)
+    }
+
+    /// Whether a name refers to a symbol of the global scope, rather than to
+    /// one of a function or type shadowing it.
+    pub fn is_global(&self, name: &str) -> bool {
+        self.scopes
+            .iter()
+            .rposition(|scope| scope.symbols.iter().any(|symbol| symbol.name == name))
+            == Some(0)
+    }
+
+    /// The variables of the global scope, the functions and externs left out.
+    pub fn global_variables(&self) -> impl Iterator<Item = &Symbol> {
+        self.scopes[0]
+            .symbols
+            .iter()
+            .filter(|symbol| !symbol.declared)
+    }
+
+    pub fn lookup(&self, identifier: String) -> Option<Symbol> {
         let scopes_len = self.scopes.len();
 
         for i in (0..scopes_len).rev() {
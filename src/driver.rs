@@ -0,0 +1,511 @@
+use crate::analysis::AnalysisVisitor;
+#[cfg(feature = "llvm")]
+use crate::codegen::{CodeGenOptions, LLVMCodeGenVisitor};
+use crate::context::Context;
+#[cfg(feature = "llvm")]
+use crate::header;
+use crate::hoist;
+use crate::interpreter::{InterpreterVisitor, RunError};
+#[cfg(feature = "llvm")]
+use crate::jit;
+use crate::module;
+#[cfg(feature = "llvm")]
+use crate::runtime;
+use crate::syntax::SourceFile;
+use crate::type_check::TypeCheckVisitor;
+
+#[cfg(feature = "llvm")]
+use llvm::target_machine::LLVMCodeGenFileType;
+
+#[cfg(feature = "llvm")]
+use std::env;
+#[cfg(feature = "llvm")]
+use std::fs;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "llvm")]
+use std::process::Command;
+#[cfg(feature = "llvm")]
+use std::sync::OnceLock;
+
+/// What the compiler writes for its sources.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Emit {
+    /// An executable linked from the object files of the sources
+    Exe,
+    /// An object file per source
+    Obj,
+    /// Assembly per source
+    Asm,
+    /// Textual LLVM IR per source
+    LlvmIr,
+    /// LLVM bitcode per source
+    LlvmBc,
+}
+
+#[cfg(feature = "llvm")]
+impl Emit {
+    /// The extension of the file written for a source.
+    fn extension(self) -> &'static str {
+        match self {
+            Emit::Exe => "",
+            Emit::Obj => "o",
+            Emit::Asm => "s",
+            Emit::LlvmIr => "ll",
+            Emit::LlvmBc => "bc",
+        }
+    }
+}
+
+/// How `x run` runs a program.
+#[derive(Debug, Clone, Copy, PartialEq, Default, clap::ValueEnum)]
+pub enum Backend {
+    /// Compile the program in memory and run it with the JIT
+    #[cfg(feature = "llvm")]
+    #[default]
+    Llvm,
+    /// Walk the syntax tree of the program, without generating code
+    #[cfg_attr(not(feature = "llvm"), default)]
+    Interp,
+}
+
+/// How a module is compiled, as given on the command line or by the manifest
+/// of a project.
+#[derive(Clone)]
+pub struct CompileOptions {
+    /// What is written for the module, an object file for an executable.
+    pub emit: Emit,
+    /// Where to write a C header declaring the exported functions, if anywhere.
+    pub header: Option<PathBuf>,
+    /// Whether array indices are checked at runtime.
+    pub bounds_checks: bool,
+    /// The directories imported modules are looked up in after the one of the
+    /// importing file, the source roots of the dependencies of a project.
+    pub search_paths: Vec<PathBuf>,
+}
+
+/// The libraries an executable is linked with.
+#[derive(Default)]
+pub struct LinkOptions {
+    /// Directories searched for libraries, passed as `-L`.
+    pub library_paths: Vec<PathBuf>,
+    /// Libraries linked by name, passed as `-l`.
+    pub libraries: Vec<String>,
+}
+
+/// Compiles the sources given on the command line. Each x file or directory is
+/// compiled on its own next to its source, or to `output` when there is one
+/// source. An executable is linked from all of them, along with the C files
+/// and object files given, and is named after the first source by default.
+#[cfg(feature = "llvm")]
+pub fn compile_files(
+    files: &[PathBuf],
+    output: Option<PathBuf>,
+    header: bool,
+    options: &CompileOptions,
+    link: &LinkOptions,
+) -> Result<(), String> {
+    let sources = files
+        .iter()
+        .filter(|file| !has_extension(file, "c") && !has_extension(file, "o"))
+        .collect::<Vec<_>>();
+
+    if options.emit != Emit::Exe {
+        if sources.len() < files.len() {
+            return Err(
+                "C files and object files can only be linked into an executable, with `--emit exe`"
+                    .to_string(),
+            );
+        }
+
+        if output.is_some() && files.len() > 1 {
+            return Err(
+                "`-o` names the output of a single source, or of an executable".to_string(),
+            );
+        }
+
+        for source in sources {
+            let options = CompileOptions {
+                header: header.then(|| source.with_extension("h")),
+                ..options.clone()
+            };
+            let output_file = output
+                .clone()
+                .unwrap_or_else(|| source.with_extension(options.emit.extension()));
+
+            compile_module(source, &output_file, &options)?;
+        }
+
+        return Ok(());
+    }
+
+    let executable = match (output, sources.first()) {
+        (Some(output), _) => output,
+        (None, Some(source)) if !source.is_dir() => source.with_extension(""),
+        (None, Some(source)) => {
+            return Err(format!(
+                "the executable would replace the directory {}, name it with `-o`",
+                source.display()
+            ))
+        }
+        (None, None) => {
+            return Err("no x source to name the executable after, name it with `-o`".to_string())
+        }
+    };
+
+    let directory = env::temp_dir().join(format!("x-link-{}", std::process::id()));
+    fs::create_dir_all(&directory)
+        .map_err(|error| format!("cannot create {}: {}", directory.display(), error))?;
+
+    let result = compile_and_link(files, &directory, &executable, header, options, link);
+
+    let _ = fs::remove_dir_all(&directory);
+    result
+}
+
+/// Compiles the sources of an executable into object files in a directory and
+/// links them with the object files given.
+#[cfg(feature = "llvm")]
+fn compile_and_link(
+    files: &[PathBuf],
+    directory: &Path,
+    executable: &Path,
+    header: bool,
+    options: &CompileOptions,
+    link: &LinkOptions,
+) -> Result<(), String> {
+    let mut objects = Vec::new();
+
+    for (index, file) in files.iter().enumerate() {
+        // the files are numbered, two of them can have the same name in different directories
+        let object = directory.join(format!("{}-{}.o", index, module::module_name(file)?));
+
+        if has_extension(file, "o") {
+            objects.push(file.clone());
+            continue;
+        }
+
+        if has_extension(file, "c") {
+            runtime::run(
+                Command::new(runtime::c_compiler())
+                    .arg("-c")
+                    .arg(file)
+                    .arg("-o")
+                    .arg(&object),
+            )?;
+        } else {
+            let options = CompileOptions {
+                header: header.then(|| file.with_extension("h")),
+                ..options.clone()
+            };
+            compile_module(file, &object, &options)?;
+        }
+
+        objects.push(object);
+    }
+
+    link_executable(&objects, executable, link)
+}
+
+#[cfg(feature = "llvm")]
+fn has_extension(file: &Path, extension: &str) -> bool {
+    file.extension()
+        .is_some_and(|file_extension| file_extension == extension)
+}
+
+/// Links object files into an executable with the C compiler, the runtime is
+/// already part of the object files using it.
+#[cfg(feature = "llvm")]
+pub fn link_executable(
+    objects: &[PathBuf],
+    executable: &Path,
+    options: &LinkOptions,
+) -> Result<(), String> {
+    runtime::run(
+        Command::new(runtime::c_compiler())
+            .args(objects)
+            .arg("-o")
+            .arg(executable)
+            .args(
+                options
+                    .library_paths
+                    .iter()
+                    .map(|path| format!("-L{}", path.display())),
+            )
+            .args(
+                options
+                    .libraries
+                    .iter()
+                    .map(|library| format!("-l{}", library)),
+            ),
+    )
+}
+
+/// Compiles a module, a file or a directory of files, into the file its
+/// options ask for. The diagnostics are printed as they are found, the error
+/// returned sums them up.
+#[cfg(feature = "llvm")]
+pub fn compile_module(
+    source: &Path,
+    output_file: &Path,
+    options: &CompileOptions,
+) -> Result<(), String> {
+    let (codegen, _) = generate_module(source, options)?;
+
+    let output = output_file.to_path_buf();
+    let result = match options.emit {
+        // an executable is linked from object files
+        Emit::Exe | Emit::Obj => codegen.emit_asm(output, LLVMCodeGenFileType::LLVMObjectFile),
+        Emit::Asm => codegen.emit_asm(output, LLVMCodeGenFileType::LLVMAssemblyFile),
+        Emit::LlvmIr => codegen.emit_llvm_ir(output),
+        Emit::LlvmBc => codegen.emit_bitcode(output),
+    }
+    .map_err(|error| format!("cannot write {}: {}", output_file.display(), error));
+
+    let result = result.and_then(|_| match (codegen.uses_runtime(), options.emit) {
+        (true, Emit::Exe | Emit::Obj) => runtime::link_runtime(output_file)
+            .map_err(|error| format!("cannot link the runtime: {}", error)),
+        (true, _) => {
+            println!(
+                "warning: {} uses the runtime, which is only linked into object files and executables",
+                source.display()
+            );
+            Ok(())
+        }
+        (false, _) => Ok(()),
+    });
+
+    codegen.finish();
+    result
+}
+
+/// Runs the `main` function of a module with a backend and returns its exit
+/// status. `arguments` are passed to `main` after the name of the program.
+pub fn run_module(
+    source: &Path,
+    arguments: &[String],
+    libraries: &[PathBuf],
+    backend: Backend,
+    options: &CompileOptions,
+) -> Result<i32, String> {
+    let mut program = vec![source.display().to_string()];
+    program.extend(arguments.iter().cloned());
+
+    match backend {
+        #[cfg(feature = "llvm")]
+        Backend::Llvm => jit_module(source, &program, libraries, options),
+        Backend::Interp if !libraries.is_empty() => {
+            Err("the interpreter cannot load shared libraries".to_string())
+        }
+        Backend::Interp => interpret_module(source, &program, options),
+    }
+}
+
+/// Compiles a module and the modules it imports in memory, then runs its
+/// `main` function with the JIT. Extern functions are found in the compiler's
+/// own process and in the shared libraries given.
+#[cfg(feature = "llvm")]
+fn jit_module(
+    source: &Path,
+    program: &[String],
+    libraries: &[PathBuf],
+    options: &CompileOptions,
+) -> Result<i32, String> {
+    for library in libraries {
+        jit::load_library(library)?;
+    }
+
+    let (root, module_paths) = generate_module(source, options)?;
+    let mut codegens = vec![root];
+
+    if let Err(error) = generate_modules(&module_paths, options, &mut codegens) {
+        codegens.iter().for_each(LLVMCodeGenVisitor::finish);
+        return Err(error);
+    }
+
+    run_program(codegens, program)
+}
+
+/// Checks a module and runs its `main` function with the interpreter. The
+/// imported modules are part of the checked module, so they run along with it.
+fn interpret_module(
+    source: &Path,
+    program: &[String],
+    options: &CompileOptions,
+) -> Result<i32, String> {
+    let (file, context, _) = load_module(source, options)?;
+
+    let mut interpreter = InterpreterVisitor::new(source.display().to_string());
+
+    match interpreter.run(&file, program, &context) {
+        Ok(status) => Ok(status),
+        Err(RunError::Invalid(message)) => Err(message),
+        // the program stops as compiled code does when a check fails
+        Err(RunError::Stopped(message)) => {
+            eprintln!("{}", message);
+            std::process::abort()
+        }
+    }
+}
+
+/// Generates the code of the modules a program imports after its own.
+#[cfg(feature = "llvm")]
+pub fn generate_modules(
+    module_paths: &[PathBuf],
+    options: &CompileOptions,
+    codegens: &mut Vec<LLVMCodeGenVisitor>,
+) -> Result<(), String> {
+    for path in module_paths {
+        let (codegen, _) = generate_module(path, options)?;
+        codegens.push(codegen);
+    }
+
+    Ok(())
+}
+
+/// Runs the `main` function of the generated code of a program and of the
+/// modules it imports with the JIT. `program` holds the name of the program
+/// and its arguments.
+#[cfg(feature = "llvm")]
+pub fn run_program(
+    mut codegens: Vec<LLVMCodeGenVisitor>,
+    program: &[String],
+) -> Result<i32, String> {
+    let result = match codegens.iter().any(LLVMCodeGenVisitor::uses_runtime) {
+        true => load_runtime(),
+        false => Ok(()),
+    }
+    .and_then(|_| {
+        let modules = codegens.iter_mut().map(LLVMCodeGenVisitor::take_module);
+        jit::run_main(modules.collect(), program)
+    });
+
+    codegens.iter().for_each(LLVMCodeGenVisitor::finish);
+    result
+}
+
+/// Builds the runtime as a shared library and loads it, once per process.
+#[cfg(feature = "llvm")]
+pub fn load_runtime() -> Result<(), String> {
+    static RUNTIME: OnceLock<Result<(), String>> = OnceLock::new();
+
+    RUNTIME
+        .get_or_init(|| {
+            let library = runtime::shared_runtime()
+                .map_err(|error| format!("cannot build the runtime: {}", error))?;
+
+            // the library stays mapped once it is loaded
+            let loaded = jit::load_library(&library);
+            let _ = fs::remove_file(&library);
+            loaded
+        })
+        .clone()
+}
+
+/// Parses and checks a module, then generates its code. The paths of the
+/// modules it imports, directly or not, are returned along with it.
+#[cfg(feature = "llvm")]
+fn generate_module(
+    source: &Path,
+    options: &CompileOptions,
+) -> Result<(LLVMCodeGenVisitor, Vec<PathBuf>), String> {
+    let (file, context, module_name) = load_module(source, options)?;
+
+    if let Some(header_file) = &options.header {
+        let guard = format!("{}_H", module_name.to_uppercase().replace(['-', '.'], "_"));
+
+        fs::write(header_file, header::generate_header(&guard, &context))
+            .map_err(|error| format!("cannot write {}: {}", header_file.display(), error))?;
+    }
+
+    let source_name = source.display().to_string();
+    let codegen = generate_code(&file, &context, module_name, source_name, options);
+
+    Ok((codegen, context.module_paths()))
+}
+
+/// Reads a module and checks it, failing when it has errors. The checked
+/// module is returned along with its context and its name.
+fn load_module(
+    source: &Path,
+    options: &CompileOptions,
+) -> Result<(SourceFile, Context, String), String> {
+    let read = module::module_name(source)
+        .and_then(|module_name| Ok((module::read_module(source)?, module_name)));
+
+    let (files, module_name) = match read {
+        Ok(read) => read,
+        Err(error) => {
+            // reported like the diagnostics of a module that could be read
+            let mut context = Context::new();
+            context.error(error);
+
+            return Err(format!(
+                "compilation failed with {} error(s)",
+                context.error_count()
+            ));
+        }
+    };
+
+    let directory = source.parent().unwrap_or(Path::new("."));
+    let (file, context) = check_module(&files, &module_name, directory, options);
+
+    if context.has_errors() {
+        return Err(format!(
+            "compilation failed with {} error(s)",
+            context.error_count()
+        ));
+    }
+
+    Ok((file, context, module_name))
+}
+
+/// Parses the files of a module and checks it, along with the modules it
+/// imports from its directory or the search paths. The context holds the
+/// diagnostics.
+pub fn check_module(
+    files: &[(PathBuf, String)],
+    module_name: &str,
+    directory: &Path,
+    options: &CompileOptions,
+) -> (SourceFile, Context) {
+    let mut context = Context::new();
+
+    context.enter_module(module_name);
+    let mut file = module::parse_module(files, module_name, &mut context);
+
+    module::expand_modules(
+        &mut file,
+        directory,
+        &options.search_paths,
+        module_name,
+        &mut context,
+    );
+    hoist::hoist_local_declarations(&mut file, None, &mut context);
+
+    let analysis = AnalysisVisitor {};
+    analysis.visit_source_file(&file, &mut context);
+
+    let type_check = TypeCheckVisitor::new();
+    type_check.visit_source_file(&file, &mut context);
+
+    (file, context)
+}
+
+/// Generates the code of a module checked without errors.
+#[cfg(feature = "llvm")]
+pub fn generate_code(
+    file: &SourceFile,
+    context: &Context,
+    module_name: String,
+    source_name: String,
+    options: &CompileOptions,
+) -> LLVMCodeGenVisitor {
+    let mut codegen = LLVMCodeGenVisitor::new(CodeGenOptions {
+        module_name,
+        source_name,
+        bounds_checks: options.bounds_checks,
+    });
+    codegen.visit_source_file(file, context);
+
+    codegen
+}
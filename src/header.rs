@@ -0,0 +1,164 @@
+use crate::context::*;
+
+/// Writes a C header declaring the functions exported with `@export` and the
+/// types their signatures use, structures become C structs with the same fields
+/// and enumerations C enums.
+pub fn generate_header(guard: &str, context: &Context) -> String {
+    let mut functions = Vec::new();
+    let mut types = Vec::new();
+
+    // the functions of imported modules are declared by their own header
+    for export in context.exports() {
+        if context.is_imported(&export.function) {
+            continue;
+        }
+
+        let Some(Symbol {
+            symbol_type: Type::Function(function),
+            ..
+        }) = context.lookup(export.function.clone())
+        else {
+            panic!("Function {:?} not registered", export.function);
+        };
+
+        for parameter in &function.parameters {
+            collect_types(&parameter.ty, context, &mut types);
+        }
+        collect_types(&function.return_type, context, &mut types);
+
+        functions.push((export.symbol.clone(), function));
+    }
+
+    let mut header = String::new();
+
+    header.push_str(&format!("#ifndef {}\n#define {}\n\n", guard, guard));
+    header.push_str("#include <stdbool.h>\n#include <stdint.h>\n\n");
+
+    // structures may point to each other, every name is declared before the fields
+    for name in &types {
+        if let Some(TypeDefinitionKind::Structure(_)) = context.lookup_type(name).map(|d| &d.kind) {
+            header.push_str(&format!("typedef struct {} {};\n", name, name));
+        }
+    }
+
+    for name in &types {
+        match context.lookup_type(name).map(|d| &d.kind) {
+            Some(TypeDefinitionKind::Structure(fields)) => {
+                header.push_str(&format!("\nstruct {} {{\n", name));
+
+                for field in fields {
+                    header.push_str(&format!("    {} {};\n", c_type(&field.ty), field.name));
+                }
+
+                header.push_str("};\n");
+            }
+            Some(TypeDefinitionKind::Enumeration(cases)) => {
+                let cases = cases
+                    .iter()
+                    .map(|case| format!("    {}_{}", name, case))
+                    .collect::<Vec<_>>();
+
+                header.push_str(&format!(
+                    "\ntypedef enum {} {{\n{}\n}} {};\n",
+                    name,
+                    cases.join(",\n"),
+                    name
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    header.push('\n');
+
+    for (symbol, function) in functions {
+        let mut parameters = function
+            .parameters
+            .iter()
+            .map(|parameter| format!("{} {}", c_type(&parameter.ty), parameter.name))
+            .collect::<Vec<_>>();
+
+        if parameters.is_empty() {
+            parameters.push("void".to_string());
+        }
+
+        header.push_str(&format!(
+            "{} {}({});\n",
+            c_type(&function.return_type),
+            symbol,
+            parameters.join(", ")
+        ));
+    }
+
+    header.push_str(&format!("\n#endif /* {} */\n", guard));
+
+    header
+}
+
+/// Adds the user defined types a type refers to, a structure comes after the
+/// types of its fields as C needs them complete.
+fn collect_types(ty: &Type, context: &Context, types: &mut Vec<String>) {
+    match ty {
+        Type::Pointer(pointee) | Type::Optional(pointee) => collect_types(pointee, context, types),
+        Type::Identifier(name) => {
+            let Some(definition) = context.lookup_type(name) else {
+                return;
+            };
+
+            if definition.kind == TypeDefinitionKind::Primitive || types.contains(name) {
+                return;
+            }
+
+            let fields = match &definition.kind {
+                TypeDefinitionKind::Structure(fields) => fields.as_slice(),
+                _ => &[],
+            };
+            let (pointers, values): (Vec<_>, Vec<_>) =
+                fields.iter().partition(|field| match &field.ty {
+                    Type::Optional(wrapped) => wrapped.is_pointer(),
+                    ty => ty.is_pointer(),
+                });
+
+            for field in values {
+                collect_types(&field.ty, context, types);
+            }
+
+            types.push(name.clone());
+
+            // pointers only need the name, they may point back to this type
+            for field in pointers {
+                collect_types(&field.ty, context, types);
+            }
+        }
+        Type::Void
+        | Type::Function(_)
+        | Type::Result(..)
+        | Type::Generic(..)
+        | Type::Array(_)
+        | Type::Dictionary(..)
+        | Type::Tuple(_) => {}
+    }
+}
+
+fn c_type(ty: &Type) -> String {
+    match ty {
+        Type::Void => "void".to_string(),
+        Type::Pointer(pointee) => format!("{}*", c_type(pointee)),
+        // only optional pointers are exported, nil is NULL
+        Type::Optional(wrapped) => c_type(wrapped),
+        Type::Identifier(name) => match name.as_str() {
+            "f64" => "double".to_string(),
+            "f32" => "float".to_string(),
+            "bool" => "bool".to_string(),
+            "i8" | "i16" | "i32" | "i64" => format!("int{}_t", &name[1..]),
+            "u8" | "u16" | "u32" | "u64" => format!("uint{}_t", &name[1..]),
+            _ => name.clone(),
+        },
+        Type::Function(_) => panic!("Function types have no C representation"),
+        Type::Result(..) => panic!("Result types have no C representation"),
+        Type::Generic(..) => panic!("Generic types have no C representation"),
+        Type::Array(_) => panic!("Array types have no C representation"),
+        Type::Dictionary(..) => panic!("Dictionary types have no C representation"),
+        Type::Tuple(_) => panic!("Tuple types have no C representation"),
+    }
+}
@@ -0,0 +1,686 @@
+use crate::context::Context;
+use crate::syntax::*;
+
+use std::collections::{HashMap, HashSet};
+
+/// Moves the functions and types declared inside function bodies to the top
+/// level of the file. Each is renamed after the function declaring it, as in
+/// `main.helper`, and the references to it in the enclosing block are renamed
+/// along, so that it is only visible from that block and does not clash with
+/// the declarations of other blocks.
+///
+/// The top-level declarations of an imported module are renamed after the
+/// module the same way, as in `geometry.Vector2`, and references to the
+/// declarations of imported modules are replaced by their qualified names.
+pub fn hoist_local_declarations(
+    file: &mut SourceFile,
+    module: Option<&str>,
+    context: &mut Context,
+) {
+    let mut top_level = Scope::default();
+
+    for item in &file.code_block.items {
+        let CodeBlockItem::Declaration(declaration) = item else {
+            continue;
+        };
+
+        if let (Some(module), Some(name)) = (module, declared_name(declaration)) {
+            top_level
+                .declarations
+                .insert(name.clone(), format!("{}.{}", module, name));
+        }
+    }
+
+    // keyed by the new names, as the types are renamed before their bodies are visited
+    let types = type_members(&file.code_block)
+        .into_iter()
+        .map(|(name, members)| match top_level.declarations.get(&name) {
+            Some(qualified) => (qualified.clone(), members),
+            None => (name, members),
+        })
+        .collect();
+
+    let mut hoister = Hoister {
+        scopes: vec![top_level],
+        barrier: 0,
+        module: module.map(str::to_string),
+        modules: file
+            .code_block
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                CodeBlockItem::Declaration(Declaration::ModuleImportDeclaration(import)) => {
+                    Some(import.name.name.clone())
+                }
+                _ => None,
+            })
+            .collect(),
+        names: HashSet::new(),
+        hoisted: Vec::new(),
+        context,
+    };
+
+    for item in &mut file.code_block.items {
+        if let CodeBlockItem::Declaration(declaration) = item {
+            hoister.visit_declaration(declaration);
+            hoister.visit_declaration_bodies(declaration, &types);
+        }
+    }
+
+    file.code_block.items.append(&mut hoister.hoisted);
+}
+
+/// The name a declaration adds to the scope declaring it, externs keep the
+/// name of their C symbol.
+fn declared_name(node: &Declaration) -> Option<&String> {
+    match node {
+        Declaration::VariableDeclaration(variable) => Some(&variable.identifier.name),
+        Declaration::FunctionDeclaration(function) => Some(&function.identifier.name),
+        Declaration::TypeDeclaration(declaration) => Some(&declaration.name.name),
+        Declaration::EnumDeclaration(declaration) => Some(&declaration.name.name),
+        Declaration::ProtocolDeclaration(declaration) => Some(&declaration.name.name),
+        Declaration::ExternLibraryDeclaration(library) => Some(&library.name.name),
+        Declaration::ExternDeclaration(_)
+        | Declaration::ExtensionDeclaration(_)
+        | Declaration::CImportDeclaration(_)
+        | Declaration::ModuleImportDeclaration(_) => None,
+    }
+}
+
+/// The fields and methods of the types declared in a block by type name, they
+/// are visible unqualified in the methods of extensions too.
+fn type_members(node: &CodeBlock) -> HashMap<String, Vec<String>> {
+    let mut types = HashMap::new();
+
+    for item in &node.items {
+        if let CodeBlockItem::Declaration(
+            Declaration::TypeDeclaration(TypeDeclaration {
+                name, container, ..
+            })
+            | Declaration::EnumDeclaration(EnumDeclaration {
+                name, container, ..
+            }),
+        ) = item
+        {
+            types.insert(name.name.clone(), member_names(container));
+        }
+    }
+
+    types
+}
+
+fn member_names(node: &MemberBlockContainer) -> Vec<String> {
+    node.member_block
+        .members
+        .iter()
+        .filter_map(|member| match member {
+            MemberBlockItem::VariableDeclaration(field) => Some(field.identifier.name.clone()),
+            MemberBlockItem::FunctionDeclaration(method) => Some(method.identifier.name.clone()),
+            MemberBlockItem::EnumCaseDeclaration(_) => None,
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct Scope {
+    /// The new names of the declarations of the scope.
+    declarations: HashMap<String, String>,
+    /// The variables, parameters and members of the scope, they shadow the
+    /// declarations of enclosing scopes of the same name.
+    variables: HashSet<String>,
+}
+
+struct Hoister<'a> {
+    /// The scopes of the block being visited, the innermost block last.
+    scopes: Vec<Scope>,
+    /// The first scope of the function being visited, the variables of the
+    /// enclosing functions are not visible from it.
+    barrier: usize,
+    /// The module being visited, none for the program.
+    module: Option<String>,
+    /// The modules the file imports.
+    modules: HashSet<String>,
+    /// The names given so far, a name declared twice in the same function gets
+    /// a number.
+    names: HashSet<String>,
+    hoisted: Vec<CodeBlockItem>,
+    context: &'a mut Context,
+}
+
+impl Hoister<'_> {
+    /// Hoists the local declarations of the bodies of a top-level or hoisted
+    /// declaration, bodies are named after the function or method they belong to.
+    fn visit_declaration_bodies(
+        &mut self,
+        node: &mut Declaration,
+        types: &HashMap<String, Vec<String>>,
+    ) {
+        match node {
+            Declaration::FunctionDeclaration(function) => {
+                let prefix = function.identifier.name.clone();
+                let parameters = parameter_names(&function.signature);
+                self.visit_function_body(&prefix, parameters, &mut function.body);
+            }
+            Declaration::TypeDeclaration(TypeDeclaration {
+                name, container, ..
+            })
+            | Declaration::EnumDeclaration(EnumDeclaration {
+                name, container, ..
+            })
+            | Declaration::ExtensionDeclaration(ExtensionDeclaration {
+                name, container, ..
+            }) => {
+                let type_name = name.name.clone();
+                let mut members = member_names(container);
+
+                // the members of a type extended in the same file
+                if let Some(type_members) = types.get(&type_name) {
+                    members.extend(type_members.iter().cloned());
+                }
+
+                for member in &mut container.member_block.members {
+                    if let MemberBlockItem::FunctionDeclaration(method) = member {
+                        let prefix = format!("{}.{}", type_name, method.identifier.name);
+                        let mut variables = parameter_names(&method.signature);
+                        variables.extend(members.iter().cloned());
+                        variables.push("self".to_string());
+
+                        self.visit_function_body(&prefix, variables, &mut method.body);
+                    }
+                }
+            }
+            Declaration::ProtocolDeclaration(protocol) => {
+                let requirements = protocol
+                    .requirements
+                    .iter()
+                    .map(|requirement| requirement.identifier.name.clone())
+                    .collect::<Vec<_>>();
+
+                for requirement in &mut protocol.requirements {
+                    if let Some(body) = &mut requirement.body {
+                        let prefix =
+                            format!("{}.{}", protocol.name.name, requirement.identifier.name);
+                        let mut variables = parameter_names(&requirement.signature);
+                        variables.extend(requirements.iter().cloned());
+                        variables.push("self".to_string());
+
+                        self.visit_function_body(&prefix, variables, body);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Visits the body of a function, which only sees the declarations of the
+    /// enclosing functions and not their variables.
+    fn visit_function_body(
+        &mut self,
+        prefix: &str,
+        variables: Vec<String>,
+        node: &mut CodeBlockContainer,
+    ) {
+        let barrier = std::mem::replace(&mut self.barrier, self.scopes.len());
+        self.scopes.push(Scope {
+            declarations: HashMap::new(),
+            variables: variables.into_iter().collect(),
+        });
+
+        self.visit_code_block(prefix, &mut node.code_block);
+
+        self.scopes.pop();
+        self.barrier = barrier;
+    }
+
+    /// Takes the local declarations out of a block and renames the references
+    /// to them, nested blocks may declare their own.
+    fn visit_code_block(&mut self, prefix: &str, node: &mut CodeBlock) {
+        let mut declarations = Vec::new();
+        let mut scope = Scope::default();
+
+        for item in std::mem::take(&mut node.items) {
+            let CodeBlockItem::Declaration(declaration) = item else {
+                node.items.push(item);
+                continue;
+            };
+
+            let name = match &declaration {
+                Declaration::VariableDeclaration(_) => {
+                    node.items.push(CodeBlockItem::Declaration(declaration));
+                    continue;
+                }
+                Declaration::FunctionDeclaration(function) => {
+                    if !function.attributes.is_empty() {
+                        self.context.error(format!(
+                            "function {:?} is declared inside {:?}, only top-level functions can have attributes",
+                            function.identifier.name, prefix
+                        ));
+                    }
+
+                    Some(&function.identifier.name)
+                }
+                Declaration::TypeDeclaration(declaration) => Some(&declaration.name.name),
+                Declaration::EnumDeclaration(declaration) => Some(&declaration.name.name),
+                Declaration::ProtocolDeclaration(declaration) => Some(&declaration.name.name),
+                Declaration::ExtensionDeclaration(_) => None,
+                Declaration::ExternDeclaration(_)
+                | Declaration::ExternLibraryDeclaration(_)
+                | Declaration::CImportDeclaration(_)
+                | Declaration::ModuleImportDeclaration(_) => {
+                    self.context.error(format!(
+                        "extern declarations and imports cannot be made inside {:?}, move them to the top level",
+                        prefix
+                    ));
+                    continue;
+                }
+            };
+
+            if let Some(name) = name {
+                let unique = self.unique_name(prefix, name);
+                scope.declarations.insert(name.clone(), unique);
+            }
+
+            declarations.push(declaration);
+        }
+
+        self.scopes.push(scope);
+
+        for item in &mut node.items {
+            self.visit_code_block_item(prefix, item);
+        }
+
+        // local declarations do not see the variables declared by the block
+        self.scopes.last_mut().unwrap().variables.clear();
+
+        for mut declaration in declarations {
+            self.visit_declaration(&mut declaration);
+            self.visit_declaration_bodies(&mut declaration, &HashMap::new());
+            self.hoisted.push(CodeBlockItem::Declaration(declaration));
+        }
+
+        self.scopes.pop();
+    }
+
+    fn unique_name(&mut self, prefix: &str, name: &str) -> String {
+        let mut unique = format!("{}.{}", prefix, name);
+        let mut count = 1;
+
+        while self.names.contains(&unique) {
+            count += 1;
+            unique = format!("{}.{}.{}", prefix, name, count);
+        }
+
+        self.names.insert(unique.clone());
+        unique
+    }
+
+    /// Whether a variable of the function being visited hides the declarations
+    /// of enclosing scopes with the name.
+    fn is_variable(&self, name: &str) -> bool {
+        self.scopes[self.barrier..]
+            .iter()
+            .any(|scope| scope.variables.contains(name))
+    }
+
+    fn declare_variable(&mut self, name: &str) {
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .variables
+            .insert(name.to_string());
+    }
+
+    /// Renames a reference to a declaration, unless a variable of the same
+    /// name is declared in a scope nested in the one of the declaration.
+    fn rename(&self, identifier: &mut Identifier) {
+        for (depth, scope) in self.scopes.iter().enumerate().rev() {
+            if depth >= self.barrier && scope.variables.contains(&identifier.name) {
+                return;
+            }
+
+            if let Some(name) = scope.declarations.get(&identifier.name) {
+                identifier.name = name.clone();
+                return;
+            }
+        }
+    }
+
+    /// Renames the name of a type, which variables do not hide.
+    fn rename_type(&self, identifier: &mut Identifier) {
+        if let Some(name) = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.declarations.get(&identifier.name))
+        {
+            identifier.name = name.clone();
+        }
+    }
+
+    /// Renames a hoisted or top-level declaration and the types it refers to,
+    /// its bodies are visited once it has its new name.
+    fn visit_declaration(&mut self, node: &mut Declaration) {
+        match node {
+            Declaration::VariableDeclaration(variable) => {
+                self.rename(&mut variable.identifier);
+
+                if let Some(ty) = &mut variable.type_annotation {
+                    self.visit_type(ty);
+                }
+            }
+            Declaration::FunctionDeclaration(function) => {
+                self.rename(&mut function.identifier);
+                self.visit_function_signature(&mut function.signature);
+            }
+            Declaration::TypeDeclaration(TypeDeclaration {
+                name,
+                conformances,
+                container,
+                ..
+            })
+            | Declaration::EnumDeclaration(EnumDeclaration {
+                name,
+                conformances,
+                container,
+                ..
+            })
+            | Declaration::ExtensionDeclaration(ExtensionDeclaration {
+                name,
+                conformances,
+                container,
+            }) => {
+                self.rename_type(name);
+
+                for conformance in conformances {
+                    self.rename_type(conformance);
+                }
+
+                for member in &mut container.member_block.members {
+                    match member {
+                        MemberBlockItem::VariableDeclaration(field) => {
+                            if let Some(ty) = &mut field.type_annotation {
+                                self.visit_type(ty);
+                            }
+                        }
+                        MemberBlockItem::FunctionDeclaration(method) => {
+                            self.visit_function_signature(&mut method.signature)
+                        }
+                        MemberBlockItem::EnumCaseDeclaration(_) => {}
+                    }
+                }
+            }
+            Declaration::ProtocolDeclaration(protocol) => {
+                self.rename_type(&mut protocol.name);
+
+                for requirement in &mut protocol.requirements {
+                    self.visit_function_signature(&mut requirement.signature);
+                }
+            }
+            Declaration::ExternLibraryDeclaration(library) => {
+                self.rename_type(&mut library.name);
+                self.rename_type(&mut library.protocol);
+            }
+            Declaration::ExternDeclaration(external) => {
+                self.visit_function_signature(&mut external.signature)
+            }
+            Declaration::CImportDeclaration(_) | Declaration::ModuleImportDeclaration(_) => {}
+        }
+    }
+
+    fn visit_function_signature(&mut self, node: &mut FunctionSignature) {
+        for parameter in &mut node.parameters.parameters {
+            self.visit_type(&mut parameter.parameter_type);
+        }
+
+        if let Some(clause) = &mut node.return_clause {
+            self.visit_type(&mut clause.return_type);
+        }
+    }
+
+    fn visit_type(&mut self, node: &mut TypeSyntax) {
+        match node {
+            TypeSyntax::IdentifierType(identifier) => {
+                self.rename_type(identifier);
+                self.check_visibility(&identifier.name);
+            }
+            TypeSyntax::GenericType(identifier, arguments) => {
+                self.rename_type(identifier);
+                self.check_visibility(&identifier.name);
+
+                for argument in arguments {
+                    self.visit_type(argument);
+                }
+            }
+            TypeSyntax::PointerType(wrapped)
+            | TypeSyntax::OptionalType(wrapped)
+            | TypeSyntax::ArrayType(wrapped) => self.visit_type(wrapped),
+            TypeSyntax::ResultType(first, second) | TypeSyntax::DictionaryType(first, second) => {
+                self.visit_type(first);
+                self.visit_type(second);
+            }
+            TypeSyntax::TupleType(elements) => {
+                for element in elements {
+                    self.visit_type(element);
+                }
+            }
+            TypeSyntax::FunctionType(parameters, return_type) => {
+                for parameter in parameters {
+                    self.visit_type(parameter);
+                }
+
+                self.visit_type(return_type);
+            }
+        }
+    }
+
+    fn visit_code_block_item(&mut self, prefix: &str, node: &mut CodeBlockItem) {
+        match node {
+            CodeBlockItem::Declaration(Declaration::VariableDeclaration(variable)) => {
+                if let Some(ty) = &mut variable.type_annotation {
+                    self.visit_type(ty);
+                }
+
+                if let Some(expression) = &mut variable.expression {
+                    self.visit_expression(prefix, expression);
+                }
+
+                self.declare_variable(&variable.identifier.name);
+            }
+            CodeBlockItem::Declaration(_) => {}
+            CodeBlockItem::Expression(expression) => self.visit_expression(prefix, expression),
+            CodeBlockItem::Statement(Statement::Return(statement)) => {
+                if let Some(expression) = &mut statement.expression {
+                    self.visit_expression(prefix, expression);
+                }
+            }
+            CodeBlockItem::Statement(Statement::Assignment(statement)) => {
+                self.visit_expression(prefix, &mut statement.target);
+                self.visit_expression(prefix, &mut statement.value);
+            }
+            CodeBlockItem::Statement(Statement::Destructuring(statement)) => {
+                if let Some(ty) = &mut statement.type_annotation {
+                    self.visit_type(ty);
+                }
+
+                self.visit_expression(prefix, &mut statement.value);
+
+                for name in statement.names.iter().flatten() {
+                    self.declare_variable(&name.name);
+                }
+            }
+        }
+    }
+
+    /// The module a name refers to, unless a variable hides it.
+    fn module(&self, node: &Expression) -> Option<String> {
+        match node {
+            Expression::Identifier(identifier)
+                if self.modules.contains(&identifier.name)
+                    && !self.is_variable(&identifier.name) =>
+            {
+                Some(identifier.name.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Reports a qualified name referring to a declaration another module
+    /// keeps private.
+    fn check_visibility(&mut self, name: &str) {
+        if name
+            .split_once('.')
+            .is_some_and(|(module, _)| self.modules.contains(module))
+        {
+            self.context.check_visibility(name, self.module.as_deref());
+        }
+    }
+
+    /// Replaces `module.function(...)` by a call of the qualified function
+    /// and `module.name` by the qualified name.
+    fn qualify(&mut self, node: &mut Expression) {
+        match node {
+            Expression::MethodCall(call) if !call.optional => {
+                if let Some(module) = self.module(&call.receiver) {
+                    let name = format!("{}.{}", module, call.method.name);
+                    self.check_visibility(&name);
+
+                    *node = Expression::FunctionCall(FunctionCallExpression {
+                        function: Identifier { name },
+                        arguments: ArgumentList {
+                            arguments: std::mem::take(&mut call.arguments.arguments),
+                        },
+                    });
+                }
+            }
+            Expression::MemberAccess(access) if !access.optional => {
+                if let Some(module) = self.module(&access.base) {
+                    let name = format!("{}.{}", module, access.member.name);
+                    self.check_visibility(&name);
+
+                    *node = Expression::Identifier(Identifier { name });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_expression(&mut self, prefix: &str, node: &mut Expression) {
+        self.qualify(node);
+
+        match node {
+            // type names are identifiers too, as in `Color.red`
+            Expression::Identifier(identifier) => self.rename(identifier),
+            Expression::FunctionCall(call) => {
+                self.rename(&mut call.function);
+                self.visit_arguments(prefix, &mut call.arguments);
+            }
+            Expression::MethodCall(call) => {
+                self.visit_expression(prefix, &mut call.receiver);
+                self.visit_arguments(prefix, &mut call.arguments);
+            }
+            Expression::MemberAccess(access) => self.visit_expression(prefix, &mut access.base),
+            Expression::BooleanLiteral(_)
+            | Expression::FloatNumberLiteral(_)
+            | Expression::StringLiteral(_)
+            | Expression::NilLiteral => {}
+            Expression::Result(result) => self.visit_expression(prefix, &mut result.value),
+            Expression::BinaryOperator(operator) => {
+                self.visit_expression(prefix, &mut operator.left);
+                self.visit_expression(prefix, &mut operator.right);
+            }
+            Expression::Tuple(tuple) => {
+                for element in &mut tuple.expressions.items {
+                    self.visit_expression(prefix, element);
+                }
+            }
+            Expression::If(if_expression) => self.visit_if_expression(prefix, if_expression),
+            Expression::Match(match_expression) => {
+                self.visit_expression(prefix, &mut match_expression.subject);
+
+                for arm in &mut match_expression.arms {
+                    let binding = match &mut arm.pattern {
+                        Pattern::Literal(literal) => {
+                            self.visit_expression(prefix, literal);
+                            None
+                        }
+                        Pattern::Binding(name) | Pattern::Result(_, Some(name)) => Some(name),
+                        _ => None,
+                    };
+
+                    let variables = binding.map(|name| name.name.clone()).into_iter().collect();
+                    self.visit_nested_block(prefix, variables, &mut arm.body);
+                }
+            }
+            Expression::Try(try_expression) => {
+                self.visit_expression(prefix, &mut try_expression.expression)
+            }
+            Expression::Array(array) => {
+                for element in &mut array.elements {
+                    self.visit_expression(prefix, element);
+                }
+            }
+            Expression::Dictionary(dictionary) => {
+                for (key, value) in &mut dictionary.entries {
+                    self.visit_expression(prefix, key);
+                    self.visit_expression(prefix, value);
+                }
+            }
+            Expression::Index(index) => {
+                self.visit_expression(prefix, &mut index.base);
+                self.visit_expression(prefix, &mut index.index);
+            }
+            Expression::Closure(closure) => {
+                self.visit_function_signature(&mut closure.signature);
+
+                let parameters = parameter_names(&closure.signature);
+                self.visit_nested_block(prefix, parameters, &mut closure.body);
+            }
+        }
+    }
+
+    /// Visits a block that binds variables of its own, as the parameters of a
+    /// closure or the binding of `if let`.
+    fn visit_nested_block(
+        &mut self,
+        prefix: &str,
+        variables: Vec<String>,
+        node: &mut CodeBlockContainer,
+    ) {
+        self.scopes.push(Scope {
+            declarations: HashMap::new(),
+            variables: variables.into_iter().collect(),
+        });
+
+        self.visit_code_block(prefix, &mut node.code_block);
+        self.scopes.pop();
+    }
+
+    fn visit_if_expression(&mut self, prefix: &str, node: &mut IfExpression) {
+        self.visit_expression(prefix, &mut node.condition);
+
+        let binding = node.binding.iter().map(|name| name.name.clone()).collect();
+        self.visit_nested_block(prefix, binding, &mut node.then_branch);
+
+        match &mut node.else_clause {
+            Some(ElseClause::Else(block)) => self.visit_nested_block(prefix, Vec::new(), block),
+            Some(ElseClause::ElseIf(if_expression)) => {
+                self.visit_if_expression(prefix, if_expression)
+            }
+            None => {}
+        }
+    }
+
+    fn visit_arguments(&mut self, prefix: &str, node: &mut ArgumentList) {
+        for argument in &mut node.arguments {
+            self.visit_expression(prefix, &mut argument.expression);
+        }
+    }
+}
+
+fn parameter_names(node: &FunctionSignature) -> Vec<String> {
+    node.parameters
+        .parameters
+        .iter()
+        .map(|parameter| parameter.name.name.clone())
+        .collect()
+}
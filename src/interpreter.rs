@@ -0,0 +1,1662 @@
+use crate::builtins;
+use crate::context::*;
+use crate::syntax::*;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::io::Write;
+use std::rc::Rc;
+
+/// A value of a program run by the interpreter. Values are represented the
+/// way the code generator lays them out, arrays and dictionaries refer to
+/// their elements so copies of them share the elements.
+#[derive(Clone)]
+pub enum Value<'a> {
+    Void,
+    Bool(bool),
+    /// An integer of any type, sign extended from the size of a signed type
+    /// and zero extended from the size of an unsigned one, a `u64` keeps its
+    /// bits.
+    Integer(i64),
+    /// A floating point number, an `f32` is rounded to single precision.
+    Float(f64),
+    /// What a `*u8` points to, a string literal or one returned by a builtin.
+    String(Rc<CString>),
+    /// The arguments given to `main`, a `**u8` only C functions can read so
+    /// the interpreter does not keep them.
+    Arguments,
+    Optional(Option<Box<Value<'a>>>),
+    /// Whether the result is `ok`, and its value or its error.
+    Result(bool, Box<Value<'a>>),
+    Tuple(Vec<Value<'a>>),
+    /// The fields of a structure in declaration order.
+    Struct(Vec<Value<'a>>),
+    /// The index of a case of an enum.
+    Case(usize),
+    Array(Rc<RefCell<Vec<Value<'a>>>>),
+    /// The entries of a dictionary, its keys are compared the way the hash map
+    /// of the runtime compares them.
+    Dictionary(Rc<RefCell<Vec<(Value<'a>, Value<'a>)>>>),
+    /// A value used as a protocol, along with the name of its concrete type
+    /// the methods of the protocol are looked up on.
+    Existential(String, Box<Value<'a>>),
+    Closure(Rc<Closure<'a>>),
+}
+
+pub enum Closure<'a> {
+    /// A declared function used as a value.
+    Function(String),
+    /// A closure expression along with the variables it captured when it was
+    /// evaluated.
+    Expression {
+        node: &'a ClosureExpression,
+        captures: HashMap<String, Value<'a>>,
+        return_type: Type,
+        current_type: Option<String>,
+        substitution: HashMap<String, Type>,
+    },
+}
+
+/// Why the interpreter did not run a program to its end.
+#[derive(Debug, PartialEq)]
+pub enum RunError {
+    /// The program cannot be run, as when it has no `main` function.
+    Invalid(String),
+    /// An error stopped the program, as an index out of bounds. Compiled code
+    /// prints it to the standard error and aborts.
+    Stopped(String),
+}
+
+/// How evaluation leaves the expression being evaluated early.
+pub enum Unwind<'a> {
+    /// A `return`, or a `?` without a value, returning from the function.
+    Return(Value<'a>),
+    /// An error stopping the program, as an index out of bounds.
+    Error(String),
+}
+
+type Flow<'a, T = Value<'a>> = Result<T, Unwind<'a>>;
+
+/// A function, method or default implementation of a protocol requirement.
+#[derive(Clone, Copy)]
+struct Function<'a> {
+    signature: &'a FunctionSignature,
+    body: &'a CodeBlockContainer,
+    /// The type declaring the method, none for functions.
+    owner: Option<&'a str>,
+}
+
+/// Runs a checked program by walking its syntax tree instead of compiling it,
+/// with the semantics of the code the LLVM code generator emits for it.
+/// Extern functions are provided by the builtins, no C code can be called.
+pub struct InterpreterVisitor<'a> {
+    functions: HashMap<String, Function<'a>>,
+    named_values: HashMap<String, Value<'a>>,
+    globals: HashMap<String, Value<'a>>,
+    current_type: Option<String>,
+    return_type: Type,
+    /// The type arguments of the generic function being run.
+    substitution: HashMap<String, Type>,
+    /// The value of each string literal, evaluating a literal again gives the
+    /// same pointer as the constant of compiled code does.
+    strings: HashMap<*const String, Rc<CString>>,
+    /// The name of the source file, errors report locations in it.
+    source_name: String,
+}
+
+impl<'a> InterpreterVisitor<'a> {
+    pub fn new(source_name: String) -> Self {
+        Self {
+            functions: HashMap::new(),
+            named_values: HashMap::new(),
+            globals: HashMap::new(),
+            current_type: None,
+            return_type: Type::Void,
+            substitution: HashMap::new(),
+            strings: HashMap::new(),
+            source_name,
+        }
+    }
+
+    /// Runs the `main` function of a program with its arguments, the first
+    /// one being its name, and returns what it returns.
+    pub fn run(
+        &mut self,
+        node: &'a SourceFile,
+        arguments: &[String],
+        context: &Context,
+    ) -> Result<i32, RunError> {
+        let result = self.visit_source_file(node, context).and_then(|_| {
+            let Some(main) = self.functions.get("main").copied() else {
+                return Ok(None);
+            };
+
+            // `main` may take the count of the arguments and the arguments
+            let arguments = [Value::Integer(arguments.len() as i64), Value::Arguments];
+
+            self.call_body(
+                main,
+                None,
+                arguments.into_iter().collect(),
+                HashMap::new(),
+                context,
+            )
+            .map(Some)
+        });
+        let _ = std::io::stdout().flush();
+
+        match result {
+            Ok(Some(Value::Integer(status))) => Ok(status as i32),
+            Ok(Some(_)) => Ok(0),
+            Ok(None) => Err(RunError::Invalid(
+                "the program has no `main` function".to_string(),
+            )),
+            Err(Unwind::Error(message)) => Err(RunError::Stopped(message)),
+            Err(Unwind::Return(_)) => unreachable!(),
+        }
+    }
+
+    /// Declares the functions of a program and evaluates its globals.
+    pub fn visit_source_file(&mut self, node: &'a SourceFile, context: &Context) -> Flow<'a, ()> {
+        self.declare_functions(&node.code_block);
+        self.declare_globals(&node.code_block, context)
+    }
+
+    /// Collects every function and method by the name the code generator
+    /// gives it, as in `area` or `Vector2.scaled`, so that calls do not depend
+    /// on declaration order.
+    fn declare_functions(&mut self, node: &'a CodeBlock) {
+        for declaration in node.declarations() {
+            let (type_name, member_block) = match declaration {
+                Declaration::FunctionDeclaration(function) => {
+                    self.functions.insert(
+                        function.identifier.name.clone(),
+                        Function {
+                            signature: &function.signature,
+                            body: &function.body,
+                            owner: None,
+                        },
+                    );
+                    continue;
+                }
+                Declaration::ProtocolDeclaration(protocol) => {
+                    for requirement in &protocol.requirements {
+                        if let Some(body) = &requirement.body {
+                            self.functions.insert(
+                                format!("{}.{}", protocol.name.name, requirement.identifier.name),
+                                Function {
+                                    signature: &requirement.signature,
+                                    body,
+                                    owner: Some(&protocol.name.name),
+                                },
+                            );
+                        }
+                    }
+                    continue;
+                }
+                Declaration::TypeDeclaration(declaration) => {
+                    (&declaration.name.name, &declaration.container.member_block)
+                }
+                Declaration::EnumDeclaration(declaration) => {
+                    (&declaration.name.name, &declaration.container.member_block)
+                }
+                Declaration::ExtensionDeclaration(declaration) => {
+                    (&declaration.name.name, &declaration.container.member_block)
+                }
+                _ => continue,
+            };
+
+            for member in &member_block.members {
+                if let MemberBlockItem::FunctionDeclaration(method) = member {
+                    self.functions.insert(
+                        format!("{}.{}", type_name, method.identifier.name),
+                        Function {
+                            signature: &method.signature,
+                            body: &method.body,
+                            owner: Some(type_name),
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    /// Evaluates the top-level variables in order, those of the imported
+    /// modules included.
+    fn declare_globals(&mut self, node: &'a CodeBlock, context: &Context) -> Flow<'a, ()> {
+        for declaration in node.declarations() {
+            if let Declaration::VariableDeclaration(variable) = declaration {
+                let expression = variable
+                    .expression
+                    .as_ref()
+                    .expect("globals are initialized after type checking");
+                let value = self.visit_expression(expression, context)?;
+
+                self.globals.insert(variable.identifier.name.clone(), value);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs the body of a function with its arguments, fields are visible
+    /// unqualified in the body of a method.
+    fn call_body(
+        &mut self,
+        function: Function<'a>,
+        self_value: Option<Value<'a>>,
+        arguments: Vec<Value<'a>>,
+        substitution: HashMap<String, Type>,
+        context: &Context,
+    ) -> Flow<'a> {
+        let outer_values = std::mem::take(&mut self.named_values);
+        let outer_substitution = std::mem::replace(&mut self.substitution, substitution);
+
+        if let (Some(type_name), Some(self_value)) = (function.owner, self_value) {
+            let self_type = self.resolve(&context.lookup_type(type_name).unwrap().declared_type());
+
+            if let (Some(fields), Value::Struct(values)) = (context.fields(&self_type), &self_value)
+            {
+                for (field, value) in fields.iter().zip(values) {
+                    self.named_values.insert(field.name.clone(), value.clone());
+                }
+            }
+
+            self.named_values.insert("self".to_string(), self_value);
+        }
+
+        for (parameter, value) in function
+            .signature
+            .parameters
+            .parameters
+            .iter()
+            .zip(arguments)
+        {
+            self.named_values.insert(parameter.name.name.clone(), value);
+        }
+
+        let return_type = function
+            .signature
+            .return_clause
+            .as_ref()
+            .map(|r| self.resolve(&Type::from(r.return_type.clone())))
+            .unwrap_or(Type::Void);
+
+        let outer_type =
+            std::mem::replace(&mut self.current_type, function.owner.map(String::from));
+        let outer_return_type = std::mem::replace(&mut self.return_type, return_type.clone());
+
+        let result = self.visit_function_body(function.body, &return_type, context);
+
+        self.named_values = outer_values;
+        self.substitution = outer_substitution;
+        self.current_type = outer_type;
+        self.return_type = outer_return_type;
+
+        result
+    }
+
+    /// Returns the value of the tail expression of a function body, or the
+    /// value it returned before.
+    fn visit_function_body(
+        &mut self,
+        node: &'a CodeBlockContainer,
+        return_type: &Type,
+        context: &Context,
+    ) -> Flow<'a> {
+        match self.visit_code_block_value(&node.code_block, context) {
+            Ok(_) if *return_type == Type::Void => Ok(Value::Void),
+            Ok(value) => {
+                let Some(CodeBlockItem::Expression(tail)) = node.code_block.items.last() else {
+                    unreachable!();
+                };
+
+                let tail_type = self.expression_type(tail, context);
+                Ok(self.coerce(value, &tail_type, return_type, context))
+            }
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Replaces the type parameters of the generic function being run by its
+    /// type arguments.
+    fn resolve(&self, ty: &Type) -> Type {
+        ty.substitute(&self.substitution)
+    }
+
+    fn expression_type(&self, node: &Expression, context: &Context) -> Type {
+        self.resolve(&context.expression_type(node))
+    }
+
+    pub fn visit_code_block_item(
+        &mut self,
+        node: &'a CodeBlockItem,
+        context: &Context,
+    ) -> Flow<'a> {
+        match node {
+            CodeBlockItem::Declaration(Declaration::VariableDeclaration(variable)) => {
+                self.visit_variable_declaration(variable, context)?;
+                Ok(Value::Void)
+            }
+            // other declarations are hoisted out of function bodies
+            CodeBlockItem::Declaration(_) => Ok(Value::Void),
+            CodeBlockItem::Statement(statement) => {
+                self.visit_statement(statement, context)?;
+                Ok(Value::Void)
+            }
+            CodeBlockItem::Expression(expression) => self.visit_expression(expression, context),
+        }
+    }
+
+    pub fn visit_variable_declaration(
+        &mut self,
+        node: &'a VariableDeclaration,
+        context: &Context,
+    ) -> Flow<'a, ()> {
+        let expression = node
+            .expression
+            .as_ref()
+            .expect("variables are initialized after type checking");
+        let mut value = self.visit_expression(expression, context)?;
+
+        if let Some(type_annotation) = &node.type_annotation {
+            let value_type = self.expression_type(expression, context);
+            value = self.coerce(
+                value,
+                &value_type,
+                &Type::from(type_annotation.clone()),
+                context,
+            );
+        }
+
+        self.named_values
+            .insert(node.identifier.name.clone(), value);
+
+        Ok(())
+    }
+
+    pub fn visit_statement(&mut self, node: &'a Statement, context: &Context) -> Flow<'a, ()> {
+        match node {
+            Statement::Return(statement) => self.visit_return_statement(statement, context),
+            Statement::Assignment(statement) => self.visit_assignment_statement(statement, context),
+            Statement::Destructuring(statement) => {
+                self.visit_destructuring_statement(statement, context)
+            }
+        }
+    }
+
+    pub fn visit_destructuring_statement(
+        &mut self,
+        node: &'a DestructuringStatement,
+        context: &Context,
+    ) -> Flow<'a, ()> {
+        let mut value = self.visit_expression(&node.value, context)?;
+
+        if let Some(type_annotation) = &node.type_annotation {
+            let value_type = self.expression_type(&node.value, context);
+            value = self.coerce(
+                value,
+                &value_type,
+                &Type::from(type_annotation.clone()),
+                context,
+            );
+        }
+
+        let Value::Tuple(elements) = value else {
+            unreachable!();
+        };
+
+        for (name, element) in node.names.iter().zip(elements) {
+            if let Some(name) = name {
+                self.named_values.insert(name.name.clone(), element);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn visit_assignment_statement(
+        &mut self,
+        node: &'a AssignmentStatement,
+        context: &Context,
+    ) -> Flow<'a, ()> {
+        let Expression::Index(target) = &node.target else {
+            panic!("Only array elements can be assigned to");
+        };
+
+        if let Type::Dictionary(key_type, value_type) = self.expression_type(&target.base, context)
+        {
+            return self.visit_dictionary_assignment(
+                target,
+                &node.value,
+                &key_type,
+                &value_type,
+                context,
+            );
+        }
+
+        let Type::Array(element_type) = self.expression_type(&target.base, context) else {
+            panic!("Indexing a value that is not an array");
+        };
+
+        let (elements, index) = self.visit_element(target, context)?;
+
+        let value = self.visit_expression(&node.value, context)?;
+        let value_type = self.expression_type(&node.value, context);
+        let value = self.coerce(value, &value_type, &element_type, context);
+
+        elements.borrow_mut()[index] = value;
+
+        Ok(())
+    }
+
+    pub fn visit_return_statement(
+        &mut self,
+        node: &'a ReturnStatement,
+        context: &Context,
+    ) -> Flow<'a, ()> {
+        let value = match &node.expression {
+            Some(expression) => {
+                let value = self.visit_expression(expression, context)?;
+                let value_type = self.expression_type(expression, context);
+                let return_type = self.return_type.clone();
+                self.coerce(value, &value_type, &return_type, context)
+            }
+            None => Value::Void,
+        };
+
+        Err(Unwind::Return(value))
+    }
+
+    pub fn visit_expression(&mut self, node: &'a Expression, context: &Context) -> Flow<'a> {
+        match node {
+            Expression::BooleanLiteral(value) => Ok(Value::Bool(*value)),
+            Expression::FloatNumberLiteral(value) => {
+                let ty = self.expression_type(node, context);
+                Ok(self.visit_float_number_literal(*value, &ty))
+            }
+            Expression::StringLiteral(value) => Ok(self.visit_string_literal(value)),
+            Expression::Identifier(identifier) => Ok(self.visit_identifier(identifier, context)),
+            Expression::Tuple(tuple) => {
+                let ty = self.expression_type(node, context);
+                self.visit_tuple(tuple, &ty, context)
+            }
+            Expression::BinaryOperator(op) => self.visit_binary_operator_expression(op, context),
+            Expression::FunctionCall(function_call) => {
+                self.visit_function_call(function_call, context)
+            }
+            Expression::MethodCall(method_call) => self.visit_method_call(method_call, context),
+            Expression::MemberAccess(member_access) => {
+                self.visit_member_access(member_access, context)
+            }
+            Expression::If(if_expression) => {
+                let ty = self.expression_type(node, context);
+                self.visit_if_expression(if_expression, &ty, context)
+            }
+            Expression::Match(match_expression) => {
+                let ty = self.expression_type(node, context);
+                self.visit_match_expression(match_expression, &ty, context)
+            }
+            Expression::NilLiteral => Ok(Value::Optional(None)),
+            Expression::Result(result) => {
+                let ty = self.expression_type(node, context);
+                self.visit_result_expression(result, &ty, context)
+            }
+            Expression::Try(try_expression) => self.visit_try_expression(try_expression, context),
+            Expression::Array(array) => {
+                let ty = self.expression_type(node, context);
+                self.visit_array_expression(array, &ty, context)
+            }
+            Expression::Dictionary(dictionary) => {
+                let ty = self.expression_type(node, context);
+                self.visit_dictionary_expression(dictionary, &ty, context)
+            }
+            Expression::Index(index) => self.visit_index_expression(index, context),
+            Expression::Closure(closure) => {
+                let ty = self.expression_type(node, context);
+                Ok(self.visit_closure_expression(closure, &ty, context))
+            }
+        }
+    }
+
+    /// Captures the variables a closure uses by copying them, as the code
+    /// generator copies them to the environment of the closure.
+    pub fn visit_closure_expression(
+        &mut self,
+        node: &'a ClosureExpression,
+        ty: &Type,
+        context: &Context,
+    ) -> Value<'a> {
+        let Type::Function(function) = ty else {
+            unreachable!();
+        };
+
+        let captures = context
+            .captures(node)
+            .iter()
+            .filter_map(|name| Some((name.clone(), self.named_values.get(name)?.clone())))
+            .collect();
+
+        Value::Closure(Rc::new(Closure::Expression {
+            node,
+            captures,
+            return_type: self.resolve(&function.return_type),
+            current_type: self.current_type.clone(),
+            substitution: self.substitution.clone(),
+        }))
+    }
+
+    /// Calls a closure or a declared function used as a value.
+    fn call_closure(
+        &mut self,
+        closure: Value<'a>,
+        arguments: Vec<Value<'a>>,
+        context: &Context,
+    ) -> Flow<'a> {
+        let Value::Closure(closure) = closure else {
+            unreachable!();
+        };
+
+        let Closure::Expression {
+            node,
+            captures,
+            return_type,
+            current_type,
+            substitution,
+        } = closure.as_ref()
+        else {
+            let Closure::Function(name) = closure.as_ref() else {
+                unreachable!();
+            };
+
+            return self.call_function(name, arguments, HashMap::new(), context);
+        };
+
+        let outer_values = std::mem::replace(&mut self.named_values, captures.clone());
+        let outer_substitution = std::mem::replace(&mut self.substitution, substitution.clone());
+        let outer_type = std::mem::replace(&mut self.current_type, current_type.clone());
+        let outer_return_type = std::mem::replace(&mut self.return_type, return_type.clone());
+
+        for (parameter, value) in node.signature.parameters.parameters.iter().zip(arguments) {
+            self.named_values.insert(parameter.name.name.clone(), value);
+        }
+
+        let result = self.visit_function_body(&node.body, return_type, context);
+
+        self.named_values = outer_values;
+        self.substitution = outer_substitution;
+        self.current_type = outer_type;
+        self.return_type = outer_return_type;
+
+        result
+    }
+
+    pub fn visit_array_expression(
+        &mut self,
+        node: &'a ArrayExpression,
+        ty: &Type,
+        context: &Context,
+    ) -> Flow<'a> {
+        let Type::Array(element_type) = ty else {
+            panic!("Array literal of type {}", ty);
+        };
+
+        let mut elements = Vec::new();
+
+        for element in &node.elements {
+            let value = self.visit_expression(element, context)?;
+            let value_type = self.expression_type(element, context);
+            elements.push(self.coerce(value, &value_type, element_type, context));
+        }
+
+        Ok(Value::Array(Rc::new(RefCell::new(elements))))
+    }
+
+    pub fn visit_index_expression(
+        &mut self,
+        node: &'a IndexExpression,
+        context: &Context,
+    ) -> Flow<'a> {
+        if let Type::Dictionary(key_type, value_type) = self.expression_type(&node.base, context) {
+            return self.visit_dictionary_get(node, &key_type, &value_type, context);
+        }
+
+        let (elements, index) = self.visit_element(node, context)?;
+        let element = elements.borrow()[index].clone();
+
+        Ok(element)
+    }
+
+    /// The elements of an array and the index of one of them, checked against
+    /// the count. There is no memory to read past the end of an array, indices
+    /// are checked with or without bounds checks.
+    fn visit_element(
+        &mut self,
+        node: &'a IndexExpression,
+        context: &Context,
+    ) -> Flow<'a, (Rc<RefCell<Vec<Value<'a>>>>, usize)> {
+        let Value::Array(elements) = self.visit_expression(&node.base, context)? else {
+            panic!("Indexing a value that is not an array");
+        };
+        let Value::Integer(index) = self.visit_expression(&node.index, context)? else {
+            panic!("Indexing an array with a value that is not an integer");
+        };
+
+        let count = elements.borrow().len();
+
+        // negative indices are out of bounds too
+        if index as u64 >= count as u64 {
+            return Err(Unwind::Error(format!(
+                "{}:{}:{}: index {} is out of bounds for an array of {} elements",
+                self.source_name, node.location.line, node.location.column, index, count
+            )));
+        }
+
+        Ok((elements, index as usize))
+    }
+
+    pub fn visit_dictionary_expression(
+        &mut self,
+        node: &'a DictionaryExpression,
+        ty: &Type,
+        context: &Context,
+    ) -> Flow<'a> {
+        let Type::Dictionary(key_type, value_type) = self.resolve(ty) else {
+            panic!("Dictionary literal of type {}", ty);
+        };
+
+        let entries = Rc::new(RefCell::new(Vec::new()));
+
+        for (key, value) in &node.entries {
+            let key = self.visit_key(key, &key_type, context)?;
+
+            let value_llvm = self.visit_expression(value, context)?;
+            let ty = self.expression_type(value, context);
+            let value = self.coerce(value_llvm, &ty, &value_type, context);
+
+            self.dictionary_insert(&entries, key, value, &key_type, context)?;
+        }
+
+        Ok(Value::Dictionary(entries))
+    }
+
+    /// Looks a key up, the value is copied out of the dictionary when it holds
+    /// the key and nil otherwise.
+    fn visit_dictionary_get(
+        &mut self,
+        node: &'a IndexExpression,
+        key_type: &Type,
+        value_type: &Type,
+        context: &Context,
+    ) -> Flow<'a> {
+        let Value::Dictionary(entries) = self.visit_expression(&node.base, context)? else {
+            unreachable!();
+        };
+        let key = self.visit_key(&node.index, key_type, context)?;
+
+        let Some(position) = self.dictionary_position(&entries, &key, key_type, context)? else {
+            return Ok(Value::Optional(None));
+        };
+
+        let value = entries.borrow()[position].1.clone();
+        let result_type = value_type.clone().or_nil();
+
+        Ok(self.coerce(value, value_type, &result_type, context))
+    }
+
+    /// Inserts the value for the key or, when the value is nil, removes the key.
+    fn visit_dictionary_assignment(
+        &mut self,
+        target: &'a IndexExpression,
+        value: &'a Expression,
+        key_type: &Type,
+        value_type: &Type,
+        context: &Context,
+    ) -> Flow<'a, ()> {
+        let Value::Dictionary(entries) = self.visit_expression(&target.base, context)? else {
+            unreachable!();
+        };
+        let key = self.visit_key(&target.index, key_type, context)?;
+
+        let optional_type = value_type.clone().or_nil();
+        let value_value = self.visit_expression(value, context)?;
+        let ty = self.expression_type(value, context);
+        let optional = self.coerce(value_value, &ty, &optional_type, context);
+
+        let Value::Optional(Some(wrapped)) = optional else {
+            if let Some(position) = self.dictionary_position(&entries, &key, key_type, context)? {
+                entries.borrow_mut().remove(position);
+            }
+
+            return Ok(());
+        };
+
+        // a dictionary of optionals stores the optional itself
+        let stored = match value_type == &optional_type {
+            true => Value::Optional(Some(wrapped)),
+            false => *wrapped,
+        };
+
+        self.dictionary_insert(&entries, key, stored, key_type, context)
+    }
+
+    fn dictionary_insert(
+        &mut self,
+        entries: &Rc<RefCell<Vec<(Value<'a>, Value<'a>)>>>,
+        key: Value<'a>,
+        value: Value<'a>,
+        key_type: &Type,
+        context: &Context,
+    ) -> Flow<'a, ()> {
+        match self.dictionary_position(entries, &key, key_type, context)? {
+            Some(position) => entries.borrow_mut()[position].1 = value,
+            None => entries.borrow_mut().push((key, value)),
+        }
+
+        Ok(())
+    }
+
+    /// The position of the entry of a key, strings are compared by their
+    /// contents, structures with their `equals` method and other keys by value.
+    fn dictionary_position(
+        &mut self,
+        entries: &Rc<RefCell<Vec<(Value<'a>, Value<'a>)>>>,
+        key: &Value<'a>,
+        key_type: &Type,
+        context: &Context,
+    ) -> Flow<'a, Option<usize>> {
+        let count = entries.borrow().len();
+
+        for position in 0..count {
+            let other = entries.borrow()[position].0.clone();
+
+            let equal = match (key, &other) {
+                (Value::String(a), Value::String(b)) => a == b,
+                (Value::Struct(_), _) => {
+                    let method = context.lookup_method(key_type, "equals").unwrap();
+
+                    match self.call_method(key_type, other, &method, vec![key.clone()], context)? {
+                        Value::Bool(equal) => equal,
+                        _ => unreachable!(),
+                    }
+                }
+                (a, b) => scalar(a) == scalar(b),
+            };
+
+            if equal {
+                return Ok(Some(position));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn visit_key(&mut self, node: &'a Expression, key_type: &Type, context: &Context) -> Flow<'a> {
+        let key = self.visit_expression(node, context)?;
+        let ty = self.expression_type(node, context);
+
+        Ok(self.coerce(key, &ty, key_type, context))
+    }
+
+    /// The value of the branch taken is converted to the type `ty` of the if,
+    /// which can be a protocol the values of the branches conform to.
+    pub fn visit_if_expression(
+        &mut self,
+        node: &'a IfExpression,
+        ty: &Type,
+        context: &Context,
+    ) -> Flow<'a> {
+        let condition = self.visit_expression(&node.condition, context)?;
+
+        // `if let` runs the then branch when the optional holds a value
+        let (taken, binding) = match (&node.binding, condition) {
+            (Some(binding), Value::Optional(value)) => {
+                (value.is_some(), value.map(|v| (binding, v)))
+            }
+            (_, Value::Bool(condition)) => (condition, None),
+            _ => unreachable!(),
+        };
+
+        if taken {
+            let outer_values = self.named_values.clone();
+
+            if let Some((binding, value)) = binding {
+                self.named_values.insert(binding.name.clone(), *value);
+            }
+
+            let value = self.visit_branch_value(&node.then_branch.code_block, ty, context);
+            self.named_values = outer_values;
+
+            return value;
+        }
+
+        match &node.else_clause {
+            Some(ElseClause::Else(container)) => {
+                self.visit_branch_value(&container.code_block, ty, context)
+            }
+            Some(ElseClause::ElseIf(if_expression)) => {
+                self.visit_if_expression(if_expression, ty, context)
+            }
+            None => Ok(Value::Void),
+        }
+    }
+
+    pub fn visit_result_expression(
+        &mut self,
+        node: &'a ResultExpression,
+        ty: &Type,
+        context: &Context,
+    ) -> Flow<'a> {
+        let Type::Result(value, error) = ty else {
+            panic!("Result built as a value of type {}", ty);
+        };
+
+        let payload_type = match node.variant {
+            ResultVariant::Ok => value,
+            ResultVariant::Err => error,
+        };
+
+        let payload = self.visit_expression(&node.value, context)?;
+        let payload = self.coerce(
+            payload,
+            &self.expression_type(&node.value, context),
+            payload_type,
+            context,
+        );
+
+        Ok(Value::Result(
+            node.variant == ResultVariant::Ok,
+            Box::new(payload),
+        ))
+    }
+
+    /// Continues with the value of a result or an optional, or returns its
+    /// error or nil from the current function.
+    pub fn visit_try_expression(&mut self, node: &'a TryExpression, context: &Context) -> Flow<'a> {
+        let operand = self.visit_expression(&node.expression, context)?;
+        let operand_type = self.expression_type(&node.expression, context);
+        let return_type = self.return_type.clone();
+
+        match (operand, &operand_type, &return_type) {
+            (Value::Optional(Some(value)) | Value::Result(true, value), ..) => Ok(*value),
+            (
+                Value::Result(false, error),
+                Type::Result(_, error_type),
+                Type::Result(_, returned),
+            ) => {
+                let error = self.coerce(*error, error_type, returned, context);
+                Err(Unwind::Return(Value::Result(false, Box::new(error))))
+            }
+            _ => Err(Unwind::Return(Value::Optional(None))),
+        }
+    }
+
+    /// Runs the first arm whose pattern matches, the type checker made sure
+    /// that one always does.
+    pub fn visit_match_expression(
+        &mut self,
+        node: &'a MatchExpression,
+        ty: &Type,
+        context: &Context,
+    ) -> Flow<'a> {
+        let subject = self.visit_expression(&node.subject, context)?;
+        let subject_type = self.expression_type(&node.subject, context);
+
+        for arm in &node.arms {
+            if !self.visit_pattern(&arm.pattern, &subject, &subject_type, context)? {
+                continue;
+            }
+
+            let outer_values = self.named_values.clone();
+
+            match (&arm.pattern, &subject) {
+                (Pattern::Binding(name), Value::Optional(Some(value))) => {
+                    self.named_values
+                        .insert(name.name.clone(), value.as_ref().clone());
+                }
+                (Pattern::Binding(name), value) => {
+                    self.named_values.insert(name.name.clone(), value.clone());
+                }
+                (Pattern::Result(_, Some(name)), Value::Result(_, payload)) => {
+                    self.named_values
+                        .insert(name.name.clone(), payload.as_ref().clone());
+                }
+                _ => {}
+            }
+
+            let value = self.visit_branch_value(&arm.body.code_block, ty, context);
+            self.named_values = outer_values;
+
+            return value;
+        }
+
+        Err(Unwind::Error("no arm of the match matched".to_string()))
+    }
+
+    /// Whether a pattern matches the subject of a match.
+    fn visit_pattern(
+        &mut self,
+        pattern: &'a Pattern,
+        subject: &Value<'a>,
+        subject_type: &Type,
+        context: &Context,
+    ) -> Flow<'a, bool> {
+        Ok(match (pattern, subject) {
+            (Pattern::Binding(_), Value::Optional(value)) => value.is_some(),
+            (Pattern::Wildcard | Pattern::Binding(_), _) => true,
+            (Pattern::Nil, Value::Optional(value)) => value.is_none(),
+            (Pattern::Result(variant, _), Value::Result(ok, _)) => {
+                *ok == (*variant == ResultVariant::Ok)
+            }
+            (Pattern::EnumCase(case), Value::Case(index)) => {
+                let Some(TypeDefinitionKind::Enumeration(cases)) = subject_type
+                    .nominal()
+                    .and_then(|name| context.lookup_type(name))
+                    .map(|d| &d.kind)
+                else {
+                    panic!("Type {} has no case {:?}", subject_type, case.name);
+                };
+
+                cases.iter().position(|c| *c == case.name) == Some(*index)
+            }
+            (Pattern::Literal(literal), _) => {
+                let value = self.visit_expression(literal, context)?;
+                compare(&BinaryOperator::Equal, subject_type, subject, &value)
+            }
+            _ => panic!("Pattern {:?} cannot match a {}", pattern, subject_type),
+        })
+    }
+
+    /// Runs the items of a nested block and returns the value of its tail
+    /// expression, void when the block does not produce a value. Variables
+    /// declared inside the block are not visible once it ends.
+    /// The value of a branch of an if or a match, converted to the type `ty`
+    /// of the whole expression.
+    fn visit_branch_value(
+        &mut self,
+        node: &'a CodeBlock,
+        ty: &Type,
+        context: &Context,
+    ) -> Flow<'a> {
+        let value = self.visit_code_block_value(node, context)?;
+
+        match node.items.last() {
+            Some(CodeBlockItem::Expression(tail)) => {
+                let from = self.expression_type(tail, context);
+                Ok(self.coerce(value, &from, ty, context))
+            }
+            _ => Ok(value),
+        }
+    }
+
+    pub fn visit_code_block_value(&mut self, node: &'a CodeBlock, context: &Context) -> Flow<'a> {
+        let outer_values = self.named_values.clone();
+        let mut result = Ok(Value::Void);
+
+        for (i, item) in node.items.iter().enumerate() {
+            result = match item {
+                CodeBlockItem::Expression(expr) if i == node.items.len() - 1 => {
+                    self.visit_expression(expr, context)
+                }
+                _ => self
+                    .visit_code_block_item(item, context)
+                    .map(|_| Value::Void),
+            };
+
+            if result.is_err() {
+                break;
+            }
+        }
+
+        self.named_values = outer_values;
+
+        result
+    }
+
+    pub fn visit_function_call(
+        &mut self,
+        node: &'a FunctionCallExpression,
+        context: &Context,
+    ) -> Flow<'a> {
+        if let Some(function) = context.indirect_call(node) {
+            let function = function.substitute(&self.substitution);
+            let closure = self.visit_identifier(&node.function, context);
+            let arguments = self.visit_arguments(&function, &node.arguments, context)?;
+            return self.call_closure(closure, arguments, context);
+        }
+
+        // methods of the current type can be called without a receiver
+        if let Some(type_name) = self.current_type.clone() {
+            let self_type = self.resolve(&context.lookup_type(&type_name).unwrap().declared_type());
+            let method = context.lookup_method(&self_type, &node.function.name);
+
+            if let Some(method) = method {
+                let receiver = self.named_values["self"].clone();
+                let arguments = self.visit_arguments(&method.function, &node.arguments, context)?;
+
+                // in a default implementation self is a value of the protocol
+                if context.is_protocol(&type_name) {
+                    return self.call_dynamic(receiver, &method.name, arguments, context);
+                }
+
+                return self.call_method(&self_type, receiver, &method, arguments, context);
+            }
+        }
+
+        let signature = match context.lookup(node.function.name.clone()) {
+            Some(Symbol {
+                symbol_type: Type::Function(signature),
+                ..
+            }) => signature,
+            _ => return self.visit_initializer(node, context),
+        };
+
+        if signature.generic_parameters.is_empty() {
+            let arguments = self.visit_arguments(&signature, &node.arguments, context)?;
+            return self.call_function(&node.function.name, arguments, HashMap::new(), context);
+        }
+
+        // a generic function runs with the type arguments the type checker inferred
+        let type_arguments = context
+            .generic_arguments(node)
+            .iter()
+            .map(|argument| self.resolve(argument))
+            .collect::<Vec<_>>();
+
+        let substitution = signature.substitution(&type_arguments);
+        let function = signature.substitute(&substitution);
+        let arguments = self.visit_arguments(&function, &node.arguments, context)?;
+
+        self.call_function(&node.function.name, arguments, substitution, context)
+    }
+
+    /// Calls a declared function, or the builtin of an extern function.
+    fn call_function(
+        &mut self,
+        name: &str,
+        arguments: Vec<Value<'a>>,
+        substitution: HashMap<String, Type>,
+        context: &Context,
+    ) -> Flow<'a> {
+        match self.functions.get(name).copied() {
+            Some(function) => self.call_body(function, None, arguments, substitution, context),
+            None => call_extern(name, &arguments),
+        }
+    }
+
+    /// Builds a value of a type from its fields given in declaration order.
+    pub fn visit_initializer(
+        &mut self,
+        node: &'a FunctionCallExpression,
+        context: &Context,
+    ) -> Flow<'a> {
+        let type_name = &node.function.name;
+
+        let ty = match context.generic_arguments(node) {
+            [] => Type::Identifier(type_name.clone()),
+            arguments => Type::Generic(
+                type_name.clone(),
+                arguments.iter().map(|a| self.resolve(a)).collect(),
+            ),
+        };
+
+        let Some(fields) = context.fields(&ty) else {
+            panic!("Function {:?} not registered", type_name);
+        };
+
+        let mut values = Vec::new();
+
+        for (field, argument) in fields.iter().zip(&node.arguments.arguments) {
+            let value = self.visit_expression(&argument.expression, context)?;
+            let argument_type = self.expression_type(&argument.expression, context);
+            values.push(self.coerce(value, &argument_type, &field.ty, context));
+        }
+
+        Ok(Value::Struct(values))
+    }
+
+    pub fn visit_method_call(
+        &mut self,
+        node: &'a MethodCallExpression,
+        context: &Context,
+    ) -> Flow<'a> {
+        // selfless functions are called on the type, as in `LibMath.add(1, 2)`
+        if let Some(type_name) = self.static_type_name(&node.receiver, context) {
+            let method = context
+                .lookup_type(&type_name)
+                .and_then(|definition| definition.method(&node.method.name))
+                .unwrap()
+                .clone();
+
+            let arguments = self.visit_arguments(&method.function, &node.arguments, context)?;
+
+            return match &method.implementation {
+                MethodImplementation::External(symbol) => call_extern(symbol, &arguments),
+                _ => {
+                    let name = format!("{}.{}", type_name, method.name);
+                    let function = self.functions[&name];
+                    self.call_body(function, None, arguments, HashMap::new(), context)
+                }
+            };
+        }
+
+        let receiver = self.visit_expression(&node.receiver, context)?;
+
+        // `?.` calls the method on the value of the optional if there is one
+        if let (true, Type::Optional(wrapped)) =
+            (node.optional, self.expression_type(&node.receiver, context))
+        {
+            let Some(method) = context.lookup_method(&wrapped, &node.method.name) else {
+                let function = self.function_field(&wrapped, &node.method.name, context);
+                let return_type = function.return_type.as_ref().clone();
+
+                return self.optional_chain(receiver, &return_type, context, |this, receiver| {
+                    let closure = this.field(receiver, &wrapped, &node.method.name, context);
+                    let arguments = this.visit_arguments(&function, &node.arguments, context)?;
+                    this.call_closure(closure, arguments, context)
+                });
+            };
+            let return_type = method.function.return_type.as_ref().clone();
+
+            return self.optional_chain(receiver, &return_type, context, |this, receiver| {
+                this.visit_method(&wrapped, receiver, &method, node, context)
+            });
+        }
+
+        let receiver_type = self.expression_type(&node.receiver, context);
+
+        // a field holding a closure is called like a method
+        let Some(method) = context.lookup_method(&receiver_type, &node.method.name) else {
+            let function = self.function_field(&receiver_type, &node.method.name, context);
+            let closure = self.field(receiver, &receiver_type, &node.method.name, context);
+            let arguments = self.visit_arguments(&function, &node.arguments, context)?;
+            return self.call_closure(closure, arguments, context);
+        };
+
+        self.visit_method(&receiver_type, receiver, &method, node, context)
+    }
+
+    /// Calls a method on a value, protocol methods are dispatched dynamically.
+    fn visit_method(
+        &mut self,
+        receiver_type: &Type,
+        receiver: Value<'a>,
+        method: &Method,
+        node: &'a MethodCallExpression,
+        context: &Context,
+    ) -> Flow<'a> {
+        let arguments = self.visit_arguments(&method.function, &node.arguments, context)?;
+
+        if let Type::Identifier(type_name) = receiver_type {
+            if context.is_protocol(type_name) {
+                return self.call_dynamic(receiver, &method.name, arguments, context);
+            }
+        }
+
+        self.call_method(receiver_type, receiver, method, arguments, context)
+    }
+
+    /// Calls the implementation of a method of a concrete type, a method
+    /// inherited from a protocol runs its default implementation with the
+    /// receiver as a value of the protocol.
+    fn call_method(
+        &mut self,
+        receiver_type: &Type,
+        receiver: Value<'a>,
+        method: &Method,
+        arguments: Vec<Value<'a>>,
+        context: &Context,
+    ) -> Flow<'a> {
+        let type_name = receiver_type.nominal().unwrap();
+
+        if let MethodImplementation::Default(protocol_name) = &method.implementation {
+            let function = self.functions[&format!("{}.{}", protocol_name, method.name)];
+            let existential = Value::Existential(type_name.to_string(), Box::new(receiver));
+
+            return self.call_body(
+                function,
+                Some(existential),
+                arguments,
+                HashMap::new(),
+                context,
+            );
+        }
+
+        // the methods of a generic type run with its type arguments
+        let substitution = match receiver_type {
+            Type::Generic(_, type_arguments) => context
+                .lookup_type(type_name)
+                .unwrap()
+                .substitution(type_arguments),
+            _ => HashMap::new(),
+        };
+
+        let function = self.functions[&format!("{}.{}", type_name, method.name)];
+        self.call_body(function, Some(receiver), arguments, substitution, context)
+    }
+
+    /// Calls a protocol method on an existential, on the concrete type it holds.
+    fn call_dynamic(
+        &mut self,
+        existential: Value<'a>,
+        name: &str,
+        arguments: Vec<Value<'a>>,
+        context: &Context,
+    ) -> Flow<'a> {
+        let Value::Existential(concrete, value) = existential else {
+            panic!("Protocol method {:?} called on a concrete value", name);
+        };
+
+        let concrete_type = Type::Identifier(concrete);
+        let method = context.lookup_method(&concrete_type, name).unwrap();
+
+        self.call_method(&concrete_type, *value, &method, arguments, context)
+    }
+
+    fn visit_arguments(
+        &mut self,
+        function: &TypeFunction,
+        node: &'a ArgumentList,
+        context: &Context,
+    ) -> Flow<'a, Vec<Value<'a>>> {
+        let mut arguments = Vec::new();
+
+        for (parameter, argument) in function.parameters.iter().zip(&node.arguments) {
+            let value = self.visit_expression(&argument.expression, context)?;
+            let argument_type = self.expression_type(&argument.expression, context);
+            arguments.push(self.coerce(value, &argument_type, &parameter.ty, context));
+        }
+
+        // the extra arguments of a variadic function are promoted to integers
+        // the way C promotes them
+        for argument in node.arguments.iter().skip(function.parameters.len()) {
+            let value = match self.visit_expression(&argument.expression, context)? {
+                Value::Bool(value) => Value::Integer(value as i64),
+                Value::Case(index) => Value::Integer(index as i64),
+                value => value,
+            };
+
+            arguments.push(value);
+        }
+
+        Ok(arguments)
+    }
+
+    pub fn visit_member_access(
+        &mut self,
+        node: &'a MemberAccessExpression,
+        context: &Context,
+    ) -> Flow<'a> {
+        if let Some(type_name) = self.static_type_name(&node.base, context) {
+            let Some(TypeDefinitionKind::Enumeration(cases)) =
+                context.lookup_type(&type_name).map(|d| &d.kind)
+            else {
+                panic!("Type {:?} has no case {:?}", type_name, node.member.name);
+            };
+
+            let index = cases
+                .iter()
+                .position(|case| *case == node.member.name)
+                .unwrap();
+
+            return Ok(Value::Case(index));
+        }
+
+        let base = self.visit_expression(&node.base, context)?;
+
+        // `?.` reads the field of the value of the optional if there is one
+        if let (true, Type::Optional(wrapped)) =
+            (node.optional, self.expression_type(&node.base, context))
+        {
+            let field_type = self.field_type(&wrapped, &node.member.name, context);
+
+            return self.optional_chain(base, &field_type, context, |this, base| {
+                Ok(this.field(base, &wrapped, &node.member.name, context))
+            });
+        }
+
+        let base_type = self.expression_type(&node.base, context);
+
+        Ok(self.field(base, &base_type, &node.member.name, context))
+    }
+
+    fn function_field(&self, ty: &Type, name: &str, context: &Context) -> TypeFunction {
+        match self.field_type(ty, name, context) {
+            Type::Function(function) => function,
+            ty => panic!("Field {:?} of type {} is called as a method", name, ty),
+        }
+    }
+
+    fn field_type(&self, ty: &Type, name: &str, context: &Context) -> Type {
+        let Some(fields) = context.fields(ty) else {
+            panic!("Type {} has no field {:?}", ty, name);
+        };
+
+        fields
+            .into_iter()
+            .find(|field| field.name == name)
+            .unwrap()
+            .ty
+    }
+
+    fn field(&self, base: Value<'a>, ty: &Type, name: &str, context: &Context) -> Value<'a> {
+        let values = match base {
+            // the count is the only member of an array or a dictionary
+            Value::Array(elements) => return Value::Integer(elements.borrow().len() as i64),
+            Value::Dictionary(entries) => return Value::Integer(entries.borrow().len() as i64),
+            Value::Struct(values) | Value::Tuple(values) => values,
+            _ => panic!("Type {} has no field {:?}", ty, name),
+        };
+
+        let Some(fields) = context.fields(ty) else {
+            panic!("Type {} has no field {:?}", ty, name);
+        };
+
+        let index = fields.iter().position(|field| field.name == name).unwrap();
+
+        values.into_iter().nth(index).unwrap()
+    }
+
+    /// The type named by the base of a member access when it is not a value,
+    /// as in `Color.red`.
+    fn static_type_name(&self, node: &Expression, context: &Context) -> Option<String> {
+        let Expression::Identifier(identifier) = node else {
+            return None;
+        };
+
+        if self.named_values.contains_key(&identifier.name) {
+            return None;
+        }
+
+        context
+            .lookup_type(&identifier.name)
+            .map(|definition| definition.name.clone())
+    }
+
+    /// Converts a value to the type it is used as, concrete values used as a
+    /// protocol are wrapped into an existential and values used as an optional
+    /// into the optional.
+    fn coerce(&self, value: Value<'a>, from: &Type, to: &Type, context: &Context) -> Value<'a> {
+        match (&self.resolve(from), &self.resolve(to)) {
+            (Type::Identifier(concrete), Type::Identifier(protocol))
+                if concrete != protocol && context.is_protocol(protocol) =>
+            {
+                Value::Existential(concrete.clone(), Box::new(value))
+            }
+            (from, Type::Optional(wrapped)) if !matches!(from, Type::Optional(_)) => {
+                let value = self.coerce(value, from, wrapped, context);
+                Value::Optional(Some(Box::new(value)))
+            }
+            _ => value,
+        }
+    }
+
+    /// Applies `visit` to the value of an optional when it is not nil, the
+    /// result of type `value_type` is wrapped into an optional unless it
+    /// already is one. Void results are only computed for their side effects.
+    fn optional_chain(
+        &mut self,
+        optional: Value<'a>,
+        value_type: &Type,
+        context: &Context,
+        visit: impl FnOnce(&mut Self, Value<'a>) -> Flow<'a>,
+    ) -> Flow<'a> {
+        let result_type = value_type.clone().or_nil();
+
+        let Value::Optional(value) = optional else {
+            unreachable!();
+        };
+
+        let Some(value) = value else {
+            return Ok(match result_type {
+                Type::Void => Value::Void,
+                _ => Value::Optional(None),
+            });
+        };
+
+        let value = visit(self, *value)?;
+
+        if result_type == Type::Void {
+            return Ok(Value::Void);
+        }
+
+        Ok(self.coerce(value, value_type, &result_type, context))
+    }
+
+    /// Number literals are f64 unless the type checker gave them the type of
+    /// the value they are used as.
+    pub fn visit_float_number_literal(&self, value: f64, ty: &Type) -> Value<'a> {
+        if ty.is_signed_integer() {
+            return Value::Integer(wrap(value as i64, ty));
+        }
+
+        if ty.is_integer() {
+            return Value::Integer(wrap(value as u64 as i64, ty));
+        }
+
+        Value::Float(round(value, ty))
+    }
+
+    pub fn visit_string_literal(&mut self, value: &'a String) -> Value<'a> {
+        let string = self
+            .strings
+            .entry(value as *const _)
+            .or_insert_with(|| Rc::new(CString::new(value.as_str()).unwrap()));
+
+        Value::String(string.clone())
+    }
+
+    pub fn visit_identifier(&self, identifier: &Identifier, context: &Context) -> Value<'a> {
+        if let Some(value) = self.named_values.get(&identifier.name) {
+            value.clone()
+        } else if let Some(value) = self.globals.get(&identifier.name) {
+            value.clone()
+        } else if let Some(Symbol {
+            symbol_type: Type::Function(_),
+            ..
+        }) = context.lookup(identifier.name.clone())
+        {
+            Value::Closure(Rc::new(Closure::Function(identifier.name.clone())))
+        } else {
+            panic!("Unknown identifier: {}", identifier.name);
+        }
+    }
+
+    /// Builds a tuple of the elements, converted to the element types of the
+    /// tuple it is used as.
+    pub fn visit_tuple(
+        &mut self,
+        tuple: &'a TupleExpression,
+        ty: &Type,
+        context: &Context,
+    ) -> Flow<'a> {
+        let Type::Tuple(element_types) = self.resolve(ty) else {
+            panic!("Tuple of type {}", ty);
+        };
+
+        let mut values = Vec::new();
+
+        for (element, element_type) in tuple.expressions.items.iter().zip(&element_types) {
+            let value = self.visit_expression(element, context)?;
+            let value_type = self.expression_type(element, context);
+            values.push(self.coerce(value, &value_type, element_type, context));
+        }
+
+        Ok(Value::Tuple(values))
+    }
+
+    pub fn visit_binary_operator_expression(
+        &mut self,
+        node: &'a BinaryOperatorExpression,
+        context: &Context,
+    ) -> Flow<'a> {
+        if let BinaryOperator::Coalesce = node.operator {
+            return self.visit_coalesce(node, context);
+        }
+
+        let lhs = self.visit_expression(&node.left, context)?;
+        let rhs = self.visit_expression(&node.right, context)?;
+        let operand_type = self.expression_type(&node.left, context);
+
+        // optionals are only compared to nil
+        if let Type::Optional(_) = &operand_type {
+            let optional = match *node.left {
+                Expression::NilLiteral => rhs,
+                _ => lhs,
+            };
+            let Value::Optional(value) = optional else {
+                unreachable!();
+            };
+
+            return Ok(match node.operator {
+                BinaryOperator::Equal => Value::Bool(value.is_none()),
+                _ => Value::Bool(value.is_some()),
+            });
+        }
+
+        if node.operator.is_comparison() {
+            return Ok(Value::Bool(compare(
+                &node.operator,
+                &operand_type,
+                &lhs,
+                &rhs,
+            )));
+        }
+
+        match (lhs, rhs) {
+            (Value::Integer(lhs), Value::Integer(rhs)) => {
+                let value = match node.operator {
+                    BinaryOperator::Add => lhs.wrapping_add(rhs),
+                    BinaryOperator::Subtract => lhs.wrapping_sub(rhs),
+                    BinaryOperator::Multiply => lhs.wrapping_mul(rhs),
+                    _ if rhs == 0 => return Err(Unwind::Error("division by zero".to_string())),
+                    BinaryOperator::Divide if operand_type.is_signed_integer() => {
+                        lhs.wrapping_div(rhs)
+                    }
+                    BinaryOperator::Divide => (lhs as u64 / rhs as u64) as i64,
+                    _ => unreachable!(),
+                };
+
+                Ok(Value::Integer(wrap(value, &operand_type)))
+            }
+            (Value::Float(lhs), Value::Float(rhs)) => {
+                let value = match node.operator {
+                    BinaryOperator::Add => lhs + rhs,
+                    BinaryOperator::Subtract => lhs - rhs,
+                    BinaryOperator::Multiply => lhs * rhs,
+                    BinaryOperator::Divide => lhs / rhs,
+                    _ => unreachable!(),
+                };
+
+                Ok(Value::Float(round(value, &operand_type)))
+            }
+            _ => panic!("Operator {:?} applied to a {}", node.operator, operand_type),
+        }
+    }
+
+    /// The default of `??` is only evaluated when the optional is nil.
+    fn visit_coalesce(
+        &mut self,
+        node: &'a BinaryOperatorExpression,
+        context: &Context,
+    ) -> Flow<'a> {
+        let optional = self.visit_expression(&node.left, context)?;
+        let optional_type = self.expression_type(&node.left, context);
+        let default_type = self.expression_type(&node.right, context);
+
+        let Type::Optional(wrapped) = &optional_type else {
+            panic!("?? applied to a value of type {}", optional_type);
+        };
+
+        // the result is optional when the default is
+        let result_type = if context.is_assignable(&default_type, wrapped) {
+            wrapped.as_ref().clone()
+        } else {
+            optional_type.clone()
+        };
+
+        match optional {
+            Value::Optional(Some(value)) if result_type != optional_type => Ok(*value),
+            optional @ Value::Optional(Some(_)) => Ok(optional),
+            _ => {
+                let default = self.visit_expression(&node.right, context)?;
+                Ok(self.coerce(default, &default_type, &result_type, context))
+            }
+        }
+    }
+}
+
+/// Calls the builtin implementing an extern function.
+fn call_extern<'a>(name: &str, arguments: &[Value<'a>]) -> Flow<'a> {
+    match builtins::call(name, arguments) {
+        Some(result) => result.map_err(Unwind::Error),
+        None => Err(Unwind::Error(format!(
+            "the interpreter does not provide the extern function {:?}",
+            name
+        ))),
+    }
+}
+
+/// Converts an integer to the size of its type, as the registers of compiled
+/// code hold it.
+fn wrap(value: i64, ty: &Type) -> i64 {
+    let Type::Identifier(name) = ty else {
+        return value;
+    };
+
+    match name.as_str() {
+        "i8" => value as i8 as i64,
+        "i16" => value as i16 as i64,
+        "i32" => value as i32 as i64,
+        "u8" => value as u8 as i64,
+        "u16" => value as u16 as i64,
+        "u32" => value as u32 as i64,
+        _ => value,
+    }
+}
+
+/// Rounds a number to single precision for an `f32`.
+fn round(value: f64, ty: &Type) -> f64 {
+    match ty {
+        Type::Identifier(name) if name == "f32" => value as f32 as f64,
+        _ => value,
+    }
+}
+
+/// The bits of a value that is not a floating point number, bools, enum
+/// cases and pointers compare like unsigned integers.
+fn scalar(value: &Value) -> i64 {
+    match value {
+        Value::Integer(value) => *value,
+        Value::Bool(value) => *value as i64,
+        Value::Case(index) => *index as i64,
+        Value::String(string) => string.as_ptr() as i64,
+        _ => panic!("Value compared as a scalar"),
+    }
+}
+
+fn compare(operator: &BinaryOperator, operand_type: &Type, lhs: &Value, rhs: &Value) -> bool {
+    use std::cmp::Ordering;
+
+    let ordering = match (lhs, rhs) {
+        // comparisons with NaN are false, unordered ones included
+        (Value::Float(lhs), Value::Float(rhs)) => match lhs.partial_cmp(rhs) {
+            Some(ordering) => ordering,
+            None => return false,
+        },
+        _ if operand_type.is_signed_integer() => scalar(lhs).cmp(&scalar(rhs)),
+        _ => (scalar(lhs) as u64).cmp(&(scalar(rhs) as u64)),
+    };
+
+    match operator {
+        BinaryOperator::Equal => ordering == Ordering::Equal,
+        BinaryOperator::NotEqual => ordering != Ordering::Equal,
+        BinaryOperator::LessThan => ordering == Ordering::Less,
+        BinaryOperator::LessThanOrEqual => ordering != Ordering::Greater,
+        BinaryOperator::GreaterThan => ordering == Ordering::Greater,
+        BinaryOperator::GreaterThanOrEqual => ordering != Ordering::Less,
+        _ => unreachable!(),
+    }
+}
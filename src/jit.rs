@@ -0,0 +1,252 @@
+use llvm::core::*;
+use llvm::execution_engine::*;
+use llvm::prelude::*;
+use llvm::support::{LLVMLoadLibraryPermanently, LLVMSearchForAddressOfSymbol};
+use llvm::target::{LLVM_InitializeNativeAsmPrinter, LLVM_InitializeNativeTarget};
+use llvm::target_machine::LLVMCodeModel;
+
+use std::collections::HashSet;
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::ptr;
+
+/// Makes the symbols of a shared library available to the programs run with
+/// the JIT, for the rest of the process.
+pub fn load_library(path: &Path) -> Result<(), String> {
+    let filename = CString::new(path.as_os_str().as_bytes()).unwrap();
+
+    match unsafe { LLVMLoadLibraryPermanently(filename.as_ptr()) } {
+        0 => Ok(()),
+        _ => Err(format!("cannot load library {}", path.display())),
+    }
+}
+
+/// Compiles modules with MCJIT and calls the `main` function of one of them
+/// with the arguments of the program, the first one being its name, and
+/// returns what it returns. The engine takes the modules over and disposes of
+/// them. Extern functions are looked up in the process, which links libc, and
+/// in the libraries loaded before.
+pub fn run_main(modules: Vec<LLVMModuleRef>, arguments: &[String]) -> Result<i32, String> {
+    unsafe {
+        if let Err(error) = check_symbols(&modules, &mut HashSet::new()) {
+            modules
+                .into_iter()
+                .for_each(|module| LLVMDisposeModule(module));
+            return Err(error);
+        }
+
+        let mut modules = modules.into_iter();
+        let Some(first) = modules.next() else {
+            return Err("no module to run".to_string());
+        };
+
+        let engine = match create_engine(first) {
+            Ok(engine) => engine,
+            Err(error) => {
+                modules.for_each(|module| LLVMDisposeModule(module));
+                return Err(error);
+            }
+        };
+
+        for module in modules {
+            LLVMAddModule(engine, module);
+        }
+
+        let mut main: LLVMValueRef = ptr::null_mut();
+
+        if LLVMFindFunction(engine, c"main".as_ptr(), &mut main) != 0 {
+            LLVMDisposeExecutionEngine(engine);
+            return Err("the program has no `main` function".to_string());
+        }
+
+        let arguments = arguments
+            .iter()
+            .map(|argument| CString::new(argument.as_str()).unwrap())
+            .collect::<Vec<_>>();
+        let argv = arguments
+            .iter()
+            .map(|argument| argument.as_ptr())
+            .collect::<Vec<_>>();
+        let environment = [ptr::null()];
+
+        LLVMRunStaticConstructors(engine);
+
+        let status = LLVMRunFunctionAsMain(
+            engine,
+            main,
+            argv.len() as u32,
+            argv.as_ptr(),
+            environment.as_ptr(),
+        );
+
+        LLVMRunStaticDestructors(engine);
+        LLVMDisposeExecutionEngine(engine);
+
+        Ok(status)
+    }
+}
+
+/// A JIT modules are added to one after the other, their code stays loaded so
+/// that the modules added later call the functions and read the globals of
+/// those before. It backs the interactive sessions.
+pub struct Session {
+    engine: LLVMExecutionEngineRef,
+    /// The LLVM context of the empty module the engine is created with.
+    context: LLVMContextRef,
+    /// The symbols the modules added define.
+    defined: HashSet<CString>,
+}
+
+impl Session {
+    pub fn new() -> Result<Self, String> {
+        unsafe {
+            let context = LLVMContextCreate();
+            let module = LLVMModuleCreateWithNameInContext(c"session".as_ptr(), context);
+
+            match create_engine(module) {
+                Ok(engine) => Ok(Self {
+                    engine,
+                    context,
+                    defined: HashSet::new(),
+                }),
+                Err(error) => {
+                    LLVMContextDispose(context);
+                    Err(error)
+                }
+            }
+        }
+    }
+
+    /// Adds modules, unless they call functions that neither they, the
+    /// modules before nor the process define. The session takes the modules
+    /// over and disposes of them.
+    pub fn add_modules(&mut self, modules: Vec<LLVMModuleRef>) -> Result<(), String> {
+        unsafe {
+            let mut defined = self.defined.clone();
+
+            if let Err(error) = check_symbols(&modules, &mut defined) {
+                modules
+                    .into_iter()
+                    .for_each(|module| LLVMDisposeModule(module));
+                return Err(error);
+            }
+
+            self.defined = defined;
+
+            for module in modules {
+                LLVMAddModule(self.engine, module);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Calls a function taking no arguments of one of the modules, compiling
+    /// the modules added since the last call.
+    pub fn call(&mut self, function: &str) -> Result<(), String> {
+        let name = CString::new(function).unwrap();
+
+        unsafe {
+            match LLVMGetFunctionAddress(self.engine, name.as_ptr()) {
+                0 => Err(format!("cannot find the function {:?}", function)),
+                address => {
+                    let function: extern "C" fn() = mem::transmute(address as usize);
+                    function();
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// Disposes of the engine along with the modules, the LLVM contexts of the
+    /// modules have to be disposed of afterwards.
+    pub fn finish(self) {
+        unsafe {
+            LLVMDisposeExecutionEngine(self.engine);
+            LLVMContextDispose(self.context);
+        }
+    }
+}
+
+/// Creates an MCJIT engine for a module, which it takes over.
+unsafe fn create_engine(module: LLVMModuleRef) -> Result<LLVMExecutionEngineRef, String> {
+    LLVMLinkInMCJIT();
+    LLVM_InitializeNativeTarget();
+    LLVM_InitializeNativeAsmPrinter();
+
+    let mut options: LLVMMCJITCompilerOptions = mem::zeroed();
+    LLVMInitializeMCJITCompilerOptions(&mut options, mem::size_of_val(&options));
+    // the code is allocated anywhere, far from the libraries it calls
+    options.CodeModel = LLVMCodeModel::LLVMCodeModelLarge;
+
+    let mut engine: LLVMExecutionEngineRef = ptr::null_mut();
+    let mut error: *mut i8 = ptr::null_mut();
+
+    if LLVMCreateMCJITCompilerForModule(
+        &mut engine,
+        module,
+        &mut options,
+        mem::size_of_val(&options),
+        &mut error,
+    ) != 0
+    {
+        let message = CStr::from_ptr(error).to_string_lossy().to_string();
+        LLVMDisposeMessage(error);
+        LLVMDisposeModule(module);
+
+        return Err(format!("cannot create the JIT: {}", message));
+    }
+
+    Ok(engine)
+}
+
+/// Checks that every function declared by the modules is defined by one of
+/// them, by the modules before whose symbols are in `defined`, or found in the
+/// process, MCJIT would crash calling it. The symbols the modules define are
+/// added to `defined`.
+unsafe fn check_symbols(
+    modules: &[LLVMModuleRef],
+    defined: &mut HashSet<CString>,
+) -> Result<(), String> {
+    let mut declared = Vec::new();
+
+    // the symbols of the process itself
+    LLVMLoadLibraryPermanently(ptr::null());
+
+    for module in modules {
+        let mut function = LLVMGetFirstFunction(*module);
+
+        while !function.is_null() {
+            let mut length = 0;
+            let name = LLVMGetValueName2(function, &mut length);
+            let name = CStr::from_ptr(name).to_owned();
+
+            match LLVMIsDeclaration(function) {
+                0 => {
+                    defined.insert(name);
+                }
+                _ => declared.push(name),
+            }
+
+            function = LLVMGetNextFunction(function);
+        }
+    }
+
+    for name in declared {
+        let intrinsic = name.to_bytes().starts_with(b"llvm.");
+
+        if !intrinsic
+            && !defined.contains(&name)
+            && LLVMSearchForAddressOfSymbol(name.as_ptr()).is_null()
+        {
+            return Err(format!(
+                "cannot find the extern function {:?}, load the library defining it with --load",
+                name.to_string_lossy()
+            ));
+        }
+    }
+
+    Ok(())
+}
@@ -3,11 +3,14 @@ use crate::token::{Keyword, Token};
 use std::iter::Peekable;
 use std::str::Chars;
 
-static TEST: u32 = 10;
-const test: u32 = 10;
-
 pub struct Lexer {}
 
+impl Default for Lexer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Lexer {
     pub fn new() -> Lexer {
         Lexer {}
@@ -35,7 +38,16 @@ impl Lexer {
                 '[' => return Some(Token::LeftBracket),
                 ']' => return Some(Token::RightBracket),
                 ',' => return Some(Token::Comma),
-                '.' => return Some(Token::Dot),
+                '@' => return Some(Token::At),
+                '"' => return Some(Token::String(self.next_string(input))),
+                '.' => {
+                    if input.clone().take(2).eq(['.', '.']) {
+                        input.nth(1);
+                        return Some(Token::Ellipsis);
+                    }
+
+                    return Some(Token::Dot);
+                }
                 '+' => return Some(Token::Plus),
                 '-' => {
                     if input.next_if(|c| *c == '>').is_some() {
@@ -52,7 +64,38 @@ impl Lexer {
 
                     return Some(Token::Colon);
                 }
-                '=' => return Some(Token::Equal),
+                '=' => {
+                    if input.next_if(|c| *c == '>').is_some() {
+                        return Some(Token::FatArrow);
+                    }
+
+                    return Some(Token::Equal);
+                }
+                '?' => {
+                    if input.next_if(|c| *c == '.').is_some() {
+                        return Some(Token::QuestionDot);
+                    }
+
+                    if input.next_if(|c| *c == '?').is_some() {
+                        return Some(Token::QuestionQuestion);
+                    }
+
+                    return Some(Token::Question);
+                }
+                '<' => {
+                    if input.next_if(|c| *c == '=').is_some() {
+                        return Some(Token::LessThanOrEqual);
+                    }
+
+                    return Some(Token::LessThan);
+                }
+                '>' => {
+                    if input.next_if(|c| *c == '=').is_some() {
+                        return Some(Token::GreaterThanOrEqual);
+                    }
+
+                    return Some(Token::GreaterThan);
+                }
                 '~' => {
                     if input.next_if(|c| *c == '=').is_some() {
                         return Some(Token::NotEqual);
@@ -62,7 +105,7 @@ impl Lexer {
                 }
                 '/' => {
                     if input.next_if(|c| *c == '/').is_some() {
-                        while let Some(comment_character) = input.next() {
+                        for comment_character in input.by_ref() {
                             if comment_character == '\n' || comment_character == '\r' {
                                 break;
                             }
@@ -74,7 +117,7 @@ impl Lexer {
                     return Some(Token::Slash);
                 }
                 _ => {
-                    if c == '_' && input.peek().map_or(true, |c| !c.is_alphabetic()) {
+                    if c == '_' && input.peek().is_none_or(|c| !c.is_alphabetic()) {
                         return Some(Token::Wildcard);
                     }
 
@@ -92,9 +135,15 @@ impl Lexer {
                             "true" => Some(Token::Keyword(Keyword::True)),
                             "false" => Some(Token::Keyword(Keyword::False)),
                             "nil" => Some(Token::Keyword(Keyword::Nil)),
+                            "ok" => Some(Token::Keyword(Keyword::Ok)),
+                            "err" => Some(Token::Keyword(Keyword::Err)),
                             "enum" => Some(Token::Keyword(Keyword::Enum)),
                             "type" => Some(Token::Keyword(Keyword::Type)),
                             "proto" => Some(Token::Keyword(Keyword::Proto)),
+                            "extend" => Some(Token::Keyword(Keyword::Extend)),
+                            "selfless" => Some(Token::Keyword(Keyword::Selfless)),
+                            "import" => Some(Token::Keyword(Keyword::Import)),
+                            "pub" => Some(Token::Keyword(Keyword::Pub)),
                             "return" => Some(Token::Keyword(Keyword::Return)),
                             "break" => Some(Token::Keyword(Keyword::Break)),
                             "continue" => Some(Token::Keyword(Keyword::Continue)),
@@ -111,7 +160,7 @@ impl Lexer {
             }
         }
 
-        return None;
+        None
     }
 
     fn next_identifier(&self, input: &mut Peekable<Chars>, first: char) -> String {
@@ -122,7 +171,31 @@ impl Lexer {
             identifier.push(c);
         }
 
-        return identifier;
+        identifier
+    }
+
+    fn next_string(&self, input: &mut Peekable<Chars>) -> String {
+        let mut string = String::new();
+
+        // an unterminated string runs to the end of the input
+        while let Some(c) = input.next_if(|c| *c != '"') {
+            if c != '\\' {
+                string.push(c);
+                continue;
+            }
+
+            match input.next() {
+                Some('n') => string.push('\n'),
+                Some('t') => string.push('\t'),
+                Some('r') => string.push('\r'),
+                Some(c) => string.push(c),
+                None => break,
+            }
+        }
+
+        input.next();
+
+        string
     }
 
     fn next_number(&self, input: &mut Peekable<Chars>, first: char) -> f64 {
@@ -133,6 +206,6 @@ impl Lexer {
             number.push(c);
         }
 
-        return number.parse().unwrap_or(0.0); // TODO: maybe error instead of 0
+        number.parse().unwrap_or(0.0) // TODO: maybe error instead of 0
     }
 }
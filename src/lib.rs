@@ -0,0 +1,38 @@
+//! The x compiler. The front end, `Lexer`, `Parser`, the syntax tree, the
+//! `Context` and the checks run by `driver::check_module`, builds on its own,
+//! along with the interpreter. The LLVM backend needs the `llvm` feature.
+
+#[cfg(feature = "llvm")]
+extern crate llvm_sys as llvm;
+
+#[cfg(feature = "llvm")]
+mod abi;
+pub mod analysis;
+#[cfg(feature = "llvm")]
+pub mod build;
+mod builtins;
+pub mod c_import;
+#[cfg(feature = "llvm")]
+mod codegen;
+pub mod context;
+pub mod driver;
+pub mod header;
+pub mod hoist;
+pub mod interpreter;
+#[cfg(feature = "llvm")]
+mod jit;
+pub mod lexer;
+pub mod manifest;
+pub mod module;
+pub mod parser;
+#[cfg(feature = "llvm")]
+pub mod repl;
+#[cfg(feature = "llvm")]
+mod runtime;
+pub mod syntax;
+pub mod token;
+pub mod type_check;
+
+pub use context::Context;
+pub use lexer::Lexer;
+pub use parser::Parser;
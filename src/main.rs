@@ -1,23 +1,8 @@
-extern crate llvm_sys as llvm;
-
-mod analysis;
-mod codegen;
-mod context;
-mod lexer;
-mod parser;
-mod syntax;
-mod token;
-mod type_check;
-
-use analysis::AnalysisVisitor;
-use codegen::LLVMCodeGenVisitor;
-use context::Context;
-use lexer::Lexer;
-use parser::Parser;
-use type_check::TypeCheckVisitor;
+use x::driver::{self, Backend, CompileOptions, Emit};
+#[cfg(feature = "llvm")]
+use x::{build, driver::LinkOptions, repl};
 
 use clap::{Parser as ClapParser, Subcommand};
-use std::fs;
 use std::path::PathBuf;
 
 #[derive(ClapParser)]
@@ -29,43 +14,134 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Compile a source file
-    Compile { files: Vec<PathBuf> },
+    /// Compile source files, or the files of a directory as one module, and link C and object files into an executable
+    #[cfg(feature = "llvm")]
+    Compile {
+        files: Vec<PathBuf>,
+        /// Where to write the output, for a single source or an executable
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// What to write for the sources
+        #[arg(long, value_enum, default_value_t = Emit::Obj)]
+        emit: Emit,
+        /// Search a directory for libraries when linking an executable
+        #[arg(short = 'L', value_name = "DIRECTORY")]
+        library_paths: Vec<PathBuf>,
+        /// Link an executable with a library
+        #[arg(short = 'l', value_name = "LIBRARY")]
+        libraries: Vec<String>,
+        /// Write a C header declaring the exported functions next to each source
+        #[arg(long)]
+        header: bool,
+        /// Do not check array indices at runtime, for release builds
+        #[arg(long)]
+        no_bounds_checks: bool,
+    },
+    /// Run the main function of a module, exiting with its status
+    Run {
+        file: PathBuf,
+        /// Load a shared library to resolve extern functions with
+        #[arg(long, value_name = "LIBRARY")]
+        load: Vec<PathBuf>,
+        /// Do not check array indices at runtime
+        #[arg(long)]
+        no_bounds_checks: bool,
+        /// How to run the program
+        #[arg(long, value_enum, default_value_t)]
+        backend: Backend,
+        /// The arguments passed to the program
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        arguments: Vec<String>,
+    },
+    /// Read declarations and expressions interactively, running each input with the JIT
+    #[cfg(feature = "llvm")]
+    Repl {
+        /// Load a shared library to resolve extern functions with
+        #[arg(long, value_name = "LIBRARY")]
+        load: Vec<PathBuf>,
+    },
+    /// Build the project described by the x.toml manifest of a directory into its target directory
+    #[cfg(feature = "llvm")]
+    Build {
+        #[arg(default_value = ".")]
+        path: PathBuf,
+        /// Do not check array indices at runtime
+        #[arg(long)]
+        release: bool,
+    },
 }
 
 fn main() {
     let cli = Cli::parse();
 
     match cli.command {
-        Some(Commands::Compile { files }) => {
-            for source_file in files {
-                let code = fs::read_to_string(source_file.clone()).unwrap();
-                let mut input = code.chars().peekable();
-
-                let lexer = Lexer::new();
-                let parser = Parser::new(lexer);
-
-                let file = parser.parse(&mut input);
-                // println!("{:?}", file);
-
-                println!("compiling: \n{:}", code);
-
-                let mut context = Context::new();
-
-                let analysis = AnalysisVisitor {};
-                analysis.visit_source_file(&file, &mut context);
-
-                let type_check = TypeCheckVisitor::new();
-                type_check.visit_source_file(&file, &mut context);
-
-                let mut codegen = LLVMCodeGenVisitor::new();
-                codegen.visit_source_file(&file); // TODO: maybe pass and use context here
-
-                let output_file = source_file.with_extension("o");
-
-                codegen.emit_ir();
-                codegen.emit_asm(output_file);
-                codegen.finish();
+        #[cfg(feature = "llvm")]
+        Some(Commands::Compile {
+            files,
+            output,
+            emit,
+            library_paths,
+            libraries,
+            header,
+            no_bounds_checks,
+        }) => {
+            let options = CompileOptions {
+                emit,
+                header: None,
+                bounds_checks: !no_bounds_checks,
+                search_paths: Vec::new(),
+            };
+            let link = LinkOptions {
+                library_paths,
+                libraries,
+            };
+
+            if let Err(message) = driver::compile_files(&files, output, header, &options, &link) {
+                eprintln!("error: {}", message);
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Run {
+            file,
+            load,
+            no_bounds_checks,
+            backend,
+            arguments,
+        }) => {
+            let options = CompileOptions {
+                emit: Emit::Obj,
+                header: None,
+                bounds_checks: !no_bounds_checks,
+                search_paths: Vec::new(),
+            };
+
+            match driver::run_module(&file, &arguments, &load, backend, &options) {
+                Ok(status) => std::process::exit(status),
+                Err(message) => {
+                    eprintln!("error: {}", message);
+                    std::process::exit(1);
+                }
+            }
+        }
+        #[cfg(feature = "llvm")]
+        Some(Commands::Repl { load }) => {
+            let options = CompileOptions {
+                emit: Emit::Obj,
+                header: None,
+                bounds_checks: true,
+                search_paths: Vec::new(),
+            };
+
+            if let Err(message) = repl::repl(&load, options) {
+                eprintln!("error: {}", message);
+                std::process::exit(1);
+            }
+        }
+        #[cfg(feature = "llvm")]
+        Some(Commands::Build { path, release }) => {
+            if let Err(message) = build::build(&path, release) {
+                eprintln!("error: {}", message);
+                std::process::exit(1);
             }
         }
         None => {
@@ -0,0 +1,198 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The name of the file describing a project, at the root of its directory.
+pub const MANIFEST_FILE: &str = "x.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageType {
+    #[default]
+    Executable,
+    Library,
+}
+
+/// A dependency on another project, found at a path relative to the
+/// directory of the manifest naming it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dependency {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// The contents of an `x.toml` file:
+///
+/// ```toml
+/// [package]
+/// name = "app"
+/// type = "executable"     # or "library"
+/// sources = ["src"]
+///
+/// [c]
+/// sources = ["native/clock.c"]
+/// objects = ["native/vendor.o"]
+/// libraries = ["m"]
+///
+/// [dependencies]
+/// units = { path = "../units" }
+/// ```
+///
+/// Paths are relative to the directory of the manifest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub name: String,
+    pub package_type: PackageType,
+    /// The directories holding the modules of the project, each `.x` file
+    /// and each subdirectory of one is a module.
+    pub sources: Vec<PathBuf>,
+    pub c_sources: Vec<PathBuf>,
+    pub c_objects: Vec<PathBuf>,
+    /// The system libraries an executable links with, passed as `-l`.
+    pub libraries: Vec<String>,
+    pub dependencies: Vec<Dependency>,
+}
+
+/// The tables of an `x.toml` file as they are written.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestFile {
+    package: PackageTable,
+    #[serde(default)]
+    c: CTable,
+    #[serde(default)]
+    dependencies: BTreeMap<String, DependencyTable>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PackageTable {
+    name: String,
+    #[serde(rename = "type", default)]
+    package_type: PackageType,
+    #[serde(default = "default_sources")]
+    sources: Vec<PathBuf>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct CTable {
+    #[serde(default)]
+    sources: Vec<PathBuf>,
+    #[serde(default)]
+    objects: Vec<PathBuf>,
+    #[serde(default)]
+    libraries: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DependencyTable {
+    path: PathBuf,
+}
+
+fn default_sources() -> Vec<PathBuf> {
+    vec![PathBuf::from("src")]
+}
+
+impl Manifest {
+    pub fn read(directory: &Path) -> Result<Manifest, String> {
+        let path = directory.join(MANIFEST_FILE);
+        let code = fs::read_to_string(&path)
+            .map_err(|error| format!("cannot read {}: {}", path.display(), error))?;
+
+        Manifest::parse(&code).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    pub fn parse(code: &str) -> Result<Manifest, String> {
+        let file: ManifestFile = toml::from_str(code).map_err(|error| error.to_string())?;
+
+        Ok(Manifest {
+            name: file.package.name,
+            package_type: file.package.package_type,
+            sources: file.package.sources,
+            c_sources: file.c.sources,
+            c_objects: file.c.objects,
+            libraries: file.c.libraries,
+            // in order of their names
+            dependencies: file
+                .dependencies
+                .into_iter()
+                .map(|(name, dependency)| Dependency {
+                    name,
+                    path: dependency.path,
+                })
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_manifest() {
+        let manifest = Manifest::parse(
+            r#"
+            # an application
+            [package]
+            name = "app"
+            type = "executable"
+            sources = ["src", "generated"]
+
+            [c]
+            sources = ["native/clock.c", "native/#odd.c",]
+            libraries = ["m"]
+
+            [dependencies]
+            units = { path = "../units" }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(manifest.name, "app");
+        assert_eq!(manifest.package_type, PackageType::Executable);
+        assert_eq!(
+            manifest.sources,
+            vec![PathBuf::from("src"), PathBuf::from("generated")]
+        );
+        assert_eq!(
+            manifest.c_sources,
+            vec![
+                PathBuf::from("native/clock.c"),
+                PathBuf::from("native/#odd.c")
+            ]
+        );
+        assert!(manifest.c_objects.is_empty());
+        assert_eq!(manifest.libraries, vec!["m".to_string()]);
+        assert_eq!(
+            manifest.dependencies,
+            vec![Dependency {
+                name: "units".to_string(),
+                path: PathBuf::from("../units"),
+            }]
+        );
+    }
+
+    #[test]
+    fn defaults_and_errors() {
+        let manifest =
+            Manifest::parse("[package]\nname = \"units\"\ntype = \"library\"\n").unwrap();
+
+        assert_eq!(manifest.package_type, PackageType::Library);
+        assert_eq!(manifest.sources, vec![PathBuf::from("src")]);
+
+        assert!(Manifest::parse("[package]\ntype = \"library\"\n").is_err());
+        assert!(Manifest::parse("[package]\nname = \"x\"\ntype = \"plugin\"\n").is_err());
+        assert!(
+            Manifest::parse("[package]\nname = \"x\"\n[dependencies]\nunits = \"1.0\"\n").is_err()
+        );
+
+        let error =
+            Manifest::parse("[package]\nname = \"x\"\n\n[c]\nsource = [\"a.c\"]\n").unwrap_err();
+        assert!(error.contains("line 5"), "{}", error);
+        assert!(error.contains("unknown field `source`"), "{}", error);
+    }
+}
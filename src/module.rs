@@ -0,0 +1,272 @@
+use crate::c_import;
+use crate::context::{Context, PrivateDeclaration};
+use crate::hoist;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::syntax::*;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The name of the module a file or directory compiles to, `geometry.x` and
+/// `geometry/` are both the module `geometry`.
+pub fn module_name(path: &Path) -> Result<String, String> {
+    match path.file_stem() {
+        Some(stem) => Ok(stem.to_string_lossy().to_string()),
+        None => Err(format!(
+            "cannot read module {}, its path does not name a file or a directory",
+            path.display()
+        )),
+    }
+}
+
+/// Reads the files of a module along with their source, the files of a
+/// directory are read in order of their names.
+pub fn read_module(path: &Path) -> Result<Vec<(PathBuf, String)>, String> {
+    let error = |error: std::io::Error| format!("cannot read module {}: {}", path.display(), error);
+
+    let mut files = if path.is_dir() {
+        let mut files = Vec::new();
+
+        for entry in fs::read_dir(path).map_err(error)? {
+            let file = entry.map_err(error)?.path();
+
+            if file.extension().is_some_and(|extension| extension == "x") {
+                files.push(file);
+            }
+        }
+
+        files
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    files.sort();
+
+    files
+        .into_iter()
+        .map(|file| {
+            let code = fs::read_to_string(&file).map_err(error)?;
+            Ok((file, code))
+        })
+        .collect()
+}
+
+/// Parses the files of a module into one source file, C headers are imported
+/// relative to the file importing them.
+pub fn parse_module(files: &[(PathBuf, String)], name: &str, context: &mut Context) -> SourceFile {
+    let mut module = SourceFile {
+        code_block: CodeBlock { items: Vec::new() },
+    };
+
+    for (path, code) in files {
+        let mut input = code.chars().peekable();
+
+        let lexer = Lexer::new();
+        let parser = Parser::new(lexer);
+
+        let mut file = parser.parse(&mut input);
+
+        let directory = path.parent().unwrap_or(Path::new("."));
+        c_import::expand_imports(&mut file, directory, context);
+        register_private_declarations(&file, path, name, context);
+
+        module.code_block.items.append(&mut file.code_block.items);
+    }
+
+    module
+}
+
+/// Reads the declarations of every module imported by a file into its
+/// `import` declarations, renamed after their module. Modules are looked up
+/// next to the importing module, then in the search paths, and each is read
+/// once, the imports of a module already read stay empty.
+///
+/// The declarations are only declared in the object file of the program, the
+/// object file of each module defines them.
+pub fn expand_modules(
+    file: &mut SourceFile,
+    directory: &Path,
+    search_paths: &[PathBuf],
+    root: &str,
+    context: &mut Context,
+) {
+    for item in &mut file.code_block.items {
+        let CodeBlockItem::Declaration(Declaration::ModuleImportDeclaration(import)) = item else {
+            continue;
+        };
+
+        let name = import.name.name.clone();
+
+        if name == root {
+            context.error(format!(
+                "module {:?} imports itself, directly or through the modules it imports",
+                name
+            ));
+            continue;
+        }
+
+        if context.is_module(&name) {
+            continue;
+        }
+
+        let Some(path) = std::iter::once(directory)
+            .chain(search_paths.iter().map(PathBuf::as_path))
+            .flat_map(|directory| [directory.join(format!("{}.x", name)), directory.join(&name)])
+            .find(|path| path.exists())
+        else {
+            context.error(format!(
+                "cannot find module {:?}, expected {}.x or a directory {} next to the importing file or in the dependencies",
+                name, name, name
+            ));
+            continue;
+        };
+
+        context.register_module(&name, &path);
+
+        let files = match read_module(&path) {
+            Ok(files) => files,
+            Err(error) => {
+                context.error(error);
+                continue;
+            }
+        };
+
+        let mut module = parse_module(&files, &name, context);
+        let module_directory = path.parent().unwrap_or(Path::new("."));
+        expand_modules(&mut module, module_directory, search_paths, root, context);
+
+        for item in std::mem::take(&mut module.code_block.items) {
+            let CodeBlockItem::Declaration(mut declaration) = item else {
+                continue;
+            };
+
+            if let Declaration::FunctionDeclaration(function) = &mut declaration {
+                // the entry point of the module's own program, if any
+                if function.identifier.name == "main" {
+                    continue;
+                }
+
+                // exported under the name of the function before it is renamed
+                for attribute in &mut function.attributes {
+                    if attribute.name.name == "export" && attribute.value.is_none() {
+                        attribute.value = Some(function.identifier.name.clone());
+                    }
+                }
+            }
+
+            module
+                .code_block
+                .items
+                .push(CodeBlockItem::Declaration(declaration));
+        }
+
+        hoist::hoist_local_declarations(&mut module, Some(&name), context);
+
+        import.declarations = module
+            .code_block
+            .items
+            .into_iter()
+            .filter_map(|item| match item {
+                CodeBlockItem::Declaration(declaration) => Some(declaration),
+                _ => None,
+            })
+            .collect();
+    }
+}
+
+/// Records the declarations of a file of a module that are not marked `pub`,
+/// under the names other modules know them by.
+fn register_private_declarations(
+    file: &SourceFile,
+    path: &Path,
+    module: &str,
+    context: &mut Context,
+) {
+    let private = |kind, location: &SourceLocation| PrivateDeclaration {
+        kind,
+        module: module.to_string(),
+        location: format!("{}:{}:{}", path.display(), location.line, location.column),
+    };
+
+    for item in &file.code_block.items {
+        let CodeBlockItem::Declaration(declaration) = item else {
+            continue;
+        };
+
+        let private_declaration = match declaration {
+            Declaration::FunctionDeclaration(function)
+                if function.visibility == Visibility::Private =>
+            {
+                Some(("function", &function.identifier.name, &function.location))
+            }
+            Declaration::VariableDeclaration(variable)
+                if variable.visibility == Visibility::Private =>
+            {
+                Some(("global", &variable.identifier.name, &variable.location))
+            }
+            Declaration::ProtocolDeclaration(protocol)
+                if protocol.visibility == Visibility::Private =>
+            {
+                Some(("protocol", &protocol.name.name, &protocol.location))
+            }
+            Declaration::TypeDeclaration(declaration)
+                if declaration.visibility == Visibility::Private =>
+            {
+                Some(("type", &declaration.name.name, &declaration.location))
+            }
+            Declaration::EnumDeclaration(declaration)
+                if declaration.visibility == Visibility::Private =>
+            {
+                Some(("enum", &declaration.name.name, &declaration.location))
+            }
+            _ => None,
+        };
+
+        if let Some((kind, name, location)) = private_declaration {
+            context.register_private(format!("{}.{}", module, name), private(kind, location));
+        }
+
+        let (type_name, container) = match declaration {
+            Declaration::TypeDeclaration(declaration) => {
+                (&declaration.name.name, &declaration.container)
+            }
+            Declaration::EnumDeclaration(declaration) => {
+                (&declaration.name.name, &declaration.container)
+            }
+            Declaration::ExtensionDeclaration(extension) => {
+                (&extension.name.name, &extension.container)
+            }
+            _ => continue,
+        };
+
+        // the members added to a type of another module belong to the module extending it
+        let type_name = match type_name.contains('.') {
+            true => type_name.clone(),
+            false => format!("{}.{}", module, type_name),
+        };
+
+        for member in &container.member_block.members {
+            let (kind, name, visibility, location) = match member {
+                MemberBlockItem::VariableDeclaration(field) => (
+                    "field",
+                    &field.identifier.name,
+                    field.visibility,
+                    &field.location,
+                ),
+                MemberBlockItem::FunctionDeclaration(method) => (
+                    "method",
+                    &method.identifier.name,
+                    method.visibility,
+                    &method.location,
+                ),
+                MemberBlockItem::EnumCaseDeclaration(_) => continue,
+            };
+
+            if visibility == Visibility::Private {
+                let name = format!("{}.{}", type_name, name);
+                context.register_private(name, private(kind, location));
+            }
+        }
+    }
+}
@@ -2,29 +2,56 @@ use crate::lexer::Lexer;
 use crate::syntax::*;
 use crate::token::*;
 
+use std::cell::RefCell;
 use std::iter::Peekable;
 use std::str::Chars;
 
 pub struct Parser {
     lexer: Lexer,
+    /// The text being parsed, source locations are computed from it.
+    source: RefCell<Vec<char>>,
 }
 
 impl Parser {
     pub fn new(lexer: Lexer) -> Parser {
-        Parser { lexer }
+        Parser {
+            lexer,
+            source: RefCell::new(Vec::new()),
+        }
     }
 
     pub fn parse(&self, input: &mut Peekable<Chars>) -> SourceFile {
+        *self.source.borrow_mut() = input.clone().collect();
+
         SourceFile {
             code_block: self.parse_code_block(input),
         }
     }
 
+    /// The location of the next token that is not whitespace.
+    fn location(&self, input: &Peekable<Chars>) -> SourceLocation {
+        let source = self.source.borrow();
+        let whitespace = input
+            .clone()
+            .take_while(|c| c.is_whitespace() && *c != '\n')
+            .count();
+        let offset = (source.len() - input.clone().count() + whitespace).min(source.len());
+
+        let line_start = source[..offset]
+            .iter()
+            .rposition(|c| *c == '\n')
+            .map_or(0, |newline| newline + 1);
+
+        SourceLocation {
+            line: source[..offset].iter().filter(|c| **c == '\n').count() + 1,
+            column: offset - line_start + 1,
+        }
+    }
+
     fn parse_code_block_container(
         &self,
         input: &mut Peekable<Chars>,
     ) -> Option<CodeBlockContainer> {
-        println!("parsing code block container");
         let mut token = self.lexer.next_token(input, true)?;
 
         input.peek();
@@ -44,7 +71,6 @@ impl Parser {
     }
 
     fn parse_code_block(&self, input: &mut Peekable<Chars>) -> CodeBlock {
-        println!("parsing code block");
         let mut items = Vec::new();
 
         loop {
@@ -69,29 +95,55 @@ impl Parser {
     }
 
     fn parse_code_block_item(&self, input: &mut Peekable<Chars>) -> Option<CodeBlockItem> {
-        println!("parsing code block item");
         let token = self.lexer.peek_token(input, true);
 
         if let Some(token) = token {
             match token {
+                Token::Keyword(Keyword::Let) if self.opens_paren(input) => {
+                    self.lexer.next_token(input, true);
+                    let statement = self.parse_destructuring_statement(input)?;
+                    Some(CodeBlockItem::Statement(Statement::Destructuring(
+                        statement,
+                    )))
+                }
                 Token::Keyword(Keyword::Let)
                 | Token::Keyword(Keyword::Fun)
                 | Token::Keyword(Keyword::Extern)
                 | Token::Keyword(Keyword::Enum)
-                | Token::Keyword(Keyword::Type) => {
+                | Token::Keyword(Keyword::Type)
+                | Token::Keyword(Keyword::Proto)
+                | Token::Keyword(Keyword::Extend)
+                | Token::Keyword(Keyword::Import)
+                | Token::Keyword(Keyword::Pub)
+                | Token::At
+                    // `fun(` starts a closure rather than a declaration
+                    if !(token == Token::Keyword(Keyword::Fun) && self.opens_paren(input)) =>
+                {
                     let declaration = self.parse_declaration(input)?;
-                    return Some(CodeBlockItem::Declaration(declaration));
+                    Some(CodeBlockItem::Declaration(declaration))
                 }
                 Token::Keyword(Keyword::Loop)
                 | Token::Keyword(Keyword::Return)
                 | Token::Keyword(Keyword::Break)
                 | Token::Keyword(Keyword::Continue) => {
                     let statement = self.parse_statement(input)?;
-                    return Some(CodeBlockItem::Statement(statement));
+                    Some(CodeBlockItem::Statement(statement))
                 }
                 _ => {
                     let expression = self.parse_expression(input)?;
-                    return Some(CodeBlockItem::Expression(expression));
+
+                    if self.lexer.peek_token(input, false) == Some(Token::Assign) {
+                        self.lexer.next_token(input, false);
+
+                        return Some(CodeBlockItem::Statement(Statement::Assignment(
+                            AssignmentStatement {
+                                target: expression,
+                                value: self.parse_expression(input)?,
+                            },
+                        )));
+                    }
+
+                    Some(CodeBlockItem::Expression(expression))
                 }
             }
         } else {
@@ -99,58 +151,354 @@ impl Parser {
         }
     }
 
+    /// Whether the keyword ahead is followed by `(`, as in the destructuring
+    /// `let (a, b)` or the closure `fun(x: T)`.
+    fn opens_paren(&self, input: &Peekable<Chars>) -> bool {
+        let mut input = input.clone();
+        self.lexer.next_token(&mut input, true);
+
+        self.lexer.next_token(&mut input, true) == Some(Token::LeftParen)
+    }
+
+    /// Parses `(a, b): (T, U) := value` following a `let`, `_` skips an element.
+    fn parse_destructuring_statement(
+        &self,
+        input: &mut Peekable<Chars>,
+    ) -> Option<DestructuringStatement> {
+        if self.lexer.next_token(input, true)? != Token::LeftParen {
+            return None;
+        }
+
+        let mut names = Vec::new();
+
+        loop {
+            match self.lexer.next_token(input, true)? {
+                Token::Wildcard => names.push(None),
+                token => names.push(Some(self.parse_identifier(token)?)),
+            }
+
+            match self.lexer.next_token(input, true)? {
+                Token::Comma => continue,
+                Token::RightParen => break,
+                _ => return None,
+            }
+        }
+
+        let mut type_annotation = None;
+
+        if self.lexer.peek_token(input, true) == Some(Token::Colon) {
+            self.lexer.next_token(input, true);
+            type_annotation = Some(self.parse_type(input)?);
+        }
+
+        if self.lexer.next_token(input, true)? != Token::Assign {
+            return None;
+        }
+
+        Some(DestructuringStatement {
+            names,
+            type_annotation,
+            value: self.parse_expression(input)?,
+        })
+    }
+
     fn parse_statement(&self, input: &mut Peekable<Chars>) -> Option<Statement> {
-        println!("parsing statement");
-        None
+        let token = self.lexer.next_token(input, true)?;
+
+        match token {
+            Token::Keyword(Keyword::Return) => {
+                let statement = self.parse_return_statement(input)?;
+                Some(Statement::Return(statement))
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_return_statement(&self, input: &mut Peekable<Chars>) -> Option<ReturnStatement> {
+        // the returned value must start on the same line as the return keyword
+        let expression = match self.lexer.peek_token(input, false) {
+            None | Some(Token::Newline) | Some(Token::RightBrace) => None,
+            Some(_) => Some(self.parse_expression(input)?),
+        };
+
+        Some(ReturnStatement { expression })
     }
 
     fn parse_declaration(&self, input: &mut Peekable<Chars>) -> Option<Declaration> {
-        println!("parsing declaration");
-        let token = self.lexer.next_token(input, true)?;
+        let attributes = self.parse_attributes(input)?;
+
+        self.skip_blank_lines(input);
+        let location = self.location(input);
+        let (visibility, token) = self.parse_visibility(input)?;
+
+        // only functions and extern libraries take attributes for now
+        if !attributes.is_empty() {
+            return match token {
+                Token::Keyword(Keyword::Fun) => {
+                    let mut function = self.parse_fun_declaration(visibility, location, input)?;
+                    function.attributes = attributes;
+                    Some(Declaration::FunctionDeclaration(function))
+                }
+                Token::Keyword(Keyword::Extern) if visibility == Visibility::Private => {
+                    let library = self.parse_extern_library_declaration(attributes, input)?;
+                    Some(Declaration::ExternLibraryDeclaration(library))
+                }
+                _ => None,
+            };
+        }
 
         match token {
             Token::Keyword(Keyword::Let) => {
-                let variable = self.parse_let_declaration(input)?;
-                return Some(Declaration::VariableDeclaration(variable));
+                let variable = self.parse_let_declaration(visibility, location, input)?;
+                Some(Declaration::VariableDeclaration(variable))
             }
             Token::Keyword(Keyword::Fun) => {
-                let function = self.parse_fun_declaration(input)?;
-                return Some(Declaration::FunctionDeclaration(function));
-            }
-            Token::Keyword(Keyword::Extern) => {
-                let ext = self.parse_extern_declaration(input)?;
-                return Some(Declaration::ExternDeclaration(ext));
+                let function = self.parse_fun_declaration(visibility, location, input)?;
+                Some(Declaration::FunctionDeclaration(function))
             }
             Token::Keyword(Keyword::Enum) => {
-                let ext = self.parse_enum_declaration(input)?;
-                return Some(Declaration::EnumDeclaration(ext));
+                let ext = self.parse_enum_declaration(visibility, location, input)?;
+                Some(Declaration::EnumDeclaration(ext))
             }
             Token::Keyword(Keyword::Type) => {
-                let ext = self.parse_type_declaration(input)?;
-                return Some(Declaration::TypeDeclaration(ext));
+                let ext = self.parse_type_declaration(visibility, location, input)?;
+                Some(Declaration::TypeDeclaration(ext))
+            }
+            Token::Keyword(Keyword::Proto) => {
+                let protocol = self.parse_protocol_declaration(visibility, location, input)?;
+                Some(Declaration::ProtocolDeclaration(protocol))
+            }
+            // externs are visible to every module, extensions and imports have no name of their own
+            _ if visibility == Visibility::Public => None,
+            Token::Keyword(Keyword::Extern) => {
+                if let Token::String(_) = self.lexer.peek_token(input, true)? {
+                    let library = self.parse_extern_library_declaration(Vec::new(), input)?;
+                    return Some(Declaration::ExternLibraryDeclaration(library));
+                }
+
+                let ext = self.parse_extern_declaration(input)?;
+                Some(Declaration::ExternDeclaration(ext))
             }
+            Token::Keyword(Keyword::Extend) => {
+                let extension = self.parse_extension_declaration(input)?;
+                Some(Declaration::ExtensionDeclaration(extension))
+            }
+            Token::Keyword(Keyword::Import) => self.parse_import_declaration(input),
             _ => None,
         }
     }
 
-    fn parse_enum_declaration(&self, input: &mut Peekable<Chars>) -> Option<EnumDeclaration> {
+    /// Parses the `pub` in front of a declaration, returns the visibility of the
+    /// declaration along with the token following it.
+    fn parse_visibility(&self, input: &mut Peekable<Chars>) -> Option<(Visibility, Token)> {
+        let token = self.lexer.next_token(input, true)?;
+
+        if token == Token::Keyword(Keyword::Pub) {
+            return Some((Visibility::Public, self.lexer.next_token(input, true)?));
+        }
+
+        Some((Visibility::Private, token))
+    }
+
+    /// Skips the blank lines and comments before the next token, so that the
+    /// location of a declaration is the one of its first token.
+    fn skip_blank_lines(&self, input: &mut Peekable<Chars>) {
+        loop {
+            if input.next_if(|c| c.is_whitespace()).is_some() {
+                continue;
+            }
+
+            let mut lookahead = input.clone();
+
+            if lookahead.next() == Some('/') && lookahead.next() == Some('/') {
+                while input.next_if(|c| *c != '\n').is_some() {}
+                continue;
+            }
+
+            break;
+        }
+    }
+
+    fn parse_enum_declaration(
+        &self,
+        visibility: Visibility,
+        location: SourceLocation,
+        input: &mut Peekable<Chars>,
+    ) -> Option<EnumDeclaration> {
         let token = self.lexer.next_token(input, true)?;
 
         Some(EnumDeclaration {
+            visibility,
+            location,
             name: self.parse_identifier(token)?,
+            generic_parameters: self.parse_generic_parameters(input)?,
+            conformances: self.parse_conformances(input)?,
             container: self.parse_member_block_container(input)?,
         })
     }
 
-    fn parse_type_declaration(&self, input: &mut Peekable<Chars>) -> Option<TypeDeclaration> {
+    fn parse_type_declaration(
+        &self,
+        visibility: Visibility,
+        location: SourceLocation,
+        input: &mut Peekable<Chars>,
+    ) -> Option<TypeDeclaration> {
         let token = self.lexer.next_token(input, true)?;
 
         Some(TypeDeclaration {
+            visibility,
+            location,
+            name: self.parse_identifier(token)?,
+            generic_parameters: self.parse_generic_parameters(input)?,
+            conformances: self.parse_conformances(input)?,
+            container: self.parse_member_block_container(input)?,
+        })
+    }
+
+    fn parse_extension_declaration(
+        &self,
+        input: &mut Peekable<Chars>,
+    ) -> Option<ExtensionDeclaration> {
+        let token = self.lexer.next_token(input, true)?;
+
+        Some(ExtensionDeclaration {
             name: self.parse_identifier(token)?,
+            conformances: self.parse_conformances(input)?,
             container: self.parse_member_block_container(input)?,
         })
     }
 
+    /// Parses the generic parameters following the name of a declaration, as in
+    /// `<A, B: Drawable + Named>`, there are none when the name is not followed
+    /// by `<`.
+    fn parse_generic_parameters(
+        &self,
+        input: &mut Peekable<Chars>,
+    ) -> Option<Vec<GenericParameter>> {
+        let mut parameters = Vec::new();
+
+        if self.lexer.peek_token(input, false) != Some(Token::LessThan) {
+            return Some(parameters);
+        }
+
+        self.lexer.next_token(input, false);
+
+        loop {
+            let token = self.lexer.next_token(input, true)?;
+            let name = self.parse_identifier(token)?;
+            let mut bounds = Vec::new();
+
+            if self.lexer.peek_token(input, true)? == Token::Colon {
+                self.lexer.next_token(input, true);
+
+                loop {
+                    let token = self.lexer.next_token(input, true)?;
+                    bounds.push(self.parse_identifier(token)?);
+
+                    if self.lexer.peek_token(input, true)? != Token::Plus {
+                        break;
+                    }
+
+                    self.lexer.next_token(input, true);
+                }
+            }
+
+            parameters.push(GenericParameter { name, bounds });
+
+            match self.lexer.next_token(input, true)? {
+                Token::Comma => continue,
+                Token::GreaterThan => break,
+                _ => return None,
+            }
+        }
+
+        Some(parameters)
+    }
+
+    fn parse_conformances(&self, input: &mut Peekable<Chars>) -> Option<Vec<Identifier>> {
+        let mut conformances = Vec::new();
+
+        if self.lexer.peek_token(input, true)? != Token::Colon {
+            return Some(conformances);
+        }
+
+        self.lexer.next_token(input, true);
+
+        loop {
+            let token = self.lexer.next_token(input, true)?;
+            conformances.push(self.parse_identifier(token)?);
+
+            if self.lexer.peek_token(input, true)? != Token::Comma {
+                break;
+            }
+
+            self.lexer.next_token(input, true);
+        }
+
+        Some(conformances)
+    }
+
+    fn parse_protocol_declaration(
+        &self,
+        visibility: Visibility,
+        location: SourceLocation,
+        input: &mut Peekable<Chars>,
+    ) -> Option<ProtocolDeclaration> {
+        let mut token = self.lexer.next_token(input, true)?;
+        let name = self.parse_identifier(token)?;
+
+        token = self.lexer.next_token(input, true)?;
+
+        if token != Token::LeftBrace {
+            return None;
+        }
+
+        let mut requirements = Vec::new();
+
+        loop {
+            let attributes = self.parse_attributes(input)?;
+            token = self.lexer.next_token(input, true)?;
+
+            match token {
+                Token::RightBrace if attributes.is_empty() => break,
+                Token::Keyword(Keyword::Fun) => {
+                    token = self.lexer.next_token(input, true)?;
+
+                    let selfless = token == Token::Keyword(Keyword::Selfless);
+
+                    if selfless {
+                        token = self.lexer.next_token(input, true)?;
+                    }
+
+                    let identifier = self.parse_identifier(token)?;
+                    let signature = self.parse_function_signature(input)?;
+
+                    let mut body = None;
+
+                    if self.lexer.peek_token(input, true)? == Token::LeftBrace {
+                        body = Some(self.parse_code_block_container(input)?);
+                    }
+
+                    requirements.push(FunctionRequirement {
+                        attributes,
+                        selfless,
+                        identifier,
+                        signature,
+                        body,
+                    });
+                }
+                _ => return None,
+            }
+        }
+
+        Some(ProtocolDeclaration {
+            visibility,
+            location,
+            name,
+            requirements,
+        })
+    }
+
     fn parse_member_block_container(
         &self,
         input: &mut Peekable<Chars>,
@@ -179,17 +527,11 @@ impl Parser {
     fn parse_member_block(&self, input: &mut Peekable<Chars>) -> MemberBlock {
         let mut members = Vec::new();
 
-        loop {
-            let Some(token) = self.lexer.peek_token(input, true) else {
-                break;
-            };
-
+        while let Some(token) = self.lexer.peek_token(input, true) {
             if token == Token::RightBrace {
                 break;
             }
 
-            self.lexer.next_token(input, true);
-
             let Some(member) = self.parse_member_item(input) else {
                 break;
             };
@@ -201,18 +543,29 @@ impl Parser {
     }
 
     fn parse_member_item(&self, input: &mut Peekable<Chars>) -> Option<MemberBlockItem> {
-        let token = self.lexer.next_token(input, true)?;
+        self.skip_blank_lines(input);
+        let location = self.location(input);
+        let (visibility, token) = self.parse_visibility(input)?;
 
         match token {
-            // TODO: add case for "case"
+            // enum cases are as visible as their enum
+            Token::Identifier(name) if visibility == Visibility::Private => {
+                if self.lexer.peek_token(input, true) == Some(Token::Comma) {
+                    self.lexer.next_token(input, true);
+                }
+
+                Some(MemberBlockItem::EnumCaseDeclaration(EnumCaseDeclaration {
+                    name: Identifier { name },
+                }))
+            }
             Token::Keyword(Keyword::Let) => {
-                let variable = self.parse_let_declaration(input)?;
-                return Some(MemberBlockItem::VariableDeclaration(variable));
+                let variable = self.parse_let_declaration(visibility, location, input)?;
+                Some(MemberBlockItem::VariableDeclaration(variable))
             }
             Token::Keyword(Keyword::Fun) => {
                 // TODO: add cases for init
-                let function = self.parse_fun_declaration(input)?;
-                return Some(MemberBlockItem::FunctionDeclaration(function));
+                let function = self.parse_fun_declaration(visibility, location, input)?;
+                Some(MemberBlockItem::FunctionDeclaration(function))
             }
             // Token::Keyword(Keyword::Enum) => {
             //     let enumeration = self.parse_enum_declaration(input)?;
@@ -226,36 +579,61 @@ impl Parser {
         }
     }
 
-    fn parse_let_declaration(&self, input: &mut Peekable<Chars>) -> Option<VariableDeclaration> {
-        println!("parsing let declaration");
-        let mut token = self.lexer.next_token(input, true)?;
+    fn parse_let_declaration(
+        &self,
+        visibility: Visibility,
+        location: SourceLocation,
+        input: &mut Peekable<Chars>,
+    ) -> Option<VariableDeclaration> {
+        let token = self.lexer.next_token(input, true)?;
         let identifier = self.parse_identifier(token)?;
 
-        token = self.lexer.peek_token(input, true)?;
-        if token != Token::Assign {
-            return None;
+        let mut type_annotation = None;
+
+        if self.lexer.peek_token(input, true) == Some(Token::Colon) {
+            self.lexer.next_token(input, true);
+            type_annotation = Some(self.parse_type(input)?);
         }
 
-        self.lexer.next_token(input, true)?;
+        let mut expression = None;
+
+        if self.lexer.peek_token(input, true) == Some(Token::Assign) {
+            self.lexer.next_token(input, true);
+            expression = Some(self.parse_expression(input)?);
+        }
 
-        let expression = self.parse_expression(input)?;
+        if type_annotation.is_none() && expression.is_none() {
+            return None;
+        }
 
         Some(VariableDeclaration {
+            visibility,
+            location,
             identifier,
+            type_annotation,
             expression,
         })
     }
 
-    fn parse_fun_declaration(&self, input: &mut Peekable<Chars>) -> Option<FunctionDeclaration> {
-        println!("parsing fun declaration");
+    fn parse_fun_declaration(
+        &self,
+        visibility: Visibility,
+        location: SourceLocation,
+        input: &mut Peekable<Chars>,
+    ) -> Option<FunctionDeclaration> {
         let token = self.lexer.next_token(input, true)?;
         let identifier = self.parse_identifier(token)?;
+        let generic_parameters = self.parse_generic_parameters(input)?;
 
         let signature = self.parse_function_signature(input)?;
         let body = self.parse_code_block_container(input)?;
 
         Some(FunctionDeclaration {
+            attributes: Vec::new(),
+            visibility,
+            location,
             identifier,
+            generic_parameters,
             signature,
             body,
         })
@@ -278,8 +656,93 @@ impl Parser {
         })
     }
 
+    fn parse_extern_library_declaration(
+        &self,
+        attributes: Vec<Attribute>,
+        input: &mut Peekable<Chars>,
+    ) -> Option<ExternLibraryDeclaration> {
+        let Token::String(abi) = self.lexer.next_token(input, true)? else {
+            return None;
+        };
+
+        let mut token = self.lexer.next_token(input, true)?;
+        let name = self.parse_identifier(token)?;
+
+        token = self.lexer.next_token(input, true)?;
+
+        if token != Token::Colon {
+            return None;
+        }
+
+        token = self.lexer.next_token(input, true)?;
+        let protocol = self.parse_identifier(token)?;
+
+        Some(ExternLibraryDeclaration {
+            attributes,
+            abi,
+            name,
+            protocol,
+        })
+    }
+
+    /// Parses `import c "header.h"` or `import module` following `import`.
+    fn parse_import_declaration(&self, input: &mut Peekable<Chars>) -> Option<Declaration> {
+        let token = self.lexer.next_token(input, true)?;
+        let name = self.parse_identifier(token)?;
+
+        if name.name == "c" {
+            if let Some(Token::String(path)) = self.lexer.peek_token(input, false) {
+                self.lexer.next_token(input, false);
+                return Some(Declaration::CImportDeclaration(CImportDeclaration { path }));
+            }
+        }
+
+        Some(Declaration::ModuleImportDeclaration(
+            ModuleImportDeclaration {
+                name,
+                declarations: Vec::new(),
+            },
+        ))
+    }
+
+    /// Parses the attributes in front of a declaration, `@name("value")` or
+    /// `@name` when the attribute has no value.
+    fn parse_attributes(&self, input: &mut Peekable<Chars>) -> Option<Vec<Attribute>> {
+        let mut attributes = Vec::new();
+
+        while self.lexer.peek_token(input, true)? == Token::At {
+            self.lexer.next_token(input, true);
+
+            let mut token = self.lexer.next_token(input, true)?;
+            let name = self.parse_identifier(token)?;
+
+            if self.lexer.peek_token(input, false) != Some(Token::LeftParen) {
+                attributes.push(Attribute { name, value: None });
+                continue;
+            }
+
+            self.lexer.next_token(input, false);
+
+            let Token::String(value) = self.lexer.next_token(input, true)? else {
+                return None;
+            };
+
+            token = self.lexer.next_token(input, true)?;
+
+            if token != Token::RightParen {
+                return None;
+            }
+
+            attributes.push(Attribute {
+                name,
+                value: Some(value),
+            });
+        }
+
+        Some(attributes)
+    }
+
     fn parse_function_signature(&self, input: &mut Peekable<Chars>) -> Option<FunctionSignature> {
-        println!("parsing function signature");
         let parameters = self.parse_function_parameters(input)?;
         let return_clause = self.parse_return_clause(input);
 
@@ -290,7 +753,6 @@ impl Parser {
     }
 
     fn parse_function_parameters(&self, input: &mut Peekable<Chars>) -> Option<FunctionParameters> {
-        println!("parsing function parameters");
         let mut token = self.lexer.next_token(input, true)?;
 
         if token != Token::LeftParen {
@@ -298,6 +760,7 @@ impl Parser {
         }
 
         let mut parameters = Vec::new();
+        let mut variadic = false;
 
         loop {
             token = self.lexer.peek_token(input, true)?;
@@ -307,6 +770,17 @@ impl Parser {
                 break;
             }
 
+            // `...` has to be the last parameter
+            if token == Token::Ellipsis {
+                self.lexer.next_token(input, true);
+                variadic = true;
+                continue;
+            }
+
+            if variadic {
+                return None;
+            }
+
             if let Some(parameter) = self.parse_function_parameter(input) {
                 parameters.push(parameter);
             }
@@ -318,11 +792,13 @@ impl Parser {
             }
         }
 
-        Some(FunctionParameters { parameters })
+        Some(FunctionParameters {
+            parameters,
+            variadic,
+        })
     }
 
     fn parse_function_parameter(&self, input: &mut Peekable<Chars>) -> Option<FunctionParameter> {
-        println!("parsing function parameter");
         let mut token = self.lexer.next_token(input, true)?;
         let name = self.parse_identifier(token)?; // TODO: add label support
 
@@ -344,7 +820,6 @@ impl Parser {
     }
 
     fn parse_return_clause(&self, input: &mut Peekable<Chars>) -> Option<ReturnClause> {
-        println!("parsing return clause");
         let token = self.lexer.peek_token(input, true)?;
 
         if token != Token::Arrow {
@@ -358,27 +833,223 @@ impl Parser {
     }
 
     fn parse_type(&self, input: &mut Peekable<Chars>) -> Option<TypeSyntax> {
-        println!("parsing type");
-        let token = self.lexer.next_token(input, true)?;
+        let mut ty = self.parse_non_optional_type(input)?;
+
+        // `?` applies to everything written before it, `*u8?` is an optional pointer
+        while self.lexer.peek_token(input, false) == Some(Token::Question) {
+            self.lexer.next_token(input, false);
+            ty = TypeSyntax::OptionalType(Box::new(ty));
+        }
+
+        Some(ty)
+    }
+
+    fn parse_non_optional_type(&self, input: &mut Peekable<Chars>) -> Option<TypeSyntax> {
+        let mut token = self.lexer.next_token(input, true)?;
+
+        // the types of other modules are qualified by the module, as in `geometry.Vector2`
+        if let Token::Identifier(module) = &token {
+            if self.lexer.peek_token(input, false) == Some(Token::Dot) {
+                self.lexer.next_token(input, false);
+                let name = self.parse_identifier(self.lexer.next_token(input, false)?)?;
+                token = Token::Identifier(format!("{}.{}", module, name.name));
+            }
+        }
 
         match token {
+            Token::Identifier(name)
+                if name == "optional"
+                    && self.lexer.peek_token(input, false) == Some(Token::LessThan) =>
+            {
+                self.lexer.next_token(input, false);
+                let wrapped = self.parse_type(input)?;
+
+                if self.lexer.next_token(input, true)? != Token::GreaterThan {
+                    return None;
+                }
+
+                Some(TypeSyntax::OptionalType(Box::new(wrapped)))
+            }
+            Token::Identifier(name)
+                if name == "result"
+                    && self.lexer.peek_token(input, false) == Some(Token::LessThan) =>
+            {
+                self.lexer.next_token(input, false);
+                let value = self.parse_type(input)?;
+
+                if self.lexer.next_token(input, true)? != Token::Comma {
+                    return None;
+                }
+
+                let error = self.parse_type(input)?;
+
+                if self.lexer.next_token(input, true)? != Token::GreaterThan {
+                    return None;
+                }
+
+                Some(TypeSyntax::ResultType(Box::new(value), Box::new(error)))
+            }
+            Token::Identifier(name)
+                if self.lexer.peek_token(input, false) == Some(Token::LessThan) =>
+            {
+                self.lexer.next_token(input, false);
+                let mut arguments = Vec::new();
+
+                loop {
+                    arguments.push(self.parse_type(input)?);
+
+                    match self.lexer.next_token(input, true)? {
+                        Token::Comma => continue,
+                        Token::GreaterThan => break,
+                        _ => return None,
+                    }
+                }
+
+                Some(TypeSyntax::GenericType(Identifier { name }, arguments))
+            }
             Token::Identifier(name) => Some(TypeSyntax::IdentifierType(Identifier { name })),
+            Token::LeftBracket => {
+                let element = self.parse_type(input)?;
+
+                match self.lexer.next_token(input, true)? {
+                    Token::RightBracket => Some(TypeSyntax::ArrayType(Box::new(element))),
+                    Token::Colon => {
+                        let value = self.parse_type(input)?;
+
+                        if self.lexer.next_token(input, true)? != Token::RightBracket {
+                            return None;
+                        }
+
+                        Some(TypeSyntax::DictionaryType(
+                            Box::new(element),
+                            Box::new(value),
+                        ))
+                    }
+                    _ => None,
+                }
+            }
+            Token::Asterisk => Some(TypeSyntax::PointerType(Box::new(
+                self.parse_non_optional_type(input)?,
+            ))),
+            // a single type in parentheses is only grouped, as in `(*u8)?`
+            Token::LeftParen => {
+                let mut elements = Vec::new();
+
+                if self.lexer.peek_token(input, true)? == Token::RightParen {
+                    self.lexer.next_token(input, true);
+                } else {
+                    loop {
+                        elements.push(self.parse_type(input)?);
+
+                        match self.lexer.next_token(input, true)? {
+                            Token::Comma => continue,
+                            Token::RightParen => break,
+                            _ => return None,
+                        }
+                    }
+                }
+
+                if self.lexer.peek_token(input, false) == Some(Token::Arrow) {
+                    self.lexer.next_token(input, false);
+                    let return_type = self.parse_type(input)?;
+
+                    return Some(TypeSyntax::FunctionType(elements, Box::new(return_type)));
+                }
+
+                if elements.len() == 1 {
+                    return elements.pop();
+                }
+
+                Some(TypeSyntax::TupleType(elements))
+            }
             _ => None,
         }
     }
 
     fn parse_expression(&self, input: &mut Peekable<Chars>) -> Option<Expression> {
-        println!("parsing expression");
-
-        let left = self.parse_primary_expression(input)?;
+        let left = self.parse_postfix_expression(input)?;
         self.parse_binary_operation(input, left, 1)
     }
 
+    fn parse_postfix_expression(&self, input: &mut Peekable<Chars>) -> Option<Expression> {
+        let mut expression = self.parse_primary_expression(input)?;
+
+        // member accesses, indexing and `?` have to stay on the line of their base
+        // expression
+        loop {
+            let token = match self.lexer.peek_token(input, false) {
+                Some(token @ (Token::Dot | Token::QuestionDot)) => token,
+                Some(Token::Question) => {
+                    self.lexer.next_token(input, false);
+
+                    expression = Expression::Try(TryExpression {
+                        expression: Box::new(expression),
+                    });
+                    continue;
+                }
+                Some(Token::LeftBracket) => {
+                    let location = self.location(input);
+                    self.lexer.next_token(input, false);
+
+                    let index = self.parse_expression(input)?;
+
+                    if self.lexer.next_token(input, true)? != Token::RightBracket {
+                        return None;
+                    }
+
+                    expression = Expression::Index(IndexExpression {
+                        base: Box::new(expression),
+                        index: Box::new(index),
+                        location,
+                    });
+                    continue;
+                }
+                _ => break,
+            };
+
+            self.lexer.next_token(input, false);
+
+            let optional = token == Token::QuestionDot;
+
+            // the elements of a tuple are named by their index, which is read here
+            // as the lexer would take `.0.1` for a number
+            let member = if input.peek().is_some_and(|c| c.is_ascii_digit()) {
+                let mut name = String::new();
+
+                while let Some(c) = input.next_if(|c| c.is_ascii_digit()) {
+                    name.push(c);
+                }
+
+                Identifier { name }
+            } else {
+                let token = self.lexer.next_token(input, true)?;
+                self.parse_identifier(token)?
+            };
+
+            if self.lexer.peek_token(input, false) == Some(Token::LeftParen) {
+                self.lexer.next_token(input, false);
+
+                expression = Expression::MethodCall(MethodCallExpression {
+                    receiver: Box::new(expression),
+                    method: member,
+                    arguments: self.parse_argument_list(input)?,
+                    optional,
+                });
+            } else {
+                expression = Expression::MemberAccess(MemberAccessExpression {
+                    base: Box::new(expression),
+                    member,
+                    optional,
+                });
+            }
+        }
+
+        Some(expression)
+    }
+
     fn parse_primary_expression(&self, input: &mut Peekable<Chars>) -> Option<Expression> {
         let token = self.lexer.next_token(input, true)?;
 
-        println!("{:?}", token);
-
         match token {
             Token::Identifier(name) => {
                 let Some(token) = self.lexer.peek_token(input, true) else {
@@ -387,7 +1058,7 @@ impl Parser {
 
                 if token == Token::LeftParen {
                     self.lexer.next_token(input, true);
-                    let arguments = self.parse_tuple(input)?;
+                    let arguments = self.parse_argument_list(input)?;
 
                     return Some(Expression::FunctionCall(FunctionCallExpression {
                         function: Identifier { name },
@@ -398,65 +1069,273 @@ impl Parser {
                 Some(Expression::Identifier(Identifier { name }))
             }
             Token::Number(value) => Some(Expression::FloatNumberLiteral(value)),
+            Token::String(value) => Some(Expression::StringLiteral(value)),
             Token::Keyword(Keyword::True) => Some(Expression::BooleanLiteral(true)),
             Token::Keyword(Keyword::False) => Some(Expression::BooleanLiteral(false)),
-            Token::Keyword(Keyword::If) => self
-                .parse_if_expression(input)
-                .map(|if_expr| Expression::If(if_expr)),
+            Token::Keyword(Keyword::If) => self.parse_if_expression(input).map(Expression::If),
+            Token::Keyword(Keyword::Match) => {
+                self.parse_match_expression(input).map(Expression::Match)
+            }
             Token::Keyword(Keyword::Nil) => Some(Expression::NilLiteral),
-            Token::LeftParen => self
-                .parse_tuple(input)
-                .map(|tuple| Expression::Tuple(tuple)),
+            Token::Keyword(keyword @ (Keyword::Ok | Keyword::Err)) => {
+                if self.lexer.next_token(input, true)? != Token::LeftParen {
+                    return None;
+                }
+
+                let value = self.parse_expression(input)?;
+
+                if self.lexer.next_token(input, true)? != Token::RightParen {
+                    return None;
+                }
+
+                Some(Expression::Result(ResultExpression {
+                    variant: Self::result_variant(&keyword),
+                    value: Box::new(value),
+                }))
+            }
+            Token::LeftParen => self.parse_tuple(input),
+            Token::LeftBracket => self.parse_array(input),
+            Token::Keyword(Keyword::Fun) => Some(Expression::Closure(ClosureExpression {
+                signature: self.parse_function_signature(input)?,
+                body: self.parse_code_block_container(input)?,
+            })),
             _ => None,
         }
     }
 
+    /// Parses the elements of an array literal up to the closing bracket, the
+    /// opening bracket has already been consumed. A colon after the first
+    /// element makes the literal a dictionary.
+    fn parse_array(&self, input: &mut Peekable<Chars>) -> Option<Expression> {
+        let mut elements = Vec::new();
+
+        if self.lexer.peek_token(input, true)? == Token::Colon {
+            self.lexer.next_token(input, true);
+
+            if self.lexer.next_token(input, true)? != Token::RightBracket {
+                return None;
+            }
+
+            return Some(Expression::Dictionary(DictionaryExpression {
+                entries: Vec::new(),
+            }));
+        }
+
+        loop {
+            if self.lexer.peek_token(input, true)? == Token::RightBracket {
+                self.lexer.next_token(input, true);
+                break;
+            }
+
+            let element = self.parse_expression(input)?;
+
+            if elements.is_empty() && self.lexer.peek_token(input, true)? == Token::Colon {
+                return self
+                    .parse_dictionary(input, element)
+                    .map(Expression::Dictionary);
+            }
+
+            elements.push(element);
+
+            match self.lexer.next_token(input, true)? {
+                Token::Comma => continue,
+                Token::RightBracket => break,
+                _ => return None,
+            }
+        }
+
+        Some(Expression::Array(ArrayExpression { elements }))
+    }
+
+    /// Parses the entries of a dictionary literal given its first key, the
+    /// colon following the key has not been consumed yet.
+    fn parse_dictionary(
+        &self,
+        input: &mut Peekable<Chars>,
+        first_key: Expression,
+    ) -> Option<DictionaryExpression> {
+        let mut entries = Vec::new();
+        let mut key = first_key;
+
+        loop {
+            if self.lexer.next_token(input, true)? != Token::Colon {
+                return None;
+            }
+
+            entries.push((key, self.parse_expression(input)?));
+
+            match self.lexer.next_token(input, true)? {
+                Token::Comma => {}
+                Token::RightBracket => break,
+                _ => return None,
+            }
+
+            // a trailing comma before the closing bracket
+            if self.lexer.peek_token(input, true)? == Token::RightBracket {
+                self.lexer.next_token(input, true);
+                break;
+            }
+
+            key = self.parse_expression(input)?;
+        }
+
+        Some(DictionaryExpression { entries })
+    }
+
     fn parse_if_expression(&self, input: &mut Peekable<Chars>) -> Option<IfExpression> {
+        let mut binding = None;
+
+        if self.lexer.peek_token(input, true)? == Token::Keyword(Keyword::Let) {
+            self.lexer.next_token(input, true);
+
+            let token = self.lexer.next_token(input, true)?;
+            binding = Some(self.parse_identifier(token)?);
+
+            if self.lexer.next_token(input, true)? != Token::Assign {
+                return None;
+            }
+        }
+
         let condition = self.parse_expression(input)?;
-        let then_expression = self.parse_expression_container(input)?;
+        let then_branch = self.parse_code_block_container(input)?;
 
         let Some(token) = self.lexer.peek_token(input, true) else {
-            return None;
+            return Some(IfExpression {
+                binding,
+                condition: Box::new(condition),
+                then_branch,
+                else_clause: None,
+            });
         };
 
         if token != Token::Keyword(Keyword::Else) {
-            return None;
+            return Some(IfExpression {
+                binding,
+                condition: Box::new(condition),
+                then_branch,
+                else_clause: None,
+            });
         }
 
         self.lexer.next_token(input, true);
 
-        let else_expression = self.parse_expression_container(input)?;
+        let else_clause = if self.lexer.peek_token(input, true)? == Token::Keyword(Keyword::If) {
+            self.lexer.next_token(input, true);
+            ElseClause::ElseIf(Box::new(self.parse_if_expression(input)?))
+        } else {
+            ElseClause::Else(self.parse_code_block_container(input)?)
+        };
 
         Some(IfExpression {
+            binding,
             condition: Box::new(condition),
-            then_expression: Box::new(then_expression),
-            else_expression: Box::new(else_expression),
+            then_branch,
+            else_clause: Some(else_clause),
         })
     }
 
-    fn parse_expression_container(&self, input: &mut Peekable<Chars>) -> Option<Expression> {
-        let Some(token) = self.lexer.peek_token(input, true) else {
-            return None;
-        };
+    fn parse_match_expression(&self, input: &mut Peekable<Chars>) -> Option<MatchExpression> {
+        let subject = self.parse_expression(input)?;
 
-        if token != Token::LeftBrace {
+        if self.lexer.next_token(input, true)? != Token::LeftBrace {
             return None;
         }
 
-        self.lexer.next_token(input, true);
-        let expression = self.parse_expression(input)?;
+        let mut arms = Vec::new();
 
-        let Some(token) = self.lexer.peek_token(input, true) else {
-            return None;
-        };
+        loop {
+            if self.lexer.peek_token(input, true)? == Token::RightBrace {
+                self.lexer.next_token(input, true);
+                break;
+            }
 
-        if token != Token::RightBrace {
-            return None;
+            let pattern = self.parse_pattern(input)?;
+
+            if self.lexer.next_token(input, true)? != Token::FatArrow {
+                return None;
+            }
+
+            // a single expression is the same as a block holding only it
+            let body = if self.lexer.peek_token(input, true)? == Token::LeftBrace {
+                self.parse_code_block_container(input)?
+            } else {
+                CodeBlockContainer {
+                    code_block: CodeBlock {
+                        items: vec![CodeBlockItem::Expression(self.parse_expression(input)?)],
+                    },
+                }
+            };
+
+            arms.push(MatchArm { pattern, body });
+
+            // arms are separated by new lines or commas
+            if self.lexer.peek_token(input, true)? == Token::Comma {
+                self.lexer.next_token(input, true);
+            }
         }
 
-        self.lexer.next_token(input, true);
+        Some(MatchExpression {
+            subject: Box::new(subject),
+            arms,
+        })
+    }
 
-        return Some(expression);
+    fn parse_pattern(&self, input: &mut Peekable<Chars>) -> Option<Pattern> {
+        let token = self.lexer.next_token(input, true)?;
+
+        match token {
+            Token::Wildcard => Some(Pattern::Wildcard),
+            Token::Keyword(Keyword::Nil) => Some(Pattern::Nil),
+            Token::Keyword(Keyword::Let) => {
+                let token = self.lexer.next_token(input, true)?;
+                Some(Pattern::Binding(self.parse_identifier(token)?))
+            }
+            Token::Dot => {
+                let token = self.lexer.next_token(input, true)?;
+                Some(Pattern::EnumCase(self.parse_identifier(token)?))
+            }
+            Token::Keyword(keyword @ (Keyword::Ok | Keyword::Err)) => {
+                if self.lexer.next_token(input, true)? != Token::LeftParen {
+                    return None;
+                }
+
+                let binding = match self.lexer.next_token(input, true)? {
+                    Token::Wildcard => None,
+                    Token::Keyword(Keyword::Let) => {
+                        let token = self.lexer.next_token(input, true)?;
+                        Some(self.parse_identifier(token)?)
+                    }
+                    _ => return None,
+                };
+
+                if self.lexer.next_token(input, true)? != Token::RightParen {
+                    return None;
+                }
+
+                Some(Pattern::Result(Self::result_variant(&keyword), binding))
+            }
+            Token::Number(value) => Some(Pattern::Literal(Expression::FloatNumberLiteral(value))),
+            Token::Minus => match self.lexer.next_token(input, true)? {
+                Token::Number(value) => {
+                    Some(Pattern::Literal(Expression::FloatNumberLiteral(-value)))
+                }
+                _ => None,
+            },
+            Token::Keyword(Keyword::True) => {
+                Some(Pattern::Literal(Expression::BooleanLiteral(true)))
+            }
+            Token::Keyword(Keyword::False) => {
+                Some(Pattern::Literal(Expression::BooleanLiteral(false)))
+            }
+            _ => None,
+        }
+    }
+
+    fn result_variant(keyword: &Keyword) -> ResultVariant {
+        match keyword {
+            Keyword::Ok => ResultVariant::Ok,
+            _ => ResultVariant::Err,
+        }
     }
 
     fn parse_binary_operation(
@@ -467,8 +1346,6 @@ impl Parser {
     ) -> Option<Expression> {
         let mut new_left = left;
 
-        println!("parsing binary expression");
-
         loop {
             let Some(token) = self.lexer.peek_token(input, true) else {
                 return Some(new_left);
@@ -478,35 +1355,27 @@ impl Parser {
                 return Some(new_left);
             };
 
-            self.lexer.next_token(input, true);
-
-            dbg!(&token);
-
             let operator_precedence = operator.precedence();
 
             if operator_precedence < precedence {
                 return Some(new_left);
             }
 
-            let mut right = self.parse_primary_expression(input)?;
+            self.lexer.next_token(input, true);
+
+            let mut right = self.parse_postfix_expression(input)?;
 
             if let Some(next_token) = self.lexer.peek_token(input, true) {
                 if let Some(next_operator) = BinaryOperator::from_token(&next_token) {
                     let next_operator_precedence = next_operator.precedence();
 
                     if operator_precedence < next_operator_precedence {
-                        let Some(new_right) =
-                            self.parse_binary_operation(input, right, operator_precedence + 1)
-                        else {
-                            return None;
-                        };
-
-                        right = new_right;
+                        right =
+                            self.parse_binary_operation(input, right, operator_precedence + 1)?;
                     }
                 }
             }
 
-            println!("setting a new left");
             new_left = Expression::BinaryOperator(BinaryOperatorExpression {
                 operator,
                 left: Box::new(new_left),
@@ -515,44 +1384,75 @@ impl Parser {
         }
     }
 
-    fn parse_tuple(&self, input: &mut Peekable<Chars>) -> Option<TupleExpression> {
-        Some(TupleExpression {
-            expressions: self.parse_expression_list(input),
-        })
+    /// Parses the expressions in parentheses up to the closing one, the opening
+    /// parenthesis has already been consumed. A single expression is only
+    /// grouped, several make a tuple.
+    fn parse_tuple(&self, input: &mut Peekable<Chars>) -> Option<Expression> {
+        let mut expressions = self.parse_expression_list(input)?;
+
+        if expressions.len() == 1 {
+            return expressions.pop();
+        }
+
+        Some(Expression::Tuple(TupleExpression {
+            expressions: ExpressionList { items: expressions },
+        }))
     }
 
-    fn parse_expression_list(&self, input: &mut Peekable<Chars>) -> ExpressionList {
+    fn parse_expression_list(&self, input: &mut Peekable<Chars>) -> Option<Vec<Expression>> {
         let mut expressions = Vec::new();
 
         loop {
-            let expression = self.parse_expression(input);
+            expressions.push(self.parse_expression(input)?);
 
-            if let Some(expression) = expression {
-                expressions.push(expression);
+            match self.lexer.next_token(input, true)? {
+                Token::Comma => continue,
+                Token::RightParen => break,
+                _ => return None,
             }
+        }
 
-            let Some(token) = self.lexer.peek_token(input, true) else {
+        Some(expressions)
+    }
+
+    /// Parses the arguments of a call up to the closing parenthesis, the
+    /// opening parenthesis has already been consumed.
+    fn parse_argument_list(&self, input: &mut Peekable<Chars>) -> Option<ArgumentList> {
+        let mut arguments = Vec::new();
+
+        loop {
+            if self.lexer.peek_token(input, true)? == Token::RightParen {
+                self.lexer.next_token(input, true);
                 break;
-            };
+            }
 
-            match token {
-                Token::Comma => {
-                    self.lexer.next_token(input, true);
-                }
-                Token::RightParen => {
-                    self.lexer.next_token(input, true);
-                    break;
-                }
-                _ => break, // TODO: probably error this out
+            let mut lookahead = input.clone();
+            let mut label = None;
+
+            if let (Some(Token::Identifier(name)), Some(Token::Colon)) = (
+                self.lexer.next_token(&mut lookahead, true),
+                self.lexer.next_token(&mut lookahead, true),
+            ) {
+                *input = lookahead;
+                label = Some(Identifier { name });
+            }
+
+            arguments.push(Argument {
+                label,
+                expression: self.parse_expression(input)?,
+            });
+
+            match self.lexer.next_token(input, true)? {
+                Token::Comma => continue,
+                Token::RightParen => break,
+                _ => return None,
             }
         }
 
-        ExpressionList { items: expressions }
+        Some(ArgumentList { arguments })
     }
 
     fn parse_identifier(&self, token: Token) -> Option<Identifier> {
-        println!("parsing identifier: {:?}", token);
-
         if let Token::Identifier(name) = token {
             Some(Identifier { name })
         } else {
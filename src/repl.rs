@@ -0,0 +1,685 @@
+use crate::analysis::AnalysisVisitor;
+use crate::c_import;
+use crate::codegen::{CodeGenOptions, LLVMCodeGenVisitor};
+use crate::context::{Context, Symbol, Type, TypeDefinitionKind};
+use crate::driver::{self, CompileOptions};
+use crate::hoist;
+use crate::jit;
+use crate::lexer::Lexer;
+use crate::module;
+use crate::parser::Parser;
+use crate::syntax::*;
+use crate::type_check::TypeCheckVisitor;
+
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::io::{self, BufRead, Write};
+use std::os::raw::c_int;
+use std::path::{Path, PathBuf};
+
+extern "C" {
+    fn fflush(stream: *mut c_void) -> c_int;
+}
+
+const MODULE_NAME: &str = "repl";
+/// The function the code of an input runs in, numbered after the input.
+const EVALUATE: &str = "repl_evaluate";
+/// The global holding the value an input ends with, numbered the same way.
+const VALUE: &str = "repl_value";
+
+const HELP: &str = "\
+declarations and variables are kept for the inputs that follow
+expressions are evaluated and their value is printed
+:type <expression>  prints the type of an expression
+:ir <code>          prints the LLVM IR code is compiled to
+:quit               leaves, as does the end of the input";
+
+/// An interactive session. Each input is checked against the declarations
+/// and variables of the inputs before it, then compiled into a module of its
+/// own that the JIT keeps, so that only the new input runs.
+struct Session {
+    context: Context,
+    /// The inputs run so far, the context refers to their syntax trees.
+    inputs: Vec<SourceFile>,
+    /// The modules imported by the inputs, imported again by each input.
+    imports: Vec<String>,
+    /// The code generated for the inputs and the modules they import, the
+    /// JIT runs their modules.
+    codegens: Vec<LLVMCodeGenVisitor>,
+    jit: jit::Session,
+    /// The function printing each type, see `Printer`.
+    printers: HashMap<String, String>,
+    options: CompileOptions,
+}
+
+/// Reads inputs from the standard input until its end or `:quit`, an input
+/// spans several lines while its braces, brackets or parentheses are open.
+/// Extern functions are found in the process and in the libraries given.
+pub fn repl(libraries: &[PathBuf], options: CompileOptions) -> Result<(), String> {
+    for library in libraries {
+        jit::load_library(library)?;
+    }
+
+    let mut context = Context::new();
+    context.enter_module(MODULE_NAME);
+
+    let mut session = Session {
+        context,
+        inputs: Vec::new(),
+        imports: Vec::new(),
+        codegens: Vec::new(),
+        jit: jit::Session::new()?,
+        printers: HashMap::new(),
+        options,
+    };
+
+    println!("x repl, :help lists the commands");
+
+    session.read(&mut io::stdin().lock().lines());
+    session.finish();
+
+    Ok(())
+}
+
+impl Session {
+    fn read(&mut self, lines: &mut impl Iterator<Item = io::Result<String>>) {
+        loop {
+            let mut input = String::new();
+
+            loop {
+                print!("{}", if input.is_empty() { "> " } else { "... " });
+                io::stdout().flush().unwrap();
+
+                let Some(Ok(line)) = lines.next() else {
+                    return;
+                };
+
+                input.push_str(&line);
+                input.push('\n');
+
+                if nesting(&input) <= 0 {
+                    break;
+                }
+            }
+
+            let command = input.trim();
+
+            match command
+                .split_once(char::is_whitespace)
+                .unwrap_or((command, ""))
+            {
+                ("", _) => continue,
+                (":quit" | ":q", _) => return,
+                (":help", _) => println!("{}", HELP),
+                (":type", expression) => self.print_type(expression),
+                (":ir", code) => self.print_ir(code),
+                (other, _) if other.starts_with(':') => {
+                    println!("unknown command {}, :help lists the commands", other)
+                }
+                _ => {
+                    self.evaluate(&input, true);
+                }
+            }
+        }
+    }
+
+    /// Checks an input, runs its code and prints the value it ends with when
+    /// `show` is set. The session is left as it was when the input has errors,
+    /// whether it ran is returned.
+    fn evaluate(&mut self, input: &str, show: bool) -> bool {
+        let number = self.inputs.len();
+        let evaluate = format!("{}_{}", EVALUATE, number);
+        let saved = self.context.clone();
+
+        let Some(file) = self.check(input, &evaluate) else {
+            self.context = saved;
+            return false;
+        };
+
+        let (value, value_type) = match tail_value(&file, &evaluate, &self.context) {
+            Some((expression, ty)) if show => (Some(expression), Some(ty)),
+            _ => (None, None),
+        };
+        let value_name = format!("{}_{}", VALUE, number);
+
+        for (name, ty) in bindings(&file, &evaluate, &self.context)
+            .into_iter()
+            .chain(value_type.clone().map(|ty| (value_name.clone(), ty)))
+        {
+            self.context.register_symbol(Symbol {
+                name,
+                symbol_type: ty,
+                declared: false,
+            });
+        }
+
+        let imported = self
+            .context
+            .module_paths()
+            .split_off(saved.module_paths().len());
+        let mut codegens = Vec::new();
+
+        if let Err(error) = driver::generate_modules(&imported, &self.options, &mut codegens) {
+            codegens.iter().for_each(LLVMCodeGenVisitor::finish);
+            self.context = saved;
+            println!("error: {}", error);
+            return false;
+        }
+
+        codegens.push(self.generate(
+            &file,
+            &evaluate,
+            value.map(|expression| (expression, value_name.as_str())),
+        ));
+
+        let modules = codegens
+            .iter_mut()
+            .map(LLVMCodeGenVisitor::take_module)
+            .collect();
+        let result = match codegens.iter().any(LLVMCodeGenVisitor::uses_runtime) {
+            true => driver::load_runtime(),
+            false => Ok(()),
+        }
+        .and_then(|_| self.jit.add_modules(modules));
+
+        // the modules added stay with the JIT, the others are disposed of
+        self.codegens.extend(codegens);
+
+        if let Err(error) = result {
+            self.context = saved;
+            println!("error: {}", error);
+            return false;
+        }
+
+        let result = self.jit.call(&format!("{}.{}", MODULE_NAME, evaluate));
+
+        // the output of the code is buffered by C
+        unsafe { fflush(std::ptr::null_mut()) };
+
+        if let Err(error) = result {
+            println!("error: {}", error);
+        }
+
+        for name in imports(&file) {
+            if !self.imports.contains(&name) {
+                self.imports.push(name);
+            }
+        }
+
+        self.inputs.push(file);
+
+        if let Some(ty) = value_type {
+            self.print(&value_name, &ty);
+        }
+
+        true
+    }
+
+    /// Prints the value of a global of the session, through functions written
+    /// for its type the first time one is printed.
+    fn print(&mut self, name: &str, ty: &Type) {
+        let printers = self.printers.clone();
+
+        let mut printer = Printer {
+            context: &self.context,
+            functions: Vec::new(),
+            names: &mut self.printers,
+        };
+        let show = printer.show(ty);
+        let functions = printer.functions;
+
+        // the value is printed with printf, which the inputs may declare already
+        let printf = match self.context.lookup("printf".to_string()) {
+            Some(_) => "",
+            None => "extern fun printf(format: *u8, ...) -> i32\n",
+        };
+
+        let code = format!(
+            "{}\n{}\n{}({})\nprintf(\"\\n\")\n",
+            printf,
+            functions.join("\n"),
+            show,
+            name
+        );
+
+        if !self.evaluate(&code, false) {
+            self.printers = printers;
+        }
+    }
+
+    fn print_type(&mut self, expression: &str) {
+        let saved = self.context.clone();
+        let evaluate = format!("{}_{}", EVALUATE, self.inputs.len());
+
+        if let Some(file) = self.check(expression, &evaluate) {
+            match tail_value(&file, &evaluate, &self.context) {
+                Some((_, ty)) => println!("{}", ty),
+                None => println!("no value"),
+            }
+        }
+
+        self.context = saved;
+    }
+
+    fn print_ir(&mut self, code: &str) {
+        let saved = self.context.clone();
+        let evaluate = format!("{}_{}", EVALUATE, self.inputs.len());
+
+        if let Some(file) = self.check(code, &evaluate) {
+            let codegen = self.generate(&file, &evaluate, None);
+
+            if let Some(ir) = codegen.function_ir(&format!("{}.{}", MODULE_NAME, evaluate)) {
+                println!("{}", ir.trim_end());
+            }
+
+            codegen.finish();
+        }
+
+        self.context = saved;
+    }
+
+    /// Parses an input and checks it against the session, the diagnostics are
+    /// printed as they are found. The declarations of the input stay at the
+    /// top level and its code goes into the function `evaluate`, which
+    /// returns 0 after it. None is returned when there are errors, the context
+    /// then has to be restored.
+    fn check(&mut self, input: &str, evaluate: &str) -> Option<SourceFile> {
+        let mut characters = input.chars().peekable();
+        let parsed = Parser::new(Lexer::new()).parse(&mut characters);
+        let rest = characters.collect::<String>();
+
+        if !rest.trim().is_empty() {
+            println!("error: cannot parse {:?}", rest.trim());
+            return None;
+        }
+
+        // the modules imported before are imported again, they are only read once
+        let program = self
+            .imports
+            .iter()
+            .map(|name| format!("import {}\n", name))
+            .collect::<String>()
+            + &format!("fun {}() -> i32 {{\n0\n}}\n", evaluate);
+        let mut file = Parser::new(Lexer::new()).parse(&mut program.chars().peekable());
+
+        let Some(CodeBlockItem::Declaration(Declaration::FunctionDeclaration(mut function))) =
+            file.code_block.items.pop()
+        else {
+            unreachable!();
+        };
+
+        let mut code = Vec::new();
+
+        for item in parsed.code_block.items {
+            match item {
+                CodeBlockItem::Declaration(declaration)
+                    if !matches!(declaration, Declaration::VariableDeclaration(_)) =>
+                {
+                    file.code_block
+                        .items
+                        .push(CodeBlockItem::Declaration(declaration));
+                }
+                item => code.push(item),
+            }
+        }
+
+        function.body.code_block.items.splice(0..0, code);
+
+        let declared = file
+            .code_block
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                CodeBlockItem::Declaration(Declaration::FunctionDeclaration(function)) => {
+                    Some(&function.identifier.name)
+                }
+                _ => None,
+            })
+            .chain(
+                function
+                    .body
+                    .code_block
+                    .items
+                    .iter()
+                    .flat_map(declared_variables),
+            );
+
+        for name in declared.collect::<Vec<_>>() {
+            if self.context.lookup(name.clone()).is_some() {
+                self.context
+                    .error(format!("{:?} is already declared", name));
+            }
+        }
+
+        file.code_block.items.push(CodeBlockItem::Declaration(
+            Declaration::FunctionDeclaration(function),
+        ));
+
+        let directory = Path::new(".");
+        let context = &mut self.context;
+
+        c_import::expand_imports(&mut file, directory, context);
+        module::expand_modules(
+            &mut file,
+            directory,
+            &self.options.search_paths,
+            MODULE_NAME,
+            context,
+        );
+        hoist::hoist_local_declarations(&mut file, None, context);
+
+        let analysis = AnalysisVisitor {};
+        analysis.visit_source_file(&file, context);
+
+        let type_check = TypeCheckVisitor::new();
+        type_check.visit_source_file(&file, context);
+
+        match context.has_errors() {
+            true => None,
+            false => Some(file),
+        }
+    }
+
+    /// Generates the code of a checked input, the declarations of the inputs
+    /// before are declared in its module.
+    fn generate(
+        &self,
+        file: &SourceFile,
+        evaluate: &str,
+        value: Option<(&Expression, &str)>,
+    ) -> LLVMCodeGenVisitor {
+        let mut codegen = LLVMCodeGenVisitor::new(CodeGenOptions {
+            module_name: MODULE_NAME.to_string(),
+            source_name: format!("<{}>", MODULE_NAME),
+            bounds_checks: self.options.bounds_checks,
+        });
+        codegen.visit_input(file, &self.inputs, evaluate, value, &self.context);
+
+        codegen
+    }
+
+    /// Disposes of the JIT, then of the code generated for it.
+    fn finish(self) {
+        self.jit.finish();
+        self.codegens.iter().for_each(LLVMCodeGenVisitor::finish);
+    }
+}
+
+/// The code of a checked input, the body of the function it runs in.
+fn code<'a>(file: &'a SourceFile, evaluate: &str) -> &'a [CodeBlockItem] {
+    file.code_block
+        .items
+        .iter()
+        .find_map(|item| match item {
+            CodeBlockItem::Declaration(Declaration::FunctionDeclaration(function))
+                if function.identifier.name == evaluate =>
+            {
+                Some(function.body.code_block.items.as_slice())
+            }
+            _ => None,
+        })
+        .unwrap()
+}
+
+/// The expression the code of an input ends with along with its type, unless
+/// it has no value.
+fn tail_value<'a>(
+    file: &'a SourceFile,
+    evaluate: &str,
+    context: &Context,
+) -> Option<(&'a Expression, Type)> {
+    // the code is followed by the `0` returned
+    let items = code(file, evaluate);
+
+    match items.len().checked_sub(2).map(|index| &items[index]) {
+        Some(CodeBlockItem::Expression(expression)) => {
+            Some((expression, context.expression_type(expression)))
+                .filter(|(_, ty)| *ty != Type::Void)
+        }
+        _ => None,
+    }
+}
+
+/// The variables the code of an input declares at its top level, along with
+/// their types. They become globals of the session.
+fn bindings(file: &SourceFile, evaluate: &str, context: &Context) -> Vec<(String, Type)> {
+    let mut bindings = Vec::new();
+
+    for item in code(file, evaluate) {
+        match item {
+            CodeBlockItem::Declaration(Declaration::VariableDeclaration(variable)) => {
+                let ty = match (&variable.type_annotation, &variable.expression) {
+                    (Some(annotation), _) => Type::from(annotation.clone()),
+                    (None, Some(expression)) => context.expression_type(expression),
+                    (None, None) => Type::Void,
+                };
+
+                bindings.push((variable.identifier.name.clone(), ty));
+            }
+            CodeBlockItem::Statement(Statement::Destructuring(statement)) => {
+                let ty = match &statement.type_annotation {
+                    Some(annotation) => Type::from(annotation.clone()),
+                    None => context.expression_type(&statement.value),
+                };
+
+                let Type::Tuple(elements) = ty else {
+                    continue;
+                };
+
+                for (name, ty) in statement.names.iter().zip(elements) {
+                    if let Some(name) = name {
+                        bindings.push((name.name.clone(), ty));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    bindings
+}
+
+/// The names of the variables a declaration or statement declares.
+fn declared_variables(node: &CodeBlockItem) -> Vec<&String> {
+    match node {
+        CodeBlockItem::Declaration(Declaration::VariableDeclaration(variable)) => {
+            vec![&variable.identifier.name]
+        }
+        CodeBlockItem::Statement(Statement::Destructuring(statement)) => statement
+            .names
+            .iter()
+            .flatten()
+            .map(|name| &name.name)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// The modules an input imports.
+fn imports(file: &SourceFile) -> Vec<String> {
+    file.code_block
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            CodeBlockItem::Declaration(Declaration::ModuleImportDeclaration(import)) => {
+                Some(import.name.name.clone())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Writes the functions printing values of each type, as x code.
+struct Printer<'a> {
+    context: &'a Context,
+    functions: Vec<String>,
+    /// The function printing each type, by the name of the type, including
+    /// the functions written for the inputs before.
+    names: &'a mut HashMap<String, String>,
+}
+
+impl Printer<'_> {
+    /// The name of the function printing a value of a type, written the first
+    /// time it is needed.
+    fn show(&mut self, ty: &Type) -> String {
+        if let Some(name) = self.names.get(&ty.to_string()) {
+            return name.clone();
+        }
+
+        let name = format!("repl_show_{}", self.names.len());
+        // named before its body is written, for types that contain themselves
+        self.names.insert(ty.to_string(), name.clone());
+
+        let body = self.body(ty);
+        self.functions.push(format!(
+            "fun {}(value: {}) -> i32 {{\n{}\n0\n}}\n",
+            name, ty, body
+        ));
+
+        name
+    }
+
+    fn body(&mut self, ty: &Type) -> String {
+        let format = |format: &str| format!("printf(\"{}\", value)", format);
+
+        match ty {
+            Type::Identifier(name) => match name.as_str() {
+                "f32" | "f64" => format("%g"),
+                "i8" | "i16" | "i32" => format("%d"),
+                "u8" | "u16" | "u32" => format("%u"),
+                "i64" => format("%ld"),
+                "u64" => format("%lu"),
+                "bool" => "if value {\nprintf(\"true\")\n} else {\nprintf(\"false\")\n}".to_string(),
+                _ => self.nominal(ty, name),
+            },
+            Type::Generic(name, _) => self.nominal(ty, name),
+            Type::Pointer(pointee) if **pointee == Type::Identifier("u8".to_string()) => {
+                format("%s")
+            }
+            Type::Pointer(_) => format("%p"),
+            Type::Optional(wrapped) => format!(
+                "if let repl_value := value {{\n{}(repl_value)\n}} else {{\nprintf(\"nil\")\n}}",
+                self.show(wrapped)
+            ),
+            Type::Result(value, error) => format!(
+                "match value {{\nok(let repl_value) => {}(repl_value)\nerr(let repl_error) => {}(repl_error)\n}}",
+                self.wrapped("ok(", value, ")"),
+                self.wrapped("err(", error, ")")
+            ),
+            Type::Array(element) => {
+                let elements = self.elements(element);
+                format!(
+                    "printf(\"[\")\n{}(value, 0)\nprintf(\"]\")",
+                    elements
+                )
+            }
+            Type::Dictionary(..) => format!(
+                "printf(\"<{} with %ld entries>\", value.count)",
+                ty
+            ),
+            Type::Tuple(elements) => {
+                let mut lines = vec!["printf(\"(\")".to_string()];
+
+                for (index, element) in elements.iter().enumerate() {
+                    if index > 0 {
+                        lines.push("printf(\", \")".to_string());
+                    }
+                    lines.push(format!("{}(value.{})", self.show(element), index));
+                }
+
+                lines.push("printf(\")\")".to_string());
+                lines.join("\n")
+            }
+            Type::Function(_) | Type::Void => format!("printf(\"<{}>\")", ty),
+        }
+    }
+
+    /// Prints the fields of a structure or the case of an enum.
+    fn nominal(&mut self, ty: &Type, name: &str) -> String {
+        let kind = self
+            .context
+            .lookup_type(name)
+            .map(|definition| &definition.kind);
+
+        match kind {
+            Some(TypeDefinitionKind::Structure(_)) => {
+                let fields = self.context.fields(ty).unwrap_or_default();
+                let mut lines = vec![format!("printf(\"{}(\")", ty)];
+
+                for (index, field) in fields.iter().enumerate() {
+                    let separator = if index > 0 { ", " } else { "" };
+                    lines.push(format!("printf(\"{}{}: \")", separator, field.name));
+                    lines.push(format!("{}(value.{})", self.show(&field.ty), field.name));
+                }
+
+                lines.push("printf(\")\")".to_string());
+                lines.join("\n")
+            }
+            Some(TypeDefinitionKind::Enumeration(cases)) => {
+                let arms = cases
+                    .iter()
+                    .map(|case| format!(".{} => printf(\".{}\")", case, case))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                format!("match value {{\n{}\n}}", arms)
+            }
+            _ => format!("printf(\"<{}>\")", ty),
+        }
+    }
+
+    /// A function printing a value between a prefix and a suffix.
+    fn wrapped(&mut self, prefix: &str, ty: &Type, suffix: &str) -> String {
+        let name = format!("repl_show_{}", self.names.len());
+        self.names
+            .insert(format!("{}{}{}", prefix, ty, suffix), name.clone());
+
+        let show = self.show(ty);
+        self.functions.push(format!(
+            "fun {}(value: {}) -> i32 {{\nprintf(\"{}\")\n{}(value)\nprintf(\"{}\")\n0\n}}\n",
+            name, ty, prefix, show, suffix
+        ));
+
+        name
+    }
+
+    /// A function printing the elements of an array from an index on,
+    /// separated by commas.
+    fn elements(&mut self, ty: &Type) -> String {
+        let name = format!("repl_show_{}", self.names.len());
+        self.names
+            .insert(format!("elements of {}", ty), name.clone());
+
+        let show = self.show(ty);
+        self.functions.push(format!(
+            "fun {}(value: [{}], index: i64) -> i32 {{\nif index < value.count {{\nif index > 0 {{\nprintf(\", \")\n}}\n{}(value[index])\n{}(value, index + 1)\n}}\n0\n}}\n",
+            name, ty, show, name
+        ));
+
+        name
+    }
+}
+
+/// How deeply the braces, brackets and parentheses of an input are nested at
+/// its end, strings and comments left out.
+fn nesting(input: &str) -> i32 {
+    let mut depth = 0;
+
+    for line in input.lines() {
+        let mut in_string = false;
+        let mut previous = ' ';
+
+        for character in line.chars() {
+            match character {
+                '"' if previous != '\\' => in_string = !in_string,
+                '/' if previous == '/' && !in_string => break,
+                '{' | '[' | '(' if !in_string => depth += 1,
+                '}' | ']' | ')' if !in_string => depth -= 1,
+                _ => {}
+            }
+            previous = character;
+        }
+    }
+
+    depth
+}
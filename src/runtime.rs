@@ -0,0 +1,88 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The C source of the hash map behind dictionaries, built into the compiler so
+/// that it does not have to be found next to it.
+const DICTIONARY_SOURCE: &str = include_str!("../runtime/dictionary.c");
+
+/// The C compiler the runtime and C sources are built with, `$CC` or `cc`.
+pub fn c_compiler() -> String {
+    env::var("CC").unwrap_or("cc".to_string())
+}
+
+/// Compiles the runtime with the C compiler and merges it into the object file
+/// of a program so that the program links on its own.
+pub fn link_runtime(object_file: &Path) -> Result<(), String> {
+    let compiler = c_compiler();
+    let directory = env::temp_dir();
+    let stem = format!("x-runtime-{}", std::process::id());
+
+    let source_file = directory.join(format!("{}.c", stem));
+    let runtime_object = directory.join(format!("{}.o", stem));
+    let merged_object = directory.join(format!("{}-merged.o", stem));
+
+    fs::write(&source_file, DICTIONARY_SOURCE).map_err(|error| error.to_string())?;
+
+    let result = run(Command::new(&compiler)
+        .args(["-c", "-O2", "-fPIC"])
+        .arg(&source_file)
+        .arg("-o")
+        .arg(&runtime_object))
+    .and_then(|_| {
+        // a relocatable link combines both objects into one
+        run(Command::new(&compiler)
+            .arg("-r")
+            .arg(object_file)
+            .arg(&runtime_object)
+            .arg("-o")
+            .arg(&merged_object))
+    })
+    .and_then(|_| fs::copy(&merged_object, object_file).map_err(|error| error.to_string()));
+
+    for file in [source_file, runtime_object, merged_object] {
+        let _ = fs::remove_file(file);
+    }
+
+    result.map(|_| ())
+}
+
+/// Compiles the runtime into a shared library in the temporary directory, for
+/// programs run without being linked.
+pub fn shared_runtime() -> Result<PathBuf, String> {
+    let directory = env::temp_dir();
+    let stem = format!("x-runtime-{}", std::process::id());
+
+    let source_file = directory.join(format!("{}.c", stem));
+    let library = directory.join(format!("{}.so", stem));
+
+    fs::write(&source_file, DICTIONARY_SOURCE).map_err(|error| error.to_string())?;
+
+    let result = run(Command::new(c_compiler())
+        .args(["-shared", "-O2", "-fPIC"])
+        .arg(&source_file)
+        .arg("-o")
+        .arg(&library));
+
+    let _ = fs::remove_file(source_file);
+
+    result.map(|_| library)
+}
+
+/// Runs a command, failing with its error output if it does not succeed.
+pub fn run(command: &mut Command) -> Result<(), String> {
+    let output = command
+        .output()
+        .map_err(|error| format!("cannot run {:?}: {}", command.get_program(), error))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "{:?} failed:\n{}",
+            command.get_program(),
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    Ok(())
+}
@@ -15,6 +15,33 @@ pub struct CodeBlock {
     pub items: Vec<CodeBlockItem>,
 }
 
+impl CodeBlock {
+    /// The declarations of the block along with those of the modules it
+    /// imports, in order.
+    pub fn declarations(&self) -> Vec<&Declaration> {
+        let mut declarations = Vec::new();
+
+        for item in &self.items {
+            if let CodeBlockItem::Declaration(declaration) = item {
+                collect_declarations(declaration, &mut declarations);
+            }
+        }
+
+        declarations
+    }
+}
+
+fn collect_declarations<'a>(node: &'a Declaration, declarations: &mut Vec<&'a Declaration>) {
+    match node {
+        Declaration::ModuleImportDeclaration(import) => {
+            for declaration in &import.declarations {
+                collect_declarations(declaration, declarations);
+            }
+        }
+        declaration => declarations.push(declaration),
+    }
+}
+
 #[derive(Debug)]
 pub enum CodeBlockItem {
     Declaration(Declaration),
@@ -27,22 +54,70 @@ pub enum Declaration {
     VariableDeclaration(VariableDeclaration),
     FunctionDeclaration(FunctionDeclaration),
     ExternDeclaration(ExternDeclaration),
+    ExternLibraryDeclaration(ExternLibraryDeclaration),
     TypeDeclaration(TypeDeclaration),
     EnumDeclaration(EnumDeclaration),
+    ProtocolDeclaration(ProtocolDeclaration),
+    ExtensionDeclaration(ExtensionDeclaration),
+    CImportDeclaration(CImportDeclaration),
+    ModuleImportDeclaration(ModuleImportDeclaration),
 }
 
 #[derive(Debug)]
 pub struct TypeDeclaration {
+    pub visibility: Visibility,
+    pub location: SourceLocation,
     pub name: Identifier,
+    pub generic_parameters: Vec<GenericParameter>,
+    pub conformances: Vec<Identifier>,
     pub container: MemberBlockContainer,
 }
 
 #[derive(Debug)]
 pub struct EnumDeclaration {
+    pub visibility: Visibility,
+    pub location: SourceLocation,
+    pub name: Identifier,
+    pub generic_parameters: Vec<GenericParameter>,
+    pub conformances: Vec<Identifier>,
+    pub container: MemberBlockContainer,
+}
+
+#[derive(Debug)]
+pub struct ProtocolDeclaration {
+    pub visibility: Visibility,
+    pub location: SourceLocation,
+    pub name: Identifier,
+    pub requirements: Vec<FunctionRequirement>,
+}
+
+/// A function signature that conforming types must implement, the body is a
+/// default implementation used by types that do not implement it themselves.
+#[derive(Debug)]
+pub struct FunctionRequirement {
+    pub attributes: Vec<Attribute>,
+    pub selfless: bool,
+    pub identifier: Identifier,
+    pub signature: FunctionSignature,
+    pub body: Option<CodeBlockContainer>,
+}
+
+/// Adds methods and conformances to an existing type.
+#[derive(Debug)]
+pub struct ExtensionDeclaration {
     pub name: Identifier,
+    pub conformances: Vec<Identifier>,
     pub container: MemberBlockContainer,
 }
 
+/// Whether a declaration can be used from other modules, top-level declarations
+/// and members are private to the module declaring them unless marked `pub`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Visibility {
+    Public,
+    Private,
+}
+
 #[derive(Debug)]
 pub struct MemberBlockContainer {
     pub member_block: MemberBlock,
@@ -55,20 +130,32 @@ pub struct MemberBlock {
 
 #[derive(Debug)]
 pub enum MemberBlockItem {
-    // EnumCaseDeclaration(EnumCaseDeclaration),
+    EnumCaseDeclaration(EnumCaseDeclaration),
     VariableDeclaration(VariableDeclaration),
     FunctionDeclaration(FunctionDeclaration),
 }
 
+#[derive(Debug)]
+pub struct EnumCaseDeclaration {
+    pub name: Identifier,
+}
+
 #[derive(Debug)]
 pub struct VariableDeclaration {
+    pub visibility: Visibility,
+    pub location: SourceLocation,
     pub identifier: Identifier,
-    pub expression: Expression,
+    pub type_annotation: Option<TypeSyntax>,
+    pub expression: Option<Expression>,
 }
 
 #[derive(Debug)]
 pub struct FunctionDeclaration {
+    pub attributes: Vec<Attribute>,
+    pub visibility: Visibility,
+    pub location: SourceLocation,
     pub identifier: Identifier,
+    pub generic_parameters: Vec<GenericParameter>,
     pub signature: FunctionSignature,
     pub body: CodeBlockContainer,
 }
@@ -79,6 +166,49 @@ pub struct ExternDeclaration {
     pub signature: FunctionSignature,
 }
 
+/// A foreign library whose functions are the selfless requirements of a
+/// protocol, as in `extern "C" LibMath: LibMathProto`.
+#[derive(Debug)]
+pub struct ExternLibraryDeclaration {
+    pub attributes: Vec<Attribute>,
+    pub abi: String,
+    pub name: Identifier,
+    pub protocol: Identifier,
+}
+
+/// Declares the contents of a C header, as in `import c "header.h"`. The
+/// import is replaced by the declarations of the header before analysis.
+#[derive(Debug)]
+pub struct CImportDeclaration {
+    pub path: String,
+}
+
+/// Makes the declarations of another module available by qualified names, as
+/// in `import geometry` then `geometry.Vector2`. The declarations of the
+/// module are read into the import before analysis, see
+/// `module::expand_modules`.
+#[derive(Debug)]
+pub struct ModuleImportDeclaration {
+    pub name: Identifier,
+    pub declarations: Vec<Declaration>,
+}
+
+/// An annotation written before a declaration, as in `@prefix("m_")` or
+/// `@export`.
+#[derive(Debug)]
+pub struct Attribute {
+    pub name: Identifier,
+    pub value: Option<String>,
+}
+
+/// A type parameter of a generic function or type, as `T` or `T: Drawable`.
+/// The type it stands for has to conform to every protocol it is bound by.
+#[derive(Debug, Clone)]
+pub struct GenericParameter {
+    pub name: Identifier,
+    pub bounds: Vec<Identifier>,
+}
+
 #[derive(Debug)]
 pub struct FunctionSignature {
     pub parameters: FunctionParameters,
@@ -88,6 +218,8 @@ pub struct FunctionSignature {
 #[derive(Debug)]
 pub struct FunctionParameters {
     pub parameters: Vec<FunctionParameter>,
+    /// Whether the parameters end with `...`, only allowed for extern functions.
+    pub variadic: bool,
 }
 
 #[derive(Debug)]
@@ -106,27 +238,162 @@ pub struct ReturnClause {
 pub enum Expression {
     Identifier(Identifier),
     FunctionCall(FunctionCallExpression),
+    MethodCall(MethodCallExpression),
+    MemberAccess(MemberAccessExpression),
     BooleanLiteral(bool),
     FloatNumberLiteral(f64),
+    StringLiteral(String),
     NilLiteral,
+    Result(ResultExpression),
     // IntegerNumberLiteral(u64)
     BinaryOperator(BinaryOperatorExpression),
     Tuple(TupleExpression),
     If(IfExpression),
-    // MatchExpression
+    Match(MatchExpression),
+    Try(TryExpression),
+    Array(ArrayExpression),
+    Dictionary(DictionaryExpression),
+    Index(IndexExpression),
+    Closure(ClosureExpression),
+}
+
+/// A line and column in the source file, both counted from 1. Declarations
+/// generated from C headers are located at the default, line 0.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SourceLocation {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// `[a, b, c]`, an array holding the values of the elements.
+#[derive(Debug)]
+pub struct ArrayExpression {
+    pub elements: Vec<Expression>,
+}
+
+/// `[key: value, ...]`, a dictionary holding the entries, `[:]` when empty.
+#[derive(Debug)]
+pub struct DictionaryExpression {
+    pub entries: Vec<(Expression, Expression)>,
+}
+
+/// `fun(x: f64) -> f64 { x * factor }`, a function value that can use the
+/// variables of the enclosing functions.
+#[derive(Debug)]
+pub struct ClosureExpression {
+    pub signature: FunctionSignature,
+    pub body: CodeBlockContainer,
+}
+
+/// `base[index]`, an element of an array or the value of a key in a
+/// dictionary. The location is reported when the index is out of bounds at
+/// runtime.
+#[derive(Debug)]
+pub struct IndexExpression {
+    pub base: Box<Expression>,
+    pub index: Box<Expression>,
+    pub location: SourceLocation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResultVariant {
+    Ok,
+    Err,
+}
+
+/// `ok(value)` or `err(error)`, a value of the result type it is used as.
+#[derive(Debug)]
+pub struct ResultExpression {
+    pub variant: ResultVariant,
+    pub value: Box<Expression>,
+}
+
+/// `expression?`, the value of a result or an optional, returning the error or
+/// nil from the enclosing function when there is no value.
+#[derive(Debug)]
+pub struct TryExpression {
+    pub expression: Box<Expression>,
 }
 
+/// With a binding, as in `if let value := optional {}`, the condition is an
+/// optional and the then branch runs with its value bound when it is not nil.
 #[derive(Debug)]
 pub struct IfExpression {
+    pub binding: Option<Identifier>,
     pub condition: Box<Expression>,
-    pub then_expression: Box<Expression>,
-    pub else_expression: Box<Expression>,
+    pub then_branch: CodeBlockContainer,
+    pub else_clause: Option<ElseClause>,
+}
+
+#[derive(Debug)]
+pub enum ElseClause {
+    Else(CodeBlockContainer),
+    ElseIf(Box<IfExpression>),
+}
+
+#[derive(Debug)]
+pub struct MatchExpression {
+    pub subject: Box<Expression>,
+    pub arms: Vec<MatchArm>,
+}
+
+#[derive(Debug)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub body: CodeBlockContainer,
+}
+
+#[derive(Debug)]
+pub enum Pattern {
+    /// `_`, matches anything.
+    Wildcard,
+    /// `nil`, matches an optional without a value.
+    Nil,
+    /// `let name`, matches an optional with a value and binds it, or any other
+    /// value.
+    Binding(Identifier),
+    /// `.case`, matches a case of the enum being matched.
+    EnumCase(Identifier),
+    /// `ok(let name)` or `err(let name)`, matches a result of that variant and
+    /// binds its payload, `ok(_)` does not bind it.
+    Result(ResultVariant, Option<Identifier>),
+    /// A number or boolean literal.
+    Literal(Expression),
 }
 
 #[derive(Debug)]
 pub struct FunctionCallExpression {
     pub function: Identifier,
-    pub arguments: TupleExpression,
+    pub arguments: ArgumentList,
+}
+
+#[derive(Debug)]
+pub struct MethodCallExpression {
+    pub receiver: Box<Expression>,
+    pub method: Identifier,
+    pub arguments: ArgumentList,
+    /// Called with `?.`, the call only happens when the receiver is not nil.
+    pub optional: bool,
+}
+
+#[derive(Debug)]
+pub struct MemberAccessExpression {
+    pub base: Box<Expression>,
+    pub member: Identifier,
+    /// Accessed with `?.`, the member is only read when the base is not nil.
+    /// The elements of a tuple are members named by their index, as in `.0`.
+    pub optional: bool,
+}
+
+#[derive(Debug)]
+pub struct ArgumentList {
+    pub arguments: Vec<Argument>,
+}
+
+#[derive(Debug)]
+pub struct Argument {
+    pub label: Option<Identifier>,
+    pub expression: Expression,
 }
 
 #[derive(Debug)]
@@ -135,6 +402,14 @@ pub enum BinaryOperator {
     Subtract,
     Multiply,
     Divide,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+    /// `??`, the value of an optional or a default when it is nil.
+    Coalesce,
 }
 
 impl BinaryOperator {
@@ -144,6 +419,13 @@ impl BinaryOperator {
             Token::Minus => Some(BinaryOperator::Subtract),
             Token::Asterisk => Some(BinaryOperator::Multiply),
             Token::Slash => Some(BinaryOperator::Divide),
+            Token::Equal => Some(BinaryOperator::Equal),
+            Token::NotEqual => Some(BinaryOperator::NotEqual),
+            Token::LessThan => Some(BinaryOperator::LessThan),
+            Token::LessThanOrEqual => Some(BinaryOperator::LessThanOrEqual),
+            Token::GreaterThan => Some(BinaryOperator::GreaterThan),
+            Token::GreaterThanOrEqual => Some(BinaryOperator::GreaterThanOrEqual),
+            Token::QuestionQuestion => Some(BinaryOperator::Coalesce),
             _ => None,
         }
     }
@@ -154,8 +436,26 @@ impl BinaryOperator {
             BinaryOperator::Subtract => 10,
             BinaryOperator::Multiply => 20,
             BinaryOperator::Divide => 20,
+            BinaryOperator::Coalesce => 7,
+            BinaryOperator::Equal
+            | BinaryOperator::NotEqual
+            | BinaryOperator::LessThan
+            | BinaryOperator::LessThanOrEqual
+            | BinaryOperator::GreaterThan
+            | BinaryOperator::GreaterThanOrEqual => 5,
         }
     }
+
+    pub fn is_comparison(&self) -> bool {
+        !matches!(
+            self,
+            BinaryOperator::Add
+                | BinaryOperator::Subtract
+                | BinaryOperator::Multiply
+                | BinaryOperator::Divide
+                | BinaryOperator::Coalesce
+        )
+    }
 }
 
 #[derive(Debug)]
@@ -165,6 +465,8 @@ pub struct BinaryOperatorExpression {
     pub right: Box<Expression>,
 }
 
+/// `(a, b)`, a tuple of two or more values. A single expression in parentheses
+/// is not a tuple.
 #[derive(Debug)]
 pub struct TupleExpression {
     pub expressions: ExpressionList,
@@ -177,11 +479,34 @@ pub struct ExpressionList {
 
 #[derive(Debug)]
 pub enum Statement {
-    // return
+    Return(ReturnStatement),
+    Assignment(AssignmentStatement),
+    Destructuring(DestructuringStatement),
     // break
     // continue
 }
 
+#[derive(Debug)]
+pub struct ReturnStatement {
+    pub expression: Option<Expression>,
+}
+
+/// `target := value`, only elements of arrays and dictionaries can be assigned to.
+#[derive(Debug)]
+pub struct AssignmentStatement {
+    pub target: Expression,
+    pub value: Expression,
+}
+
+/// `let (a, b) := value`, declares a variable for each element of a tuple.
+/// Elements written as `_` are not bound.
+#[derive(Debug)]
+pub struct DestructuringStatement {
+    pub names: Vec<Option<Identifier>>,
+    pub type_annotation: Option<TypeSyntax>,
+    pub value: Expression,
+}
+
 #[derive(Debug, Clone)]
 pub struct Identifier {
     pub name: String,
@@ -190,4 +515,20 @@ pub struct Identifier {
 #[derive(Debug, Clone)]
 pub enum TypeSyntax {
     IdentifierType(Identifier),
+    /// A raw pointer, `*T`.
+    PointerType(Box<TypeSyntax>),
+    /// `T?` or `optional<T>`.
+    OptionalType(Box<TypeSyntax>),
+    /// `result<T, E>`, a value of type `T` or an error of type `E`.
+    ResultType(Box<TypeSyntax>, Box<TypeSyntax>),
+    /// A generic type given its type arguments, as in `Pair<i32, f64>`.
+    GenericType(Identifier, Vec<TypeSyntax>),
+    /// `[T]`, an array of values of type `T`.
+    ArrayType(Box<TypeSyntax>),
+    /// `[K: V]`, a dictionary from keys of type `K` to values of type `V`.
+    DictionaryType(Box<TypeSyntax>, Box<TypeSyntax>),
+    /// `(A, B)`, a tuple of values of each type.
+    TupleType(Vec<TypeSyntax>),
+    /// `(A, B) -> R`, a function value, `()` as the return type for none.
+    FunctionType(Vec<TypeSyntax>, Box<TypeSyntax>),
 }
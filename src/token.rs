@@ -9,10 +9,16 @@ pub enum Keyword {
     True,
     False,
     Nil,
+    Ok,
+    Err,
     Extern,
     Enum,
     Type,
     Proto,
+    Extend,
+    Selfless,
+    Import,
+    Pub,
     Return,
     Break,
     Continue,
@@ -23,12 +29,19 @@ pub enum Token {
     Keyword(Keyword),
     Identifier(String),
     Number(f64),
+    String(String),
+    At,
     Wildcard,
     Newline,
     Arrow,
     Colon,
     Comma,
     Dot,
+    Ellipsis,
+    Question,
+    QuestionDot,
+    QuestionQuestion,
+    FatArrow,
     Plus,
     Minus,
     Asterisk,
@@ -36,6 +49,10 @@ pub enum Token {
     Tilde,
     Equal,
     NotEqual,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
     Assign,
     LeftParen,
     RightParen,
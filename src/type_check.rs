@@ -1,133 +1,2525 @@
 use crate::context::*;
 use crate::syntax::*;
 
+use std::collections::HashMap;
+
 pub struct TypeCheckVisitor {}
 
+/// Which side of a call between this language and C a signature is on.
+#[derive(Clone, Copy, PartialEq)]
+enum CBoundary {
+    /// An extern function, called from this language.
+    Extern,
+    /// An exported function, called from C.
+    Export,
+}
+
+impl Default for TypeCheckVisitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TypeCheckVisitor {
     pub fn new() -> TypeCheckVisitor {
         Self {}
     }
 
     pub fn visit_source_file(&self, node: &SourceFile, context: &mut Context) {
+        // globals are emitted as constants, they cannot run code
+        for item in &node.code_block.items {
+            if let CodeBlockItem::Declaration(Declaration::VariableDeclaration(variable)) = item {
+                if !matches!(
+                    variable.expression,
+                    Some(Expression::FloatNumberLiteral(_)) | Some(Expression::BooleanLiteral(_))
+                ) {
+                    context.error(format!(
+                        "global {:?} must be initialized with a number or boolean literal",
+                        variable.identifier.name
+                    ));
+                }
+            }
+        }
+
         self.visit_code_block(&node.code_block, context);
     }
 
-    pub fn visit_code_block(&self, node: &CodeBlock, context: &mut Context) {
+    /// Returns the type of the tail expression of the block, or void when the
+    /// block does not end with an expression.
+    pub fn visit_code_block(&self, node: &CodeBlock, context: &mut Context) -> Type {
+        let mut tail_type = Type::Void;
+
         for item in &node.items {
-            self.visit_code_block_item(&item, context);
+            tail_type = self.visit_code_block_item(item, context);
         }
+
+        tail_type
     }
 
-    pub fn visit_code_block_container(&self, node: &CodeBlockContainer, context: &mut Context) {
-        self.visit_code_block(&node.code_block, context);
+    pub fn visit_code_block_container(
+        &self,
+        node: &CodeBlockContainer,
+        context: &mut Context,
+    ) -> Type {
+        self.visit_code_block(&node.code_block, context)
+    }
+
+    /// Visits a code block whose value is used as the `expected` type, its
+    /// tail expression is visited as an expected expression.
+    fn visit_expected_code_block(
+        &self,
+        node: &CodeBlock,
+        expected: Option<&Type>,
+        context: &mut Context,
+    ) -> Type {
+        let Some(expected) = expected else {
+            return self.visit_code_block(node, context);
+        };
+
+        let mut tail_type = Type::Void;
+
+        for (i, item) in node.items.iter().enumerate() {
+            tail_type = match item {
+                CodeBlockItem::Expression(expr) if i == node.items.len() - 1 => {
+                    self.visit_expected_expression(expr, expected, context)
+                }
+                _ => self.visit_code_block_item(item, context),
+            };
+        }
+
+        tail_type
     }
 
-    pub fn visit_code_block_item(&self, code_block_item: &CodeBlockItem, context: &mut Context) {
+    pub fn visit_code_block_item(
+        &self,
+        code_block_item: &CodeBlockItem,
+        context: &mut Context,
+    ) -> Type {
         match code_block_item {
-            CodeBlockItem::Declaration(decl) => self.visit_declaration(&decl, context),
-            CodeBlockItem::Expression(expr) => {
-                self.visit_expression(&expr, context);
+            CodeBlockItem::Declaration(decl) => self.visit_declaration(decl, context),
+            CodeBlockItem::Expression(expr) => return self.visit_expression(expr, context),
+            CodeBlockItem::Statement(stmt) => self.visit_statement(stmt, context),
+        };
+
+        Type::Void
+    }
+
+    pub fn visit_statement(&self, node: &Statement, context: &mut Context) {
+        match node {
+            Statement::Return(statement) => self.visit_return_statement(statement, context),
+            Statement::Assignment(statement) => self.visit_assignment_statement(statement, context),
+            Statement::Destructuring(statement) => {
+                self.visit_destructuring_statement(statement, context)
+            }
+        }
+    }
+
+    /// Declares a variable for each element of a tuple, there must be as many
+    /// names as elements.
+    pub fn visit_destructuring_statement(
+        &self,
+        node: &DestructuringStatement,
+        context: &mut Context,
+    ) {
+        let annotated_type = node.type_annotation.clone().map(Type::from);
+
+        if let Some(annotated_type) = &annotated_type {
+            self.check_type(annotated_type, context);
+        }
+
+        let value_type = self.visit_expression(&node.value, context);
+
+        let value_type = match annotated_type {
+            Some(annotated_type) => {
+                if !self.check_assignable(&node.value, &value_type, &annotated_type, context) {
+                    context.error(format!(
+                        "a tuple of type {} cannot be initialized with a value of type {}",
+                        annotated_type, value_type
+                    ));
+                }
+
+                annotated_type
+            }
+            None => value_type,
+        };
+
+        let elements = match value_type {
+            Type::Tuple(elements) if elements.len() == node.names.len() => elements,
+            Type::Tuple(elements) => {
+                context.error(format!(
+                    "cannot destructure a tuple of {} elements into {} names",
+                    elements.len(),
+                    node.names.len()
+                ));
+                vec![Type::Void; node.names.len()]
+            }
+            Type::Void => vec![Type::Void; node.names.len()],
+            _ => {
+                context.error(format!(
+                    "only tuples can be destructured but the value has type {}",
+                    value_type
+                ));
+                vec![Type::Void; node.names.len()]
+            }
+        };
+
+        for (name, ty) in node.names.iter().zip(elements) {
+            if let Some(name) = name {
+                context.register_symbol(Symbol {
+                    name: name.name.clone(),
+                    symbol_type: ty,
+                    declared: false,
+                });
             }
-            _ => return,
+        }
+    }
+
+    /// Checks `array[index] := value` and `dictionary[key] := value`, variables
+    /// cannot be assigned to. Assigning nil to a key of a dictionary removes it.
+    pub fn visit_assignment_statement(&self, node: &AssignmentStatement, context: &mut Context) {
+        if !matches!(node.target, Expression::Index(_)) {
+            context
+                .error("only elements of arrays and dictionaries can be assigned to".to_string());
+            return;
+        }
+
+        let target_type = self.visit_expression(&node.target, context);
+        let value_type = self.visit_expected_expression(&node.value, &target_type, context);
+
+        if target_type != Type::Void
+            && !self.check_assignable(&node.value, &value_type, &target_type, context)
+        {
+            context.error(format!(
+                "cannot assign a value of type {} to an element of type {}",
+                value_type, target_type
+            ));
+        }
+    }
+
+    pub fn visit_return_statement(&self, node: &ReturnStatement, context: &mut Context) {
+        let expected = context
+            .current_function()
+            .map_or(Type::Void, |function| function.return_type.clone());
+        let value_type = node
+            .expression
+            .as_ref()
+            .map(|expression| self.visit_expected_expression(expression, &expected, context))
+            .unwrap_or(Type::Void);
+
+        let Some(function) = context.current_function() else {
+            context.error("return statement outside of a function".to_string());
+            return;
         };
+
+        let name = function.name.clone();
+        let return_type = function.return_type.clone();
+
+        if return_type == Type::Void {
+            if node.expression.is_some() {
+                context.error(format!(
+                    "function {:?} has no return type but returns a value of type {}",
+                    name, value_type
+                ));
+            }
+        } else if node.expression.is_none() {
+            context.error(format!(
+                "function {:?} must return a value of type {}",
+                name, return_type
+            ));
+        } else if !self.check_assignable(
+            node.expression.as_ref().unwrap(),
+            &value_type,
+            &return_type,
+            context,
+        ) {
+            context.error(format!(
+                "function {:?} returns {} but the returned value has type {}",
+                name, return_type, value_type
+            ));
+        }
     }
 
     pub fn visit_declaration(&self, node: &Declaration, context: &mut Context) {
         match node {
             Declaration::VariableDeclaration(variable) => {
-                self.visit_variable_declaration(&variable, context)
+                self.visit_variable_declaration(variable, context)
             }
             Declaration::FunctionDeclaration(function) => {
-                self.visit_function_declaration(&function, context)
+                self.visit_function_declaration(function, context)
             }
-            Declaration::ExternDeclaration(ext) => self.visit_extern_declaration(&ext, context),
+            Declaration::ExternDeclaration(ext) => self.visit_extern_declaration(ext, context),
             Declaration::EnumDeclaration(enumeration) => {
-                self.visit_enum_declaration(&enumeration, context)
+                self.visit_enum_declaration(enumeration, context)
+            }
+            Declaration::TypeDeclaration(_type) => self.visit_type_declaration(_type, context),
+            Declaration::ProtocolDeclaration(protocol) => {
+                self.visit_protocol_declaration(protocol, context)
+            }
+            Declaration::ExtensionDeclaration(extension) => {
+                self.visit_extension_declaration(extension, context)
+            }
+            // libraries are bound to their protocol in the analysis
+            Declaration::ExternLibraryDeclaration(library) => {
+                self.visit_extern_library_declaration(library, context)
+            }
+            Declaration::CImportDeclaration(_) => {}
+            // checked along with the program, generic declarations are emitted in it
+            Declaration::ModuleImportDeclaration(import) => {
+                context.enter_module(&import.name.name);
+
+                for declaration in &import.declarations {
+                    self.visit_declaration(declaration, context);
+                }
+
+                context.exit_module();
             }
-            Declaration::TypeDeclaration(_type) => self.visit_type_declaration(&_type, context),
         }
     }
 
-    pub fn visit_variable_declaration(&self, node: &VariableDeclaration, context: &mut Context) {}
+    pub fn visit_variable_declaration(&self, node: &VariableDeclaration, context: &mut Context) {
+        let annotated_type = node.type_annotation.clone().map(Type::from);
+
+        if let Some(annotated_type) = &annotated_type {
+            self.check_type(annotated_type, context);
+        }
+
+        let Some(expression) = &node.expression else {
+            context.error(format!(
+                "variable {:?} must be initialized",
+                node.identifier.name
+            ));
+
+            context.register_symbol(Symbol {
+                name: node.identifier.name.clone(),
+                symbol_type: annotated_type.unwrap_or(Type::Void),
+                declared: false,
+            });
+
+            return;
+        };
+
+        let value_type = match &annotated_type {
+            Some(annotated_type) => {
+                self.visit_expected_expression(expression, annotated_type, context)
+            }
+            None => self.visit_expression(expression, context),
+        };
+
+        if value_type == Type::Void {
+            context.error(format!(
+                "cannot assign a void value to {:?}",
+                node.identifier.name
+            ));
+        }
+
+        if annotated_type.is_none() && matches!(expression, Expression::NilLiteral) {
+            context.error(format!(
+                "the type of {:?} cannot be inferred from nil, annotate it with an optional type",
+                node.identifier.name
+            ));
+        }
+
+        if annotated_type.is_none() && matches!(expression, Expression::Result(_)) {
+            context.error(format!(
+                "the type of {:?} cannot be inferred from ok or err, annotate it with a result type",
+                node.identifier.name
+            ));
+        }
+
+        if annotated_type.is_none() && value_type == Type::Array(Box::new(Type::Void)) {
+            context.error(format!(
+                "the type of {:?} cannot be inferred from an empty array, annotate it with an array type",
+                node.identifier.name
+            ));
+        }
+
+        if annotated_type.is_none()
+            && value_type == Type::Dictionary(Box::new(Type::Void), Box::new(Type::Void))
+        {
+            context.error(format!(
+                "the type of {:?} cannot be inferred from an empty dictionary, annotate it with a dictionary type",
+                node.identifier.name
+            ));
+        }
+
+        if let (None, Type::Generic(name, arguments)) = (&annotated_type, &value_type) {
+            if arguments.is_empty() {
+                context.error(format!(
+                    "the type arguments of {:?} cannot be inferred from a case, annotate {:?} with its type",
+                    name, node.identifier.name
+                ));
+            }
+        }
+
+        if let Some(annotated_type) = &annotated_type {
+            if !self.check_assignable(expression, &value_type, annotated_type, context) {
+                context.error(format!(
+                    "{:?} is declared as {} but initialized with a value of type {}",
+                    node.identifier.name, annotated_type, value_type
+                ));
+            }
+        }
+
+        context.register_symbol(Symbol {
+            name: node.identifier.name.clone(),
+            symbol_type: annotated_type.unwrap_or(value_type),
+            declared: false,
+        });
+    }
 
     pub fn visit_function_declaration(&self, node: &FunctionDeclaration, context: &mut Context) {
-        self.visit_code_block_container(&node.body, context);
+        if context.export_symbol(&node.identifier.name).is_some() {
+            if !node.generic_parameters.is_empty() {
+                context.error(format!(
+                    "generic function {:?} cannot be exported, C has no generics",
+                    node.identifier.name
+                ));
+            }
+
+            self.check_exported_signature(node, context);
+        }
+
+        let generic_parameters =
+            self.visit_generic_parameters(&node.identifier, &node.generic_parameters, context);
+
+        context.enter_type_parameters(&generic_parameters);
+        self.visit_function(&node.identifier, &node.signature, &node.body, context);
+        context.exit_type_parameters();
     }
 
-    pub fn visit_extern_declaration(&self, node: &ExternDeclaration, context: &mut Context) {}
+    /// Checks that type parameters are only bound by protocols and that their
+    /// names are unique within the declaration.
+    pub fn visit_generic_parameters(
+        &self,
+        owner: &Identifier,
+        node: &[GenericParameter],
+        context: &mut Context,
+    ) -> Vec<TypeParameter> {
+        for (i, parameter) in node.iter().enumerate() {
+            if node[..i].iter().any(|p| p.name.name == parameter.name.name) {
+                context.error(format!(
+                    "{:?} declares the type parameter {:?} twice",
+                    owner.name, parameter.name.name
+                ));
+            }
 
-    pub fn visit_enum_declaration(&self, node: &EnumDeclaration, context: &mut Context) {}
+            for bound in &parameter.bounds {
+                if !context.is_protocol(&bound.name) {
+                    context.error(format!(
+                        "type parameter {:?} of {:?} can only be bound by a protocol but {:?} is not one",
+                        parameter.name.name, owner.name, bound.name
+                    ));
+                }
+            }
+        }
 
-    pub fn visit_type_declaration(&self, node: &TypeDeclaration, context: &mut Context) {}
+        node.iter().cloned().map(TypeParameter::from).collect()
+    }
 
-    pub fn visit_expression(&self, expression: &Expression, context: &mut Context) -> Type {
-        match expression {
-            Expression::BinaryOperator(binary_expr) => {
-                self.visit_binary_operator_expression(&binary_expr, context)
+    /// Checks that generic types are given as many type arguments as they have
+    /// parameters and that the arguments satisfy the bounds of the parameters.
+    pub fn check_type(&self, ty: &Type, context: &mut Context) {
+        match ty {
+            Type::Void => {}
+            Type::Identifier(name) => {
+                let is_generic = context
+                    .lookup_type(name)
+                    .is_some_and(|definition| !definition.generic_parameters.is_empty());
+
+                if is_generic {
+                    context.error(format!(
+                        "generic type {:?} needs type arguments, as in {}<...>",
+                        name, name
+                    ));
+                }
             }
-            Expression::Identifier(identifier) => {
-                self.visit_identifier_expression(&identifier, context)
+            Type::Function(function) => {
+                for parameter in &function.parameters {
+                    self.check_type(&parameter.ty, context);
+                }
+
+                self.check_type(&function.return_type, context);
+            }
+            Type::Pointer(wrapped) | Type::Optional(wrapped) | Type::Array(wrapped) => {
+                self.check_type(wrapped, context)
+            }
+            Type::Result(value, error) => {
+                self.check_type(value, context);
+                self.check_type(error, context);
+            }
+            Type::Dictionary(key, value) => {
+                self.check_type(key, context);
+                self.check_type(value, context);
+                self.check_hashable(key, context);
+            }
+            Type::Tuple(elements) => {
+                for element in elements {
+                    self.check_type(element, context);
+                }
+            }
+            Type::Generic(name, arguments) => {
+                for argument in arguments {
+                    self.check_type(argument, context);
+                }
+
+                let Some(definition) = context.lookup_type(name) else {
+                    context.error(format!("unknown generic type {:?}", name));
+                    return;
+                };
+
+                let parameters = definition.generic_parameters.clone();
+
+                if parameters.len() != arguments.len() {
+                    context.error(format!(
+                        "{:?} takes {} type argument(s) but {} were given",
+                        name,
+                        parameters.len(),
+                        arguments.len()
+                    ));
+                    return;
+                }
+
+                self.check_bounds(name, &parameters, arguments, context);
             }
-            Expression::BooleanLiteral(_) => Type::Identifier("bool".to_string()),
-            Expression::FloatNumberLiteral(_) => Type::Identifier("float".to_string()),
-            Expression::FunctionCall(call) => self.visit_function_call_expression(&call, context),
-            _ => return Type::Void,
         }
     }
 
-    pub fn visit_identifier_expression(
+    fn check_hashable(&self, key: &Type, context: &mut Context) {
+        if *key != Type::Void && !context.is_hashable(key) {
+            context.error(format!(
+                "{} cannot be the key of a dictionary, it does not conform to {:?}",
+                key, HASHABLE
+            ));
+        }
+    }
+
+    fn check_bounds(
+        &self,
+        owner: &str,
+        parameters: &[TypeParameter],
+        arguments: &[Type],
+        context: &mut Context,
+    ) {
+        for (parameter, argument) in parameters.iter().zip(arguments) {
+            for bound in &parameter.bounds {
+                let conforms = match argument {
+                    Type::Identifier(name) => context.conforms_to(name, bound),
+                    _ => false,
+                };
+
+                if !conforms {
+                    context.error(format!(
+                        "{} of {:?} must conform to {:?} but {} does not",
+                        parameter.name, owner, bound, argument
+                    ));
+                }
+            }
+        }
+    }
+
+    pub fn visit_function(
         &self,
         identifier: &Identifier,
+        signature: &FunctionSignature,
+        body: &CodeBlockContainer,
         context: &mut Context,
-    ) -> Type {
-        let Some(symbol) = context.lookup(identifier.name.clone()) else {
-            println!("undeclared identifier {:?}", identifier.name);
-            return Type::Void; // TODO: should we return unknown(String) instead?
+    ) {
+        let return_type = signature
+            .return_clause
+            .as_ref()
+            .map(|r| Type::from(r.return_type.clone()))
+            .unwrap_or(Type::Void);
+        let name = self.source_name(&identifier.name);
+
+        if signature.parameters.variadic {
+            context.error(format!(
+                "function {:?} cannot be variadic, only extern functions can take `...`",
+                name
+            ));
+        }
+
+        self.check_type(&return_type, context);
+
+        context.enter_scope();
+        context.enter_function(identifier.name.clone(), return_type.clone());
+
+        for param in &signature.parameters.parameters {
+            let parameter_type = Type::from(param.parameter_type.clone());
+            self.check_type(&parameter_type, context);
+
+            context.register_symbol(Symbol {
+                name: param.name.name.clone(),
+                symbol_type: parameter_type,
+                declared: false,
+            });
+        }
+
+        let error_count = context.error_count();
+        let expected = Some(&return_type).filter(|ty| **ty != Type::Void);
+        let tail_type = self.visit_expected_code_block(&body.code_block, expected, context);
+
+        // a tail expression without a type has already been reported
+        let tail_failed = tail_type == Type::Void
+            && matches!(
+                body.code_block.items.last(),
+                Some(CodeBlockItem::Expression(_))
+            )
+            && context.error_count() > error_count;
+
+        if !tail_failed {
+            self.check_function_tail(name, body, &return_type, tail_type, context);
+        }
+
+        context.exit_function();
+        context.exit_scope();
+    }
+
+    /// The name a function is declared with, without the prefix and counter
+    /// that hoisting adds to local functions, as in `main.helper.2`.
+    fn source_name<'a>(&self, name: &'a str) -> &'a str {
+        name.rsplit('.')
+            .find(|segment| !segment.chars().all(|c| c.is_ascii_digit()))
+            .unwrap_or(name)
+    }
+
+    fn check_function_tail(
+        &self,
+        name: &str,
+        body: &CodeBlockContainer,
+        return_type: &Type,
+        tail_type: Type,
+        context: &mut Context,
+    ) {
+        if *return_type == Type::Void {
+            // calls are usually made for their side effects, only warn about plain values
+            let is_call = matches!(
+                body.code_block.items.last(),
+                Some(CodeBlockItem::Expression(
+                    Expression::FunctionCall(_) | Expression::MethodCall(_)
+                ))
+            );
+
+            if tail_type != Type::Void && !is_call {
+                context.warning(format!(
+                    "the tail expression of {:?} has type {} but the function has no return type, the value is discarded",
+                    name, tail_type
+                ));
+            }
+
+            return;
+        }
+
+        if tail_type != Type::Void {
+            let Some(CodeBlockItem::Expression(tail)) = body.code_block.items.last() else {
+                unreachable!();
+            };
+
+            if !self.check_assignable(tail, &tail_type, return_type, context) {
+                context.error(format!(
+                    "function {:?} returns {} but its tail expression has type {}",
+                    name, return_type, tail_type
+                ));
+            }
+
+            return;
+        }
+
+        if !self.always_returns(&body.code_block) {
+            context.error(format!("function {:?} may not return a value", name));
+        }
+    }
+
+    /// Whether every path through the block ends with a return statement.
+    pub fn always_returns(&self, node: &CodeBlock) -> bool {
+        node.items.iter().any(|item| match item {
+            CodeBlockItem::Statement(Statement::Return(_)) => true,
+            CodeBlockItem::Expression(Expression::If(if_expression)) => {
+                self.if_always_returns(if_expression)
+            }
+            CodeBlockItem::Expression(Expression::Match(match_expression)) => match_expression
+                .arms
+                .iter()
+                .all(|arm| self.always_returns(&arm.body.code_block)),
+            _ => false,
+        })
+    }
+
+    fn if_always_returns(&self, node: &IfExpression) -> bool {
+        let else_returns = match &node.else_clause {
+            Some(else_clause) => self.else_always_returns(else_clause),
+            None => false,
         };
 
-        return symbol.symbol_type;
+        else_returns && self.always_returns(&node.then_branch.code_block)
     }
 
-    pub fn visit_function_call_expression(
+    fn else_always_returns(&self, node: &ElseClause) -> bool {
+        match node {
+            ElseClause::Else(container) => self.always_returns(&container.code_block),
+            ElseClause::ElseIf(if_expression) => self.if_always_returns(if_expression),
+        }
+    }
+
+    pub fn visit_extern_declaration(&self, node: &ExternDeclaration, context: &mut Context) {
+        let function = TypeFunction::from(&node.signature);
+
+        for parameter in &function.parameters {
+            self.check_type(&parameter.ty, context);
+        }
+        self.check_type(&function.return_type, context);
+
+        self.check_extern_signature(&node.identifier.name, &function, context);
+    }
+
+    /// The functions of a library are extern functions named after it.
+    pub fn visit_extern_library_declaration(
         &self,
-        node: &FunctionCallExpression,
+        node: &ExternLibraryDeclaration,
         context: &mut Context,
-    ) -> Type {
-        let Some(symbol) = context.lookup(node.function.name.clone()) else {
-            println!("undeclared function {:?}", node.function.name);
-            return Type::Void;
+    ) {
+        let Some(definition) = context.lookup_type(&node.name.name) else {
+            return;
         };
 
-        match symbol.symbol_type {
-            Type::Function(function) => {
-                // TODO: check arguments
-                return function.return_type.as_ref().clone();
+        let functions = definition
+            .methods
+            .iter()
+            .map(|method| {
+                (
+                    format!("{}.{}", node.name.name, method.name),
+                    method.function.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        for (name, function) in functions {
+            self.check_extern_signature(&name, &function, context);
+        }
+    }
+
+    /// Every type in the signature of an extern function is passed with its C
+    /// layout, see `has_c_layout`.
+    fn check_extern_signature(&self, name: &str, function: &TypeFunction, context: &mut Context) {
+        let types = function
+            .parameters
+            .iter()
+            .map(|parameter| &parameter.ty)
+            .chain([function.return_type.as_ref()]);
+
+        for ty in types {
+            if !self.has_c_layout(ty, CBoundary::Extern, context) {
+                context.error(format!(
+                    "extern function {:?} uses {} which has no C layout",
+                    name, ty
+                ));
             }
-            _ => {
-                println!(
-                    "trying to use {:?} as a function but it is a {:?}",
-                    symbol.name, symbol.symbol_type
+        }
+    }
+
+    /// Exported functions are called from C, every type in their signature
+    /// must have a C counterpart.
+    fn check_exported_signature(&self, node: &FunctionDeclaration, context: &mut Context) {
+        let mut types = node
+            .signature
+            .parameters
+            .parameters
+            .iter()
+            .map(|param| Type::from(param.parameter_type.clone()))
+            .collect::<Vec<_>>();
+
+        if let Some(return_clause) = &node.signature.return_clause {
+            types.push(Type::from(return_clause.return_type.clone()));
+        }
+
+        for ty in types {
+            if !self.has_c_layout(&ty, CBoundary::Export, context) {
+                context.error(format!(
+                    "exported function {:?} uses {} which has no C representation",
+                    node.identifier.name, ty
+                ));
+            }
+        }
+    }
+
+    /// Whether values of a type can cross a call between this language and C.
+    /// Extern functions are passed values laid out as the code generator lays
+    /// them out, type parameters, libraries and unknown types have no layout.
+    /// Exported functions are called from C code and only take types with a C
+    /// counterpart.
+    fn has_c_layout(&self, ty: &Type, boundary: CBoundary, context: &Context) -> bool {
+        let is_extern = boundary == CBoundary::Extern;
+
+        match ty {
+            Type::Void => true,
+            Type::Pointer(pointee) => is_extern || self.has_c_layout(pointee, boundary, context),
+            // a nil pointer is the null pointer, other optionals have no C counterpart
+            Type::Optional(wrapped) => {
+                (is_extern || wrapped.is_pointer()) && self.has_c_layout(wrapped, boundary, context)
+            }
+            Type::Function(_) | Type::Array(_) | Type::Dictionary(..) => is_extern,
+            Type::Result(value, error) => {
+                is_extern
+                    && self.has_c_layout(value, boundary, context)
+                    && self.has_c_layout(error, boundary, context)
+            }
+            Type::Tuple(elements) => {
+                is_extern
+                    && elements
+                        .iter()
+                        .all(|element| self.has_c_layout(element, boundary, context))
+            }
+            Type::Generic(..) if !is_extern => false,
+            Type::Identifier(name) | Type::Generic(name, _) => {
+                match context.lookup_type(name).map(|d| &d.kind) {
+                    Some(TypeDefinitionKind::Primitive)
+                    | Some(TypeDefinitionKind::Enumeration(_)) => true,
+                    Some(TypeDefinitionKind::Protocol) => is_extern,
+                    Some(TypeDefinitionKind::Structure(_)) => context
+                        .fields(ty)
+                        .unwrap_or_default()
+                        .iter()
+                        .all(|field| self.has_c_layout(&field.ty, boundary, context)),
+                    _ => false,
+                }
+            }
+        }
+    }
+
+    pub fn visit_enum_declaration(&self, node: &EnumDeclaration, context: &mut Context) {
+        self.visit_generic_parameters(&node.name, &node.generic_parameters, context);
+        self.check_conformances(&node.name.name, &node.conformances, context);
+        self.visit_member_block(&node.name.name, &node.container.member_block, context);
+    }
+
+    pub fn visit_type_declaration(&self, node: &TypeDeclaration, context: &mut Context) {
+        self.visit_generic_parameters(&node.name, &node.generic_parameters, context);
+        self.check_conformances(&node.name.name, &node.conformances, context);
+        self.visit_member_block(&node.name.name, &node.container.member_block, context);
+    }
+
+    /// Checks the default implementations of a protocol, `self` is a value of
+    /// the protocol and only its requirements can be called on it.
+    pub fn visit_protocol_declaration(&self, node: &ProtocolDeclaration, context: &mut Context) {
+        let Some(definition) = context.lookup_type(&node.name.name) else {
+            return;
+        };
+
+        let mut symbols = vec![Symbol {
+            name: "self".to_string(),
+            symbol_type: Type::Identifier(node.name.name.clone()),
+            declared: false,
+        }];
+
+        for method in definition.methods.iter().filter(|method| !method.selfless) {
+            symbols.push(Symbol {
+                name: method.name.clone(),
+                symbol_type: Type::Function(method.function.clone()),
+                declared: true,
+            });
+        }
+
+        context.enter_scope();
+
+        for symbol in symbols {
+            context.register_symbol(symbol);
+        }
+
+        for requirement in &node.requirements {
+            if let Some(body) = &requirement.body {
+                self.visit_function(
+                    &requirement.identifier,
+                    &requirement.signature,
+                    body,
+                    context,
                 );
-                return Type::Void;
             }
         }
+
+        context.exit_scope();
     }
 
-    pub fn visit_binary_operator_expression(
-        &self,
-        node: &BinaryOperatorExpression,
-        context: &mut Context,
-    ) -> Type {
-        let left = self.visit_expression(&node.left, context);
-        let right = self.visit_expression(&node.right, context);
+    pub fn visit_extension_declaration(&self, node: &ExtensionDeclaration, context: &mut Context) {
+        if context.lookup_type(&node.name.name).is_none() {
+            return;
+        }
 
-        if left != right {
-            println!(
-                "incompatible types for {:?} {:?} {:?}",
-                left, node.operator, right
-            );
+        self.check_conformances(&node.name.name, &node.conformances, context);
+        self.visit_member_block(&node.name.name, &node.container.member_block, context);
+    }
+
+    /// Checks the members of a type or enum, fields and methods are visible
+    /// unqualified from the method bodies. The type parameters of a generic
+    /// type are in scope for all of its members.
+    pub fn visit_member_block(&self, type_name: &str, node: &MemberBlock, context: &mut Context) {
+        let Some(definition) = context.lookup_type(type_name) else {
+            return;
+        };
+
+        let is_enum = matches!(definition.kind, TypeDefinitionKind::Enumeration(_));
+
+        let generic_parameters = definition.generic_parameters.clone();
+
+        let mut symbols = vec![Symbol {
+            name: "self".to_string(),
+            symbol_type: definition.declared_type(),
+            declared: false,
+        }];
+
+        if let TypeDefinitionKind::Structure(fields) = &definition.kind {
+            for field in fields {
+                symbols.push(Symbol {
+                    name: field.name.clone(),
+                    symbol_type: field.ty.clone(),
+                    declared: false,
+                });
+            }
         }
 
-        return left; // TODO: rework this without assuming left op right result in the same type
+        for method in &definition.methods {
+            symbols.push(Symbol {
+                name: method.name.clone(),
+                symbol_type: Type::Function(method.function.clone()),
+                declared: true,
+            });
+        }
+
+        context.enter_scope();
+        context.enter_type_parameters(&generic_parameters);
+
+        for symbol in symbols {
+            context.register_symbol(symbol);
+        }
+
+        for member in &node.members {
+            match member {
+                MemberBlockItem::EnumCaseDeclaration(case) => {
+                    if !is_enum {
+                        context.error(format!(
+                            "enum case {:?} declared outside of an enum",
+                            case.name.name
+                        ));
+                    }
+                }
+                MemberBlockItem::VariableDeclaration(variable) => {
+                    if is_enum {
+                        context.error(format!(
+                            "enum {:?} cannot have the stored field {:?}",
+                            type_name, variable.identifier.name
+                        ));
+                    }
+
+                    if let Some(type_annotation) = &variable.type_annotation {
+                        self.check_type(&Type::from(type_annotation.clone()), context);
+                    }
+
+                    self.visit_field_declaration(variable, context);
+                }
+                MemberBlockItem::FunctionDeclaration(function) => {
+                    if !function.generic_parameters.is_empty() {
+                        context.error(format!(
+                            "method {:?} of {:?} cannot have type parameters, only functions and types can",
+                            function.identifier.name, type_name
+                        ));
+                    }
+
+                    self.visit_function_declaration(function, context)
+                }
+            }
+        }
+
+        context.exit_type_parameters();
+        context.exit_scope();
+    }
+
+    pub fn visit_field_declaration(&self, node: &VariableDeclaration, context: &mut Context) {
+        let (Some(type_annotation), Some(expression)) = (&node.type_annotation, &node.expression)
+        else {
+            return;
+        };
+
+        let field_type = Type::from(type_annotation.clone());
+        let value_type = self.visit_expected_expression(expression, &field_type, context);
+
+        if !self.check_assignable(expression, &value_type, &field_type, context) {
+            context.error(format!(
+                "field {:?} is declared as {} but its default value has type {}",
+                node.identifier.name, field_type, value_type
+            ));
+        }
+    }
+
+    /// Checks that a type implements every requirement of the protocols it
+    /// conforms to, reporting all missing members of a protocol at once.
+    pub fn check_conformances(
+        &self,
+        type_name: &str,
+        conformances: &[Identifier],
+        context: &mut Context,
+    ) {
+        let Some(definition) = context.lookup_type(type_name) else {
+            return;
+        };
+
+        let mut errors = Vec::new();
+
+        for conformance in conformances {
+            let protocol_name = &conformance.name;
+
+            let Some(protocol) = context.lookup_type(protocol_name) else {
+                errors.push(format!("unknown protocol {:?}", protocol_name));
+                continue;
+            };
+
+            if protocol.kind != TypeDefinitionKind::Protocol {
+                errors.push(format!(
+                    "{:?} cannot conform to {:?} because it is not a protocol",
+                    type_name, protocol_name
+                ));
+                continue;
+            }
+
+            let missing = protocol
+                .methods
+                .iter()
+                .map(|requirement| requirement.substitute(&self_substitution(type_name)))
+                .filter_map(|requirement| match definition.method(&requirement.name) {
+                    Some(method) if method.satisfies(&requirement) => None,
+                    Some(method) => Some(format!("{} (found {})", requirement, method)),
+                    None => Some(requirement.to_string()),
+                })
+                .collect::<Vec<_>>();
+
+            if !missing.is_empty() {
+                errors.push(format!(
+                    "type {:?} does not conform to protocol {:?}, missing:\n    {}",
+                    type_name,
+                    protocol_name,
+                    missing.join("\n    ")
+                ));
+            }
+        }
+
+        for error in errors {
+            context.error(error);
+        }
+    }
+
+    pub fn visit_expression(&self, expression: &Expression, context: &mut Context) -> Type {
+        let expression_type = match expression {
+            Expression::BinaryOperator(binary_expr) => {
+                self.visit_binary_operator_expression(binary_expr, context)
+            }
+            Expression::Identifier(identifier) => {
+                self.visit_identifier_expression(identifier, context)
+            }
+            Expression::BooleanLiteral(_) => Type::Identifier("bool".to_string()),
+            Expression::FloatNumberLiteral(_) => Type::Identifier("f64".to_string()),
+            // strings are C strings until the language has a string type
+            Expression::StringLiteral(_) => {
+                Type::Pointer(Box::new(Type::Identifier("u8".to_string())))
+            }
+            Expression::FunctionCall(call) => self.visit_function_call_expression(call, context),
+            Expression::MethodCall(call) => self.visit_method_call_expression(call, context),
+            Expression::MemberAccess(access) => {
+                self.visit_member_access_expression(access, context)
+            }
+            Expression::If(if_expression) => self.visit_if_expression(if_expression, None, context),
+            Expression::Match(match_expression) => {
+                self.visit_match_expression(match_expression, None, context)
+            }
+            Expression::NilLiteral => Type::Optional(Box::new(Type::Void)),
+            Expression::Result(result) => self.visit_result_expression(result, context),
+            Expression::Try(try_expression) => self.visit_try_expression(try_expression, context),
+            Expression::Array(array) => self.visit_array_expression(array, None, context),
+            Expression::Dictionary(dictionary) => {
+                self.visit_dictionary_expression(dictionary, context)
+            }
+            Expression::Index(index) => self.visit_index_expression(index, context),
+            Expression::Closure(closure) => self.visit_closure_expression(closure, context),
+            Expression::Tuple(tuple) => Type::Tuple(
+                tuple
+                    .expressions
+                    .items
+                    .iter()
+                    .map(|element| self.visit_expression(element, context))
+                    .collect(),
+            ),
+        };
+
+        context.record_expression_type(expression, expression_type.clone());
+
+        expression_type
+    }
+
+    /// Visits an expression whose value is used as the `expected` type. The
+    /// elements of an array literal and the branches of an if or a match are
+    /// then checked against that type rather than against each other, so that
+    /// values of different types conforming to a protocol make a value of the
+    /// protocol.
+    pub fn visit_expected_expression(
+        &self,
+        expression: &Expression,
+        expected: &Type,
+        context: &mut Context,
+    ) -> Type {
+        let expression_type = match (expression, expected) {
+            (Expression::Array(array), Type::Array(element_type)) => {
+                self.visit_array_expression(array, Some(element_type), context)
+            }
+            (Expression::If(if_expression), _) => {
+                self.visit_if_expression(if_expression, Some(expected), context)
+            }
+            (Expression::Match(match_expression), _) => {
+                self.visit_match_expression(match_expression, Some(expected), context)
+            }
+            _ => return self.visit_expression(expression, context),
+        };
+
+        context.record_expression_type(expression, expression_type.clone());
+
+        expression_type
+    }
+
+    /// The elements of an array literal have the type of the first element that
+    /// is not a number literal, literals take that type. An array of literals
+    /// only is an array of f64 unless it is used as another array type.
+    pub fn visit_array_expression(
+        &self,
+        node: &ArrayExpression,
+        expected: Option<&Type>,
+        context: &mut Context,
+    ) -> Type {
+        if let Some(element_type) = expected {
+            for element in &node.elements {
+                let ty = self.visit_expected_expression(element, element_type, context);
+
+                if !self.check_assignable(element, &ty, element_type, context) {
+                    context.error(format!(
+                        "an array of {} cannot hold an element of type {}",
+                        element_type, ty
+                    ));
+                }
+            }
+
+            return Type::Array(Box::new(element_type.clone()));
+        }
+
+        let element_types = node
+            .elements
+            .iter()
+            .map(|element| self.visit_expression(element, context))
+            .collect::<Vec<_>>();
+
+        let element_type = node
+            .elements
+            .iter()
+            .zip(&element_types)
+            .find(|(element, _)| !self.is_constant_number(element, false))
+            .or(node.elements.iter().zip(&element_types).next())
+            .map_or(Type::Void, |(_, ty)| ty.clone());
+
+        for (element, ty) in node.elements.iter().zip(&element_types) {
+            if !self.check_assignable(element, ty, &element_type, context) {
+                context.error(format!(
+                    "array elements must all have the same type but {} is not {}",
+                    ty, element_type
+                ));
+            }
+        }
+
+        Type::Array(Box::new(element_type))
+    }
+
+    /// Keys and values are typed like the elements of an array, except that
+    /// keys made of integer literals only are i64 as floats are not hashable.
+    pub fn visit_dictionary_expression(
+        &self,
+        node: &DictionaryExpression,
+        context: &mut Context,
+    ) -> Type {
+        let entry_types = node
+            .entries
+            .iter()
+            .map(|(key, value)| {
+                (
+                    self.visit_expression(key, context),
+                    self.visit_expression(value, context),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let key_type = node
+            .entries
+            .iter()
+            .zip(&entry_types)
+            .find(|((key, _), _)| !self.is_constant_number(key, true))
+            .map_or(
+                if node.entries.is_empty() {
+                    Type::Void
+                } else {
+                    Type::Identifier("i64".to_string())
+                },
+                |(_, (ty, _))| ty.clone(),
+            );
+
+        let value_type = node
+            .entries
+            .iter()
+            .zip(&entry_types)
+            .find(|((_, value), _)| !self.is_constant_number(value, false))
+            .or(node.entries.iter().zip(&entry_types).next())
+            .map_or(Type::Void, |(_, (_, ty))| ty.clone());
+
+        for ((key, value), (ty, value_ty)) in node.entries.iter().zip(&entry_types) {
+            if !self.check_assignable(key, ty, &key_type, context) {
+                context.error(format!(
+                    "dictionary keys must all have the same type but {} is not {}",
+                    ty, key_type
+                ));
+            }
+
+            if !self.check_assignable(value, value_ty, &value_type, context) {
+                context.error(format!(
+                    "dictionary values must all have the same type but {} is not {}",
+                    value_ty, value_type
+                ));
+            }
+        }
+
+        self.check_hashable(&key_type, context);
+
+        Type::Dictionary(Box::new(key_type), Box::new(value_type))
+    }
+
+    /// An element of an array, or the value of a key in a dictionary which is
+    /// nil when the dictionary does not hold the key.
+    pub fn visit_index_expression(&self, node: &IndexExpression, context: &mut Context) -> Type {
+        let base_type = self.visit_expression(&node.base, context);
+        let index_type = self.visit_expression(&node.index, context);
+
+        if let Type::Dictionary(key, value) = base_type {
+            if !self.check_assignable(&node.index, &index_type, &key, context) {
+                context.error(format!(
+                    "a key of a dictionary of type [{}: {}] cannot have type {}",
+                    key, value, index_type
+                ));
+            }
+
+            return value.or_nil();
+        }
+
+        // literal indices are i64, the type of the count
+        let i64_type = Type::Identifier("i64".to_string());
+
+        if !index_type.is_integer()
+            && !self.check_assignable(&node.index, &index_type, &i64_type, context)
+        {
+            context.error(format!(
+                "an array index must be an integer but it has type {}",
+                index_type
+            ));
+        }
+
+        match base_type {
+            Type::Array(element) => *element,
+            Type::Void => Type::Void,
+            _ => {
+                context.error(format!("a value of type {} cannot be indexed", base_type));
+                Type::Void
+            }
+        }
+    }
+
+    pub fn visit_if_expression(
+        &self,
+        node: &IfExpression,
+        expected: Option<&Type>,
+        context: &mut Context,
+    ) -> Type {
+        let condition = self.visit_expression(&node.condition, context);
+        let bool_type = Type::Identifier("bool".to_string());
+
+        context.enter_scope();
+
+        if let Some(binding) = &node.binding {
+            let bound_type = match &condition {
+                Type::Optional(wrapped) if **wrapped != Type::Void => (**wrapped).clone(),
+                _ => {
+                    context.error(format!(
+                        "if let needs an optional value but it has type {}",
+                        condition
+                    ));
+                    Type::Void
+                }
+            };
+
+            context.register_symbol(Symbol {
+                name: binding.name.clone(),
+                symbol_type: bound_type,
+                declared: false,
+            });
+        } else if condition != bool_type {
+            context.error(format!(
+                "if condition must be of type bool but it has type {}",
+                condition
+            ));
+        }
+
+        let then_type =
+            self.visit_expected_code_block(&node.then_branch.code_block, expected, context);
+        context.exit_scope();
+
+        // without an else branch there is no value when the condition is false
+        let Some(else_clause) = &node.else_clause else {
+            return Type::Void;
+        };
+
+        let else_type = match else_clause {
+            ElseClause::Else(container) => {
+                context.enter_scope();
+                let else_type =
+                    self.visit_expected_code_block(&container.code_block, expected, context);
+                context.exit_scope();
+
+                else_type
+            }
+            ElseClause::ElseIf(if_expression) => {
+                self.visit_if_expression(if_expression, expected, context)
+            }
+        };
+
+        // a branch that always returns does not contribute a value
+        if self.always_returns(&node.then_branch.code_block) {
+            return else_type;
+        }
+
+        if self.else_always_returns(else_clause) {
+            return then_type;
+        }
+
+        if then_type == Type::Void || else_type == Type::Void {
+            return Type::Void;
+        }
+
+        let else_block = match else_clause {
+            ElseClause::Else(container) => Some(&container.code_block),
+            ElseClause::ElseIf(_) => None,
+        };
+
+        let branches = [
+            (Some(&node.then_branch.code_block), then_type.clone()),
+            (else_block, else_type.clone()),
+        ];
+
+        let Some(branch_type) = self.unify_branches(&branches, expected, context) else {
+            context.error(format!(
+                "if branches have incompatible types {} and {}",
+                then_type, else_type
+            ));
+            return then_type;
+        };
+
+        branch_type
+    }
+
+    /// The other type of the result is not known until the value is used, it
+    /// is void until then.
+    pub fn visit_result_expression(&self, node: &ResultExpression, context: &mut Context) -> Type {
+        let payload_type = Box::new(self.visit_expression(&node.value, context));
+
+        match node.variant {
+            ResultVariant::Ok => Type::Result(payload_type, Box::new(Type::Void)),
+            ResultVariant::Err => Type::Result(Box::new(Type::Void), payload_type),
+        }
+    }
+
+    /// `?` unwraps a result whose error can be returned by the enclosing
+    /// function, or an optional in a function that returns an optional.
+    pub fn visit_try_expression(&self, node: &TryExpression, context: &mut Context) -> Type {
+        let operand_type = self.visit_expression(&node.expression, context);
+
+        let Some(function) = context.current_function() else {
+            context.error("? used outside of a function".to_string());
+            return Type::Void;
+        };
+
+        let name = function.name.clone();
+        let return_type = function.return_type.clone();
+
+        match (operand_type, &return_type) {
+            (Type::Result(value, error), Type::Result(_, returned_error)) => {
+                if !context.is_assignable(&error, returned_error) {
+                    context.error(format!(
+                        "? propagates errors of type {} but function {:?} returns {}",
+                        error, name, return_type
+                    ));
+                }
+
+                *value
+            }
+            (Type::Result(value, _), _) => {
+                context.error(format!(
+                    "? on a result can only be used in a function that returns a result, function {:?} returns {}",
+                    name, return_type
+                ));
+
+                *value
+            }
+            (Type::Optional(wrapped), Type::Optional(_)) => *wrapped,
+            (Type::Optional(wrapped), _) => {
+                context.error(format!(
+                    "? on an optional can only be used in a function that returns an optional, function {:?} returns {}",
+                    name, return_type
+                ));
+
+                *wrapped
+            }
+            (operand_type, _) => {
+                context.error(format!(
+                    "? can only be applied to a result or an optional but the value has type {}",
+                    operand_type
+                ));
+
+                Type::Void
+            }
+        }
+    }
+
+    pub fn visit_match_expression(
+        &self,
+        node: &MatchExpression,
+        expected: Option<&Type>,
+        context: &mut Context,
+    ) -> Type {
+        let subject_type = self.visit_expression(&node.subject, context);
+        let mut arms = Vec::new();
+
+        for arm in &node.arms {
+            context.enter_scope();
+            self.visit_pattern(&arm.pattern, &subject_type, context);
+            let arm_type = self.visit_expected_code_block(&arm.body.code_block, expected, context);
+            context.exit_scope();
+
+            // an arm that always returns does not contribute a value
+            if !self.always_returns(&arm.body.code_block) {
+                arms.push((Some(&arm.body.code_block), arm_type));
+            }
+        }
+
+        self.check_exhaustive(node, &subject_type, context);
+
+        if arms.is_empty() || arms.iter().any(|(_, arm_type)| *arm_type == Type::Void) {
+            return Type::Void;
+        }
+
+        let Some(arm_type) = self.unify_branches(&arms, expected, context) else {
+            let types = arms
+                .iter()
+                .map(|(_, arm_type)| arm_type.to_string())
+                .collect::<Vec<_>>();
+
+            context.error(format!(
+                "match arms have incompatible types {}",
+                types.join(", ")
+            ));
+            return arms[0].1.clone();
+        };
+
+        arm_type
+    }
+
+    /// The type of the value of a branching expression, branches ending with a
+    /// number literal take the type of the other branches. Branches of
+    /// different types take the expected type when they are all assignable to
+    /// it, as values of types conforming to an expected protocol.
+    fn unify_branches(
+        &self,
+        branches: &[(Option<&CodeBlock>, Type)],
+        expected: Option<&Type>,
+        context: &mut Context,
+    ) -> Option<Type> {
+        for (_, candidate) in branches {
+            let unified = branches.iter().all(|(block, branch_type)| {
+                if branch_type == candidate {
+                    return true;
+                }
+
+                let Some(CodeBlockItem::Expression(tail)) = block.and_then(|b| b.items.last())
+                else {
+                    return false;
+                };
+
+                self.check_assignable(tail, branch_type, candidate, context)
+                    && context.expression_type(tail) == *candidate
+            });
+
+            if unified {
+                return Some(candidate.clone());
+            }
+        }
+
+        let expected = expected?;
+
+        let unified = branches.iter().all(|(block, branch_type)| {
+            if branch_type == expected {
+                return true;
+            }
+
+            let Some(CodeBlockItem::Expression(tail)) = block.and_then(|b| b.items.last()) else {
+                return false;
+            };
+
+            self.check_assignable(tail, branch_type, expected, context)
+        });
+
+        unified.then(|| expected.clone())
+    }
+
+    /// Checks a pattern against the type of the matched value and declares the
+    /// variable it binds.
+    fn visit_pattern(&self, node: &Pattern, subject_type: &Type, context: &mut Context) {
+        match node {
+            Pattern::Wildcard => {}
+            Pattern::Nil => {
+                if !matches!(subject_type, Type::Optional(_)) {
+                    context.error(format!(
+                        "nil cannot match a value of type {} which is not optional",
+                        subject_type
+                    ));
+                }
+            }
+            Pattern::Binding(name) => {
+                let bound_type = match subject_type {
+                    Type::Optional(wrapped) => (**wrapped).clone(),
+                    _ => subject_type.clone(),
+                };
+
+                context.register_symbol(Symbol {
+                    name: name.name.clone(),
+                    symbol_type: bound_type,
+                    declared: false,
+                });
+            }
+            Pattern::Result(variant, binding) => {
+                let Type::Result(value, error) = subject_type else {
+                    context.error(format!(
+                        "{} cannot match a value of type {} which is not a result",
+                        if *variant == ResultVariant::Ok {
+                            "ok"
+                        } else {
+                            "err"
+                        },
+                        subject_type
+                    ));
+                    return;
+                };
+
+                let payload_type = match variant {
+                    ResultVariant::Ok => value,
+                    ResultVariant::Err => error,
+                };
+
+                if let Some(binding) = binding {
+                    context.register_symbol(Symbol {
+                        name: binding.name.clone(),
+                        symbol_type: payload_type.as_ref().clone(),
+                        declared: false,
+                    });
+                }
+            }
+            Pattern::EnumCase(case) => {
+                let is_case = match subject_type.nominal().and_then(|n| context.lookup_type(n)) {
+                    Some(TypeDefinition {
+                        kind: TypeDefinitionKind::Enumeration(cases),
+                        ..
+                    }) => cases.contains(&case.name),
+                    _ => false,
+                };
+
+                if !is_case {
+                    context.error(format!("type {} has no case {:?}", subject_type, case.name));
+                }
+            }
+            Pattern::Literal(literal) => {
+                let literal_type = self.visit_expression(literal, context);
+                let is_scalar = subject_type.is_numeric()
+                    || *subject_type == Type::Identifier("bool".to_string());
+
+                if !is_scalar
+                    || !self.check_assignable(literal, &literal_type, subject_type, context)
+                {
+                    context.error(format!(
+                        "a pattern of type {} cannot match a value of type {}",
+                        literal_type, subject_type
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Every value has to be matched by an arm, the arms that are missing are
+    /// listed when they can be named.
+    fn check_exhaustive(&self, node: &MatchExpression, subject_type: &Type, context: &mut Context) {
+        let is_optional = matches!(subject_type, Type::Optional(_));
+        let covers =
+            |matches: &dyn Fn(&Pattern) -> bool| node.arms.iter().any(|arm| matches(&arm.pattern));
+
+        let catch_all = covers(&|pattern| match pattern {
+            Pattern::Wildcard => true,
+            Pattern::Binding(_) => !is_optional,
+            _ => false,
+        });
+
+        if catch_all {
+            return;
+        }
+
+        let mut missing = Vec::new();
+
+        match subject_type {
+            Type::Optional(_) => {
+                if !covers(&|pattern| matches!(pattern, Pattern::Nil)) {
+                    missing.push("nil".to_string());
+                }
+
+                if !covers(&|pattern| matches!(pattern, Pattern::Binding(_))) {
+                    missing.push("let value".to_string());
+                }
+            }
+            Type::Result(..) => {
+                for (variant, name) in [(ResultVariant::Ok, "ok"), (ResultVariant::Err, "err")] {
+                    if !covers(&|pattern| matches!(pattern, Pattern::Result(v, _) if *v == variant))
+                    {
+                        missing.push(format!("{}(_)", name));
+                    }
+                }
+            }
+            Type::Identifier(name) if name == "bool" => {
+                for value in [true, false] {
+                    if !covers(
+                        &|pattern| matches!(pattern, Pattern::Literal(Expression::BooleanLiteral(b)) if *b == value),
+                    ) {
+                        missing.push(value.to_string());
+                    }
+                }
+            }
+            Type::Identifier(name) | Type::Generic(name, _) => {
+                match context.lookup_type(name).map(|d| &d.kind) {
+                    Some(TypeDefinitionKind::Enumeration(cases)) => {
+                        for case in cases {
+                            if !covers(
+                                &|pattern| matches!(pattern, Pattern::EnumCase(c) if c.name == *case),
+                            ) {
+                                missing.push(format!(".{}", case));
+                            }
+                        }
+                    }
+                    _ => missing.push("_".to_string()),
+                }
+            }
+            _ => missing.push("_".to_string()),
+        }
+
+        if !missing.is_empty() {
+            context.error(format!(
+                "match on a value of type {} is not exhaustive, missing: {}",
+                subject_type,
+                missing.join(", ")
+            ));
+        }
+    }
+
+    pub fn visit_identifier_expression(
+        &self,
+        identifier: &Identifier,
+        context: &mut Context,
+    ) -> Type {
+        let Some(symbol) = context.lookup(identifier.name.clone()) else {
+            context.error(format!("undeclared identifier {:?}", identifier.name));
+            return Type::Void; // TODO: should we return unknown(String) instead?
+        };
+
+        context.capture(&identifier.name);
+
+        // a declared function used as a value is wrapped in a closure
+        if let (true, Type::Function(function)) = (symbol.declared, &symbol.symbol_type) {
+            if !context.is_global(&identifier.name) {
+                context.error(format!(
+                    "method {:?} cannot be used as a value, wrap the call in a closure instead",
+                    identifier.name
+                ));
+            } else if !function.generic_parameters.is_empty() {
+                context.error(format!(
+                    "generic function {:?} cannot be used as a value, its type arguments are unknown",
+                    identifier.name
+                ));
+            } else if function.variadic {
+                context.error(format!(
+                    "variadic function {:?} cannot be used as a value",
+                    identifier.name
+                ));
+            } else {
+                context.expose(&identifier.name);
+            }
+        }
+
+        symbol.symbol_type
+    }
+
+    /// Checks the body of a closure like that of a function, the variables of
+    /// the enclosing functions it uses are recorded as its captures.
+    pub fn visit_closure_expression(
+        &self,
+        node: &ClosureExpression,
+        context: &mut Context,
+    ) -> Type {
+        let function = TypeFunction::from(&node.signature);
+        let identifier = Identifier {
+            name: "closure".to_string(),
+        };
+
+        context.enter_closure();
+        self.visit_function(&identifier, &node.signature, &node.body, context);
+        context.exit_closure(node);
+
+        Type::Function(function)
+    }
+
+    pub fn visit_function_call_expression(
+        &self,
+        node: &FunctionCallExpression,
+        context: &mut Context,
+    ) -> Type {
+        let Some(symbol) = context.lookup(node.function.name.clone()) else {
+            if let Some(definition) = context.lookup_type(&node.function.name) {
+                if let TypeDefinitionKind::Structure(fields) = &definition.kind {
+                    let fields = fields.clone();
+                    return self.visit_initializer(&node.function.name, &fields, node, context);
+                }
+            }
+
+            context.error(format!("undeclared function {:?}", node.function.name));
+            return Type::Void;
+        };
+
+        context.capture(&node.function.name);
+
+        match symbol.symbol_type {
+            Type::Function(function) if !function.generic_parameters.is_empty() => {
+                self.visit_generic_call(&node.function.name, &function, node, context)
+            }
+            Type::Function(function) => {
+                self.check_arguments(&node.function.name, &function, &node.arguments, context);
+
+                if !symbol.declared {
+                    context.record_indirect_call(node, function.clone());
+                } else if context.is_global(&node.function.name) {
+                    context.expose(&node.function.name);
+                }
+
+                function.return_type.as_ref().clone()
+            }
+            _ => {
+                context.error(format!(
+                    "trying to use {:?} as a function but it is a {}",
+                    symbol.name, symbol.symbol_type
+                ));
+                Type::Void
+            }
+        }
+    }
+
+    /// Checks the construction of a value of a type, its fields are initialized
+    /// in declaration order. The type arguments of a generic type are inferred
+    /// from the fields.
+    pub fn visit_initializer(
+        &self,
+        type_name: &str,
+        fields: &[Field],
+        node: &FunctionCallExpression,
+        context: &mut Context,
+    ) -> Type {
+        let definition = context.lookup_type(type_name).unwrap();
+
+        let initializer = TypeFunction {
+            parameters: fields
+                .iter()
+                .map(|field| TypeFunctionParameter::new(None, field.name.clone(), field.ty.clone()))
+                .collect(),
+            return_type: Box::new(definition.declared_type()),
+            variadic: false,
+            generic_parameters: definition.generic_parameters.clone(),
+        };
+
+        if !initializer.generic_parameters.is_empty() {
+            return self.visit_generic_call(type_name, &initializer, node, context);
+        }
+
+        self.check_arguments(type_name, &initializer, &node.arguments, context);
+
+        Type::Identifier(type_name.to_string())
+    }
+
+    /// Checks a call to a generic function or initializer, the type arguments
+    /// are inferred from the arguments and recorded for code generation.
+    fn visit_generic_call(
+        &self,
+        callee: &str,
+        function: &TypeFunction,
+        node: &FunctionCallExpression,
+        context: &mut Context,
+    ) -> Type {
+        let argument_types = self.visit_arguments(&node.arguments, context);
+
+        if function.parameters.len() != node.arguments.arguments.len() {
+            self.check_argument_types(callee, function, &node.arguments, &argument_types, context);
+            return Type::Void;
+        }
+
+        let Some(arguments) = self.infer_generic_arguments(
+            callee,
+            function,
+            &node.arguments,
+            &argument_types,
+            context,
+        ) else {
+            return Type::Void;
+        };
+
+        let instance = function.substitute(&function.substitution(&arguments));
+        self.check_argument_types(callee, &instance, &node.arguments, &argument_types, context);
+        context.record_generic_arguments(node, arguments);
+
+        *instance.return_type
+    }
+
+    /// Infers the type arguments of a call by matching the types of the
+    /// parameters with those of the arguments. Literals and nil take the type
+    /// they are used as, they only decide a type parameter no other argument
+    /// does.
+    fn infer_generic_arguments(
+        &self,
+        callee: &str,
+        function: &TypeFunction,
+        node: &ArgumentList,
+        argument_types: &[Type],
+        context: &mut Context,
+    ) -> Option<Vec<Type>> {
+        let mut inferred = HashMap::new();
+
+        for adoptable in [false, true] {
+            for ((parameter, argument), argument_type) in function
+                .parameters
+                .iter()
+                .zip(&node.arguments)
+                .zip(argument_types)
+            {
+                if self.adopts_type(&argument.expression) == adoptable {
+                    self.unify(&parameter.ty, argument_type, function, &mut inferred);
+                }
+            }
+        }
+
+        let mut arguments = Vec::new();
+
+        for parameter in &function.generic_parameters {
+            let Some(argument) = inferred.remove(&parameter.name) else {
+                context.error(format!(
+                    "cannot infer the type of {} in the call to {:?}",
+                    parameter.name, callee
+                ));
+                return None;
+            };
+
+            arguments.push(argument);
+        }
+
+        self.check_bounds(callee, &function.generic_parameters, &arguments, context);
+
+        Some(arguments)
+    }
+
+    /// Binds the type parameters of `function` that appear in `parameter` to
+    /// the corresponding part of `argument`, the first binding of a parameter
+    /// wins and any mismatch is reported when the arguments are checked.
+    fn unify(
+        &self,
+        parameter: &Type,
+        argument: &Type,
+        function: &TypeFunction,
+        inferred: &mut HashMap<String, Type>,
+    ) {
+        match (parameter, argument) {
+            // nil and the other side of ok or err say nothing about the type
+            (_, Type::Void) => {}
+            (Type::Identifier(name), _)
+                if function.generic_parameters.iter().any(|p| p.name == *name) =>
+            {
+                inferred
+                    .entry(name.clone())
+                    .or_insert_with(|| argument.clone());
+            }
+            (Type::Pointer(parameter), Type::Pointer(argument))
+            | (Type::Optional(parameter), Type::Optional(argument))
+            | (Type::Array(parameter), Type::Array(argument)) => {
+                self.unify(parameter, argument, function, inferred)
+            }
+            (Type::Dictionary(key, value), Type::Dictionary(argument_key, argument_value)) => {
+                self.unify(key, argument_key, function, inferred);
+                self.unify(value, argument_value, function, inferred);
+            }
+            (Type::Tuple(parameters), Type::Tuple(arguments)) => {
+                for (parameter, argument) in parameters.iter().zip(arguments) {
+                    self.unify(parameter, argument, function, inferred);
+                }
+            }
+            // a value is used as an optional of its type
+            (Type::Optional(parameter), argument) => {
+                self.unify(parameter, argument, function, inferred)
+            }
+            (Type::Result(value, error), Type::Result(argument_value, argument_error)) => {
+                self.unify(value, argument_value, function, inferred);
+                self.unify(error, argument_error, function, inferred);
+            }
+            (Type::Generic(name, parameters), Type::Generic(argument_name, arguments))
+                if name == argument_name =>
+            {
+                for (parameter, argument) in parameters.iter().zip(arguments) {
+                    self.unify(parameter, argument, function, inferred);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether an expression takes its type from where it is used.
+    fn adopts_type(&self, expression: &Expression) -> bool {
+        self.is_constant_number(expression, false)
+            || matches!(expression, Expression::NilLiteral | Expression::Result(_))
+    }
+
+    pub fn visit_method_call_expression(
+        &self,
+        node: &MethodCallExpression,
+        context: &mut Context,
+    ) -> Type {
+        if let Some(type_name) = self.static_type_name(&node.receiver, context) {
+            let method = context
+                .lookup_type(&type_name)
+                .and_then(|definition| definition.method(&node.method.name))
+                .filter(|method| method.selfless)
+                .cloned();
+
+            let Some(method) = method else {
+                context.error(format!(
+                    "method {:?} is called on the type {:?} instead of a value",
+                    node.method.name, type_name
+                ));
+                return Type::Void;
+            };
+
+            let name = format!("{}.{}", type_name, method.name);
+            self.check_member(&name, context);
+            self.check_arguments(&name, &method.function, &node.arguments, context);
+
+            return method.function.return_type.as_ref().clone();
+        }
+
+        let receiver_type = self.visit_expression(&node.receiver, context);
+
+        let Some(receiver_type) =
+            self.chained_type(receiver_type, node.optional, &node.method, context)
+        else {
+            return Type::Void;
+        };
+
+        let method = context.lookup_method(&receiver_type, &node.method.name);
+
+        if let Some(type_name) = receiver_type.nominal() {
+            let name = format!("{}.{}", type_name, node.method.name);
+            self.check_member(&name, context);
+        }
+
+        // a field holding a closure is called like a method
+        if method.is_none() {
+            if let Some(function) = self.function_field(&receiver_type, &node.method.name, context)
+            {
+                self.check_arguments(&node.method.name, &function, &node.arguments, context);
+
+                let return_type = *function.return_type;
+
+                if node.optional {
+                    return return_type.or_nil();
+                }
+
+                return return_type;
+            }
+        }
+
+        let Some(method) = method else {
+            context.error(format!(
+                "value of type {} has no method {:?}",
+                receiver_type, node.method.name
+            ));
+            return Type::Void;
+        };
+
+        if method.selfless {
+            context.error(format!(
+                "selfless function {:?} is called on a value of type {}, call it on the type instead",
+                method.name, receiver_type
+            ));
+        }
+
+        self.check_arguments(
+            &format!("{}.{}", receiver_type, method.name),
+            &method.function,
+            &node.arguments,
+            context,
+        );
+
+        let return_type = method.function.return_type.as_ref().clone();
+
+        if node.optional {
+            return return_type.or_nil();
+        }
+
+        return_type
+    }
+
+    /// Checks that a field or method, named as in `Vector2.length`, is visible
+    /// from the module being checked.
+    fn check_member(&self, name: &str, context: &mut Context) {
+        let module = context.current_module().map(str::to_string);
+        context.check_visibility(name, module.as_deref());
+        context.expose(name);
+    }
+
+    /// The type of the closure held by a field, if the field has a function type.
+    pub fn function_field(&self, ty: &Type, name: &str, context: &Context) -> Option<TypeFunction> {
+        context
+            .fields(ty)?
+            .into_iter()
+            .find(|field| field.name == name)
+            .and_then(|field| match field.ty {
+                Type::Function(function) => Some(function),
+                _ => None,
+            })
+    }
+
+    pub fn visit_member_access_expression(
+        &self,
+        node: &MemberAccessExpression,
+        context: &mut Context,
+    ) -> Type {
+        if let Some(type_name) = self.static_type_name(&node.base, context) {
+            let is_case = match &context.lookup_type(&type_name).unwrap().kind {
+                TypeDefinitionKind::Enumeration(cases) => cases.contains(&node.member.name),
+                _ => false,
+            };
+
+            if !is_case {
+                context.error(format!(
+                    "type {:?} has no case {:?}",
+                    type_name, node.member.name
+                ));
+                return Type::Void;
+            }
+
+            // the type arguments of a generic enum come from where the case is used
+            if !context
+                .lookup_type(&type_name)
+                .unwrap()
+                .generic_parameters
+                .is_empty()
+            {
+                return Type::Generic(type_name, Vec::new());
+            }
+
+            return Type::Identifier(type_name);
+        }
+
+        let base_type = self.visit_expression(&node.base, context);
+
+        let Some(base_type) = self.chained_type(base_type, node.optional, &node.member, context)
+        else {
+            return Type::Void;
+        };
+
+        let field = context
+            .fields(&base_type)
+            .and_then(|fields| fields.into_iter().find(|f| f.name == node.member.name));
+
+        let Some(field) = field else {
+            context.error(format!(
+                "value of type {} has no field {:?}",
+                base_type, node.member.name
+            ));
+            return Type::Void;
+        };
+
+        if let Some(type_name) = base_type.nominal() {
+            self.check_member(&format!("{}.{}", type_name, node.member.name), context);
+        }
+
+        if node.optional {
+            return field.ty.or_nil();
+        }
+
+        field.ty
+    }
+
+    /// The type a member is looked up on, with `?.` the base is an optional and
+    /// members are those of the type it wraps.
+    fn chained_type(
+        &self,
+        base_type: Type,
+        optional: bool,
+        member: &Identifier,
+        context: &mut Context,
+    ) -> Option<Type> {
+        match (base_type, optional) {
+            (Type::Optional(wrapped), true) if *wrapped != Type::Void => Some(*wrapped),
+            (base_type @ Type::Optional(_), false) => {
+                context.error(format!(
+                    "value of type {} may be nil, use ?. to access {:?}",
+                    base_type, member.name
+                ));
+                None
+            }
+            (base_type, true) => {
+                context.error(format!(
+                    "?. can only be used on an optional but the value has type {}",
+                    base_type
+                ));
+                None
+            }
+            (base_type, false) => Some(base_type),
+        }
+    }
+
+    /// The name of the type a member is looked up on when the base of a member
+    /// access is a type name rather than a value, as in `Color.red`.
+    pub fn static_type_name(&self, node: &Expression, context: &Context) -> Option<String> {
+        let Expression::Identifier(identifier) = node else {
+            return None;
+        };
+
+        if context.lookup(identifier.name.clone()).is_some() {
+            return None;
+        }
+
+        context
+            .lookup_type(&identifier.name)
+            .map(|definition| definition.name.clone())
+    }
+
+    pub fn check_arguments(
+        &self,
+        callee: &str,
+        function: &TypeFunction,
+        node: &ArgumentList,
+        context: &mut Context,
+    ) {
+        let argument_types = self.visit_arguments(node, context);
+        self.check_argument_types(callee, function, node, &argument_types, context);
+    }
+
+    fn visit_arguments(&self, node: &ArgumentList, context: &mut Context) -> Vec<Type> {
+        node.arguments
+            .iter()
+            .map(|argument| self.visit_expression(&argument.expression, context))
+            .collect()
+    }
+
+    fn check_argument_types(
+        &self,
+        callee: &str,
+        function: &TypeFunction,
+        node: &ArgumentList,
+        argument_types: &[Type],
+        context: &mut Context,
+    ) {
+        let parameters = &function.parameters;
+
+        if function.variadic && node.arguments.len() < parameters.len() {
+            context.error(format!(
+                "{:?} takes at least {} argument(s) but {} were given",
+                callee,
+                parameters.len(),
+                node.arguments.len()
+            ));
+            return;
+        }
+
+        if !function.variadic && parameters.len() != node.arguments.len() {
+            context.error(format!(
+                "{:?} takes {} argument(s) but {} were given",
+                callee,
+                parameters.len(),
+                node.arguments.len()
+            ));
+            return;
+        }
+
+        for ((parameter, argument), argument_type) in
+            parameters.iter().zip(&node.arguments).zip(argument_types)
+        {
+            if let Some(label) = &argument.label {
+                if label.name != parameter.label {
+                    context.error(format!(
+                        "argument label {:?} does not match the parameter {:?} of {:?}",
+                        label.name, parameter.label, callee
+                    ));
+                }
+            }
+
+            if !self.check_assignable(&argument.expression, argument_type, &parameter.ty, context) {
+                context.error(format!(
+                    "argument {:?} of {:?} expects {} but got {}",
+                    parameter.label, callee, parameter.ty, argument_type
+                ));
+            }
+        }
+
+        // extra arguments of a variadic function go through the C default promotions
+        for (argument, argument_type) in node
+            .arguments
+            .iter()
+            .zip(argument_types)
+            .skip(parameters.len())
+        {
+            if argument.label.is_some() {
+                context.error(format!(
+                    "the variadic arguments of {:?} cannot be labeled",
+                    callee
+                ));
+            }
+
+            let is_scalar = argument_type.is_numeric()
+                || matches!(argument_type, Type::Pointer(_))
+                || *argument_type == Type::Identifier("bool".to_string());
+
+            if !is_scalar {
+                context.error(format!(
+                    "a value of type {} cannot be passed as a variadic argument of {:?}",
+                    argument_type, callee
+                ));
+            }
+        }
+    }
+
+    /// Whether the value of `expression` can be used as a `target`, number
+    /// literals take the numeric type they are used as and nil the optional.
+    pub fn check_assignable(
+        &self,
+        expression: &Expression,
+        value_type: &Type,
+        target: &Type,
+        context: &mut Context,
+    ) -> bool {
+        if let (Expression::NilLiteral, Type::Optional(_)) = (expression, target) {
+            context.record_expression_type(expression, target.clone());
+            return true;
+        }
+
+        // `ok(value)` and `err(error)` take the result type they are used as
+        if let (Expression::Result(result), Type::Result(value, error)) = (expression, target) {
+            let payload_target = match result.variant {
+                ResultVariant::Ok => value,
+                ResultVariant::Err => error,
+            };
+            let payload_type = context.expression_type(&result.value);
+
+            if !self.check_assignable(&result.value, &payload_type, payload_target, context) {
+                return false;
+            }
+
+            context.record_expression_type(expression, target.clone());
+            return true;
+        }
+
+        // the elements of an array literal take the element type it is used as
+        if let (Expression::Array(array), Type::Array(element)) = (expression, target) {
+            if value_type != target {
+                let adopted = array.elements.iter().all(|value| {
+                    let value_type = context.expression_type(value);
+                    self.check_assignable(value, &value_type, element, context)
+                });
+
+                if adopted {
+                    context.record_expression_type(expression, target.clone());
+                    return true;
+                }
+            }
+        }
+
+        // as do the keys and values of a dictionary literal
+        if let (Expression::Dictionary(dictionary), Type::Dictionary(key, value)) =
+            (expression, target)
+        {
+            if value_type != target {
+                let adopted = dictionary.entries.iter().all(|(k, v)| {
+                    let key_type = context.expression_type(k);
+                    let value_type = context.expression_type(v);
+                    self.check_assignable(k, &key_type, key, context)
+                        && self.check_assignable(v, &value_type, value, context)
+                });
+
+                if adopted {
+                    context.record_expression_type(expression, target.clone());
+                    return true;
+                }
+            }
+        }
+
+        // and the elements of a tuple the types of the tuple it is used as, which
+        // is wrapped afterwards when the tuple is used as an optional
+        let tuple_target = match target {
+            Type::Optional(wrapped) => wrapped,
+            _ => target,
+        };
+
+        if let (Expression::Tuple(tuple), Type::Tuple(elements)) = (expression, tuple_target) {
+            let values = &tuple.expressions.items;
+
+            if value_type != tuple_target && values.len() == elements.len() {
+                let adopted = values.iter().zip(elements).all(|(value, element)| {
+                    let value_type = context.expression_type(value);
+                    self.check_assignable(value, &value_type, element, context)
+                });
+
+                if adopted {
+                    context.record_expression_type(expression, tuple_target.clone());
+                    return true;
+                }
+            }
+        }
+
+        // a case of a generic enum takes the type arguments of the enum it is used as
+        if let (Type::Generic(name, arguments), Type::Generic(target_name, _)) =
+            (value_type, target)
+        {
+            if arguments.is_empty() && name == target_name {
+                context.record_expression_type(expression, target.clone());
+                return true;
+            }
+        }
+
+        // a generic initializer takes the type arguments of the type it is used as,
+        // as in `let pair: Pair<i32, f64> := Pair(first: 1, second: 2)`
+        if let (Expression::FunctionCall(call), Type::Generic(name, arguments)) =
+            (expression, target)
+        {
+            if value_type != target && value_type.nominal() == Some(name) {
+                let fields = context.fields(target).unwrap_or_default();
+
+                let adopted =
+                    fields
+                        .iter()
+                        .zip(&call.arguments.arguments)
+                        .all(|(field, argument)| {
+                            let argument_type = context.expression_type(&argument.expression);
+                            self.check_assignable(
+                                &argument.expression,
+                                &argument_type,
+                                &field.ty,
+                                context,
+                            )
+                        });
+
+                if adopted {
+                    context.record_generic_arguments(call, arguments.clone());
+                    context.record_expression_type(expression, target.clone());
+                    return true;
+                }
+            }
+        }
+
+        // a literal used as an optional number is wrapped once it has the number type
+        let number_type = match target {
+            Type::Optional(wrapped) => wrapped,
+            _ => target,
+        };
+
+        if number_type.is_numeric() && self.is_constant_number(expression, number_type.is_integer())
+        {
+            self.adopt_number_type(expression, number_type, context);
+            return true;
+        }
+
+        // an if or match whose branches end with literals is used as a number type
+        if number_type.is_numeric() {
+            if let Some(values) = self.branch_values(expression) {
+                let adopted = values.iter().all(|value| {
+                    let value_type = context.expression_type(value);
+
+                    value_type == *number_type
+                        || (self.check_assignable(value, &value_type, number_type, context)
+                            && context.expression_type(value) == *number_type)
+                });
+
+                if adopted {
+                    context.record_expression_type(expression, number_type.clone());
+                    return true;
+                }
+            }
+        }
+
+        context.is_assignable(value_type, target)
+    }
+
+    /// The tail expressions an if or match takes its value from, none when a
+    /// branch that does not return has no value.
+    fn branch_values<'a>(&self, expression: &'a Expression) -> Option<Vec<&'a Expression>> {
+        let mut blocks = Vec::new();
+
+        match expression {
+            Expression::If(if_expression) => {
+                let mut node = if_expression;
+
+                loop {
+                    blocks.push(&node.then_branch.code_block);
+
+                    match &node.else_clause {
+                        Some(ElseClause::Else(container)) => {
+                            blocks.push(&container.code_block);
+                            break;
+                        }
+                        Some(ElseClause::ElseIf(else_if)) => node = else_if,
+                        None => return None,
+                    }
+                }
+            }
+            Expression::Match(match_expression) => {
+                blocks.extend(match_expression.arms.iter().map(|arm| &arm.body.code_block));
+            }
+            _ => return None,
+        }
+
+        blocks
+            .into_iter()
+            .filter(|block| !self.always_returns(block))
+            .map(|block| match block.items.last() {
+                Some(CodeBlockItem::Expression(tail)) => Some(tail),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether an expression is a number literal or arithmetic on number
+    /// literals only, these are f64 unless they are used as another number type.
+    fn is_constant_number(&self, expression: &Expression, integer: bool) -> bool {
+        match expression {
+            Expression::FloatNumberLiteral(value) => !integer || value.fract() == 0.0,
+            Expression::BinaryOperator(binary) => {
+                !binary.operator.is_comparison()
+                    && self.is_constant_number(&binary.left, integer)
+                    && self.is_constant_number(&binary.right, integer)
+            }
+            _ => false,
+        }
+    }
+
+    fn adopt_number_type(&self, expression: &Expression, target: &Type, context: &mut Context) {
+        if let Expression::BinaryOperator(binary) = expression {
+            self.adopt_number_type(&binary.left, target, context);
+            self.adopt_number_type(&binary.right, target, context);
+        }
+
+        context.record_expression_type(expression, target.clone());
+    }
+
+    pub fn visit_binary_operator_expression(
+        &self,
+        node: &BinaryOperatorExpression,
+        context: &mut Context,
+    ) -> Type {
+        if let BinaryOperator::Coalesce = node.operator {
+            return self.visit_coalesce_expression(node, context);
+        }
+
+        let left = self.visit_expression(&node.left, context);
+        let right = self.visit_expression(&node.right, context);
+
+        // a literal operand takes the type of the other operand, as in `count + 1`
+        let operand_type =
+            if left == right || self.check_assignable(&node.right, &right, &left, context) {
+                left
+            } else if self.check_assignable(&node.left, &left, &right, context) {
+                right
+            } else {
+                context.error(format!(
+                    "incompatible types for {} {:?} {}",
+                    left, node.operator, right
+                ));
+                left
+            };
+
+        if node.operator.is_comparison() {
+            if let Type::Optional(_) = operand_type {
+                let with_nil = matches!(*node.left, Expression::NilLiteral)
+                    || matches!(*node.right, Expression::NilLiteral);
+                let is_equality = matches!(
+                    node.operator,
+                    BinaryOperator::Equal | BinaryOperator::NotEqual
+                );
+
+                if !with_nil || !is_equality || operand_type == Type::Optional(Box::new(Type::Void))
+                {
+                    context.error(format!(
+                        "a value of type {} can only be compared to nil with = and ~=",
+                        operand_type
+                    ));
+                }
+            }
+
+            return Type::Identifier("bool".to_string());
+        }
+
+        if operand_type != Type::Void && !operand_type.is_numeric() {
+            context.error(format!(
+                "operator {:?} cannot be applied to values of type {}",
+                node.operator, operand_type
+            ));
+        }
+
+        operand_type
+    }
+
+    /// `optional ?? default` is the value of the optional, or the default when
+    /// it is nil. The default may itself be optional, then so is the result.
+    fn visit_coalesce_expression(
+        &self,
+        node: &BinaryOperatorExpression,
+        context: &mut Context,
+    ) -> Type {
+        let left = self.visit_expression(&node.left, context);
+        let right = self.visit_expression(&node.right, context);
+
+        let Type::Optional(wrapped) = &left else {
+            context.error(format!(
+                "the left side of ?? must be optional but it has type {}",
+                left
+            ));
+            return right;
+        };
+
+        let wrapped = (**wrapped).clone();
+
+        if self.check_assignable(&node.right, &right, &wrapped, context) {
+            return wrapped;
+        }
+
+        if self.check_assignable(&node.right, &right, &left, context) {
+            return left;
+        }
+
+        context.error(format!(
+            "the default of ?? has type {} but the optional holds {}",
+            right, wrapped
+        ));
+
+        wrapped
     }
 }
@@ -0,0 +1,90 @@
+#![cfg(feature = "llvm")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Copies the projects of `tests/build` into the build directory, so that
+/// their `target` directories are written there, and returns its path.
+fn copy_projects(name: &str) -> PathBuf {
+    let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR"))
+        .join("build")
+        .join(name);
+    let _ = fs::remove_dir_all(&directory);
+
+    copy_directory(
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/build"),
+        &directory,
+    );
+
+    directory
+}
+
+fn copy_directory(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+
+    for entry in fs::read_dir(from).unwrap() {
+        let path = entry.unwrap().path();
+        let destination = to.join(path.file_name().unwrap());
+
+        match path.is_dir() {
+            true => copy_directory(&path, &destination),
+            false => {
+                fs::copy(&path, &destination).unwrap();
+            }
+        }
+    }
+}
+
+/// Runs `x build` in the directory of a project.
+fn build(project: &Path, options: &[&str]) {
+    let build = Command::new(env!("CARGO_BIN_EXE_x"))
+        .arg("build")
+        .args(options)
+        .current_dir(project)
+        .output()
+        .unwrap();
+    assert!(
+        build.status.success(),
+        "{}{}",
+        String::from_utf8_lossy(&build.stdout),
+        String::from_utf8_lossy(&build.stderr)
+    );
+}
+
+#[test]
+fn executables_are_built_with_their_dependencies() {
+    let app = copy_projects("executable").join("app");
+    build(&app, &[]);
+
+    // one object per module, the files of a directory are one module, and C
+    // objects mirror the paths of their sources
+    for object in [
+        "app/main.o",
+        "app/shapes.o",
+        "app/native/answer.c.o",
+        "app/native/extra/answer.c.o",
+        "units/units.o",
+    ] {
+        assert!(app.join("target/obj").join(object).exists(), "{}", object);
+    }
+
+    let output = Command::new(app.join("target/app")).output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "576 3\n3 42 6\n");
+
+    build(&app, &["--release"]);
+
+    let output = Command::new(app.join("target/app")).output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "576 3\n3 42 6\n");
+}
+
+#[test]
+fn libraries_are_archived_with_their_headers() {
+    let units = copy_projects("library").join("units");
+    build(&units, &[]);
+
+    assert!(units.join("target/libunits.a").exists());
+
+    let header = fs::read_to_string(units.join("target/include/units.h")).unwrap();
+    assert!(header.contains("double units_feet(double inches);"));
+}
@@ -0,0 +1,3 @@
+#include <stdint.h>
+
+int32_t native_question(void) { return 6; }
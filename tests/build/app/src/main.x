@@ -0,0 +1,13 @@
+import shapes
+import units
+
+extern fun printf(format: *u8, ...) -> i32
+extern fun native_answer() -> i32
+extern fun native_question() -> i32
+
+fun main() -> i32 {
+    printf("%g %g\n", shapes.square_area(2), units.units_feet(36))
+    printf("%g %d %d\n", shapes.cube_side(27), native_answer(), native_question())
+
+    0
+}
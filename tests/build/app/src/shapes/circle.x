@@ -0,0 +1,5 @@
+extern fun cbrt(x: f64) -> f64
+
+pub fun cube_side(volume: f64) -> f64 {
+    cbrt(volume)
+}
@@ -0,0 +1,6 @@
+import units
+
+pub fun square_area(feet: f64) -> f64 {
+    let side := units.inches(feet)
+    side * side
+}
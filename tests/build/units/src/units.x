@@ -0,0 +1,10 @@
+pub let inches_per_foot := 12
+
+pub fun inches(feet: f64) -> f64 {
+    feet * inches_per_foot
+}
+
+@export
+pub fun units_feet(inches: f64) -> f64 {
+    inches / inches_per_foot
+}
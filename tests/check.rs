@@ -0,0 +1,517 @@
+use x::driver::{self, CompileOptions, Emit};
+
+use std::path::{Path, PathBuf};
+
+/// Checks a program and returns its diagnostics.
+fn check(code: &str) -> Vec<String> {
+    check_in(Path::new("."), code)
+}
+
+/// Checks a program whose imports are found in `directory`.
+fn check_in(directory: &Path, code: &str) -> Vec<String> {
+    let options = CompileOptions {
+        emit: Emit::Obj,
+        header: None,
+        bounds_checks: true,
+        search_paths: Vec::new(),
+    };
+    let files = [(PathBuf::from("check.x"), code.to_string())];
+    let (_, context) = driver::check_module(&files, "check", directory, &options);
+
+    context.diagnostics().to_vec()
+}
+
+#[test]
+fn functions_that_may_not_return_are_reported() {
+    let diagnostics = check(
+        "fun sign(n: i32) -> i32 {
+    if n < 0 {
+        return 0 - 1
+    }
+}
+",
+    );
+    assert_eq!(
+        diagnostics,
+        ["error: function \"sign\" may not return a value"]
+    );
+
+    // every branch returns
+    let diagnostics = check(
+        "fun sign(n: i32) -> i32 {
+    if n < 0 {
+        return 0 - 1
+    } else if n = 0 {
+        return 0
+    } else {
+        return 1
+    }
+}
+",
+    );
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+    // a tail that fails to type-check is not reported again, local functions keep their name
+    let diagnostics = check(
+        "fun main() -> i32 {
+    let total := 3
+    fun helper() -> i32 {
+        total + 1
+    }
+    fun other(n: i32) -> i32 {
+        if n < 0 {
+            return 0
+        }
+    }
+    helper() + other(1)
+}
+",
+    );
+    assert_eq!(
+        diagnostics,
+        [
+            "error: undeclared identifier \"total\"",
+            "error: incompatible types for void Add f64",
+            "error: function \"other\" may not return a value",
+        ]
+    );
+}
+
+#[test]
+fn returned_values_must_match_the_return_type() {
+    assert_eq!(
+        check("fun answer() -> i32 {\n    true\n}\n"),
+        ["error: function \"answer\" returns i32 but its tail expression has type bool"]
+    );
+    assert_eq!(
+        check("fun answer() -> i32 {\n    return true\n}\n"),
+        ["error: function \"answer\" returns i32 but the returned value has type bool"]
+    );
+    assert_eq!(
+        check("fun log() {\n    return 1\n}\n"),
+        ["error: function \"log\" has no return type but returns a value of type f64"]
+    );
+    assert_eq!(
+        check("fun answer() -> i32 {\n    return\n}\n"),
+        ["error: function \"answer\" must return a value of type i32"]
+    );
+}
+
+#[test]
+fn tails_of_every_kind_are_checked() {
+    // a parenthesized operand and an if used as the tail expression
+    let diagnostics = check(
+        "fun scaled(a: i32, b: i32, c: i32) -> i32 {
+    (a + b) * c
+}
+
+fun larger(a: f64, b: f64) -> f64 {
+    if a < b {
+        b
+    } else {
+        a
+    }
+}
+",
+    );
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+}
+
+#[test]
+fn array_elements_take_the_declared_element_type() {
+    let declarations = "proto Shape {
+    fun area() -> f64
+}
+
+type Circle: Shape {
+    let radius: f64
+
+    fun area() -> f64 {
+        radius
+    }
+}
+
+type Point {
+    let x: f64
+}
+";
+
+    let diagnostics = check(&format!(
+        "{}\nfun main() -> i32 {{\n    let shapes: [Shape] := [Circle(radius: 1), Point(x: 1)]\n    0\n}}\n",
+        declarations
+    ));
+    assert_eq!(
+        diagnostics,
+        ["error: an array of Shape cannot hold an element of type Point"]
+    );
+
+    // without a declared type the elements must agree
+    let diagnostics = check(&format!(
+        "{}\nfun main() -> i32 {{\n    let shapes := [Circle(radius: 1), Point(x: 1)]\n    0\n}}\n",
+        declarations
+    ));
+    assert_eq!(
+        diagnostics,
+        ["error: array elements must all have the same type but Point is not Circle"]
+    );
+}
+
+#[test]
+fn branches_take_the_expected_protocol_type() {
+    let declarations = "proto Shape {
+    fun area() -> f64
+}
+
+type Square: Shape {
+    let side: f64
+
+    fun area() -> f64 {
+        side * side
+    }
+}
+
+type Circle: Shape {
+    let radius: f64
+
+    fun area() -> f64 {
+        3 * radius * radius
+    }
+}
+";
+
+    let diagnostics = check(&format!(
+        "{}\nfun pick(c: bool) -> Shape {{\n    if c {{ Square(side: 2) }} else {{ Circle(radius: 1) }}\n}}\n",
+        declarations
+    ));
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+    let diagnostics = check(&format!(
+        "{}\nfun pick(n: i32) -> f64 {{\n    let shape: Shape := match n {{\n        0 => Square(side: 2)\n        _ => Circle(radius: 1)\n    }}\n    shape.area()\n}}\n",
+        declarations
+    ));
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+    // without an expected type the branches must agree
+    let diagnostics = check(&format!(
+        "{}\nfun pick(c: bool) -> f64 {{\n    let shape := if c {{ Square(side: 2) }} else {{ Circle(radius: 1) }}\n    shape.area()\n}}\n",
+        declarations
+    ));
+    assert_eq!(
+        diagnostics,
+        ["error: if branches have incompatible types Square and Circle"]
+    );
+}
+
+#[test]
+fn extern_functions_only_use_types_with_a_c_layout() {
+    let diagnostics = check(
+        "proto LibMathProto {
+    fun selfless add(x: f64, y: f64) -> f64
+}
+
+extern \"C\" LibMath: LibMathProto
+
+extern fun measure(shape: Shape) -> i32
+extern fun library() -> LibMath
+",
+    );
+    assert_eq!(
+        diagnostics,
+        [
+            "error: extern function \"measure\" uses Shape which has no C layout",
+            "error: extern function \"library\" uses LibMath which has no C layout"
+        ]
+    );
+}
+
+#[test]
+fn exported_functions_only_use_types_with_a_c_counterpart() {
+    // arrays are laid out for extern functions but C code cannot build them
+    let diagnostics = check(
+        "extern fun total(values: [i32]) -> i32
+
+@export
+fun first(values: [i32]) -> i32 {
+    total(values)
+}
+",
+    );
+    assert_eq!(
+        diagnostics,
+        ["error: exported function \"first\" uses [i32] which has no C representation"]
+    );
+}
+
+#[test]
+fn missing_requirements_are_reported_together() {
+    let diagnostics = check(
+        "proto Shape {
+    fun area() -> f64
+    fun perimeter() -> f64
+    fun name() -> *u8
+}
+
+type Square: Shape {
+    let side: f64
+
+    fun name() -> *u8 {
+        \"square\"
+    }
+}
+",
+    );
+    assert_eq!(
+        diagnostics,
+        ["error: type \"Square\" does not conform to protocol \"Shape\", missing:\n    fun area() -> f64\n    fun perimeter() -> f64"]
+    );
+}
+
+#[test]
+fn propagated_errors_must_match_the_returned_error_type() {
+    let diagnostics = check(
+        "enum ParseError {
+    empty
+}
+
+enum ReadError {
+    closed
+}
+
+fun parse(value: i32) -> result<i32, ParseError> {
+    if value < 0 {
+        return err(ParseError.empty)
+    }
+
+    ok(value)
+}
+
+fun read(value: i32) -> result<i32, ReadError> {
+    let parsed := parse(value)?
+    ok(parsed)
+}
+",
+    );
+    assert_eq!(
+        diagnostics,
+        ["error: ? propagates errors of type ParseError but function \"read\" returns result<i32, ReadError>"]
+    );
+}
+
+#[test]
+fn generic_arguments_must_conform_to_their_bounds() {
+    let diagnostics = check(
+        "proto Shape {
+    fun area() -> f64
+}
+
+type Point {
+    let x: f64
+}
+
+fun total<T: Shape>(a: T, b: T) -> f64 {
+    a.area() + b.area()
+}
+
+fun main() -> f64 {
+    total(Point(x: 1), Point(x: 2))
+}
+",
+    );
+    assert_eq!(
+        diagnostics,
+        ["error: T of \"total\" must conform to \"Shape\" but Point does not"]
+    );
+}
+
+#[test]
+fn if_conditions_and_branches_are_checked() {
+    assert_eq!(
+        check("fun sign(n: i32) -> i32 {\n    if n { 1 } else { 0 }\n}\n"),
+        ["error: if condition must be of type bool but it has type i32"]
+    );
+    assert_eq!(
+        check("fun sign(n: i32) -> i32 {\n    let s := if n > 0 { 1 } else { true }\n    0\n}\n"),
+        ["error: if branches have incompatible types f64 and bool"]
+    );
+}
+
+#[test]
+fn only_concrete_types_are_extended() {
+    let diagnostics = check(
+        "proto Shape {
+    fun area() -> f64
+    fun selfless unit() -> f64 {
+        1
+    }
+}
+
+proto LibMathProto {
+    fun selfless sqrt(x: f64) -> f64
+}
+
+extern \"C\" LibMath: LibMathProto
+
+extend Shape {
+    fun twice() -> f64 {
+        area() * 2
+    }
+}
+
+extend Circle {
+}
+
+extend LibMath {
+}
+",
+    );
+    assert_eq!(
+        diagnostics,
+        [
+            "error: selfless requirement \"unit\" cannot have a default implementation",
+            "error: protocol \"Shape\" cannot be extended, declare a default implementation in it instead",
+            "error: cannot extend the unknown type \"Circle\"",
+            "error: extern library \"LibMath\" cannot be extended"
+        ]
+    );
+}
+
+#[test]
+fn optionals_must_be_unwrapped() {
+    let declarations = "type Point {\n    let x: f64\n}\n";
+    let check_body = |body: &str| {
+        check(&format!(
+            "{}\nfun first(point: Point?, index: i32) -> f64 {{\n{}\n}}\n",
+            declarations, body
+        ))
+    };
+
+    assert_eq!(
+        check_body("    let missing := nil\n    0"),
+        ["error: the type of \"missing\" cannot be inferred from nil, annotate it with an optional type"]
+    );
+    assert_eq!(
+        check_body("    if let value := index {\n        1\n    } else {\n        0\n    }"),
+        ["error: if let needs an optional value but it has type i32"]
+    );
+    assert_eq!(
+        check_body("    point.x"),
+        ["error: value of type Point? may be nil, use ?. to access \"x\""]
+    );
+    assert_eq!(
+        check_body("    index ?? 0"),
+        ["error: the left side of ?? must be optional but it has type i32"]
+    );
+
+    // unwrapped values are accepted
+    let diagnostics = check_body("    let x := point?.x\n    x ?? 0");
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+}
+
+#[test]
+fn dictionary_keys_must_be_hashable() {
+    let diagnostics = check(
+        "type Point {
+    let x: f64
+}
+
+fun main() -> i32 {
+    let names: [Point: i32] := [:]
+    let mixed := [1: 2, true: 3]
+    0
+}
+",
+    );
+    assert_eq!(
+        diagnostics,
+        [
+            "error: Point cannot be the key of a dictionary, it does not conform to \"Hashable\"",
+            "error: dictionary keys must all have the same type but f64 is not bool"
+        ]
+    );
+}
+
+#[test]
+fn tuples_are_destructured_into_as_many_names() {
+    let diagnostics = check(
+        "fun main() -> i32 {
+    let (a, b) := (1, 2, 3)
+    let (c, d) := 4
+    0
+}
+",
+    );
+    assert_eq!(
+        diagnostics,
+        [
+            "error: cannot destructure a tuple of 3 elements into 2 names",
+            "error: only tuples can be destructured but the value has type f64"
+        ]
+    );
+}
+
+#[test]
+fn only_closures_and_plain_functions_are_values() {
+    let diagnostics = check(
+        "type Counter {
+    let count: i32
+
+    fun next() -> i32 {
+        count + 1
+    }
+
+    fun later() -> i32 {
+        let step := next
+        0
+    }
+}
+
+fun identity<T>(value: T) -> T {
+    value
+}
+
+fun main() -> i32 {
+    let same := identity
+    0
+}
+",
+    );
+    assert_eq!(
+        diagnostics,
+        [
+            "error: method \"next\" cannot be used as a value, wrap the call in a closure instead",
+            "error: generic function \"identity\" cannot be used as a value, its type arguments are unknown"
+        ]
+    );
+}
+
+#[test]
+fn imported_declarations_must_exist_and_be_public() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/ffi");
+
+    let diagnostics = check_in(&directory, "import nowhere\n");
+    assert_eq!(
+        diagnostics,
+        ["error: cannot find module \"nowhere\", expected nowhere.x or a directory nowhere next to the importing file or in the dependencies"]
+    );
+
+    let diagnostics = check_in(
+        &directory,
+        "import geometry
+
+fun main() -> i32 {
+    let v := geometry.Vector2(x: 3, y: 4)
+    v.checked(2)
+    geometry.picked()
+    0
+}
+",
+    );
+    let geometry = directory.join("geometry.x");
+    assert_eq!(
+        diagnostics,
+        [
+            format!("error: function \"geometry.picked\" is private to module \"geometry\", declared at {}:51:1, mark it `pub` to use it from other modules", geometry.display()),
+            format!("error: method \"geometry.Vector2.checked\" is private to module \"geometry\", declared at {}:25:5, mark it `pub` to use it from other modules", geometry.display())
+        ]
+    );
+}
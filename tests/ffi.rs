@@ -0,0 +1,348 @@
+// these programs are compiled with the LLVM backend
+#![cfg(feature = "llvm")]
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Compiles an x program from `tests/ffi` into the build directory and returns
+/// the path of its copied source, the outputs are written next to it.
+fn compile(name: &str, options: &[&str]) -> PathBuf {
+    let source = compile_directory().join(format!("{}.x", name));
+    fs::copy(source_directory().join(format!("{}.x", name)), &source).unwrap();
+
+    let compile = Command::new(env!("CARGO_BIN_EXE_x"))
+        .arg("compile")
+        .args(options)
+        .arg(&source)
+        .output()
+        .unwrap();
+    assert!(
+        compile.status.success(),
+        "{}{}",
+        String::from_utf8_lossy(&compile.stdout),
+        String::from_utf8_lossy(&compile.stderr)
+    );
+
+    source
+}
+
+/// Compiles an x program from `tests/ffi` that is expected to be rejected and
+/// returns the diagnostics.
+fn compile_error(name: &str) -> String {
+    let source = compile_directory().join(format!("{}.x", name));
+    fs::copy(source_directory().join(format!("{}.x", name)), &source).unwrap();
+
+    let compile = Command::new(env!("CARGO_BIN_EXE_x"))
+        .arg("compile")
+        .arg(&source)
+        .output()
+        .unwrap();
+    assert!(!compile.status.success());
+
+    String::from_utf8_lossy(&compile.stdout).to_string()
+}
+
+/// Links a compiled x program with a C file from `tests/ffi` and returns what
+/// it prints.
+fn link_and_run(source: &Path, c_file: &str) -> String {
+    let output = Command::new(link(source, c_file)).output().unwrap();
+    assert!(output.status.success());
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Links a compiled x program with a C file from `tests/ffi` and returns the
+/// path of the executable.
+fn link(source: &Path, c_file: &str) -> PathBuf {
+    link_modules(source, &[], c_file)
+}
+
+/// Links a compiled x program with the object files of the modules it imports
+/// and a C file from `tests/ffi`, returns the path of the executable.
+fn link_modules(source: &Path, modules: &[PathBuf], c_file: &str) -> PathBuf {
+    let executable = source.with_extension("");
+    let cc = env::var("CC").unwrap_or("cc".to_string());
+    let link = Command::new(cc)
+        .arg("-no-pie")
+        .arg("-I")
+        .arg(source.parent().unwrap())
+        .arg(source.with_extension("o"))
+        .args(modules.iter().map(|module| module.with_extension("o")))
+        .arg(source_directory().join(c_file))
+        .arg("-o")
+        .arg(&executable)
+        .status()
+        .unwrap();
+    assert!(link.success());
+
+    executable
+}
+
+fn compile_directory() -> PathBuf {
+    let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("ffi");
+    fs::create_dir_all(&directory).unwrap();
+
+    directory
+}
+
+fn source_directory() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/ffi")
+}
+
+/// Compiles an x program from `tests/ffi`, links it with `lib.c` and returns
+/// what it prints.
+fn run(name: &str) -> String {
+    link_and_run(&compile(name, &[]), "lib.c")
+}
+
+#[test]
+fn structs_are_passed_by_value() {
+    assert_eq!(run("structs"), "4 6\n6\n7 0.5 0.25\n2 4 6\n36\n1.75\n");
+}
+
+#[test]
+fn c_integer_types_and_variadic_calls() {
+    assert_eq!(run("c_types"), "-5\n1 0\n42 0.5 done\n3 42\n2 2\n");
+}
+
+#[test]
+fn c_headers_are_imported() {
+    // the header is read from the directory of the source
+    fs::copy(
+        source_directory().join("lib.h"),
+        compile_directory().join("lib.h"),
+    )
+    .unwrap();
+
+    assert_eq!(run("c_import"), "4 6\n2 4 6\n-5 42 255\n");
+}
+
+#[test]
+fn exported_functions_are_called_from_c() {
+    let source = compile("exports", &["--header"]);
+    let header = fs::read_to_string(source.with_extension("h")).unwrap();
+
+    assert!(header
+        .contains("struct Box3 {\n    Vector2 origin;\n    double depth;\n    int32_t tag;\n};"));
+    assert!(header.contains("Box3 geo_grow(Box3 b, double amount);"));
+
+    assert_eq!(link_and_run(&source, "host.c"), "3 4\n1 2 3.5 8\n1\n-5\n");
+}
+
+#[test]
+fn executables_are_linked_by_the_compiler() {
+    let source = compile_directory().join("linked.x");
+    let executable = compile_directory().join("linked");
+    fs::copy(source_directory().join("structs.x"), &source).unwrap();
+
+    let compile = Command::new(env!("CARGO_BIN_EXE_x"))
+        .args(["compile", "--emit", "exe", "-o"])
+        .arg(&executable)
+        .arg(&source)
+        .arg(source_directory().join("lib.c"))
+        .args(["-l", "m"])
+        .output()
+        .unwrap();
+    assert!(
+        compile.status.success(),
+        "{}{}",
+        String::from_utf8_lossy(&compile.stdout),
+        String::from_utf8_lossy(&compile.stderr)
+    );
+
+    let output = Command::new(&executable).output().unwrap();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "4 6\n6\n7 0.5 0.25\n2 4 6\n36\n1.75\n"
+    );
+
+    let emit = Command::new(env!("CARGO_BIN_EXE_x"))
+        .args(["compile", "--emit", "llvm-ir"])
+        .arg(&source)
+        .status()
+        .unwrap();
+    assert!(emit.success());
+
+    let ir = fs::read_to_string(source.with_extension("ll")).unwrap();
+    assert!(ir.contains("define i32 @main()"));
+
+    // link errors are reported on the standard error
+    let failed = Command::new(env!("CARGO_BIN_EXE_x"))
+        .args(["compile", "--emit", "exe", "-o"])
+        .arg(&executable)
+        .arg(&source)
+        .args(["-l", "no_such_library"])
+        .output()
+        .unwrap();
+    assert_eq!(failed.status.code(), Some(1));
+    assert!(failed.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&failed.stderr).starts_with("error: "));
+}
+
+#[test]
+fn programs_are_run_with_the_jit() {
+    let library = compile_directory().join("libjit.so");
+    let cc = env::var("CC").unwrap_or("cc".to_string());
+    let build = Command::new(cc)
+        .args(["-shared", "-fPIC"])
+        .arg(source_directory().join("lib.c"))
+        .arg("-o")
+        .arg(&library)
+        .status()
+        .unwrap();
+    assert!(build.success());
+
+    let run = |name: &str, arguments: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_x"))
+            .arg("run")
+            .arg(source_directory().join(format!("{}.x", name)))
+            .args(arguments)
+            .output()
+            .unwrap()
+    };
+
+    let output = run("structs", &["--load", library.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "4 6\n6\n7 0.5 0.25\n2 4 6\n36\n1.75\n"
+    );
+
+    let output = run("arguments", &["one", "--two"]);
+    assert_eq!(output.status.code(), Some(43));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3 arguments\n");
+
+    let output = run("structs", &[]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "error: cannot find the extern function \"vector2_add\", load the library defining it with --load\n"
+    );
+}
+
+#[test]
+fn optionals_are_unwrapped() {
+    assert_eq!(
+        run("optionals"),
+        "7 21\n-1 42\nvalue 21\nno value\n1 1\n3 -1\n25 1\n1\n1 2 3\nliteral\n"
+    );
+}
+
+#[test]
+fn signed_comparisons_and_else_if_chains() {
+    assert_eq!(
+        run("conditionals"),
+        "1 0 1\n1 1\n1\nnegative zero small large\n-10 5 10\n"
+    );
+}
+
+#[test]
+fn result_errors_are_propagated() {
+    assert_eq!(run("results"), "42\n-1\n-2\nok\n");
+}
+
+#[test]
+fn generics_are_instantiated_for_each_type() {
+    assert_eq!(run("generics"), "7 2.5\n1\n3\n2 1\n5\n3 7\n2\n");
+}
+
+#[test]
+fn generic_instances_are_emitted_once() {
+    let source = compile("generics", &["--emit", "llvm-ir"]);
+    let ir = fs::read_to_string(source.with_extension("ll")).unwrap();
+    let definitions = |name: &str| {
+        ir.lines()
+            .filter(|line| line.starts_with("define") && line.contains(name))
+            .count()
+    };
+
+    // `identity(identity(count))` calls the i32 instance twice
+    assert_eq!(definitions("@\"identity<i32>\""), 1);
+    assert_eq!(definitions("@\"identity<f64>\""), 1);
+    assert_eq!(definitions("@\"larger<Circle>\""), 1);
+}
+
+#[test]
+fn protocol_values_dispatch_dynamically() {
+    assert_eq!(
+        run("protocols"),
+        "circle 3\nsquare 4\n9\ncircle 3\ncircle 12\nsquare 25\nsquare 4\n7\n"
+    );
+}
+
+#[test]
+fn default_implementations_and_extensions() {
+    assert_eq!(
+        run("extensions"),
+        "area 12\narea 12\nsquare 3\narea 1.5\narea 1.5\n4 1.5\n"
+    );
+}
+
+#[test]
+fn arrays_are_indexed_and_assigned() {
+    assert_eq!(run("arrays"), "24 3 3\n1.5 4\nb 0\n3\n");
+}
+
+#[test]
+fn indices_out_of_bounds_stop_the_program() {
+    let executable = link(&compile("bounds", &[]), "lib.c");
+    let output = Command::new(executable).output().unwrap();
+
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n");
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("bounds.x:8:26: index 3 is out of bounds for an array of 3 elements"));
+}
+
+#[test]
+fn dictionaries_hash_their_keys() {
+    assert_eq!(
+        run("dictionaries"),
+        "36 85 0 3\n37 1 2\n100 144 0\nb\n3 0\n"
+    );
+}
+
+#[test]
+fn tuples_are_built_and_destructured() {
+    assert_eq!(run("tuples"), "3 2\n1 4 0\n1 1\n1 2 x\nthree 3\n7\n10\n");
+}
+
+#[test]
+fn closures_capture_and_are_called_indirectly() {
+    assert_eq!(
+        run("closures"),
+        "6 11\n81\n18\nclicked direct\nclicked ok\n17\n9\n4\n"
+    );
+}
+
+#[test]
+fn nested_declarations_are_scoped_to_their_block() {
+    assert_eq!(run("nested"), "7 11 3628800\n4 5\n200\nyes no\n3 7\n");
+}
+
+#[test]
+fn modules_are_compiled_separately_and_linked() {
+    // the program only declares the functions of the module, its object defines them
+    let module = compile("geometry", &[]);
+    let executable = link_modules(&compile("modules", &[]), &[module], "lib.c");
+    let output = Command::new(executable).output().unwrap();
+
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "6 8 25\n108\narea 12\narea 48\nred green\n1 1\n3\n"
+    );
+}
+
+#[test]
+fn private_declarations_are_not_visible_from_other_modules() {
+    compile("geometry", &[]);
+    let errors = compile_error("private");
+
+    assert!(errors
+        .contains("function \"geometry.picked\" is private to module \"geometry\", declared at "));
+    assert!(errors.contains("geometry.x:51:1"));
+    assert!(errors.contains("method \"geometry.Vector2.checked\" is private"));
+    assert!(errors.contains("geometry.x:25:5"));
+}
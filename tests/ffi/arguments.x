@@ -0,0 +1,6 @@
+extern fun printf(format: *u8, ...) -> i32
+
+fun main(count: i32, arguments: **u8) -> i32 {
+    printf("%d arguments\n", count)
+    count + 40
+}
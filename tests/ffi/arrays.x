@@ -0,0 +1,29 @@
+extern fun printf(format: *u8, ...) -> i32
+
+type Point {
+    let x: f64
+    let y: f64
+}
+
+fun sum(values: [i32]) -> i32 {
+    values[0] + values[1] + values[2]
+}
+
+fun main() -> i32 {
+    let values: [i32] := [1, 2, 3]
+    let doubles := [0.5, 1.5]
+    let points := [Point(x: 1, y: 2), Point(x: 3, y: 4)]
+    let names := ["a", "b"]
+    let empty: [u8] := []
+    let i: i32 := 2
+
+    values[1] := 20
+    printf("%d %d %lld\n", sum(values), values[i], values.count)
+    printf("%g %g\n", doubles[1], points[1].y)
+    printf("%s %lld\n", names[1], empty.count)
+
+    let maybe: [i32]? := values
+    printf("%lld\n", maybe?.count ?? 0)
+
+    0
+}
@@ -0,0 +1,11 @@
+extern fun printf(format: *u8, ...) -> i32
+
+fun main() -> i32 {
+    let values := [1, 2, 3]
+    let index: i32 := 3
+
+    printf("%g\n", values[index - 1])
+    printf("%g\n", values[index])
+
+    0
+}
@@ -0,0 +1,13 @@
+import c "lib.h"
+
+fun main() -> i32 {
+    let v := vector2_add(Vector2(x: 1, y: 2), Vector2(x: 3, y: 4))
+    printf("%g %g\n", v.x, v.y)
+
+    let w := vector3_scale(Vector3(x: 1, y: 2, z: 3), 2)
+    printf("%g %g %g\n", w.x, w.y, w.z)
+
+    printf("%d %d %u\n", negate8(5), LIB_ANSWER, LIB_MASK)
+
+    0
+}
@@ -0,0 +1,33 @@
+extern fun printf(format: *u8, ...) -> i32
+extern fun negate8(x: i8) -> i8
+extern fun is_even(x: u32) -> bool
+extern fun calloc(count: u64, size: u64) -> *i32
+
+proto LibMathProto {
+    fun selfless add(x: f64, y: f64) -> f64
+
+    @name("lm_multiply")
+    fun selfless mul(x: i64, y: i64) -> i64
+}
+
+@prefix("lm_")
+extern "C" LibMath: LibMathProto
+
+fun main() -> i32 {
+    printf("%d\n", negate8(5))
+    printf("%d %d\n", is_even(4), is_even(7))
+
+    let count: i32 := 40 + 2
+    let half: f32 := 0.5
+    printf("%d %g %s\n", count, half, "done")
+
+    printf("%g %ld\n", LibMath.add(1, 2), LibMath.mul(6, 7))
+
+    // pointers other than strings are keys by address, not by what they point to
+    let first := calloc(1, 8)
+    let second := calloc(1, 8)
+    let handles := [first: 1, second: 2]
+    printf("%d %g\n", handles.count, handles[second] ?? 0)
+
+    0
+}
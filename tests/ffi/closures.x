@@ -0,0 +1,76 @@
+extern fun printf(format: *u8, ...) -> i32
+
+fun twice(f: (f64) -> f64, x: f64) -> f64 {
+    f(f(x))
+}
+
+fun make_adder(amount: f64) -> (f64) -> f64 {
+    fun(x: f64) -> f64 { x + amount }
+}
+
+fun square(x: f64) -> f64 {
+    x * x
+}
+
+fun apply_all(values: [i32], f: (i32) -> i32, index: i64) -> i32 {
+    if index = values.count {
+        0
+    } else {
+        f(values[index]) + apply_all(values, f, index + 1)
+    }
+}
+
+type Button {
+    let label: *u8
+    let on_click: (*u8) -> ()
+
+    fun click() {
+        on_click(label)
+    }
+}
+
+type Counter {
+    let start: i32
+
+    fun counter(step: i32) -> (i32) -> i32 {
+        fun(n: i32) -> i32 { start + offset(step * n) }
+    }
+
+    fun offset(n: i32) -> i32 {
+        n + 1
+    }
+}
+
+fun main() -> i32 {
+    let add_five := make_adder(5)
+    printf("%g %g\n", add_five(1), twice(add_five, 1))
+
+    printf("%g\n", twice(square, 3))
+
+    let factor: i32 := 3
+    let total := apply_all([1, 2, 3], fun(x: i32) -> i32 { x * factor }, 0)
+    printf("%d\n", total)
+
+    let button := Button(label: "ok", on_click: fun(label: *u8) { printf("clicked %s\n", label) })
+    button.on_click("direct")
+    button.click()
+
+    let counter := Counter(start: 10)
+    let count := counter.counter(2)
+    printf("%d\n", count(3))
+
+    let amount := 1
+    let nested := fun(a: f64) -> (f64) -> f64 {
+        fun(b: f64) -> f64 { a * b + amount }
+    }
+    let double := nested(2)
+    printf("%g\n", double(4))
+
+    let maybe: ((f64) -> f64)? := square
+
+    if let f := maybe {
+        printf("%g\n", f(2))
+    }
+
+    0
+}
@@ -0,0 +1,41 @@
+extern fun printf(format: *u8, ...) -> i32
+
+fun classify(n: i32) -> *u8 {
+    if n < 0 {
+        "negative"
+    } else if n = 0 {
+        "zero"
+    } else if n < 10 {
+        "small"
+    } else {
+        "large"
+    }
+}
+
+fun clamp(n: i64, low: i64, high: i64) -> i64 {
+    if n < low {
+        return low
+    }
+
+    if n > high {
+        return high
+    }
+
+    n
+}
+
+fun main() -> i32 {
+    let minus_one: i32 := 0 - 1
+    let minus_two: i8 := 0 - 2
+
+    printf("%d %d %d\n", minus_one < 0, minus_one > 0, minus_two <= 0 - 1)
+    printf("%d %d\n", minus_one >= 0 - 1, 0 - 5 < 3)
+
+    let small: u8 := 200
+    printf("%d\n", small > 100)
+
+    printf("%s %s %s %s\n", classify(0 - 7), classify(0), classify(3), classify(42))
+    printf("%lld %lld %lld\n", clamp(0 - 20, 0 - 10, 10), clamp(5, 0 - 10, 10), clamp(99, 0 - 10, 10))
+
+    0
+}
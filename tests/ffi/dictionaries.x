@@ -0,0 +1,59 @@
+extern fun printf(format: *u8, ...) -> i32
+
+type Point: Hashable {
+    let x: u64
+    let y: u64
+
+    fun hash() -> u64 {
+        x * 31 + y
+    }
+
+    fun equals(other: Point) -> bool {
+        if x = other.x {
+            y = other.y
+        } else {
+            false
+        }
+    }
+}
+
+enum Color {
+    red
+    green
+    blue
+}
+
+fun fill(squares: [i64: i64], n: i64) -> i64 {
+    if n = 0 {
+        squares.count
+    } else {
+        squares[n] := n * n
+        fill(squares, n - 1)
+    }
+}
+
+fun main() -> i32 {
+    let ages := ["ada": 36, "alan": 41]
+    ages["grace"] := 85
+    printf("%g %g %g %d\n", ages["ada"] ?? 0, ages["grace"] ?? 0, ages["linus"] ?? 0, ages.count)
+
+    ages["ada"] := 37
+    ages["alan"] := nil
+    printf("%g %d %d\n", ages["ada"] ?? 0, ages["alan"] = nil, ages.count)
+
+    let squares: [i64: i64] := [:]
+    printf("%lld %lld %lld\n", fill(squares, 100), squares[12] ?? 0, squares[101] ?? 0)
+
+    let names: [Point: *u8] := [Point(x: 1, y: 2): "a", Point(x: 2, y: 1): "b"]
+
+    if let name := names[Point(x: 2, y: 1)] {
+        printf("%s\n", name)
+    } else {
+        printf("missing\n")
+    }
+
+    let colors := [Color.red: 1, Color.blue: 3]
+    printf("%g %g\n", colors[Color.blue] ?? 0, colors[Color.green] ?? 0)
+
+    0
+}
@@ -0,0 +1,41 @@
+type Vector2 {
+    let x: f64
+    let y: f64
+}
+
+type Box3 {
+    let origin: Vector2
+    let depth: f64
+    let tag: i32
+}
+
+enum Axis {
+    horizontal
+    vertical
+}
+
+fun twice(value: f64) -> f64 {
+    value * 2
+}
+
+// passed in two floating-point registers
+@export
+fun scale(v: Vector2, factor: f64) -> Vector2 {
+    Vector2(x: v.x * factor, y: twice(v.y))
+}
+
+// passed and returned through memory
+@export("geo_grow")
+fun grow(b: Box3, amount: f64) -> Box3 {
+    return Box3(origin: b.origin, depth: b.depth + amount, tag: b.tag + 1)
+}
+
+@export
+fun flip(axis: Axis) -> Axis {
+    Axis.vertical
+}
+
+@export
+fun narrow(value: i8, flag: bool) -> i8 {
+    value
+}
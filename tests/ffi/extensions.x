@@ -0,0 +1,64 @@
+extern fun printf(format: *u8, ...) -> i32
+
+proto Shape {
+    fun area() -> f64
+
+    fun describe() {
+        printf("area %g\n", area())
+    }
+}
+
+type Circle {
+    let radius: f64
+}
+
+type Square: Shape {
+    let side: f64
+
+    fun area() -> f64 {
+        side * side
+    }
+
+    fun describe() {
+        printf("square %g\n", side)
+    }
+}
+
+extend Circle: Shape {
+    fun area() -> f64 {
+        radius * radius * 3
+    }
+}
+
+extend Circle {
+    fun diameter() -> f64 {
+        radius * 2
+    }
+}
+
+extend f64: Shape {
+    fun area() -> f64 {
+        self
+    }
+}
+
+fun show(shape: Shape) {
+    shape.describe()
+}
+
+fun main() -> i32 {
+    let circle := Circle(radius: 2)
+
+    // the default is used by types that do not implement the requirement
+    circle.describe()
+    show(circle)
+    show(Square(side: 3))
+
+    let number: f64 := 1.5
+    number.describe()
+    show(number)
+
+    printf("%g %g\n", circle.diameter(), number.area())
+
+    0
+}
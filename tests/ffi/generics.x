@@ -0,0 +1,84 @@
+extern fun printf(format: *u8, ...) -> i32
+
+proto Measurable {
+    fun size() -> f64
+}
+
+type Circle: Measurable {
+    let radius: f64
+
+    fun size() -> f64 {
+        radius * radius * 3
+    }
+}
+
+type Square: Measurable {
+    let side: f64
+
+    fun size() -> f64 {
+        side * side
+    }
+}
+
+type Pair<A, B> {
+    let first: A
+    let second: B
+
+    fun swap() -> Pair<B, A> {
+        Pair(first: second, second: first)
+    }
+}
+
+enum Side<T> {
+    left
+    right
+
+    fun pick(a: T, b: T) -> T {
+        match self {
+            .left => a
+            .right => b
+        }
+    }
+}
+
+fun identity<T>(value: T) -> T {
+    value
+}
+
+fun larger<T: Measurable>(a: T, b: T) -> T {
+    if a.size() > b.size() {
+        a
+    } else {
+        b
+    }
+}
+
+fun first_or<T>(value: T?, default: T) -> T {
+    value ?? default
+}
+
+fun total<T: Measurable>(items: Pair<T, T>) -> f64 {
+    items.first.size() + items.second.size()
+}
+
+fun main() -> i32 {
+    let count: i32 := 7
+
+    printf("%d %g\n", identity(identity(count)), identity(2.5))
+    printf("%g\n", larger(Circle(radius: 1), Circle(radius: 0.5)).radius)
+    printf("%g\n", larger(Square(side: 1), Square(side: 3)).side)
+
+    let pair: Pair<i32, f64> := Pair(first: 1, second: 2)
+    let swapped := pair.swap()
+    printf("%g %d\n", swapped.first, swapped.second)
+
+    printf("%g\n", total(Pair(first: Square(side: 1), second: Square(side: 2))))
+
+    let missing: i32? := nil
+    printf("%d %d\n", first_or(missing, 3), first_or(count, 0))
+
+    let side: Side<i32> := Side.right
+    printf("%d\n", side.pick(1, 2))
+
+    0
+}
@@ -0,0 +1,64 @@
+extern fun printf(format: *u8, ...) -> i32
+
+pub let unit := 1
+
+pub proto Shape {
+    fun area() -> f64
+
+    fun describe() {
+        printf("area %g\n", area())
+    }
+}
+
+pub type Vector2: Shape {
+    pub let x: f64
+    pub let y: f64
+
+    pub fun area() -> f64 {
+        x * y
+    }
+
+    pub fun scaled(factor: f64) -> Vector2 {
+        scale(self, checked(factor))
+    }
+
+    fun checked(factor: f64) -> f64 {
+        if factor < 0 {
+            0
+        } else {
+            factor
+        }
+    }
+}
+
+pub enum Color {
+    red
+    green
+}
+
+pub fun scale(v: Vector2, factor: f64) -> Vector2 {
+    Vector2(x: v.x * factor, y: v.y * factor)
+}
+
+pub fun length(x: f64, y: f64) -> f64 {
+    fun square(value: f64) -> f64 {
+        value * value
+    }
+
+    square(x) + square(y) + unit - 1
+}
+
+fun picked() -> i32 {
+    0
+}
+
+// instantiated in the program, the private function it calls is linked from this module
+pub fun first<T>(a: T, b: T) -> T {
+    picked()
+    a
+}
+
+@export
+pub fun geometry_version() -> i32 {
+    3
+}
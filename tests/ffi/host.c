@@ -0,0 +1,16 @@
+#include <stdio.h>
+
+#include "exports.h"
+
+int main(void) {
+    Vector2 v = scale((Vector2){1.5, 2}, 2);
+    printf("%g %g\n", v.x, v.y);
+
+    Box3 b = geo_grow((Box3){{1, 2}, 3, 7}, 0.5);
+    printf("%g %g %g %d\n", b.origin.x, b.origin.y, b.depth, b.tag);
+
+    printf("%d\n", flip(Axis_horizontal));
+    printf("%d\n", narrow(-5, true));
+
+    return 0;
+}
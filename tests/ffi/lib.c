@@ -0,0 +1,20 @@
+#include <stdint.h>
+#include <stdio.h>
+
+typedef struct { double x; double y; } Vector2;
+typedef struct { int32_t a; float b; double c; } Mixed;
+typedef struct { double x; double y; double z; } Vector3;
+typedef struct { int64_t a; int64_t b; } Pair;
+typedef struct { float r; float g; float b; } Color;
+
+Vector2 vector2_add(Vector2 a, Vector2 b) { Vector2 r = { a.x + b.x, a.y + b.y }; return r; }
+double mixed_sum(Mixed m) { return m.a + m.b + m.c; }
+Mixed mixed_make(int32_t a) { Mixed m = { a, 0.5f, 0.25 }; return m; }
+Vector3 vector3_scale(Vector3 v, double s) { Vector3 r = { v.x * s, v.y * s, v.z * s }; return r; }
+int64_t pair_sum(Pair a, Pair b, Pair c, Pair d) { return a.a + a.b + b.a + b.b + c.a + c.b + d.a + d.b; }
+float color_brightness(Color c) { return c.r + c.g + c.b; }
+int8_t negate8(int8_t x) { return -x; }
+uint8_t is_even(uint32_t x) { return x % 2 == 0; }
+
+double lm_add(double x, double y) { return x + y; }
+int64_t lm_multiply(int64_t x, int64_t y) { return x * y; }
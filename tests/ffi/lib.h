@@ -0,0 +1,28 @@
+#ifndef LIB_H
+#define LIB_H
+
+#include <stdint.h>
+
+#define LIB_ANSWER 42
+#define LIB_MASK (0xffu)
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct { double x; double y; } Vector2;
+typedef struct { double x; double y; double z; } Vector3;
+
+/* only used through a pointer */
+typedef struct file FILE;
+
+Vector2 vector2_add(Vector2 a, Vector2 b);
+Vector3 vector3_scale(Vector3 v, double s);
+int8_t negate8(int8_t x);
+int printf(const char *format, ...);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,32 @@
+import geometry
+
+extern fun printf(format: *u8, ...) -> i32
+
+fun length(v: geometry.Vector2) -> f64 {
+    geometry.length(v.x, v.y)
+}
+
+fun name(color: geometry.Color) -> *u8 {
+    match color {
+        .red => "red"
+        .green => "green"
+    }
+}
+
+fun main() -> i32 {
+    let v := geometry.Vector2(x: 3, y: 4)
+    let doubled := geometry.scale(v, 2)
+
+    printf("%g %g %g\n", doubled.x, doubled.y, length(v))
+    printf("%g\n", v.scaled(3).area())
+    v.describe()
+
+    let shape: geometry.Shape := doubled
+    shape.describe()
+
+    printf("%s %s\n", name(geometry.Color.red), name(geometry.Color.green))
+    printf("%g %g\n", geometry.first(geometry.unit, 2), geometry.unit)
+    printf("%d\n", geometry.geometry_version())
+
+    0
+}
@@ -0,0 +1,82 @@
+extern fun printf(format: *u8, ...) -> i32
+
+fun distance(a: f64, b: f64) -> f64 {
+    type Point {
+        let x: f64
+        let y: f64
+
+        fun length() -> f64 {
+            x + y
+        }
+    }
+
+    fun absolute(value: f64) -> f64 {
+        if value < 0 {
+            0 - value
+        } else {
+            value
+        }
+    }
+
+    let offset := Point(x: absolute(a - b), y: 1)
+    offset.length()
+}
+
+fun describe(flag: bool) -> *u8 {
+    enum Answer {
+        yes
+        no
+    }
+
+    fun answer(flag: bool) -> Answer {
+        if flag {
+            Answer.yes
+        } else {
+            Answer.no
+        }
+    }
+
+    match answer(flag) {
+        .yes => "yes"
+        .no => "no"
+    }
+}
+
+fun main() -> i32 {
+    let before: i32 := 7
+
+    fun helper(n: i32) -> i32 {
+        fun twice(m: i32) -> i32 {
+            m * 2
+        }
+
+        twice(n) + 1
+    }
+
+    fun factorial(n: i64) -> i64 {
+        if n = 0 {
+            1
+        } else {
+            n * factorial(n - 1)
+        }
+    }
+
+    let after: i32 := 5
+    printf("%d %d %lld\n", before, helper(after), factorial(10))
+    printf("%g %g\n", distance(1, 4), distance(5, 1))
+
+    if before > 1 {
+        fun helper(n: i32) -> i32 {
+            n * 100
+        }
+
+        printf("%d\n", helper(2))
+    }
+
+    printf("%s %s\n", describe(true), describe(false))
+
+    let apply := fun(n: i32) -> i32 { helper(n) }
+    printf("%d %d\n", apply(1), before)
+
+    0
+}
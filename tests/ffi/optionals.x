@@ -0,0 +1,78 @@
+extern fun printf(format: *u8, ...) -> i32
+extern fun getenv(name: *u8) -> *u8?
+
+type Vector2 {
+    let x: f64
+    let y: f64
+
+    fun length_squared() -> f64 {
+        x * x + y * y
+    }
+}
+
+enum Direction {
+    up
+    down
+    left
+}
+
+fun find(index: i32) -> Vector2? {
+    if index < 0 {
+        return nil
+    }
+
+    Vector2(x: 3, y: 4)
+}
+
+fun describe(value: i32?) -> i32 {
+    match value {
+        nil => 0 - 1
+        let v => v * 2
+    }
+}
+
+fun turn(direction: Direction) -> i32 {
+    match direction {
+        .up => 1,
+        .down => 2,
+        _ => { 3 }
+    }
+}
+
+fun main() -> i32 {
+    let missing: i32? := nil
+    let present: optional<i32> := 21
+
+    printf("%d %d\n", missing ?? 7, present ?? 7)
+    printf("%d %d\n", describe(missing), describe(present))
+
+    if let value := present {
+        printf("value %d\n", value)
+    }
+
+    if let value := missing {
+        printf("unexpected %d\n", value)
+    } else {
+        printf("no value\n")
+    }
+
+    printf("%d %d\n", missing = nil, present ~= nil)
+
+    let vector := find(1)
+    let none := find(0 - 1)
+    printf("%g %g\n", vector?.x ?? 0, none?.x ?? 0 - 1)
+    printf("%g %d\n", vector?.length_squared() ?? 0, none?.length_squared() = nil)
+
+    let home := getenv("X_OPTIONAL_TEST_UNSET")
+    printf("%d\n", home = nil)
+
+    printf("%d %d %d\n", turn(Direction.up), turn(Direction.down), turn(Direction.left))
+
+    let small: u8? := 200
+    match small ?? 0 {
+        200 => printf("literal\n")
+        _ => printf("other\n")
+    }
+
+    0
+}
@@ -0,0 +1,8 @@
+import geometry
+
+fun main() -> i32 {
+    let v := geometry.Vector2(x: 3, y: 4)
+    v.checked(2)
+    geometry.picked()
+    0
+}
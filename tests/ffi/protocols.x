@@ -0,0 +1,72 @@
+extern fun printf(format: *u8, ...) -> i32
+
+proto Shape {
+    fun area() -> f64
+    fun name() -> *u8
+}
+
+type Circle: Shape {
+    let radius: f64
+
+    fun area() -> f64 {
+        radius * radius * 3
+    }
+
+    fun name() -> *u8 {
+        "circle"
+    }
+}
+
+type Square: Shape {
+    let side: f64
+
+    fun area() -> f64 {
+        side * side
+    }
+
+    fun name() -> *u8 {
+        "square"
+    }
+}
+
+fun describe(shape: Shape) {
+    printf("%s %g\n", shape.name(), shape.area())
+}
+
+fun larger(a: Shape, b: Shape) -> Shape {
+    if a.area() > b.area() {
+        return a
+    }
+    b
+}
+
+fun pick(round: bool) -> Shape {
+    if round {
+        return Circle(radius: 2)
+    }
+    Square(side: 5)
+}
+
+fun total(shapes: [Shape]) -> f64 {
+    shapes[0].area() + shapes[1].area()
+}
+
+fun main() -> i32 {
+    describe(Circle(radius: 1))
+    describe(Square(side: 2))
+
+    let shape: Shape := Square(side: 3)
+    printf("%g\n", shape.area())
+
+    // existentials returned from a function outlive its frame
+    describe(larger(Circle(radius: 1), Square(side: 1)))
+    describe(pick(true))
+    describe(pick(false))
+
+    // the elements take the element type the array is declared with
+    let shapes: [Shape] := [Circle(radius: 1), Square(side: 2)]
+    describe(shapes[1])
+    printf("%g\n", total(shapes))
+
+    0
+}
@@ -0,0 +1,54 @@
+extern fun printf(format: *u8, ...) -> i32
+extern fun atoi(string: *u8) -> i32
+
+enum ParseError {
+    empty
+    negative
+}
+
+fun parse(string: *u8?) -> result<i32, ParseError> {
+    if let string := string {
+        let value := atoi(string)
+
+        if value < 0 {
+            return err(ParseError.negative)
+        }
+
+        return ok(value)
+    }
+
+    err(ParseError.empty)
+}
+
+fun sum(a: *u8?, b: *u8?) -> result<i32, ParseError> {
+    let total := parse(a)? + parse(b)?
+    ok(total)
+}
+
+fun first_char(string: *u8?) -> u8? {
+    let s := string?
+    nil
+}
+
+fun describe(r: result<i32, ParseError>) -> i32 {
+    match r {
+        ok(let value) => value
+        err(let error) => match error {
+            .empty => 0 - 1
+            .negative => 0 - 2
+        }
+    }
+}
+
+fun main() -> i32 {
+    printf("%d\n", describe(sum("20", "22")))
+    printf("%d\n", describe(sum("20", nil)))
+    printf("%d\n", describe(sum("-3", "1")))
+
+    match parse("7") {
+        ok(_) => printf("ok\n")
+        err(_) => printf("err\n")
+    }
+
+    0
+}
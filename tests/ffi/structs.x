@@ -0,0 +1,50 @@
+extern fun printf(format: *u8, ...) -> i32
+
+type Vector2 {
+    let x: f64
+    let y: f64
+}
+
+type Mixed {
+    let a: i32
+    let b: f32
+    let c: f64
+}
+
+type Vector3 {
+    let x: f64
+    let y: f64
+    let z: f64
+}
+
+type Pair {
+    let a: i64
+    let b: i64
+}
+
+type Color {
+    let r: f32
+    let g: f32
+    let b: f32
+}
+
+extern fun vector2_add(a: Vector2, b: Vector2) -> Vector2
+extern fun mixed_sum(m: Mixed) -> f64
+extern fun mixed_make(a: i32) -> Mixed
+extern fun vector3_scale(v: Vector3, s: f64) -> Vector3
+extern fun pair_sum(a: Pair, b: Pair, c: Pair, d: Pair) -> i64
+extern fun color_brightness(c: Color) -> f32
+
+fun main() -> i32 {
+    let v := vector2_add(Vector2(x: 1, y: 2), Vector2(x: 3, y: 4))
+    printf("%g %g\n", v.x, v.y)
+    printf("%g\n", mixed_sum(Mixed(a: 1, b: 2, c: 3)))
+    let m := mixed_make(7)
+    printf("%d %g %g\n", m.a, m.b, m.c)
+    let s := vector3_scale(Vector3(x: 1, y: 2, z: 3), 2)
+    printf("%g %g %g\n", s.x, s.y, s.z)
+    printf("%ld\n", pair_sum(Pair(a: 1, b: 2), Pair(a: 3, b: 4), Pair(a: 5, b: 6), Pair(a: 7, b: 8)))
+    printf("%g\n", color_brightness(Color(r: 0.25, g: 0.5, b: 1)))
+
+    0
+}
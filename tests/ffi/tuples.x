@@ -0,0 +1,44 @@
+extern fun printf(format: *u8, ...) -> i32
+
+fun divide(a: i32, b: i32) -> (i32, i32) {
+    (a / b, a - a / b * b)
+}
+
+fun bounds(values: [f64]) -> (f64, f64, bool) {
+    let first := values[0]
+    let last := values[values.count - 1]
+
+    if first < last {
+        (first, last, true)
+    } else {
+        (last, first, false)
+    }
+}
+
+fun swap<A, B>(pair: (A, B)) -> (B, A) {
+    (pair.1, pair.0)
+}
+
+fun main() -> i32 {
+    let (quotient, remainder) := divide(17, 5)
+    printf("%d %d\n", quotient, remainder)
+
+    let result := bounds([4, 2.5, 1])
+    printf("%g %g %d\n", result.0, result.1, result.2)
+
+    let (low, _, sorted) := bounds([1, 8])
+    printf("%g %d\n", low, sorted)
+
+    let nested: ((i32, f64), *u8) := ((1, 2), "x")
+    printf("%d %g %s\n", nested.0.0, nested.0.1, nested.1)
+
+    let (name, count) := swap((3, "three"))
+    printf("%s %g\n", name, count)
+
+    let maybe: (i32, bool)? := (7, true)
+    printf("%d\n", maybe?.0 ?? 0)
+
+    printf("%g\n", (2 + 3) * 2)
+
+    0
+}
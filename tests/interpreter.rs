@@ -0,0 +1,204 @@
+use x::driver::{self, CompileOptions, Emit};
+use x::interpreter::{InterpreterVisitor, RunError};
+use x::{Lexer, Parser};
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn source_directory() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/ffi")
+}
+
+#[test]
+fn programs_are_checked_and_run_with_the_library() {
+    let code =
+        "fun square(n: i32) -> i32 {\n    n * n\n}\n\nfun main() -> i32 {\n    square(7)\n}\n";
+
+    let file = Parser::new(Lexer::new()).parse(&mut code.chars().peekable());
+    assert_eq!(file.code_block.declarations().len(), 2);
+
+    let options = CompileOptions {
+        emit: Emit::Obj,
+        header: None,
+        bounds_checks: true,
+        search_paths: Vec::new(),
+    };
+    let files = [(PathBuf::from("square.x"), code.to_string())];
+    let (file, context) = driver::check_module(&files, "square", Path::new("."), &options);
+    assert!(!context.has_errors());
+
+    let mut interpreter = InterpreterVisitor::new("square.x".to_string());
+    assert_eq!(interpreter.run(&file, &[], &context), Ok(49));
+
+    let files = [(
+        PathBuf::from("wrong.x"),
+        code.replace("square(7)", "square(true)"),
+    )];
+    let (_, context) = driver::check_module(&files, "wrong", Path::new("."), &options);
+    assert_eq!(context.error_count(), 1);
+}
+
+#[test]
+fn errors_are_returned_to_the_host() {
+    let options = CompileOptions {
+        emit: Emit::Obj,
+        header: None,
+        bounds_checks: true,
+        search_paths: Vec::new(),
+    };
+    let run = |code: &str| {
+        let files = [(PathBuf::from("errors.x"), code.to_string())];
+        let (file, context) = driver::check_module(&files, "errors", Path::new("."), &options);
+        assert!(!context.has_errors());
+
+        InterpreterVisitor::new("errors.x".to_string()).run(&file, &[], &context)
+    };
+
+    assert_eq!(
+        run("fun main() -> i64 {\n    let values: [i64] := [1, 2]\n    values[2]\n}\n"),
+        Err(RunError::Stopped(
+            "errors.x:3:11: index 2 is out of bounds for an array of 2 elements".to_string()
+        ))
+    );
+    assert_eq!(
+        run("fun helper() -> i32 {\n    0\n}\n"),
+        Err(RunError::Invalid(
+            "the program has no `main` function".to_string()
+        ))
+    );
+}
+
+#[test]
+fn branches_of_different_types_are_dispatched_through_the_protocol() {
+    let code = "proto Shape {
+    fun area() -> f64
+}
+
+type Square: Shape {
+    let side: f64
+
+    fun area() -> f64 {
+        side * side
+    }
+}
+
+type Circle: Shape {
+    let radius: f64
+
+    fun area() -> f64 {
+        3 * radius * radius
+    }
+}
+
+fun pick(c: bool) -> Shape {
+    if c { Square(side: 2) } else { Circle(radius: 1) }
+}
+
+fun main() -> i32 {
+    let area := pick(true).area() * 10 + pick(false).area()
+    if area = 43 { 0 } else { 1 }
+}
+";
+
+    let options = CompileOptions {
+        emit: Emit::Obj,
+        header: None,
+        bounds_checks: true,
+        search_paths: Vec::new(),
+    };
+    let files = [(PathBuf::from("shapes.x"), code.to_string())];
+    let (file, context) = driver::check_module(&files, "shapes", Path::new("."), &options);
+    assert!(!context.has_errors());
+
+    let mut interpreter = InterpreterVisitor::new("shapes.x".to_string());
+    assert_eq!(interpreter.run(&file, &[], &context), Ok(0));
+}
+
+#[test]
+fn programs_are_run_with_the_interpreter() {
+    let run = |name: &str, arguments: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_x"))
+            .args(["run", "--backend", "interp"])
+            .arg(source_directory().join(format!("{}.x", name)))
+            .args(arguments)
+            .output()
+            .unwrap()
+    };
+
+    // each program prints what it prints when compiled
+    for (name, expected) in [
+        ("arrays", "24 3 3\n1.5 4\nb 0\n3\n"),
+        (
+            "closures",
+            "6 11\n81\n18\nclicked direct\nclicked ok\n17\n9\n4\n",
+        ),
+        ("dictionaries", "36 85 0 3\n37 1 2\n100 144 0\nb\n3 0\n"),
+        (
+            "extensions",
+            "area 12\narea 12\nsquare 3\narea 1.5\narea 1.5\n4 1.5\n",
+        ),
+        ("generics", "7 2.5\n1\n3\n2 1\n5\n3 7\n2\n"),
+        (
+            "optionals",
+            "7 21\n-1 42\nvalue 21\nno value\n1 1\n3 -1\n25 1\n1\n1 2 3\nliteral\n",
+        ),
+        ("results", "42\n-1\n-2\nok\n"),
+        (
+            "protocols",
+            "circle 3\nsquare 4\n9\ncircle 3\ncircle 12\nsquare 25\nsquare 4\n7\n",
+        ),
+        (
+            "conditionals",
+            "1 0 1\n1 1\n1\nnegative zero small large\n-10 5 10\n",
+        ),
+        ("tuples", "3 2\n1 4 0\n1 1\n1 2 x\nthree 3\n7\n10\n"),
+        ("nested", "7 11 3628800\n4 5\n200\nyes no\n3 7\n"),
+        (
+            "modules",
+            "6 8 25\n108\narea 12\narea 48\nred green\n1 1\n3\n",
+        ),
+    ] {
+        let output = run(name, &[]);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+
+        assert!(output.status.success(), "{}: {}", name, stdout);
+        assert_eq!(stdout, expected, "{}", name);
+    }
+
+    let output = run("arguments", &["one", "--two"]);
+    assert_eq!(output.status.code(), Some(43));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3 arguments\n");
+
+    // errors stop the program as in compiled code
+    let output = run("bounds", &[]);
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n");
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("bounds.x:8:26: index 3 is out of bounds for an array of 3 elements"));
+
+    // C functions other than the builtins cannot be called
+    let output = run("structs", &[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("the interpreter does not provide the extern function \"vector2_add\""));
+}
+
+#[test]
+fn modules_that_cannot_be_read_are_reported() {
+    let run = |path: &Path| {
+        Command::new(env!("CARGO_BIN_EXE_x"))
+            .args(["run", "--backend", "interp"])
+            .arg(path)
+            .output()
+            .unwrap()
+    };
+
+    let output = run(&source_directory().join("missing.x"));
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("error: cannot read module "));
+
+    let output = run(Path::new("."));
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout)
+        .contains("error: cannot read module ., its path does not name a file or a directory"));
+}
@@ -0,0 +1,102 @@
+#![cfg(feature = "llvm")]
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs `x repl` with inputs, one per line, and returns what it prints after
+/// its banner with the prompts left out.
+fn session(lines: &[&str]) -> String {
+    let mut repl = Command::new(env!("CARGO_BIN_EXE_x"))
+        .arg("repl")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut input = lines.join("\n");
+    input.push('\n');
+    repl.stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+
+    let output = repl.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .skip(1)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .replace("... ", "")
+        .replace("> ", "")
+}
+
+#[test]
+fn declarations_are_kept_and_values_are_printed() {
+    let output = session(&[
+        "type Point {",
+        "    let x: f64",
+        "    let y: f64",
+        "}",
+        "enum Color {",
+        "    red",
+        "    green",
+        "}",
+        "fun twice(value: f64) -> f64 {",
+        "    value * 2",
+        "}",
+        "let p := Point(x: 1, y: twice(2))",
+        "p",
+        "twice(p.y) + 1",
+        "Color.green",
+        "([1, 2, 3], true, \"text\")",
+        "let missing: f64? := nil",
+        "missing",
+        ":type p.x",
+        "unknown(1)",
+        ":quit",
+    ]);
+
+    assert_eq!(
+        output,
+        "Point(x: 1, y: 4)\n9\n.green\n([1, 2, 3], true, text)\nnil\nf64\nerror: undeclared function \"unknown\"\n"
+    );
+}
+
+#[test]
+fn code_is_shown_as_llvm_ir() {
+    let output = session(&[
+        "fun square(x: f64) -> f64 {",
+        "    x * x",
+        "}",
+        ":ir square(3)",
+    ]);
+
+    assert!(output.contains("define i32 @repl.repl_evaluate_1()"));
+    assert!(output.contains("call double @repl.square(double 3.000000e+00)"));
+}
+
+#[test]
+fn variables_are_evaluated_once() {
+    let output = session(&[
+        "extern fun puts(text: *u8) -> i32",
+        "fun answer() -> f64 {",
+        "    puts(\"evaluated\")",
+        "    42",
+        "}",
+        "let value := answer()",
+        "value + 1",
+        "let (half, two) := (value / 2, 2)",
+        "half * two",
+        "let value := 1",
+        "value",
+    ]);
+
+    assert_eq!(
+        output,
+        "evaluated\n43\n42\nerror: \"value\" is already declared\n42\n"
+    );
+}